@@ -1,7 +1,6 @@
 use mainline::Dht;
 
 use tracing::Level;
-use tracing_subscriber;
 
 fn main() {
     tracing_subscriber::fmt()
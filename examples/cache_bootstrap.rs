@@ -11,7 +11,6 @@ use std::path::PathBuf;
 use mainline::Dht;
 
 use tracing::Level;
-use tracing_subscriber;
 
 fn main() {
     tracing_subscriber::fmt()
@@ -48,6 +47,6 @@ fn main() {
 
     let bootstrap_content = bootstrap.join("\n");
     let mut file = fs::File::create(&nodes_file).expect("Failed to save bootstrapping nodes");
-    file.write(bootstrap_content.as_bytes())
+    file.write_all(bootstrap_content.as_bytes())
         .expect("Failed to write bootstrapping nodes");
 }
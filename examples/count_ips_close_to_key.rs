@@ -80,10 +80,10 @@ fn main() {
             let previous = ip_hits.get(socket);
             match previous {
                 Some(val) => {
-                    ip_hits.insert(socket.clone(), val + 1);
+                    ip_hits.insert(*socket, val + 1);
                 }
                 None => {
-                    ip_hits.insert(socket.clone(), 1);
+                    ip_hits.insert(*socket, 1);
                 }
             };
         }
@@ -97,7 +97,7 @@ fn main() {
         let furthest_distance = target.distance(furthest_node.id());
 
         let overlap_with_last_lookup: HashSet<Ipv4Addr> =
-            sockets.intersection(&last_nodes).map(|ip| *ip).collect();
+            sockets.intersection(&last_nodes).copied().collect();
 
         let overlap = overlap_with_last_lookup.len() as f64 / K as f64;
         last_nodes = sockets;
@@ -107,7 +107,7 @@ fn main() {
             ip_hits.len(),
             closest_distance,
             furthest_distance,
-            (overlap*100 as f64) as usize
+            (overlap*100_f64) as usize
         );
     }
 
@@ -124,13 +124,13 @@ fn print_histogram(hits: HashMap<Ipv4Addr, u16>, lookup_count: usize) {
     let percents: HashMap<Ipv4Addr, u64> = hits
         .into_iter()
         .map(|(ip, hits)| {
-            let percent = (hits as f32 / lookup_count as f32) * 100 as f32;
+            let percent = (hits as f32 / lookup_count as f32) * 100_f32;
             (ip, percent as u64)
         })
         .collect();
 
     for (_, percent) in percents.iter() {
-        histogram.add(percent.clone());
+        histogram.add(*percent);
     }
 
     println!("{}", histogram);
@@ -143,14 +143,14 @@ fn get_random_boostrap_nodes2() -> Vec<String> {
         .iter()
         .map(|node| node.address().to_string())
         .collect::<Box<[_]>>();
-    let slice: Vec<String> = addrs[..8].into_iter().map(|va| va.clone()).collect();
+    let slice: Vec<String> = addrs[..8].to_vec();
     slice
 }
 
 fn init_dht(use_random_boostrap_nodes: bool) -> Dht {
     if use_random_boostrap_nodes {
         let bootstrap = get_random_boostrap_nodes2();
-        return Dht::builder().bootstrap(&bootstrap).build().unwrap();
+        Dht::builder().bootstrap(&bootstrap).build().unwrap()
     } else {
         Dht::client().unwrap()
     }
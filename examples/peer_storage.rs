@@ -0,0 +1,57 @@
+use std::{collections::HashMap, net::SocketAddrV4};
+
+use mainline::{Dht, Id, PeerStorage, ServerSettings};
+use tracing::{info, Level};
+
+/// A simple unbounded in-memory store, showing where a persistent
+/// store (a database, Redis, ...) would plug into the server.
+#[derive(Debug, Default, Clone)]
+struct InMemoryStorage(HashMap<Id, HashMap<Id, SocketAddrV4>>);
+
+impl PeerStorage for InMemoryStorage {
+    fn add_peer(&mut self, info_hash: Id, peer: (&Id, SocketAddrV4)) {
+        info!(?info_hash, peer = ?peer.1, "Storing peer");
+
+        self.0.entry(info_hash).or_default().insert(*peer.0, peer.1);
+    }
+
+    fn get_random_peers(&mut self, info_hash: &Id) -> Option<Vec<SocketAddrV4>> {
+        self.0
+            .get(info_hash)
+            .map(|peers| peers.values().take(20).copied().collect())
+    }
+
+    fn peers_counts(&self) -> Vec<(Id, usize)> {
+        self.0
+            .iter()
+            .map(|(info_hash, peers)| (*info_hash, peers.len()))
+            .collect()
+    }
+
+    fn random_info_hashes(&self, max: usize) -> Vec<Id> {
+        self.0.keys().take(max).copied().collect()
+    }
+}
+
+fn main() {
+    tracing_subscriber::fmt().with_max_level(Level::INFO).init();
+
+    let client = Dht::builder()
+        .server_mode()
+        .server_settings(ServerSettings {
+            peer_storage: Some(Box::new(InMemoryStorage::default())),
+            ..Default::default()
+        })
+        .build()
+        .unwrap();
+
+    client.bootstrapped();
+
+    let info = client.info();
+
+    println!("{:?}", info);
+
+    loop {
+        std::thread::sleep(std::time::Duration::from_secs(1));
+    }
+}
@@ -5,7 +5,6 @@ use mainline::{Dht, MutableItem, SigningKey};
 use clap::Parser;
 
 use tracing::Level;
-use tracing_subscriber;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -81,7 +80,7 @@ fn put(dht: &Dht, signer: &SigningKey, value: &[u8], salt: Option<&[u8]>) {
 }
 
 fn from_hex(s: String) -> SigningKey {
-    if s.len() % 2 != 0 {
+    if !s.len().is_multiple_of(2) {
         panic!("Number of Hex characters should be even");
     }
 
@@ -32,5 +32,7 @@ fn main() {
 
     println!("{:?}", info);
 
-    loop {}
+    loop {
+        std::thread::sleep(std::time::Duration::from_secs(1));
+    }
 }
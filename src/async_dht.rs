@@ -1,21 +1,40 @@
 //! AsyncDht node.
+//!
+//! [AsyncDht] mirrors [Dht], but returns futures (and [Stream]s for queries that
+//! return multiple values, like [AsyncDht::get_peers]) instead of blocking the calling thread.
+//!
+//! It is runtime agnostic, as it only awaits on channels to the [Dht]'s actor thread,
+//! so it works inside `tokio`, `async-std`, or any other executor, without any extra feature flag.
 
 use std::{
-    net::SocketAddrV4,
+    collections::{HashMap, HashSet},
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
     pin::Pin,
     task::{Context, Poll},
+    time::{Duration, Instant},
 };
 
 use futures_lite::{Stream, StreamExt};
 
 use crate::{
     common::{
-        hash_immutable, AnnouncePeerRequestArguments, FindNodeRequestArguments,
-        GetPeersRequestArguments, GetValueRequestArguments, Id, MutableItem, Node,
-        PutImmutableRequestArguments, PutMutableRequestArguments, PutRequestSpecific,
+        FindNodeRequestArguments, GetPeersRequestArguments, GetValueRequestArguments, Id, Ipv4Net,
+        MagnetError, MutableItem, Node, PutMutableRequestArguments, PutRequestSpecific,
+        RequestSpecific, ResponseSpecific, MAX_VALUE_SIZE,
     },
-    dht::{ActorMessage, Dht, PutMutableError, ResponseSender},
-    rpc::{GetRequestSpecific, Info, PutError, PutQueryError},
+    dht::{
+        announce_peer_request, check_chunked_size, check_min_replicas, chunks_manifest,
+        get_mutable_request, get_peers_request, immutable_put_result, insert_more_recent,
+        ipv4_addresses, merged_item, more_recent, parse_chunks_manifest, peers_response,
+        sample_infohashes_request, scrape_request, with_distance, ActorMessage, AnnounceReport,
+        CancelOnDrop, Dht, ImmutableOutcome, InfohashSamples, PrefixNodes, PutMutableError,
+        PutOptions, RawResponse, RepublishHandle, RequestError, ResponseSender, ScrapeResult,
+        MAX_MERGE_ATTEMPTS,
+    },
+    rpc::{
+        GetRequestSpecific, Info, PeersResponse, PutError, PutQueryError, PutReport, QueryTiming,
+    },
+    BucketInfo, DhtEvent, RoutingTable, ServerStats, SigningKey,
 };
 
 impl Dht {
@@ -40,6 +59,76 @@ impl AsyncDht {
             .expect("actor thread unexpectedly shutdown")
     }
 
+    /// Async version of [Dht::id].
+    pub async fn id(&self) -> Id {
+        let (tx, rx) = flume::bounded::<Id>(1);
+        self.send(ActorMessage::Id(tx));
+
+        rx.recv_async()
+            .await
+            .expect("actor thread unexpectedly shutdown")
+    }
+
+    /// Same as [Dht::local_addr].
+    pub fn local_addr(&self) -> SocketAddrV4 {
+        self.0.local_addr()
+    }
+
+    /// Same as [Dht::is_shutdown].
+    pub fn is_shutdown(&self) -> bool {
+        self.0.is_shutdown()
+    }
+
+    /// Async version of [Dht::server_stats].
+    pub async fn server_stats(&self, top_n: usize) -> ServerStats {
+        let (tx, rx) = flume::bounded::<ServerStats>(1);
+        self.send(ActorMessage::ServerStats(top_n, tx));
+
+        rx.recv_async()
+            .await
+            .expect("actor thread unexpectedly shutdown")
+    }
+
+    /// Async version of [Dht::bootstrap_status].
+    pub async fn bootstrap_status(&self) -> Vec<(SocketAddrV4, bool)> {
+        let (tx, rx) = flume::bounded::<Vec<(SocketAddrV4, bool)>>(1);
+        self.send(ActorMessage::BootstrapStatus(tx));
+
+        rx.recv_async()
+            .await
+            .expect("actor thread unexpectedly shutdown")
+    }
+
+    /// Async version of [Dht::dht_size_estimate_history].
+    pub async fn dht_size_estimate_history(&self, n: usize) -> Vec<(Instant, usize, f64)> {
+        let (tx, rx) = flume::bounded::<Vec<(Instant, usize, f64)>>(1);
+        self.send(ActorMessage::DhtSizeEstimateHistory(n, tx));
+
+        rx.recv_async()
+            .await
+            .expect("actor thread unexpectedly shutdown")
+    }
+
+    /// Returns a snapshot of this node's [RoutingTable].
+    pub async fn routing_table(&self) -> RoutingTable {
+        let (tx, rx) = flume::bounded::<RoutingTable>(1);
+        self.send(ActorMessage::RoutingTable(tx));
+
+        rx.recv_async()
+            .await
+            .expect("actor thread unexpectedly shutdown")
+    }
+
+    /// Async version of [Dht::debug_buckets].
+    pub async fn debug_buckets(&self) -> Vec<BucketInfo> {
+        let (tx, rx) = flume::bounded::<Vec<BucketInfo>>(1);
+        self.send(ActorMessage::DebugBuckets(tx));
+
+        rx.recv_async()
+            .await
+            .expect("actor thread unexpectedly shutdown")
+    }
+
     /// Turn this node's routing table to a list of bootstrapping nodes.   
     pub async fn to_bootstrap(&self) -> Vec<String> {
         let (tx, rx) = flume::bounded::<Vec<String>>(1);
@@ -56,15 +145,98 @@ impl AsyncDht {
     ///
     /// Returns true if the bootstrapping was successful.
     pub async fn bootstrapped(&self) -> bool {
-        let info = self.info().await;
-        let nodes = self.find_node(*info.id()).await;
+        let nodes = self.find_node(self.id().await).await;
 
         !nodes.is_empty()
     }
 
+    /// Async version of [Dht::bootstrapped_with].
+    pub async fn bootstrapped_with(&self, min_nodes: usize, timeout: Duration) -> bool {
+        let (tx, rx) = flume::bounded::<bool>(1);
+        self.send(ActorMessage::Bootstrapped(
+            min_nodes,
+            Instant::now() + timeout,
+            tx,
+        ));
+
+        rx.recv_async()
+            .await
+            .expect("actor thread unexpectedly shutdown")
+    }
+
+    /// Stop sending and receiving any packets, see [Dht::pause].
+    pub fn pause(&self) {
+        self.send(ActorMessage::Pause);
+    }
+
+    /// Resume the network activity stopped by [Self::pause].
+    pub fn resume(&self) {
+        self.send(ActorMessage::Resume);
+    }
+
+    /// Drop packets to and from addresses in this `range`, see [Dht::ban].
+    pub fn ban(&self, range: impl Into<Ipv4Net>) {
+        self.send(ActorMessage::Ban(range.into()));
+    }
+
+    /// Remove a `range` previously banned, see [Dht::unban].
+    pub fn unban(&self, range: impl Into<Ipv4Net>) {
+        self.send(ActorMessage::Unban(range.into()));
+    }
+
+    /// Async version of [Dht::set_external_ip].
+    pub async fn set_external_ip(&self, ip: Ipv4Addr) -> Id {
+        let (tx, rx) = flume::bounded::<Id>(1);
+        self.send(ActorMessage::SetExternalIp(ip, tx));
+
+        rx.recv_async()
+            .await
+            .expect("actor thread unexpectedly shutdown")
+    }
+
+    /// Async version of [Dht::send_request].
+    pub async fn send_request(
+        &self,
+        address: SocketAddrV4,
+        request: RequestSpecific,
+    ) -> Result<ResponseSpecific, RequestError> {
+        let (tx, rx) = flume::bounded::<Result<ResponseSpecific, RequestError>>(1);
+        self.send(ActorMessage::Request(address, request, tx));
+
+        rx.recv_async()
+            .await
+            .expect("actor thread unexpectedly shutdown")
+    }
+
+    /// Async version of [Dht::get_raw].
+    pub fn get_raw(&self, request: GetRequestSpecific) -> GetStream<RawResponse> {
+        let target = *request.target();
+
+        let (tx, rx) = flume::unbounded::<RawResponse>();
+        self.send(ActorMessage::Get(
+            request,
+            ResponseSender::Raw(tx),
+            None,
+            None,
+        ));
+
+        GetStream::new(rx, self.0.cancel_on_drop(vec![target]))
+    }
+
+    /// Async version of [Dht::ping].
+    pub async fn ping(&self, address: SocketAddrV4) -> Option<Duration> {
+        let (tx, rx) = flume::bounded::<Option<Duration>>(1);
+        self.send(ActorMessage::Ping(address, tx));
+
+        rx.recv_async()
+            .await
+            .expect("actor thread unexpectedly shutdown")
+    }
+
     // === Find nodes ===
 
-    /// Returns the closest 20 [secure](Node::is_secure) nodes to a target [Id].
+    /// Returns the closest [k](crate::DhtBuilder::k_factor) (20 by default) [secure](Node::is_secure)
+    /// nodes to a target [Id].
     ///
     /// Mostly useful to crawl the DHT.
     ///
@@ -82,6 +254,8 @@ impl AsyncDht {
         self.send(ActorMessage::Get(
             GetRequestSpecific::FindNode(FindNodeRequestArguments { target }),
             ResponseSender::ClosestNodes(tx),
+            None,
+            None,
         ));
 
         rx.recv_async()
@@ -89,6 +263,46 @@ impl AsyncDht {
             .expect("Query was dropped before sending a response, please open an issue.")
     }
 
+    /// Async version of [Dht::find_node_with_distance].
+    pub async fn find_node_with_distance(&self, target: Id) -> Vec<(Node, Id)> {
+        with_distance(target, &self.find_node(target).await)
+    }
+
+    /// Async version of [Dht::find_nodes_in_prefix].
+    pub async fn find_nodes_in_prefix(&self, prefix_bits: u8, prefix: Id, max: usize) -> Vec<Node> {
+        let mut found = PrefixNodes::new(prefix_bits, prefix, max);
+
+        while !found.done() {
+            let closest_nodes = self.find_node(found.next_target()).await;
+            found.add(&closest_nodes);
+        }
+
+        found.into_nodes()
+    }
+
+    /// Async version of [Dht::sample_infohashes].
+    pub async fn sample_infohashes(&self, target: Id) -> InfohashSamples {
+        let request = sample_infohashes_request(self.id().await, target);
+
+        let receivers = self.0.send_requests(
+            self.find_node(target)
+                .await
+                .iter()
+                .map(|node| node.address()),
+            request,
+        );
+
+        let mut samples = InfohashSamples::default();
+
+        for (_, rx) in receivers {
+            if let Ok(Ok(ResponseSpecific::SampleInfohashes(response))) = rx.recv_async().await {
+                samples.add(response);
+            }
+        }
+
+        samples
+    }
+
     // === Peers ===
 
     /// Get peers for a given infohash.
@@ -105,9 +319,126 @@ impl AsyncDht {
         self.send(ActorMessage::Get(
             GetRequestSpecific::GetPeers(GetPeersRequestArguments { info_hash }),
             ResponseSender::Peers(tx),
+            None,
+            None,
+        ));
+
+        GetStream::new(rx, self.0.cancel_on_drop(vec![info_hash]))
+    }
+
+    /// Async version of [Dht::get_peers_from].
+    pub async fn get_peers_from(&self, info_hash: Id, nodes: &[SocketAddr]) -> Vec<PeersResponse> {
+        let receivers = self.0.send_requests(
+            ipv4_addresses(nodes),
+            get_peers_request(self.id().await, info_hash),
+        );
+
+        let mut responses = Vec::with_capacity(receivers.len());
+
+        for (address, rx) in receivers {
+            if let Ok(Ok(response)) = rx.recv_async().await {
+                responses.extend(peers_response(address, response));
+            }
+        }
+
+        responses
+    }
+
+    /// Async version of [Dht::get_peers_with_timeout].
+    pub fn get_peers_with_timeout(
+        &self,
+        info_hash: Id,
+        request_timeout: Duration,
+    ) -> GetStream<Vec<SocketAddrV4>> {
+        let (tx, rx) = flume::unbounded::<Vec<SocketAddrV4>>();
+        self.send(ActorMessage::Get(
+            GetRequestSpecific::GetPeers(GetPeersRequestArguments { info_hash }),
+            ResponseSender::Peers(tx),
+            Some(request_timeout),
+            None,
+        ));
+
+        GetStream::new(rx, self.0.cancel_on_drop(vec![info_hash]))
+    }
+
+    /// Async version of [Dht::get_peers_dedup].
+    pub fn get_peers_dedup(&self, info_hash: Id) -> GetStream<Vec<SocketAddrV4>> {
+        let (tx, rx) = flume::unbounded::<Vec<SocketAddrV4>>();
+        self.send(ActorMessage::Get(
+            GetRequestSpecific::GetPeers(GetPeersRequestArguments { info_hash }),
+            ResponseSender::PeersDedup(tx, HashSet::new()),
+            None,
+            None,
+        ));
+
+        GetStream::new(rx, self.0.cancel_on_drop(vec![info_hash]))
+    }
+
+    /// Async version of [Dht::get_peers_with_seeds].
+    pub fn get_peers_with_seeds(
+        &self,
+        info_hash: Id,
+        seed_nodes: &[SocketAddr],
+    ) -> GetStream<Vec<SocketAddrV4>> {
+        let (tx, rx) = flume::unbounded::<Vec<SocketAddrV4>>();
+        self.send(ActorMessage::Get(
+            GetRequestSpecific::GetPeers(GetPeersRequestArguments { info_hash }),
+            ResponseSender::Peers(tx),
+            None,
+            Some(ipv4_addresses(seed_nodes).into()),
         ));
 
-        GetStream(rx.into_stream())
+        GetStream::new(rx, self.0.cancel_on_drop(vec![info_hash]))
+    }
+
+    /// Async version of [Dht::get_peers_with_source].
+    pub fn get_peers_with_source(&self, info_hash: Id) -> GetStream<PeersResponse> {
+        let (tx, rx) = flume::unbounded::<PeersResponse>();
+        self.send(ActorMessage::Get(
+            GetRequestSpecific::GetPeers(GetPeersRequestArguments { info_hash }),
+            ResponseSender::PeersWithSource(tx),
+            None,
+            None,
+        ));
+
+        GetStream::new(rx, self.0.cancel_on_drop(vec![info_hash]))
+    }
+
+    /// Async version of [Dht::get_peers_many].
+    pub fn get_peers_many(&self, info_hashes: &[Id]) -> GetStream<(Id, Vec<SocketAddrV4>)> {
+        let (tx, rx) = flume::unbounded::<(Id, Vec<SocketAddrV4>)>();
+
+        for info_hash in info_hashes {
+            self.send(ActorMessage::Get(
+                GetRequestSpecific::GetPeers(GetPeersRequestArguments {
+                    info_hash: *info_hash,
+                }),
+                ResponseSender::PeersWithTarget(*info_hash, tx.clone()),
+                None,
+                None,
+            ));
+        }
+
+        GetStream::new(rx, self.0.cancel_on_drop(info_hashes.into()))
+    }
+
+    /// Async version of [Dht::resolve_magnet], that returns the unique peers found
+    /// once the query is done.
+    pub async fn resolve_magnet(&self, magnet_uri: &str) -> Result<Vec<SocketAddrV4>, MagnetError> {
+        let info_hash = Id::from_magnet(magnet_uri)?;
+
+        let mut stream = self.get_peers(info_hash);
+        let mut peers = Vec::new();
+
+        while let Some(response) = stream.next().await {
+            for peer in response {
+                if !peers.contains(&peer) {
+                    peers.push(peer);
+                }
+            }
+        }
+
+        Ok(peers)
     }
 
     /// Announce a peer for a given infohash.
@@ -120,32 +451,79 @@ impl AsyncDht {
         info_hash: Id,
         port: Option<u16>,
     ) -> Result<Id, PutQueryError> {
-        let (port, implied_port) = match port {
-            Some(port) => (port, None),
-            None => (0, Some(true)),
-        };
+        self.announce_peer_report(info_hash, port)
+            .await
+            .map(|report| report.info_hash)
+    }
 
-        self.put(
-            PutRequestSpecific::AnnouncePeer(AnnouncePeerRequestArguments {
-                info_hash,
-                port,
-                implied_port,
-            }),
-            None,
-        )
-        .await
-        .map_err(|error| match error {
-            PutError::Query(error) => error,
-            PutError::Concurrency(_) => {
-                unreachable!("should not receive a concurrency error from announce peer query")
+    /// Same as [Self::announce_peer], but returns an [AnnounceReport]
+    /// with the nodes that accepted the announcement.
+    pub async fn announce_peer_report(
+        &self,
+        info_hash: Id,
+        port: Option<u16>,
+    ) -> Result<AnnounceReport, PutQueryError> {
+        self.announce(info_hash, announce_peer_request(info_hash, port, false))
+            .await
+    }
+
+    /// Async version of [Dht::announce_seed].
+    pub async fn announce_seed(
+        &self,
+        info_hash: Id,
+        port: Option<u16>,
+    ) -> Result<Id, PutQueryError> {
+        self.announce(info_hash, announce_peer_request(info_hash, port, true))
+            .await
+            .map(|report| report.info_hash)
+    }
+
+    /// Async version of [Dht::scrape].
+    pub async fn scrape(&self, info_hash: Id) -> ScrapeResult {
+        let receivers = self.0.send_requests(
+            self.find_node(info_hash)
+                .await
+                .iter()
+                .map(|node| node.address()),
+            scrape_request(self.id().await, info_hash),
+        );
+
+        let mut result = ScrapeResult::default();
+
+        for (_, rx) in receivers {
+            if let Ok(Ok(ResponseSpecific::Scrape(response))) = rx.recv_async().await {
+                result.add(response);
             }
-        })
+        }
+
+        result
+    }
+
+    async fn announce(
+        &self,
+        info_hash: Id,
+        request: PutRequestSpecific,
+    ) -> Result<AnnounceReport, PutQueryError> {
+        self.put_inner(request, None)
+            .recv_async()
+            .await
+            .expect("Query was dropped before sending a response, please open an issue.")
+            .map(|report| AnnounceReport::new(info_hash, report))
+            .map_err(|error| match error {
+                PutError::Query(error) => error,
+                PutError::Concurrency(_) => {
+                    unreachable!("should not receive a concurrency error from announce peer query")
+                }
+            })
     }
 
     // === Immutable data ===
 
     /// Get an Immutable data by its sha1 hash.
     pub async fn get_immutable(&self, target: Id) -> Option<Box<[u8]>> {
+        // Stop the query after the first value, see [Dht::get_immutable].
+        let _cancel = self.0.cancel_on_drop(vec![target]);
+
         let (tx, rx) = flume::unbounded::<Box<[u8]>>();
         self.send(ActorMessage::Get(
             GetRequestSpecific::GetValue(GetValueRequestArguments {
@@ -154,20 +532,40 @@ impl AsyncDht {
                 salt: None,
             }),
             ResponseSender::Immutable(tx),
+            None,
+            None,
         ));
 
-        rx.recv_async().await.map(Some).unwrap_or(None)
+        self.recv_immutable(&rx).await
     }
 
-    /// Put an immutable data to the DHT.
-    pub async fn put_immutable(&self, value: &[u8]) -> Result<Id, PutQueryError> {
-        let target: Id = hash_immutable(value).into();
+    /// Async version of [Dht::get_immutable_with_timeout].
+    pub async fn get_immutable_with_timeout(
+        &self,
+        target: Id,
+        request_timeout: Duration,
+    ) -> Option<Box<[u8]>> {
+        let _cancel = self.0.cancel_on_drop(vec![target]);
 
-        self.put(
-            PutRequestSpecific::PutImmutable(PutImmutableRequestArguments {
+        let (tx, rx) = flume::unbounded::<Box<[u8]>>();
+        self.send(ActorMessage::Get(
+            GetRequestSpecific::GetValue(GetValueRequestArguments {
                 target,
-                v: value.into(),
+                seq: None,
+                salt: None,
             }),
+            ResponseSender::Immutable(tx),
+            Some(request_timeout),
+            None,
+        ));
+
+        self.recv_immutable(&rx).await
+    }
+
+    /// Put an immutable data to the DHT.
+    pub async fn put_immutable(&self, value: &[u8]) -> Result<Id, PutQueryError> {
+        self.put(
+            PutRequestSpecific::PutImmutable(self.0.put_immutable_request(value)),
             None,
         )
         .await
@@ -179,6 +577,96 @@ impl AsyncDht {
         })
     }
 
+    /// Async version of [Dht::put_immutable_with_options].
+    pub async fn put_immutable_with_options(
+        &self,
+        value: &[u8],
+        options: PutOptions,
+    ) -> Result<Id, PutQueryError> {
+        if options.skip_if_present {
+            let target = self.0.put_immutable_request(value).target;
+
+            let (tx, rx) = flume::unbounded::<Box<[u8]>>();
+            self.send(ActorMessage::Get(
+                GetRequestSpecific::GetValue(GetValueRequestArguments {
+                    target,
+                    seq: None,
+                    salt: None,
+                }),
+                ResponseSender::Immutable(tx),
+                None,
+                None,
+            ));
+
+            let min_present = options.min_present.max(1);
+            let mut present = 0;
+
+            while present < min_present && rx.recv_async().await.is_ok() {
+                present += 1;
+            }
+
+            if present >= min_present {
+                return Ok(target);
+            }
+        }
+
+        self.put_immutable(value).await
+    }
+
+    /// Async version of [Dht::put_immutable_chunked].
+    pub async fn put_immutable_chunked(&self, value: &[u8]) -> Result<Id, PutQueryError> {
+        let encoded = self.0.encode_immutable(value);
+        check_chunked_size(&encoded)?;
+
+        let receivers = encoded
+            .chunks(MAX_VALUE_SIZE)
+            .map(|chunk| self.0.put_encoded_immutable(chunk))
+            .collect::<Vec<_>>();
+
+        let mut chunks = Vec::with_capacity(receivers.len());
+
+        for rx in receivers {
+            chunks.push(immutable_put_result(rx.recv_async().await.expect(
+                "Query was dropped before sending a response, please open an issue.",
+            ))?);
+        }
+
+        immutable_put_result(
+            self.0
+                .put_encoded_immutable(&chunks_manifest(&chunks))
+                .recv_async()
+                .await
+                .expect("Query was dropped before sending a response, please open an issue."),
+        )
+    }
+
+    /// Async version of [Dht::get_immutable_chunked].
+    pub async fn get_immutable_chunked(&self, manifest: Id) -> Option<Box<[u8]>> {
+        let chunks = parse_chunks_manifest(
+            &self
+                .0
+                .get_encoded_immutable(manifest)
+                .recv_async()
+                .await
+                .ok()?,
+        )?;
+
+        let _cancel = self.0.cancel_on_drop(chunks.clone());
+
+        let receivers = chunks
+            .iter()
+            .map(|target| self.0.get_encoded_immutable(*target))
+            .collect::<Vec<_>>();
+
+        let mut encoded = Vec::with_capacity(chunks.len() * MAX_VALUE_SIZE);
+
+        for rx in receivers {
+            encoded.extend_from_slice(&rx.recv_async().await.ok()?);
+        }
+
+        self.0.decode_immutable(&encoded)
+    }
+
     // === Mutable data ===
 
     /// Get a mutable data by its `public_key` and optional `salt`.
@@ -209,9 +697,61 @@ impl AsyncDht {
                 salt,
             }),
             ResponseSender::Mutable(tx),
+            None,
+            None,
         ));
 
-        GetStream(rx.into_stream())
+        GetStream::new(rx, self.0.cancel_on_drop(vec![target]))
+    }
+
+    /// Async version of [Dht::get_mutable_with_seeds].
+    pub fn get_mutable_with_seeds(
+        &self,
+        public_key: &[u8; 32],
+        salt: Option<&[u8]>,
+        more_recent_than: Option<i64>,
+        seed_nodes: &[SocketAddr],
+    ) -> GetStream<MutableItem> {
+        let salt = salt.map(|s| s.into());
+        let target = MutableItem::target_from_key(public_key, salt.as_deref());
+        let (tx, rx) = flume::unbounded::<MutableItem>();
+        self.send(ActorMessage::Get(
+            GetRequestSpecific::GetValue(GetValueRequestArguments {
+                target,
+                seq: more_recent_than,
+                salt,
+            }),
+            ResponseSender::Mutable(tx),
+            None,
+            Some(ipv4_addresses(seed_nodes).into()),
+        ));
+
+        GetStream::new(rx, self.0.cancel_on_drop(vec![target]))
+    }
+
+    /// Async version of [Dht::get_mutable_with_timeout].
+    pub fn get_mutable_with_timeout(
+        &self,
+        public_key: &[u8; 32],
+        salt: Option<&[u8]>,
+        more_recent_than: Option<i64>,
+        request_timeout: Duration,
+    ) -> GetStream<MutableItem> {
+        let salt = salt.map(|s| s.into());
+        let target = MutableItem::target_from_key(public_key, salt.as_deref());
+        let (tx, rx) = flume::unbounded::<MutableItem>();
+        self.send(ActorMessage::Get(
+            GetRequestSpecific::GetValue(GetValueRequestArguments {
+                target,
+                seq: more_recent_than,
+                salt,
+            }),
+            ResponseSender::Mutable(tx),
+            Some(request_timeout),
+            None,
+        ));
+
+        GetStream::new(rx, self.0.cancel_on_drop(vec![target]))
     }
 
     /// Get the most recent [MutableItem] from the network.
@@ -224,18 +764,94 @@ impl AsyncDht {
         let mut stream = self.get_mutable(public_key, salt, None);
 
         while let Some(item) = stream.next().await {
-            if let Some(mr) = &most_recent {
-                if item.seq() == mr.seq && item.value() > &mr.value {
-                    most_recent = Some(item)
-                }
-            } else {
-                most_recent = Some(item);
+            most_recent = Some(more_recent(most_recent, item));
+        }
+
+        most_recent
+    }
+
+    /// Async version of [Dht::get_mutable_many].
+    pub async fn get_mutable_many(
+        &self,
+        public_key: &[u8; 32],
+        salts: &[&[u8]],
+    ) -> HashMap<Box<[u8]>, MutableItem> {
+        let (tx, rx) = flume::unbounded::<MutableItem>();
+
+        for salt in salts {
+            self.send(ActorMessage::Get(
+                get_mutable_request(public_key, Some(salt)),
+                ResponseSender::Mutable(tx.clone()),
+                None,
+                None,
+            ));
+        }
+        drop(tx);
+
+        let mut items = HashMap::new();
+        while let Ok(item) = rx.recv_async().await {
+            insert_more_recent(&mut items, item);
+        }
+
+        items
+    }
+
+    /// Async version of [Dht::get_mutable_most_recent_until].
+    pub async fn get_mutable_most_recent_until(
+        &self,
+        public_key: &[u8; 32],
+        salt: Option<&[u8]>,
+        ceiling: i64,
+    ) -> Option<MutableItem> {
+        let mut most_recent: Option<MutableItem> = None;
+        let mut stream = self.get_mutable(public_key, salt, None);
+
+        while let Some(item) = stream.next().await {
+            let item = more_recent(most_recent, item);
+            let done = item.seq() >= ceiling;
+
+            most_recent = Some(item);
+
+            if done {
+                break;
             }
         }
 
         most_recent
     }
 
+    /// Async version of [Dht::confirm_absent].
+    pub async fn confirm_absent(
+        &self,
+        public_key: &[u8; 32],
+        salt: Option<&[u8]>,
+        quorum: usize,
+    ) -> bool {
+        let (items_tx, items_rx) = flume::unbounded::<MutableItem>();
+        self.send(ActorMessage::Get(
+            get_mutable_request(public_key, salt),
+            ResponseSender::Mutable(items_tx),
+            None,
+            None,
+        ));
+
+        // Joins the same query, to receive the closest responding nodes once it is done.
+        let (tx, rx) = flume::bounded::<Box<[Node]>>(1);
+        self.send(ActorMessage::Get(
+            get_mutable_request(public_key, salt),
+            ResponseSender::ClosestNodes(tx),
+            None,
+            None,
+        ));
+
+        let closest_nodes = rx
+            .recv_async()
+            .await
+            .expect("Query was dropped before sending a response, please open an issue.");
+
+        items_rx.is_empty() && closest_nodes.len() >= quorum
+    }
+
     /// Put a mutable data to the DHT.
     ///
     /// # Lost Update Problem
@@ -301,6 +917,69 @@ impl AsyncDht {
         })
     }
 
+    /// Same as [Self::put_mutable], but only returns successfully once at least
+    /// `min_replicas` nodes acknowledged storing the `item`.
+    ///
+    /// See [Dht::put_mutable_with_min_replicas].
+    pub async fn put_mutable_with_min_replicas(
+        &self,
+        item: MutableItem,
+        cas: Option<i64>,
+        min_replicas: usize,
+    ) -> Result<Id, PutMutableError> {
+        let request = PutRequestSpecific::PutMutable(PutMutableRequestArguments::from(item, cas));
+
+        let report = self
+            .put_inner(request, None)
+            .recv_async()
+            .await
+            .expect("Query was dropped before sending a response, please open an issue.")?;
+
+        check_min_replicas(report, min_replicas)
+    }
+
+    /// Put a mutable data to the DHT, merging it with the most recent [MutableItem]
+    /// stored at the same `signer`'s public key and `salt`, if any.
+    ///
+    /// See [Dht::put_mutable_merged].
+    pub async fn put_mutable_merged(
+        &self,
+        signer: SigningKey,
+        salt: Option<&[u8]>,
+        value: &[u8],
+        merge: impl Fn(&MutableItem, &[u8]) -> Vec<u8>,
+    ) -> Result<Id, PutMutableError> {
+        let public_key = signer.verifying_key().to_bytes();
+
+        let mut attempts = 0;
+
+        loop {
+            attempts += 1;
+
+            let most_recent = self.get_mutable_most_recent(&public_key, salt).await;
+            let (item, cas) = merged_item(&signer, salt, value, most_recent, &merge)?;
+
+            match self.put_mutable(item, cas).await {
+                Err(PutMutableError::Concurrency(_)) if attempts < MAX_MERGE_ATTEMPTS => {}
+                result => return result,
+            }
+        }
+    }
+
+    /// Async version of [Dht::clear_mutable].
+    pub async fn clear_mutable(
+        &self,
+        signer: SigningKey,
+        salt: Option<&[u8]>,
+        current_seq: i64,
+    ) -> Result<Id, PutMutableError> {
+        self.put_mutable(
+            MutableItem::new(signer, &[], current_seq + 1, salt),
+            Some(current_seq),
+        )
+        .await
+    }
+
     // === Raw ===
 
     /// Get closet nodes to a specific target, that support [BEP_0044](https://www.bittorrent.org/beps/bep_0044.html).
@@ -316,6 +995,8 @@ impl AsyncDht {
                 seq: None,
             }),
             ResponseSender::ClosestNodes(tx),
+            None,
+            None,
         ));
 
         rx.recv_async()
@@ -323,6 +1004,19 @@ impl AsyncDht {
             .expect("Query was dropped before sending a response, please open an issue.")
     }
 
+    /// Async version of [Dht::estimate_storage_pressure].
+    pub async fn estimate_storage_pressure(&self, target: Id) -> f64 {
+        self.get_closest_nodes(target).await;
+
+        let (tx, rx) = flume::bounded::<Option<f64>>(1);
+        self.send(ActorMessage::StoragePressure(target, tx));
+
+        rx.recv_async()
+            .await
+            .expect("actor thread unexpectedly shutdown")
+            .unwrap_or(1.0)
+    }
+
     /// Send a PUT request to the closest nodes, and optionally some extra nodes.
     ///
     /// This is useful to put data to regions of the DHT other than the closest nodes
@@ -341,6 +1035,161 @@ impl AsyncDht {
             .recv_async()
             .await
             .expect("Query was dropped before sending a response, please open an issue.")
+            .map(|report| report.target)
+    }
+
+    // === Debug ===
+
+    /// Same as [Dht::subscribe_events], the returned receiver can be
+    /// consumed asynchronously with [flume::Receiver::recv_async].
+    pub fn subscribe_events(&self) -> flume::Receiver<DhtEvent> {
+        self.0.subscribe_events()
+    }
+
+    /// Async version of [Dht::find_node_timed].
+    pub async fn find_node_timed(&self, target: Id) -> (Box<[Node]>, QueryTiming) {
+        let (tx, rx) = flume::bounded::<Box<[Node]>>(1);
+        let timing = self.0.get_timed(
+            || GetRequestSpecific::FindNode(FindNodeRequestArguments { target }),
+            ResponseSender::ClosestNodes(tx),
+        );
+
+        (
+            rx.recv_async()
+                .await
+                .expect("Query was dropped before sending a response, please open an issue."),
+            timing
+                .recv_async()
+                .await
+                .expect("Query was dropped before sending a response, please open an issue."),
+        )
+    }
+
+    /// Async version of [Dht::get_peers_timed].
+    pub async fn get_peers_timed(&self, info_hash: Id) -> (Vec<SocketAddrV4>, QueryTiming) {
+        let (tx, rx) = flume::unbounded::<Vec<SocketAddrV4>>();
+        let timing = self.0.get_timed(
+            || GetRequestSpecific::GetPeers(GetPeersRequestArguments { info_hash }),
+            ResponseSender::Peers(tx),
+        );
+
+        let mut peers = Vec::new();
+        while let Ok(response) = rx.recv_async().await {
+            for peer in response {
+                if !peers.contains(&peer) {
+                    peers.push(peer);
+                }
+            }
+        }
+
+        (
+            peers,
+            timing
+                .recv_async()
+                .await
+                .expect("Query was dropped before sending a response, please open an issue."),
+        )
+    }
+
+    /// Same as [Dht::put_immutable_repeating], which doesn't block.
+    pub fn put_immutable_repeating(
+        &self,
+        value: &[u8],
+        interval: Option<Duration>,
+    ) -> RepublishHandle {
+        self.0.put_immutable_repeating(value, interval)
+    }
+
+    /// Same as [Dht::put_mutable_repeating], which doesn't block.
+    pub fn put_mutable_repeating(
+        &self,
+        item: MutableItem,
+        interval: Option<Duration>,
+    ) -> RepublishHandle {
+        self.0.put_mutable_repeating(item, interval)
+    }
+
+    /// Async version of [Dht::get_immutable_outcome].
+    pub async fn get_immutable_outcome(&self, target: Id) -> ImmutableOutcome {
+        let _cancel = self.0.cancel_on_drop(vec![target]);
+
+        let (tx, rx) = flume::unbounded::<Box<[u8]>>();
+        let timing = self.0.get_timed(
+            || {
+                GetRequestSpecific::GetValue(GetValueRequestArguments {
+                    target,
+                    seq: None,
+                    salt: None,
+                })
+            },
+            ResponseSender::Immutable(tx),
+        );
+
+        match self.recv_immutable(&rx).await {
+            Some(value) => ImmutableOutcome::Found(value),
+            None => ImmutableOutcome::from_timing(
+                timing
+                    .recv_async()
+                    .await
+                    .expect("Query was dropped before sending a response, please open an issue."),
+            ),
+        }
+    }
+
+    /// Async version of [Dht::get_immutable_timed].
+    pub async fn get_immutable_timed(&self, target: Id) -> (Option<Box<[u8]>>, QueryTiming) {
+        let (tx, rx) = flume::unbounded::<Box<[u8]>>();
+        let timing = self.0.get_timed(
+            || {
+                GetRequestSpecific::GetValue(GetValueRequestArguments {
+                    target,
+                    seq: None,
+                    salt: None,
+                })
+            },
+            ResponseSender::Immutable(tx),
+        );
+
+        (
+            self.recv_immutable(&rx).await,
+            timing
+                .recv_async()
+                .await
+                .expect("Query was dropped before sending a response, please open an issue."),
+        )
+    }
+
+    /// Async version of [Dht::get_mutable_timed].
+    pub async fn get_mutable_timed(
+        &self,
+        public_key: &[u8; 32],
+        salt: Option<&[u8]>,
+        more_recent_than: Option<i64>,
+    ) -> (Vec<MutableItem>, QueryTiming) {
+        let (tx, rx) = flume::unbounded::<MutableItem>();
+        let timing = self.0.get_timed(
+            || {
+                let mut request = get_mutable_request(public_key, salt);
+                if let GetRequestSpecific::GetValue(args) = &mut request {
+                    args.seq = more_recent_than;
+                }
+                request
+            },
+            ResponseSender::Mutable(tx),
+        );
+
+        let mut items = Vec::new();
+        while let Ok(item) = rx.recv_async().await {
+            items.push(item);
+        }
+
+        (
+            items,
+            timing
+                .recv_async()
+                .await
+                .expect("Query was dropped before sending a response, please open an issue."),
+        )
     }
 
     // === Private Methods ===
@@ -349,33 +1198,65 @@ impl AsyncDht {
         &self,
         request: PutRequestSpecific,
         extra_nodes: Option<Box<[Node]>>,
-    ) -> flume::Receiver<Result<Id, PutError>> {
-        let (tx, rx) = flume::bounded::<Result<Id, PutError>>(1);
+    ) -> flume::Receiver<Result<PutReport, PutError>> {
+        let (tx, rx) = flume::bounded::<Result<PutReport, PutError>>(1);
         self.send(ActorMessage::Put(request, tx, extra_nodes));
 
         rx
     }
 
+    /// Return the first immutable value received that the [crate::ValueCodec] can decode.
+    async fn recv_immutable(&self, rx: &flume::Receiver<Box<[u8]>>) -> Option<Box<[u8]>> {
+        while let Ok(encoded) = rx.recv_async().await {
+            if let Some(value) = self.0.decode_immutable(&encoded) {
+                return Some(value);
+            }
+        }
+
+        None
+    }
+
     fn send(&self, message: ActorMessage) {
         self.0.send(message)
     }
 }
 
 /// A [Stream] of incoming peers, immutable or mutable values.
-pub struct GetStream<T: 'static>(flume::r#async::RecvStream<'static, T>);
+///
+/// Dropping it (or calling [Self::cancel]) before it ends cancels its query, unless
+/// other streams or iterators are still waiting for the same target.
+pub struct GetStream<T: 'static> {
+    // Dropped before `cancel`, so the actor sees this receiver as disconnected.
+    inner: flume::r#async::RecvStream<'static, T>,
+    _cancel: CancelOnDrop,
+}
+
+impl<T> GetStream<T> {
+    fn new(rx: flume::Receiver<T>, cancel: CancelOnDrop) -> Self {
+        Self {
+            inner: rx.into_stream(),
+            _cancel: cancel,
+        }
+    }
+
+    /// Stop waiting for responses, and cancel the query if no one else is waiting for it.
+    ///
+    /// Same as dropping this stream.
+    pub fn cancel(self) {}
+}
 
 impl<T> Stream for GetStream<T> {
     type Item = T;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         let this = self.get_mut();
-        this.0.poll_next(cx)
+        this.inner.poll_next(cx)
     }
 }
 
 #[cfg(test)]
 mod test {
-    use std::{str::FromStr, time::Duration};
+    use std::str::FromStr;
 
     use ed25519_dalek::SigningKey;
     use futures::StreamExt;
@@ -657,7 +1538,7 @@ mod test {
                 futures::executor::block_on(async {
                     let result = dht.put_mutable(item, None).await;
                     if i == 0 {
-                        assert!(matches!(result, Ok(_)))
+                        assert!(result.is_ok())
                     } else {
                         assert!(matches!(
                             result,
@@ -696,7 +1577,7 @@ mod test {
             {
                 let item = MutableItem::new(signer.clone(), &value, 1000, None);
 
-                let (sender, _) = flume::bounded::<Result<Id, PutError>>(1);
+                let (sender, _) = flume::bounded::<Result<PutReport, PutError>>(1);
                 let request =
                     PutRequestSpecific::PutMutable(PutMutableRequestArguments::from(item, None));
                 dht.0
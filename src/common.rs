@@ -1,14 +1,22 @@
 //! Miscellaneous common structs used throughout the library.
 
+mod bloom;
+mod codec;
 mod id;
 mod immutable;
+mod ip_net;
+mod magnet;
 pub mod messages;
 mod mutable;
 mod node;
 mod routing_table;
 
+pub use bloom::*;
+pub use codec::*;
 pub use id::*;
 pub use immutable::*;
+pub use ip_net::*;
+pub use magnet::*;
 pub use messages::*;
 pub use mutable::*;
 pub use node::*;
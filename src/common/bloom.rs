@@ -0,0 +1,137 @@
+//! [BEP_0033](https://www.bittorrent.org/beps/bep_0033.html) scrape bloom filters.
+
+use std::{
+    fmt::{self, Debug, Formatter},
+    net::Ipv4Addr,
+};
+
+use sha1_smol::Sha1;
+
+/// Size of a [BloomFilter] in bytes.
+pub const BLOOM_FILTER_SIZE: usize = 256;
+
+const BITS: usize = BLOOM_FILTER_SIZE * 8;
+
+#[derive(Clone, PartialEq, Eq)]
+/// A bloom filter of peers' IP addresses, used to estimate the number of seeds (`BFsd`)
+/// and downloaders (`BFpe`) of an info hash without transferring their addresses.
+///
+/// Read [BEP_0033](https://www.bittorrent.org/beps/bep_0033.html) for more information.
+pub struct BloomFilter(Box<[u8; BLOOM_FILTER_SIZE]>);
+
+impl BloomFilter {
+    /// Create an empty bloom filter.
+    pub fn new() -> Self {
+        Self(Box::new([0; BLOOM_FILTER_SIZE]))
+    }
+
+    /// Create a bloom filter from its bytes, returns `None` if it isn't
+    /// exactly [BLOOM_FILTER_SIZE] bytes long.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        bytes.try_into().ok().map(|bytes| Self(Box::new(bytes)))
+    }
+
+    /// Returns the bytes of this bloom filter.
+    pub fn as_bytes(&self) -> &[u8; BLOOM_FILTER_SIZE] {
+        &self.0
+    }
+
+    /// Insert an IP address.
+    pub fn insert(&mut self, ip: Ipv4Addr) {
+        for index in indices(ip) {
+            self.0[index / 8] |= 1 << (index % 8);
+        }
+    }
+
+    /// Returns true if the IP address was probably inserted,
+    /// and false if it definitely wasn't.
+    pub fn contains(&self, ip: Ipv4Addr) -> bool {
+        indices(ip)
+            .iter()
+            .all(|index| self.0[index / 8] & (1 << (index % 8)) != 0)
+    }
+
+    /// Insert all the IP addresses of `other` in this filter,
+    /// useful to combine the filters returned by multiple nodes.
+    pub fn union(&mut self, other: &Self) {
+        for (byte, other) in self.0.iter_mut().zip(other.0.iter()) {
+            *byte |= other;
+        }
+    }
+
+    /// Estimate the number of distinct IP addresses inserted.
+    pub fn estimate_count(&self) -> usize {
+        let zeros = self
+            .0
+            .iter()
+            .map(|byte| byte.count_zeros() as usize)
+            .sum::<usize>()
+            .clamp(1, BITS - 1);
+
+        let m = BITS as f64;
+
+        ((zeros as f64 / m).ln() / (2.0 * (1.0 - 1.0 / m).ln())) as usize
+    }
+}
+
+impl Default for BloomFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debug for BloomFilter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BloomFilter")
+            .field("estimate_count", &self.estimate_count())
+            .finish()
+    }
+}
+
+/// The two bit indices (k = 2) of an IP address, from its sha1 hash.
+fn indices(ip: Ipv4Addr) -> [usize; 2] {
+    let mut hasher = Sha1::new();
+    hasher.update(&ip.octets());
+    let hash = hasher.digest().bytes();
+
+    [
+        (hash[0] as usize | (hash[1] as usize) << 8) % BITS,
+        (hash[2] as usize | (hash[3] as usize) << 8) % BITS,
+    ]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn insert_and_estimate() {
+        let mut filter = BloomFilter::new();
+        assert_eq!(filter.estimate_count(), 0);
+
+        for i in 0..100 {
+            filter.insert(Ipv4Addr::new(10, 0, 0, i));
+        }
+
+        assert!(filter.contains(Ipv4Addr::new(10, 0, 0, 42)));
+
+        let estimate = filter.estimate_count();
+        assert!((90..=110).contains(&estimate), "estimate: {estimate}");
+    }
+
+    #[test]
+    fn union() {
+        let mut a = BloomFilter::new();
+        let mut b = BloomFilter::new();
+
+        a.insert(Ipv4Addr::new(10, 0, 0, 1));
+        b.insert(Ipv4Addr::new(10, 0, 0, 2));
+
+        a.union(&b);
+
+        assert!(a.contains(Ipv4Addr::new(10, 0, 0, 1)));
+        assert!(a.contains(Ipv4Addr::new(10, 0, 0, 2)));
+        assert_eq!(BloomFilter::from_bytes(a.as_bytes()), Some(a));
+        assert_eq!(BloomFilter::from_bytes(&[0; 10]), None);
+    }
+}
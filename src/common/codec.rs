@@ -0,0 +1,35 @@
+//! Pluggable encoding of immutable values, see [crate::DhtBuilder::value_codec].
+
+use std::fmt::Debug;
+
+use dyn_clone::DynClone;
+
+/// A trait for transparently encoding immutable values before storing them
+/// in the DHT, and decoding them after getting them, for example to compress them.
+///
+/// The target of an immutable value is the sha1 hash of its _encoded_ bytes,
+/// and the encoded bytes are subject to the [crate::MAX_VALUE_SIZE] limit.
+pub trait ValueCodec: Send + Sync + Debug + DynClone {
+    /// Encode a value before storing it.
+    fn encode(&self, value: &[u8]) -> Box<[u8]>;
+
+    /// Decode a stored value, or return `None` if it can't be decoded,
+    /// in which case the value is ignored.
+    fn decode(&self, encoded: &[u8]) -> Option<Box<[u8]>>;
+}
+
+dyn_clone::clone_trait_object!(ValueCodec);
+
+#[derive(Debug, Clone)]
+/// The default [ValueCodec], storing values as they are.
+pub struct IdentityCodec;
+
+impl ValueCodec for IdentityCodec {
+    fn encode(&self, value: &[u8]) -> Box<[u8]> {
+        value.into()
+    }
+
+    fn decode(&self, encoded: &[u8]) -> Option<Box<[u8]>> {
+        Some(encoded.into())
+    }
+}
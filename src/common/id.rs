@@ -1,7 +1,10 @@
 //! Kademlia node Id or a lookup target
 use crc::{Crc, CRC_32_ISCSI};
 use getrandom::getrandom;
+#[cfg(feature = "serde")]
+use serde::{de::Error as _, Deserializer, Serializer};
 use serde::{Deserialize, Serialize};
+use sha1_smol::Sha1;
 use std::convert::TryInto;
 use std::{
     fmt::{self, Debug, Display, Formatter},
@@ -16,8 +19,12 @@ pub const MAX_DISTANCE: u8 = ID_SIZE as u8 * 8;
 const IPV4_MASK: u32 = 0x030f3fff;
 const CASTAGNOLI: Crc<u32> = Crc::<u32>::new(&CRC_32_ISCSI);
 
-#[derive(Clone, Copy, PartialEq, Ord, PartialOrd, Eq, Hash, Serialize, Deserialize)]
+#[derive(Clone, Copy, PartialEq, Ord, PartialOrd, Eq, Hash)]
+#[cfg_attr(not(feature = "serde"), derive(Serialize, Deserialize))]
 /// Kademlia node Id or a lookup target
+///
+/// With the `serde` feature, serialized as a hex string in human readable formats (like JSON),
+/// and as 20 bytes otherwise.
 pub struct Id([u8; ID_SIZE]);
 
 impl Id {
@@ -29,6 +36,25 @@ impl Id {
         Id(bytes)
     }
 
+    /// Generate a random Id that shares the first `prefix_bits` bits with `prefix`.
+    pub fn random_with_prefix(prefix: &Id, prefix_bits: u8) -> Id {
+        let mut bytes = Id::random().0;
+
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            let bits = (prefix_bits as usize).saturating_sub(i * 8).min(8);
+            let mask = (0xff_u16 << (8 - bits)) as u8;
+
+            *byte = (prefix.0[i] & mask) | (*byte & !mask);
+        }
+
+        Id(bytes)
+    }
+
+    /// Returns true if this Id shares the first `prefix_bits` bits with `prefix`.
+    pub fn has_prefix(&self, prefix: &Id, prefix_bits: u8) -> bool {
+        self.xor(prefix).leading_zeros() >= prefix_bits
+    }
+
     /// Create a new Id from some bytes. Returns Err if the input is not 20 bytes long.
     pub fn from_bytes<T: AsRef<[u8]>>(bytes: T) -> Result<Id, InvalidIdSize> {
         let bytes = bytes.as_ref();
@@ -53,6 +79,34 @@ impl Id {
         MAX_DISTANCE - self.xor(other).leading_zeros()
     }
 
+    /// Generate a random Id at the given `distance` from this Id, see [Self::distance].
+    ///
+    /// Useful to look up nodes for a specific routing table bucket.
+    ///
+    /// A `distance` of 0 returns this Id, and distances above 160 are capped.
+    pub fn random_at_distance(&self, distance: u8) -> Id {
+        let distance = distance.min(MAX_DISTANCE);
+
+        if distance == 0 {
+            return *self;
+        }
+
+        let mut bytes = Id::random().0;
+
+        // The first bit that differs from this Id.
+        let bit = (MAX_DISTANCE - distance) as usize;
+        let (byte, offset) = (bit / 8, bit % 8);
+        let differ = 0x80 >> offset;
+        // Bits before the differing bit, in the same byte.
+        let shared = !(0xff_u8 >> offset);
+
+        bytes[..byte].copy_from_slice(&self.0[..byte]);
+        bytes[byte] =
+            (self.0[byte] & shared) | (!self.0[byte] & differ) | (bytes[byte] & !shared & !differ);
+
+        Id(bytes)
+    }
+
     /// Returns the number of leading zeros in the binary representation of `self`.
     pub fn leading_zeros(&self) -> u8 {
         for (i, byte) in self.0.iter().enumerate() {
@@ -65,7 +119,18 @@ impl Id {
         160
     }
 
+    /// Returns the number of leading bits this Id shares with `other`.
+    ///
+    /// Common prefix bits with self is 160
+    /// Common prefix bits with the furthest Id is 0
+    pub fn common_prefix_bits(&self, other: &Id) -> usize {
+        self.xor(other).leading_zeros() as usize
+    }
+
     /// Performs bitwise XOR between two Ids
+    ///
+    /// This is the full Kademlia XOR metric, comparable with [Ord],
+    /// while [Self::distance] is its simplified (bucket index) form.
     pub fn xor(&self, other: &Id) -> Id {
         let mut result = [0_u8; 20];
 
@@ -104,6 +169,19 @@ impl Id {
         from_ipv4_and_r(bytes[1..].try_into().expect("infallible"), ipv4, bytes[0])
     }
 
+    /// Derive an Id deterministically from a `seed`, useful for reproducible tests.
+    pub fn from_seed(seed: &[u8; 32]) -> Id {
+        Id(seed_hash(seed))
+    }
+
+    /// Same as [Self::from_ipv4], but deterministically derived from a `seed`
+    /// instead of random bytes.
+    pub fn from_ipv4_and_seed(ipv4: Ipv4Addr, seed: &[u8; 32]) -> Id {
+        let bytes = seed_hash(seed);
+
+        from_ipv4_and_r(bytes, ipv4, bytes[0])
+    }
+
     /// Validate that this Id is valid with respect to [BEP_0042](http://bittorrent.org/beps/bep_0042.html).
     pub fn is_valid_for_ip(&self, ipv4: Ipv4Addr) -> bool {
         if ipv4.is_private() || ipv4.is_link_local() || ipv4.is_loopback() {
@@ -124,6 +202,13 @@ fn first_21_bits(bytes: &[u8]) -> [u8; 3] {
     [bytes[0], bytes[1], bytes[2] & 0xf8]
 }
 
+fn seed_hash(seed: &[u8; 32]) -> [u8; ID_SIZE] {
+    let mut hasher = Sha1::new();
+    hasher.update(seed);
+
+    hasher.digest().bytes()
+}
+
 fn from_ipv4_and_r(bytes: [u8; 20], ip: Ipv4Addr, r: u8) -> Id {
     let mut bytes = bytes;
     let prefix = id_prefix_ipv4(ip, r);
@@ -186,18 +271,21 @@ impl FromStr for Id {
     type Err = DecodeIdError;
 
     fn from_str(s: &str) -> Result<Id, DecodeIdError> {
-        if s.len() % 2 != 0 {
+        if !s.len().is_multiple_of(2) {
             return Err(DecodeIdError::OddNumberOfCharacters);
         }
 
         let mut bytes = Vec::with_capacity(s.len() / 2);
 
-        for i in 0..s.len() / 2 {
-            let byte_str = &s[i * 2..(i * 2) + 2];
-            if let Ok(byte) = u8::from_str_radix(byte_str, 16) {
-                bytes.push(byte);
-            } else {
-                return Err(DecodeIdError::InvalidHexCharacter(byte_str.into()));
+        // Iterate over raw bytes, so non ASCII input can't split a character and panic.
+        for pair in s.as_bytes().chunks(2) {
+            match (hex_value(pair[0]), hex_value(pair[1])) {
+                (Some(high), Some(low)) => bytes.push((high << 4) | low),
+                _ => {
+                    return Err(DecodeIdError::InvalidHexCharacter(
+                        String::from_utf8_lossy(pair).into(),
+                    ))
+                }
             }
         }
 
@@ -205,6 +293,35 @@ impl FromStr for Id {
     }
 }
 
+/// Value of an ASCII hex digit, unlike [u8::from_str_radix] it rejects signs like `+`.
+fn hex_value(byte: u8) -> Option<u8> {
+    (byte as char).to_digit(16).map(|digit| digit as u8)
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for Id {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.collect_str(self)
+        } else {
+            self.0.serialize(serializer)
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Id {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            let hex = String::deserialize(deserializer)?;
+
+            Id::from_str(&hex).map_err(D::Error::custom)
+        } else {
+            <[u8; ID_SIZE]>::deserialize(deserializer).map(Id)
+        }
+    }
+}
+
 impl Debug for Id {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         write!(f, "Id({})", self)
@@ -212,6 +329,7 @@ impl Debug for Id {
 }
 
 #[derive(Debug)]
+/// Input is not [ID_SIZE] bytes long, contains the actual length in bytes.
 pub struct InvalidIdSize(usize);
 
 impl std::error::Error for InvalidIdSize {}
@@ -223,7 +341,7 @@ impl std::fmt::Display for InvalidIdSize {
 }
 
 #[derive(thiserror::Error, Debug)]
-/// Mainline crate error enum.
+/// Errors parsing an [Id] from a hex string, see [Id::from_str].
 pub enum DecodeIdError {
     /// Id is expected to by 20 bytes.
     #[error(transparent)]
@@ -285,6 +403,48 @@ mod test {
         assert_eq!(distance, MAX_DISTANCE)
     }
 
+    #[test]
+    fn xor_with_self() {
+        let id = Id::random();
+
+        assert_eq!(id.xor(&id), Id([0; ID_SIZE]));
+        assert_eq!(id.common_prefix_bits(&id), 160);
+    }
+
+    #[test]
+    fn xor_with_furthest() {
+        let id = Id::random();
+        let target = id.xor(&Id([0xff; ID_SIZE]));
+
+        assert_eq!(id.xor(&target), Id([0xff; ID_SIZE]));
+        assert_eq!(id.common_prefix_bits(&target), 0);
+    }
+
+    #[test]
+    fn common_prefix_bits() {
+        let id = Id::from_str("0639A1E24FBB8AB277DF033476AB0DE10FAB3BDC").unwrap();
+        let target = Id::from_str("035b1aeb9737ade1a80933594f405d3f772aa08e").unwrap();
+
+        assert_eq!(id.common_prefix_bits(&target), 5);
+        assert_eq!(
+            id.common_prefix_bits(&target),
+            (MAX_DISTANCE - id.distance(&target)) as usize
+        );
+    }
+
+    #[test]
+    fn random_with_prefix() {
+        let prefix = Id::random();
+
+        for prefix_bits in [0, 1, 7, 8, 13, 160] {
+            let id = Id::random_with_prefix(&prefix, prefix_bits);
+
+            assert!(id.has_prefix(&prefix, prefix_bits));
+        }
+
+        assert_eq!(Id::random_with_prefix(&prefix, MAX_DISTANCE), prefix);
+    }
+
     #[test]
     fn from_u8_20() {
         let bytes = [8; 20];
@@ -318,6 +478,20 @@ mod test {
         }
     }
 
+    #[test]
+    fn from_seed() {
+        let seed = [42; 32];
+        let ip = Ipv4Addr::new(124, 31, 75, 21);
+
+        assert_eq!(Id::from_seed(&seed), Id::from_seed(&seed));
+        assert_ne!(Id::from_seed(&seed), Id::from_seed(&[43; 32]));
+
+        let secure = Id::from_ipv4_and_seed(ip, &seed);
+
+        assert_eq!(secure, Id::from_ipv4_and_seed(ip, &seed));
+        assert!(secure.is_valid_for_ip(ip));
+    }
+
     #[test]
     fn is_valid_for_ipv4() {
         let valid_vectors = vec![
@@ -353,4 +527,55 @@ mod test {
             assert!(id.is_valid_for_ip(ip));
         }
     }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_hex() {
+        let id = Id::random();
+
+        // Bencode is considered a human readable format by serde.
+        let encoded = serde_bencode::to_bytes(&id).unwrap();
+        assert_eq!(encoded, format!("40:{id}").into_bytes());
+
+        let decoded: Id = serde_bencode::from_bytes(&encoded).unwrap();
+        assert_eq!(decoded, id);
+
+        assert!(serde_bencode::from_bytes::<Id>(b"4:zzzz").is_err());
+    }
+
+    #[test]
+    fn from_str_errors() {
+        let valid = "0639a1e24fbb8ab277df033476ab0de10fab3bdc";
+
+        assert!(matches!(
+            Id::from_str(&valid[..19]),
+            Err(DecodeIdError::OddNumberOfCharacters)
+        ));
+        assert!(matches!(
+            Id::from_str(&valid[..38]),
+            Err(DecodeIdError::InvalidIdSize(InvalidIdSize(19)))
+        ));
+        assert!(matches!(
+            Id::from_str(&format!("zz{}", &valid[2..])),
+            Err(DecodeIdError::InvalidHexCharacter(pair)) if pair == "zz"
+        ));
+        assert!(matches!(
+            Id::from_str(&format!("+a{}", &valid[2..])),
+            Err(DecodeIdError::InvalidHexCharacter(pair)) if pair == "+a"
+        ));
+        // A multi-byte character split across pairs.
+        assert!(matches!(
+            Id::from_str(&format!("0é{}", &valid[3..])),
+            Err(DecodeIdError::InvalidHexCharacter(_))
+        ));
+    }
+
+    #[test]
+    fn random_at_distance() {
+        let id = Id::random();
+
+        for distance in 0..=MAX_DISTANCE {
+            assert_eq!(id.distance(&id.random_at_distance(distance)), distance);
+        }
+    }
 }
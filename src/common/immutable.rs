@@ -5,6 +5,10 @@ use sha1_smol::Sha1;
 use super::ID_SIZE;
 use crate::Id;
 
+/// Maximum size in bytes of a value (the `v` field) stored in the DHT, for both
+/// immutable and mutable items, as per [BEP_0044](https://www.bittorrent.org/beps/bep_0044.html).
+pub const MAX_VALUE_SIZE: usize = 1000;
+
 pub fn validate_immutable(v: &[u8], target: Id) -> bool {
     hash_immutable(v) == *target.as_bytes()
 }
@@ -0,0 +1,126 @@
+//! Ipv4 address ranges in CIDR notation, used to block abusive peers.
+
+use std::{
+    fmt::{self, Display, Formatter},
+    net::{AddrParseError, Ipv4Addr},
+    str::FromStr,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// A range of Ipv4 addresses, like `192.168.0.0/16`, or a single address (`/32`).
+pub struct Ipv4Net {
+    addr: Ipv4Addr,
+    prefix_len: u8,
+}
+
+impl Ipv4Net {
+    /// Create a new range from an address and a prefix length, ignoring the host bits of `addr`.
+    ///
+    /// Returns an error if `prefix_len` is larger than 32.
+    pub fn new(addr: Ipv4Addr, prefix_len: u8) -> Result<Self, DecodeIpv4NetError> {
+        if prefix_len > 32 {
+            return Err(DecodeIpv4NetError::InvalidPrefixLength(
+                prefix_len.to_string(),
+            ));
+        }
+
+        Ok(Self {
+            addr: Ipv4Addr::from(u32::from(addr) & mask(prefix_len)),
+            prefix_len,
+        })
+    }
+
+    /// The first address in this range.
+    pub fn addr(&self) -> Ipv4Addr {
+        self.addr
+    }
+
+    /// Number of leading bits shared by all addresses in this range.
+    pub fn prefix_len(&self) -> u8 {
+        self.prefix_len
+    }
+
+    /// Returns true if `ip` is in this range.
+    pub fn contains(&self, ip: &Ipv4Addr) -> bool {
+        u32::from(*ip) & mask(self.prefix_len) == u32::from(self.addr)
+    }
+}
+
+fn mask(prefix_len: u8) -> u32 {
+    u32::MAX.checked_shl(32 - prefix_len as u32).unwrap_or(0)
+}
+
+impl From<Ipv4Addr> for Ipv4Net {
+    fn from(addr: Ipv4Addr) -> Self {
+        Self {
+            addr,
+            prefix_len: 32,
+        }
+    }
+}
+
+impl FromStr for Ipv4Net {
+    type Err = DecodeIpv4NetError;
+
+    /// Parse either a single address `1.2.3.4`, or a range `1.2.3.0/24`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('/') {
+            Some((addr, prefix_len)) => Ipv4Net::new(
+                addr.parse()?,
+                prefix_len
+                    .parse()
+                    .map_err(|_| DecodeIpv4NetError::InvalidPrefixLength(prefix_len.into()))?,
+            ),
+            None => Ok(Ipv4Addr::from_str(s)?.into()),
+        }
+    }
+}
+
+impl Display for Ipv4Net {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+/// Errors parsing an [Ipv4Net].
+pub enum DecodeIpv4NetError {
+    /// The address part is not a valid Ipv4 address.
+    #[error(transparent)]
+    InvalidAddress(#[from] AddrParseError),
+
+    /// The prefix length is not a number between 0 and 32.
+    #[error("Invalid prefix length: {0}")]
+    InvalidPrefixLength(String),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn contains() {
+        let range: Ipv4Net = "192.168.1.77/16".parse().unwrap();
+
+        assert_eq!(range.addr(), Ipv4Addr::new(192, 168, 0, 0));
+        assert_eq!(range.to_string(), "192.168.0.0/16");
+        assert!(range.contains(&Ipv4Addr::new(192, 168, 200, 1)));
+        assert!(!range.contains(&Ipv4Addr::new(192, 169, 0, 1)));
+
+        let single: Ipv4Net = "1.2.3.4".parse().unwrap();
+        assert!(single.contains(&Ipv4Addr::new(1, 2, 3, 4)));
+        assert!(!single.contains(&Ipv4Addr::new(1, 2, 3, 5)));
+
+        let all: Ipv4Net = "0.0.0.0/0".parse().unwrap();
+        assert!(all.contains(&Ipv4Addr::new(8, 8, 8, 8)));
+
+        assert!(matches!(
+            "1.2.3.4/33".parse::<Ipv4Net>(),
+            Err(DecodeIpv4NetError::InvalidPrefixLength(_))
+        ));
+        assert!(matches!(
+            "1.2.3/8".parse::<Ipv4Net>(),
+            Err(DecodeIpv4NetError::InvalidAddress(_))
+        ));
+    }
+}
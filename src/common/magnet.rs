@@ -0,0 +1,142 @@
+//! Parsing info hashes from BitTorrent magnet links.
+
+use std::str::FromStr;
+
+use crate::common::{Id, ID_SIZE};
+
+const BTIH_PREFIX: &str = "urn:btih:";
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+impl Id {
+    /// Parse the info hash from the `xt=urn:btih:` parameter of a
+    /// [magnet link](https://www.bittorrent.org/beps/bep_0009.html),
+    /// encoded in either hex (40 characters) or base32 (32 characters).
+    pub fn from_magnet(magnet_uri: &str) -> Result<Id, MagnetError> {
+        let query = magnet_uri
+            .get(..8)
+            .filter(|scheme| scheme.eq_ignore_ascii_case("magnet:?"))
+            .map(|_| &magnet_uri[8..])
+            .ok_or(MagnetError::InvalidUri)?;
+
+        for param in query.split('&') {
+            let (key, value) = match param.split_once('=') {
+                Some(pair) => pair,
+                None => continue,
+            };
+
+            // Multiple exact topics can be numbered `xt.1`, `xt.2`, ...
+            if key != "xt" && !key.starts_with("xt.") {
+                continue;
+            }
+
+            let value = value.replace("%3A", ":").replace("%3a", ":");
+
+            let encoded = match value.get(..BTIH_PREFIX.len()) {
+                Some(prefix) if prefix.eq_ignore_ascii_case(BTIH_PREFIX) => {
+                    &value[BTIH_PREFIX.len()..]
+                }
+                _ => continue,
+            };
+
+            return match encoded.len() {
+                40 => Id::from_str(encoded)
+                    .map_err(|_| MagnetError::InvalidInfoHash(encoded.to_string())),
+                32 => decode_base32(encoded)
+                    .map(Id::from)
+                    .ok_or_else(|| MagnetError::InvalidInfoHash(encoded.to_string())),
+                _ => Err(MagnetError::InvalidInfoHash(encoded.to_string())),
+            };
+        }
+
+        Err(MagnetError::MissingInfoHash)
+    }
+}
+
+/// Decode a 32 characters RFC 4648 base32 string (without padding) to 20 bytes.
+fn decode_base32(encoded: &str) -> Option<[u8; ID_SIZE]> {
+    let mut bytes = [0_u8; ID_SIZE];
+
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    let mut i = 0;
+
+    for c in encoded.bytes() {
+        let value = BASE32_ALPHABET
+            .iter()
+            .position(|a| *a == c.to_ascii_uppercase())?;
+
+        buffer = (buffer << 5) | value as u32;
+        bits += 5;
+
+        if bits >= 8 {
+            bits -= 8;
+            *bytes.get_mut(i)? = (buffer >> bits) as u8;
+            i += 1;
+        }
+    }
+
+    (i == ID_SIZE).then_some(bytes)
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+/// Errors parsing an info hash from a magnet link.
+pub enum MagnetError {
+    /// The uri doesn't start with `magnet:?`
+    #[error("Invalid magnet uri, expected it to start with `magnet:?`")]
+    InvalidUri,
+
+    /// The magnet link doesn't contain an `xt=urn:btih:` parameter.
+    #[error("Magnet uri is missing an `xt=urn:btih:` info hash")]
+    MissingInfoHash,
+
+    /// The info hash is neither 40 hex characters nor 32 base32 characters.
+    #[error("Invalid magnet info hash: {0}")]
+    InvalidInfoHash(String),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn from_magnet() {
+        let expected = Id::from_str("c12fe1c06bba254a9dc9f519b335aa7c1367a88a").unwrap();
+
+        assert_eq!(
+            Id::from_magnet(
+                "magnet:?xt=urn:btih:c12fe1c06bba254a9dc9f519b335aa7c1367a88a&dn=Example"
+            ),
+            Ok(expected)
+        );
+        assert_eq!(
+            Id::from_magnet("magnet:?dn=Example&xt=urn:btih:YEX6DQDLXISUVHOJ6UM3GNNKPQJWPKEK"),
+            Ok(expected)
+        );
+        assert_eq!(
+            Id::from_magnet("MAGNET:?xt.1=urn%3Abtih%3Ayex6dqdlxisuvhoj6um3gnnkpqjwpkek"),
+            Ok(expected)
+        );
+    }
+
+    #[test]
+    fn from_invalid_magnet() {
+        assert_eq!(
+            Id::from_magnet("http://example.com"),
+            Err(MagnetError::InvalidUri)
+        );
+        assert_eq!(
+            Id::from_magnet("magnet:?dn=Example"),
+            Err(MagnetError::MissingInfoHash)
+        );
+        assert_eq!(
+            Id::from_magnet("magnet:?xt=urn:btih:c12fe1"),
+            Err(MagnetError::InvalidInfoHash("c12fe1".to_string()))
+        );
+        assert_eq!(
+            Id::from_magnet("magnet:?xt=urn:btih:YEX6DQDLXISUVHOJ6UM3GNNKPQJWPKE1"),
+            Err(MagnetError::InvalidInfoHash(
+                "YEX6DQDLXISUVHOJ6UM3GNNKPQJWPKE1".to_string()
+            ))
+        );
+    }
+}
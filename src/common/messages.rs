@@ -9,7 +9,7 @@ mod internal;
 use std::convert::TryInto;
 use std::net::{Ipv4Addr, SocketAddrV4};
 
-use crate::common::{Id, Node, ID_SIZE};
+use crate::common::{BloomFilter, ClientVersion, Id, Node, COMPACT_NODE_SIZE, ID_SIZE};
 
 use super::InvalidIdSize;
 
@@ -58,6 +58,8 @@ pub enum RequestTypeSpecific {
     FindNode(FindNodeRequestArguments),
     GetPeers(GetPeersRequestArguments),
     GetValue(GetValueRequestArguments),
+    SampleInfohashes(SampleInfohashesRequestArguments),
+    Scrape(ScrapeRequestArguments),
 
     Put(PutRequest),
 }
@@ -76,6 +78,14 @@ pub enum PutRequestSpecific {
 }
 
 impl PutRequestSpecific {
+    /// The name of the request, as it is sent over the wire.
+    pub fn name(&self) -> &'static str {
+        match self {
+            PutRequestSpecific::AnnouncePeer(_) => "announce_peer",
+            PutRequestSpecific::PutImmutable(_) | PutRequestSpecific::PutMutable(_) => "put",
+        }
+    }
+
     pub fn target(&self) -> &Id {
         match self {
             PutRequestSpecific::AnnouncePeer(AnnouncePeerRequestArguments {
@@ -96,6 +106,8 @@ pub enum ResponseSpecific {
     GetMutable(GetMutableResponseArguments),
     NoValues(NoValuesResponseArguments),
     NoMoreRecentValue(NoMoreRecentValueResponseArguments),
+    SampleInfohashes(SampleInfohashesResponseArguments),
+    Scrape(ScrapeResponseArguments),
 }
 
 // === PING ===
@@ -157,6 +169,9 @@ pub struct AnnouncePeerRequestArguments {
     pub info_hash: Id,
     pub port: u16,
     pub implied_port: Option<bool>,
+    /// [BEP_0033](https://www.bittorrent.org/beps/bep_0033.html) flag,
+    /// `Some(true)` if the announcing peer is a seed.
+    pub seed: Option<bool>,
 }
 
 // === Get Immutable ===
@@ -190,6 +205,47 @@ pub struct NoMoreRecentValueResponseArguments {
     pub seq: i64,
 }
 
+// === Sample Infohashes ===
+
+/// [BEP_0051](https://www.bittorrent.org/beps/bep_0051.html) sample_infohashes request.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SampleInfohashesRequestArguments {
+    pub target: Id,
+}
+
+/// [BEP_0051](https://www.bittorrent.org/beps/bep_0051.html) sample_infohashes response.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SampleInfohashesResponseArguments {
+    pub responder_id: Id,
+    /// Seconds the requester should wait before requesting a new sample from this node.
+    pub interval: i64,
+    /// Number of info hashes stored by this node.
+    pub num: i64,
+    pub nodes: Option<Box<[Node]>>,
+    pub samples: Box<[Id]>,
+}
+
+// === Scrape ===
+
+/// [BEP_0033](https://www.bittorrent.org/beps/bep_0033.html) scrape request,
+/// sent over the wire as a `get_peers` request with the `scrape` flag.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ScrapeRequestArguments {
+    pub info_hash: Id,
+}
+
+/// [BEP_0033](https://www.bittorrent.org/beps/bep_0033.html) scrape response.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ScrapeResponseArguments {
+    pub responder_id: Id,
+    pub token: Box<[u8]>,
+    pub nodes: Option<Box<[Node]>>,
+    /// Bloom filter of the IP addresses of seeds (`BFsd`).
+    pub seeds: BloomFilter,
+    /// Bloom filter of the IP addresses of downloaders (`BFpe`).
+    pub peers: BloomFilter,
+}
+
 // === Put Immutable ===
 
 #[derive(Debug, PartialEq, Clone)]
@@ -243,6 +299,16 @@ impl Message {
                             arguments: internal::DHTGetPeersRequestArguments {
                                 id: requester_id.into(),
                                 info_hash: get_peers_args.info_hash.into(),
+                                scrape: None,
+                            },
+                        }
+                    }
+                    RequestTypeSpecific::Scrape(scrape_args) => {
+                        internal::DHTRequestSpecific::GetPeers {
+                            arguments: internal::DHTGetPeersRequestArguments {
+                                id: requester_id.into(),
+                                info_hash: scrape_args.info_hash.into(),
+                                scrape: Some(1),
                             },
                         }
                     }
@@ -255,6 +321,14 @@ impl Message {
                             },
                         }
                     }
+                    RequestTypeSpecific::SampleInfohashes(sample_infohashes_args) => {
+                        internal::DHTRequestSpecific::SampleInfohashes {
+                            arguments: internal::DHTSampleInfohashesRequestArguments {
+                                id: requester_id.into(),
+                                target: sample_infohashes_args.target.into(),
+                            },
+                        }
+                    }
                     RequestTypeSpecific::Put(PutRequest {
                         token,
                         put_request_type,
@@ -272,6 +346,7 @@ impl Message {
                                     } else {
                                         Some(0)
                                     },
+                                    seed: announce_peer_args.seed.map(|seed| seed as u8),
                                 },
                             }
                         }
@@ -388,6 +463,26 @@ impl Message {
                             },
                         }
                     }
+                    ResponseSpecific::SampleInfohashes(args) => {
+                        internal::DHTResponseSpecific::SampleInfohashes {
+                            arguments: internal::DHTSampleInfohashesResponseArguments {
+                                id: args.responder_id.into(),
+                                interval: args.interval,
+                                num: args.num,
+                                nodes: args.nodes.as_ref().map(|nodes| nodes4_to_bytes(nodes)),
+                                samples: ids_to_bytes(&args.samples),
+                            },
+                        }
+                    }
+                    ResponseSpecific::Scrape(args) => internal::DHTResponseSpecific::Scrape {
+                        arguments: internal::DHTScrapeResponseArguments {
+                            id: args.responder_id.into(),
+                            token: args.token,
+                            nodes: args.nodes.as_ref().map(|nodes| nodes4_to_bytes(nodes)),
+                            seeds: args.seeds.as_bytes().as_slice().into(),
+                            peers: args.peers.as_bytes().as_slice().into(),
+                        },
+                    },
                 }),
 
                 MessageType::Error(err) => {
@@ -427,9 +522,15 @@ impl Message {
                         },
                         internal::DHTRequestSpecific::GetPeers { arguments } => RequestSpecific {
                             requester_id: Id::from_bytes(arguments.id)?,
-                            request_type: RequestTypeSpecific::GetPeers(GetPeersRequestArguments {
-                                info_hash: Id::from_bytes(arguments.info_hash)?,
-                            }),
+                            request_type: if arguments.scrape == Some(1) {
+                                RequestTypeSpecific::Scrape(ScrapeRequestArguments {
+                                    info_hash: Id::from_bytes(arguments.info_hash)?,
+                                })
+                            } else {
+                                RequestTypeSpecific::GetPeers(GetPeersRequestArguments {
+                                    info_hash: Id::from_bytes(arguments.info_hash)?,
+                                })
+                            },
                         },
                         internal::DHTRequestSpecific::GetValue { arguments } => RequestSpecific {
                             requester_id: Id::from_bytes(arguments.id)?,
@@ -440,6 +541,16 @@ impl Message {
                                 salt: None,
                             }),
                         },
+                        internal::DHTRequestSpecific::SampleInfohashes { arguments } => {
+                            RequestSpecific {
+                                requester_id: Id::from_bytes(arguments.id)?,
+                                request_type: RequestTypeSpecific::SampleInfohashes(
+                                    SampleInfohashesRequestArguments {
+                                        target: Id::from_bytes(arguments.target)?,
+                                    },
+                                ),
+                            }
+                        }
                         internal::DHTRequestSpecific::AnnouncePeer { arguments } => {
                             RequestSpecific {
                                 requester_id: Id::from_bytes(arguments.id)?,
@@ -452,6 +563,7 @@ impl Message {
                                                 .map(|implied_port| implied_port != 0),
                                             info_hash: arguments.info_hash.into(),
                                             port: arguments.port,
+                                            seed: arguments.seed.map(|seed| seed != 0),
                                         },
                                     ),
                                 }),
@@ -572,6 +684,32 @@ impl Message {
                                 },
                             )
                         }
+                        internal::DHTResponseSpecific::SampleInfohashes { arguments } => {
+                            ResponseSpecific::SampleInfohashes(SampleInfohashesResponseArguments {
+                                responder_id: Id::from_bytes(arguments.id)?,
+                                interval: arguments.interval,
+                                num: arguments.num,
+                                nodes: match arguments.nodes {
+                                    Some(nodes) => Some(bytes_to_nodes4(nodes)?),
+                                    None => None,
+                                },
+                                samples: bytes_to_ids(&arguments.samples)?,
+                            })
+                        }
+                        internal::DHTResponseSpecific::Scrape { arguments } => {
+                            ResponseSpecific::Scrape(ScrapeResponseArguments {
+                                responder_id: Id::from_bytes(arguments.id)?,
+                                token: arguments.token,
+                                nodes: match arguments.nodes {
+                                    Some(nodes) => Some(bytes_to_nodes4(nodes)?),
+                                    None => None,
+                                },
+                                seeds: BloomFilter::from_bytes(&arguments.seeds)
+                                    .ok_or(DecodeMessageError::InvalidBloomFilter)?,
+                                peers: BloomFilter::from_bytes(&arguments.peers)
+                                    .ok_or(DecodeMessageError::InvalidBloomFilter)?,
+                            })
+                        }
                     })
                 }
 
@@ -615,6 +753,8 @@ impl Message {
                 ResponseSpecific::GetMutable(arguments) => arguments.responder_id,
                 ResponseSpecific::NoValues(arguments) => arguments.responder_id,
                 ResponseSpecific::NoMoreRecentValue(arguments) => arguments.responder_id,
+                ResponseSpecific::SampleInfohashes(arguments) => arguments.responder_id,
+                ResponseSpecific::Scrape(arguments) => arguments.responder_id,
             },
             MessageType::Error(_) => {
                 return None;
@@ -635,11 +775,19 @@ impl Message {
                 ResponseSpecific::GetImmutable(arguments) => arguments.nodes.as_deref(),
                 ResponseSpecific::NoValues(arguments) => arguments.nodes.as_deref(),
                 ResponseSpecific::NoMoreRecentValue(arguments) => arguments.nodes.as_deref(),
+                ResponseSpecific::SampleInfohashes(arguments) => arguments.nodes.as_deref(),
+                ResponseSpecific::Scrape(arguments) => arguments.nodes.as_deref(),
             },
             _ => None,
         }
     }
 
+    /// Returns the parsed client version from the `v` field, if any.
+    pub fn client_version(&self) -> Option<ClientVersion> {
+        self.version
+            .and_then(|version| ClientVersion::from_bytes(&version))
+    }
+
     pub fn get_token(&self) -> Option<(Id, &[u8])> {
         match &self.message_type {
             MessageType::Response(response_variant) => match response_variant {
@@ -660,6 +808,10 @@ impl Message {
                 ResponseSpecific::NoMoreRecentValue(arguments) => {
                     Some((arguments.responder_id, &arguments.token))
                 }
+                ResponseSpecific::SampleInfohashes(_) => None,
+                ResponseSpecific::Scrape(arguments) => {
+                    Some((arguments.responder_id, &arguments.token))
+                }
             },
             _ => None,
         }
@@ -695,37 +847,35 @@ pub fn sockaddr_to_bytes(sockaddr: &SocketAddrV4) -> [u8; 6] {
     bytes
 }
 
-const NODE_BYTE_SIZE: usize = ID_SIZE + 6;
-
 fn nodes4_to_bytes(nodes: &[Node]) -> Box<[u8]> {
-    let mut bytes = Vec::with_capacity(NODE_BYTE_SIZE * nodes.len());
-
-    for node in nodes {
-        bytes.extend_from_slice(node.id().as_bytes());
-        bytes.extend_from_slice(&sockaddr_to_bytes(&node.address()));
-    }
-
-    bytes.into_boxed_slice()
+    Node::to_compact_bytes(nodes).into_boxed_slice()
 }
 
 fn bytes_to_nodes4<T: AsRef<[u8]>>(bytes: T) -> Result<Box<[Node]>, DecodeMessageError> {
     let bytes = bytes.as_ref();
 
-    if bytes.len() % NODE_BYTE_SIZE != 0 {
+    if bytes.len() % COMPACT_NODE_SIZE != 0 {
         return Err(DecodeMessageError::InvalidNodes4);
     }
 
-    let expected_num = bytes.len() / NODE_BYTE_SIZE;
-    let mut to_ret = Vec::with_capacity(expected_num);
-    for i in 0..bytes.len() / NODE_BYTE_SIZE {
-        let i = i * NODE_BYTE_SIZE;
-        let id = Id::from_bytes(&bytes[i..i + ID_SIZE])?;
-        let sockaddr = bytes_to_sockaddr(&bytes[i + ID_SIZE..i + NODE_BYTE_SIZE])?;
-        let node = Node::new(id, sockaddr);
-        to_ret.push(node);
+    Ok(Node::from_compact_bytes(bytes).into_boxed_slice())
+}
+
+fn ids_to_bytes(ids: &[Id]) -> Box<[u8]> {
+    ids.iter()
+        .flat_map(|id| id.as_bytes().iter().copied())
+        .collect()
+}
+
+fn bytes_to_ids(bytes: &[u8]) -> Result<Box<[Id]>, DecodeMessageError> {
+    if !bytes.len().is_multiple_of(ID_SIZE) {
+        return Err(DecodeMessageError::InvalidSamples);
     }
 
-    Ok(to_ret.into_boxed_slice())
+    bytes
+        .chunks_exact(ID_SIZE)
+        .map(|chunk| Id::from_bytes(chunk).map_err(DecodeMessageError::from))
+        .collect()
 }
 
 fn peers_to_bytes(peers: &[SocketAddrV4]) -> Vec<serde_bytes::ByteBuf> {
@@ -754,6 +904,12 @@ pub enum DecodeMessageError {
     #[error("Wrong number of bytes for nodes")]
     InvalidNodes4,
 
+    #[error("Wrong number of bytes for sample_infohashes samples")]
+    InvalidSamples,
+
+    #[error("Wrong number of bytes for scrape bloom filter")]
+    InvalidBloomFilter,
+
     #[error("wrong number of bytes for port")]
     InvalidPortEncoding,
 
@@ -867,8 +1023,7 @@ mod tests {
             message_type: MessageType::Response(ResponseSpecific::FindNode(
                 FindNodeResponseArguments {
                     responder_id: Id::random(),
-                    nodes: [Node::new(Id::random(), "49.50.52.52:5354".parse().unwrap()).into()]
-                        .into(),
+                    nodes: [Node::new(Id::random(), "49.50.52.52:5354".parse().unwrap())].into(),
                 },
             )),
         };
@@ -890,6 +1045,55 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_sample_infohashes_request() {
+        let original_msg = Message {
+            transaction_id: 258,
+            version: None,
+            requester_ip: None,
+            read_only: false,
+            message_type: MessageType::Request(RequestSpecific {
+                requester_id: Id::random(),
+                request_type: RequestTypeSpecific::SampleInfohashes(
+                    SampleInfohashesRequestArguments {
+                        target: Id::random(),
+                    },
+                ),
+            }),
+        };
+
+        let serde_msg = original_msg.clone().into_serde_message();
+        let bytes = serde_msg.to_bytes().unwrap();
+        let parsed_serde_msg = internal::DHTMessage::from_bytes(&bytes).unwrap();
+        let parsed_msg = Message::from_serde_message(parsed_serde_msg).unwrap();
+        assert_eq!(parsed_msg, original_msg);
+    }
+
+    #[test]
+    fn test_sample_infohashes_response() {
+        let original_msg = Message {
+            transaction_id: 258,
+            version: Some([1, 2, 3, 4]),
+            requester_ip: Some("50.51.52.53:5455".parse().unwrap()),
+            read_only: false,
+            message_type: MessageType::Response(ResponseSpecific::SampleInfohashes(
+                SampleInfohashesResponseArguments {
+                    responder_id: Id::random(),
+                    interval: 21600,
+                    num: 3,
+                    nodes: Some([].into()),
+                    samples: [Id::random(), Id::random()].into(),
+                },
+            )),
+        };
+
+        let serde_msg = original_msg.clone().into_serde_message();
+        let bytes = serde_msg.to_bytes().unwrap();
+        let parsed_serde_msg = internal::DHTMessage::from_bytes(&bytes).unwrap();
+        let parsed_msg = Message::from_serde_message(parsed_serde_msg).unwrap();
+        assert_eq!(parsed_msg, original_msg);
+    }
+
     #[test]
     fn test_get_peers_request() {
         let original_msg = Message {
@@ -924,8 +1128,7 @@ mod tests {
                     responder_id: Id::random(),
                     token: [99, 100, 101, 102].into(),
                     nodes: Some(
-                        [Node::new(Id::random(), "49.50.52.52:5354".parse().unwrap()).into()]
-                            .into(),
+                        [Node::new(Id::random(), "49.50.52.52:5354".parse().unwrap())].into(),
                     ),
                 },
             )),
@@ -952,6 +1155,88 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_scrape_request() {
+        let original_msg = Message {
+            transaction_id: 258,
+            version: None,
+            requester_ip: None,
+            read_only: false,
+            message_type: MessageType::Request(RequestSpecific {
+                requester_id: Id::random(),
+                request_type: RequestTypeSpecific::Scrape(ScrapeRequestArguments {
+                    info_hash: Id::random(),
+                }),
+            }),
+        };
+
+        let serde_msg = original_msg.clone().into_serde_message();
+        let bytes = serde_msg.to_bytes().unwrap();
+        let parsed_serde_msg = internal::DHTMessage::from_bytes(&bytes).unwrap();
+        let parsed_msg = Message::from_serde_message(parsed_serde_msg).unwrap();
+        assert_eq!(parsed_msg, original_msg);
+    }
+
+    #[test]
+    fn test_scrape_response() {
+        let mut seeds = BloomFilter::new();
+        seeds.insert([50, 51, 52, 53].into());
+        let mut peers = BloomFilter::new();
+        peers.insert([49, 50, 52, 52].into());
+
+        let original_msg = Message {
+            transaction_id: 3,
+            version: None,
+            requester_ip: None,
+            read_only: false,
+            message_type: MessageType::Response(ResponseSpecific::Scrape(
+                ScrapeResponseArguments {
+                    responder_id: Id::random(),
+                    token: [99, 100, 101, 102].into(),
+                    nodes: None,
+                    seeds,
+                    peers,
+                },
+            )),
+        };
+
+        let serde_msg = original_msg.clone().into_serde_message();
+        let bytes = serde_msg.to_bytes().unwrap();
+        let parsed_serde_msg = internal::DHTMessage::from_bytes(&bytes).unwrap();
+        let parsed_msg = Message::from_serde_message(parsed_serde_msg).unwrap();
+        assert_eq!(parsed_msg, original_msg);
+    }
+
+    #[test]
+    fn test_announce_peer_request_seed() {
+        let original_msg = Message {
+            transaction_id: 258,
+            version: None,
+            requester_ip: None,
+            read_only: false,
+            message_type: MessageType::Request(RequestSpecific {
+                requester_id: Id::random(),
+                request_type: RequestTypeSpecific::Put(PutRequest {
+                    token: [99, 100, 101, 102].into(),
+                    put_request_type: PutRequestSpecific::AnnouncePeer(
+                        AnnouncePeerRequestArguments {
+                            info_hash: Id::random(),
+                            port: 6881,
+                            implied_port: Some(true),
+                            seed: Some(true),
+                        },
+                    ),
+                }),
+            }),
+        };
+
+        let serde_msg = original_msg.clone().into_serde_message();
+        let bytes = serde_msg.to_bytes().unwrap();
+        let parsed_serde_msg = internal::DHTMessage::from_bytes(&bytes).unwrap();
+        let parsed_msg = Message::from_serde_message(parsed_serde_msg).unwrap();
+        assert_eq!(parsed_msg, original_msg);
+    }
+
     #[test]
     fn test_get_peers_response_peers() {
         let original_msg = Message {
@@ -87,11 +87,27 @@ pub enum DHTRequestSpecific {
         #[serde(rename = "a")]
         arguments: DHTPutValueRequestArguments,
     },
+
+    #[serde(rename = "sample_infohashes")]
+    SampleInfohashes {
+        #[serde(rename = "a")]
+        arguments: DHTSampleInfohashesRequestArguments,
+    },
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(untagged)] // This means order matters! Order these from most to least detailed
 pub enum DHTResponseSpecific {
+    SampleInfohashes {
+        #[serde(rename = "r")]
+        arguments: DHTSampleInfohashesResponseArguments,
+    },
+
+    Scrape {
+        #[serde(rename = "r")]
+        arguments: DHTScrapeResponseArguments,
+    },
+
     GetMutable {
         #[serde(rename = "r")]
         arguments: DHTGetMutableResponseArguments,
@@ -134,13 +150,6 @@ pub struct DHTErrorSpecific {
     pub error_info: (i32, String),
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
-pub enum DHTErrorValue {
-    #[serde(rename = "")]
-    ErrorCode(i32),
-    ErrorDescription(String),
-}
-
 // === PING ===
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -197,6 +206,10 @@ pub struct DHTGetPeersRequestArguments {
 
     #[serde(with = "serde_bytes")]
     pub info_hash: [u8; 20],
+
+    /// [BEP_0033](https://www.bittorrent.org/beps/bep_0033.html) scrape flag.
+    #[serde(default)]
+    pub scrape: Option<u8>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -216,6 +229,27 @@ pub struct DHTGetPeersResponseArguments {
     pub values: Vec<ByteBuf>,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DHTScrapeResponseArguments {
+    #[serde(with = "serde_bytes")]
+    pub id: [u8; 20],
+
+    #[serde(with = "serde_bytes")]
+    pub token: Box<[u8]>,
+
+    #[serde(with = "serde_bytes")]
+    #[serde(default)]
+    pub nodes: Option<Box<[u8]>>,
+
+    #[serde(rename = "BFsd")]
+    #[serde(with = "serde_bytes")]
+    pub seeds: Box<[u8]>,
+
+    #[serde(rename = "BFpe")]
+    #[serde(with = "serde_bytes")]
+    pub peers: Box<[u8]>,
+}
+
 // === Announce Peer ===
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -233,6 +267,9 @@ pub struct DHTAnnouncePeerRequestArguments {
 
     #[serde(default)]
     pub implied_port: Option<u8>,
+
+    #[serde(default)]
+    pub seed: Option<u8>,
 }
 
 // === Get Value ===
@@ -338,3 +375,31 @@ pub struct DHTPutValueRequestArguments {
     #[serde(default)]
     pub salt: Option<Box<[u8]>>,
 }
+
+// === Sample Infohashes ===
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DHTSampleInfohashesRequestArguments {
+    #[serde(with = "serde_bytes")]
+    pub id: [u8; 20],
+
+    #[serde(with = "serde_bytes")]
+    pub target: [u8; 20],
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DHTSampleInfohashesResponseArguments {
+    #[serde(with = "serde_bytes")]
+    pub id: [u8; 20],
+
+    pub interval: i64,
+
+    #[serde(with = "serde_bytes")]
+    #[serde(default)]
+    pub nodes: Option<Box<[u8]>>,
+
+    pub num: i64,
+
+    #[serde(with = "serde_bytes")]
+    pub samples: Box<[u8]>,
+}
@@ -3,11 +3,10 @@
 use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
 use serde::{Deserialize, Serialize};
 use sha1_smol::Sha1;
-use std::convert::TryFrom;
 
 use crate::Id;
 
-use super::PutMutableRequestArguments;
+use super::{PutMutableRequestArguments, MAX_VALUE_SIZE};
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 /// [BEP_0044](https://www.bittorrent.org/beps/bep_0044.html)'s Mutable item.
@@ -29,6 +28,9 @@ pub struct MutableItem {
 
 impl MutableItem {
     /// Create a new mutable item from a signing key, value, sequence number and optional salt.
+    ///
+    /// Values larger than [MAX_VALUE_SIZE] are rejected locally when putting the item,
+    /// use [Self::builder] to validate the value while creating the item instead.
     pub fn new(signer: SigningKey, value: &[u8], seq: i64, salt: Option<&[u8]>) -> Self {
         let signable = encode_signable(seq, value, salt);
         let signature = signer.sign(&signable);
@@ -42,6 +44,18 @@ impl MutableItem {
         )
     }
 
+    /// Start building a [MutableItem] signed by `signer`, with an optional
+    /// compare and swap condition, see [MutableItemBuilder].
+    pub fn builder(signer: SigningKey) -> MutableItemBuilder {
+        MutableItemBuilder {
+            signer,
+            value: Box::new([]),
+            seq: None,
+            salt: None,
+            cas: None,
+        }
+    }
+
     /// Return the target of a [MutableItem] by hashing its `public_key` and an optional `salt`
     pub fn target_from_key(public_key: &[u8; 32], salt: Option<&[u8]>) -> Id {
         let mut encoded = vec![];
@@ -85,22 +99,41 @@ impl MutableItem {
         signature: &[u8],
         salt: Option<Box<[u8]>>,
     ) -> Result<Self, MutableError> {
-        let key = VerifyingKey::try_from(key).map_err(|_| MutableError::InvalidMutablePublicKey)?;
-
-        let signature =
-            Signature::from_slice(signature).map_err(|_| MutableError::InvalidMutableSignature)?;
-
-        key.verify(&encode_signable(seq, &v, salt.as_deref()), &signature)
+        let key: [u8; 32] = key
+            .try_into()
+            .map_err(|_| MutableError::InvalidMutablePublicKey)?;
+        let signature: [u8; 64] = signature
+            .try_into()
             .map_err(|_| MutableError::InvalidMutableSignature)?;
 
-        Ok(Self {
+        let item = Self {
             target,
-            key: key.to_bytes(),
+            key,
             value: v,
             seq,
-            signature: signature.to_bytes(),
+            signature,
             salt,
-        })
+        };
+
+        item.verify()?;
+
+        Ok(item)
+    }
+
+    /// Verify the ed25519 signature of this item, over the canonical encoding of its
+    /// `salt`, `seq` and `value`, using its embedded public [key](Self::key).
+    ///
+    /// Useful to reject forged items offline, for example those created with
+    /// [Self::new_signed_unchecked] from an untrusted source.
+    pub fn verify(&self) -> Result<(), MutableError> {
+        let key = VerifyingKey::from_bytes(&self.key)
+            .map_err(|_| MutableError::InvalidMutablePublicKey)?;
+
+        key.verify(
+            &encode_signable(self.seq, &self.value, self.salt.as_deref()),
+            &Signature::from_bytes(&self.signature),
+        )
+        .map_err(|_| MutableError::InvalidMutableSignature)
     }
 
     // === Getters ===
@@ -137,6 +170,97 @@ impl MutableItem {
     }
 }
 
+#[derive(Debug, Clone)]
+/// Builder for a signed [MutableItem] and its optional CAS condition,
+/// created by [MutableItem::builder].
+///
+/// ```
+/// use mainline::{MutableItem, SigningKey};
+///
+/// let signer = SigningKey::from_bytes(&[0; 32]);
+///
+/// let (item, cas) = MutableItem::builder(signer)
+///     .value(b"Hello World!")
+///     .salt(b"salt")
+///     .compare_and_swap(3)
+///     .build()
+///     .unwrap();
+///
+/// assert_eq!(item.seq(), 4);
+/// assert_eq!(cas, Some(3));
+/// // dht.put_mutable(item, cas)
+/// ```
+pub struct MutableItemBuilder {
+    signer: SigningKey,
+    value: Box<[u8]>,
+    seq: Option<i64>,
+    salt: Option<Box<[u8]>>,
+    cas: Option<i64>,
+}
+
+impl MutableItemBuilder {
+    /// Set the value of the item.
+    ///
+    /// Defaults to an empty value.
+    pub fn value(&mut self, value: &[u8]) -> &mut Self {
+        self.value = value.into();
+
+        self
+    }
+
+    /// Set the `seq` (sequence) number of the item.
+    ///
+    /// Defaults to the [compare and swap](Self::compare_and_swap) value plus one if set, or `0`.
+    pub fn seq(&mut self, seq: i64) -> &mut Self {
+        self.seq = Some(seq);
+
+        self
+    }
+
+    /// Set the salt used to derive the item's [target](MutableItem::target).
+    pub fn salt(&mut self, salt: &[u8]) -> &mut Self {
+        self.salt = Some(salt.into());
+
+        self
+    }
+
+    /// Only store this item at nodes whose current item has `seq == previous_seq`,
+    /// usually the [MutableItem::seq] of the most recent known item.
+    pub fn compare_and_swap(&mut self, previous_seq: i64) -> &mut Self {
+        self.cas = Some(previous_seq);
+
+        self
+    }
+
+    /// Sign the item and return it with the CAS condition to pass to
+    /// [crate::Dht::put_mutable].
+    ///
+    /// Returns [MutableError::SeqNotGreaterThanCas] if the `seq` is not greater than
+    /// the CAS value, as nodes would reject it anyway.
+    pub fn build(&self) -> Result<(MutableItem, Option<i64>), MutableError> {
+        let seq = self
+            .seq
+            .unwrap_or_else(|| self.cas.map(|cas| cas.saturating_add(1)).unwrap_or(0));
+
+        if self.value.len() > MAX_VALUE_SIZE {
+            return Err(MutableError::ValueTooLarge {
+                len: self.value.len(),
+                max: MAX_VALUE_SIZE,
+            });
+        }
+
+        if let Some(cas) = self.cas {
+            if seq <= cas {
+                return Err(MutableError::SeqNotGreaterThanCas { seq, cas });
+            }
+        }
+
+        let item = MutableItem::new(self.signer.clone(), &self.value, seq, self.salt.as_deref());
+
+        Ok((item, self.cas))
+    }
+}
+
 pub fn encode_signable(seq: i64, value: &[u8], salt: Option<&[u8]>) -> Box<[u8]> {
     let mut signable = vec![];
 
@@ -161,6 +285,24 @@ pub enum MutableError {
     #[error("Invalid mutable item public key")]
     /// Invalid mutable item public key
     InvalidMutablePublicKey,
+
+    #[error("Mutable item seq ({seq}) is not greater than the CAS value ({cas})")]
+    /// [MutableItemBuilder] was given a `seq` that is not greater than its CAS value.
+    SeqNotGreaterThanCas {
+        /// The sequence number of the item.
+        seq: i64,
+        /// The compare and swap value.
+        cas: i64,
+    },
+
+    #[error("Mutable item value is too large ({len} bytes), max is {max} bytes")]
+    /// [MutableItemBuilder] was given a value larger than [MAX_VALUE_SIZE].
+    ValueTooLarge {
+        /// The size of the value in bytes.
+        len: usize,
+        /// The maximum size of a value in bytes.
+        max: usize,
+    },
 }
 
 impl PutMutableRequestArguments {
@@ -209,4 +351,85 @@ mod tests {
 
         assert_eq!(&*signable, b"4:salt6:foobar3:seqi4e1:v12:Hello world!");
     }
+
+    #[test]
+    fn verify() {
+        let signer = SigningKey::from_bytes(&[0; 32]);
+
+        let mut item = MutableItem::new(signer, b"Hello world!", 4, Some(b"foobar"));
+        assert!(item.verify().is_ok());
+
+        item.value = b"Hello forged world!".as_slice().into();
+        assert!(matches!(
+            item.verify(),
+            Err(MutableError::InvalidMutableSignature)
+        ));
+
+        let forged = MutableItem::new_signed_unchecked(
+            *item.key(),
+            [0; 64],
+            b"Hello world!",
+            4,
+            Some(b"foobar"),
+        );
+        assert!(forged.verify().is_err());
+    }
+
+    #[test]
+    fn builder() {
+        let signer = SigningKey::from_bytes(&[0; 32]);
+
+        let (item, cas) = MutableItem::builder(signer.clone())
+            .value(b"Hello world!")
+            .seq(5)
+            .salt(b"foobar")
+            .compare_and_swap(4)
+            .build()
+            .unwrap();
+
+        assert_eq!(cas, Some(4));
+        assert_eq!(
+            item,
+            MutableItem::new(signer.clone(), b"Hello world!", 5, Some(b"foobar"))
+        );
+
+        let (item, cas) = MutableItem::builder(signer).build().unwrap();
+
+        assert_eq!(cas, None);
+        assert_eq!(item.seq(), 0);
+        assert_eq!(item.value(), b"");
+    }
+
+    #[test]
+    fn builder_reject_seq_not_greater_than_cas() {
+        let signer = SigningKey::from_bytes(&[0; 32]);
+
+        let result = MutableItem::builder(signer)
+            .value(b"Hello world!")
+            .seq(4)
+            .compare_and_swap(4)
+            .build();
+
+        assert!(matches!(
+            result,
+            Err(MutableError::SeqNotGreaterThanCas { seq: 4, cas: 4 })
+        ));
+    }
+
+    #[test]
+    fn builder_reject_value_too_large() {
+        let signer = SigningKey::from_bytes(&[0; 32]);
+
+        let result = MutableItem::builder(signer)
+            .value(&[0; MAX_VALUE_SIZE + 1])
+            .build();
+
+        assert!(matches!(
+            result,
+            Err(MutableError::ValueTooLarge {
+                len: 1001,
+                max: 1000
+            })
+        ));
+    }
 }
@@ -1,12 +1,15 @@
 //! Struct and implementation of the Node entry in the Kademlia routing table
 use std::{
     fmt::{self, Debug, Formatter},
-    net::SocketAddrV4,
+    net::{Ipv4Addr, SocketAddrV4},
     sync::Arc,
     time::{Duration, Instant},
 };
 
-use crate::common::Id;
+use crate::common::{Id, ID_SIZE};
+
+/// Size of a node in the compact node info format: 20 bytes [Id] and 6 bytes address.
+pub const COMPACT_NODE_SIZE: usize = ID_SIZE + 6;
 
 /// The age of a node's last_seen time before it is considered stale and removed from a full bucket
 /// on inserting a new node.
@@ -14,12 +17,13 @@ pub const STALE_TIME: Duration = Duration::from_secs(15 * 60);
 const MIN_PING_BACKOFF_INTERVAL: Duration = Duration::from_secs(10);
 pub const TOKEN_ROTATE_INTERVAL: Duration = Duration::from_secs(60 * 5);
 
-#[derive(PartialEq)]
+#[derive(Clone, PartialEq, Eq)]
 pub(crate) struct NodeInner {
     pub(crate) id: Id,
     pub(crate) address: SocketAddrV4,
     pub(crate) token: Option<Box<[u8]>>,
     pub(crate) last_seen: Instant,
+    pub(crate) client_version: Option<ClientVersion>,
 }
 
 impl NodeInner {
@@ -29,11 +33,37 @@ impl NodeInner {
             address: SocketAddrV4::new(0.into(), 0),
             token: None,
             last_seen: Instant::now(),
+            client_version: None,
         }
     }
 }
 
-#[derive(Clone, PartialEq)]
+/// The client software of a node, parsed from the `v` field of its messages.
+///
+/// Read [BEP_0020](https://www.bittorrent.org/beps/bep_0020.html) for known client identifiers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ClientVersion {
+    /// Two characters identifying the client, for example `LT` for libtorrent,
+    /// `UT` for uTorrent, or `RS` for this crate.
+    pub two_char_id: [u8; 2],
+    /// The client's version.
+    pub version: u16,
+}
+
+impl ClientVersion {
+    /// Parse a 4 bytes `v` field, returns None for any other length.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        match bytes {
+            [a, b, c, d] => Some(Self {
+                two_char_id: [*a, *b],
+                version: u16::from_be_bytes([*c, *d]),
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq)]
 /// Node entry in Kademlia routing table
 pub struct Node(pub(crate) Arc<NodeInner>);
 
@@ -43,6 +73,7 @@ impl Debug for Node {
             .field("id", &self.0.id)
             .field("address", &self.0.address)
             .field("last_seen", &self.0.last_seen.elapsed().as_secs())
+            .field("client_version", &self.0.client_version)
             .finish()
     }
 }
@@ -55,6 +86,7 @@ impl Node {
             address,
             token: None,
             last_seen: Instant::now(),
+            client_version: None,
         }))
     }
 
@@ -64,9 +96,17 @@ impl Node {
             address,
             token: Some(token),
             last_seen: Instant::now(),
+            client_version: None,
         }))
     }
 
+    /// Set the client version this node reported in its messages.
+    pub(crate) fn with_client_version(mut self, client_version: Option<ClientVersion>) -> Self {
+        Arc::make_mut(&mut self.0).client_version = client_version;
+
+        self
+    }
+
     /// Creates a node with random Id for testing purposes.
     pub fn random() -> Node {
         Node(Arc::new(NodeInner::random()))
@@ -95,6 +135,18 @@ impl Node {
         self.0.token.clone()
     }
 
+    /// Returns the client version this node reported, if it responded to us with one.
+    ///
+    /// Nodes learned about from other nodes' responses don't have a client version.
+    pub fn client_version(&self) -> Option<ClientVersion> {
+        self.0.client_version
+    }
+
+    /// Returns when this node was last seen.
+    pub fn last_seen(&self) -> Instant {
+        self.0.last_seen
+    }
+
     /// Node is last seen more than a threshold ago.
     pub fn is_stale(&self) -> bool {
         self.0.last_seen.elapsed() > STALE_TIME
@@ -105,6 +157,13 @@ impl Node {
         self.0.last_seen.elapsed() <= TOKEN_ROTATE_INTERVAL
     }
 
+    /// Approximate number of bytes held by this node.
+    pub(crate) fn approximate_size(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + std::mem::size_of::<NodeInner>()
+            + self.0.token.as_ref().map(|token| token.len()).unwrap_or(0)
+    }
+
     pub(crate) fn should_ping(&self) -> bool {
         self.0.last_seen.elapsed() > MIN_PING_BACKOFF_INTERVAL
     }
@@ -121,15 +180,52 @@ impl Node {
 
     /// Node [Id] is valid for its IP address.
     ///
+    /// Nodes with local network addresses (private, link-local or loopback)
+    /// are always considered secure, as BEP_0042 exempts them.
+    ///
     /// Check [BEP_0042](https://www.bittorrent.org/beps/bep_0042.html).
     pub fn is_secure(&self) -> bool {
         self.0.id.is_valid_for_ip(*self.0.address.ip())
     }
 
+    /// Parse nodes from the [BEP_0005](https://www.bittorrent.org/beps/bep_0005.html)
+    /// compact node info format, a concatenation of 20 bytes [Id] and 6 bytes Ipv4 address and port.
+    ///
+    /// Trailing bytes that don't make a full node are ignored.
+    pub fn from_compact_bytes(bytes: &[u8]) -> Vec<Node> {
+        bytes
+            .chunks_exact(COMPACT_NODE_SIZE)
+            .map(|chunk| {
+                let mut id = [0; ID_SIZE];
+                id.copy_from_slice(&chunk[..ID_SIZE]);
+
+                let address = &chunk[ID_SIZE..];
+                let ip = Ipv4Addr::new(address[0], address[1], address[2], address[3]);
+                let port = u16::from_be_bytes([address[4], address[5]]);
+
+                Node::new(id.into(), SocketAddrV4::new(ip, port))
+            })
+            .collect()
+    }
+
+    /// Encode nodes in the [BEP_0005](https://www.bittorrent.org/beps/bep_0005.html)
+    /// compact node info format, see [Node::from_compact_bytes].
+    pub fn to_compact_bytes(nodes: &[Node]) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(COMPACT_NODE_SIZE * nodes.len());
+
+        for node in nodes {
+            bytes.extend_from_slice(node.id().as_bytes());
+            bytes.extend_from_slice(&node.address().ip().octets());
+            bytes.extend_from_slice(&node.address().port().to_be_bytes());
+        }
+
+        bytes
+    }
+
     /// Returns true if Any of the existing nodes:
     ///  - Have the same IP as this node, And:
-    ///     = The existing nodes is Not secure.
-    ///     = The existing nodes is secure And shares the same first 21 bits.
+    ///    = The existing nodes is Not secure.
+    ///    = The existing nodes is secure And shares the same first 21 bits.
     ///
     /// Effectively, allows only One non-secure node or Eight secure nodes from the same IP, in the routing table or ClosestNodes.
     pub(crate) fn already_exists(&self, nodes: &[Self]) -> bool {
@@ -140,3 +236,147 @@ impl Node {
         })
     }
 }
+
+#[cfg(feature = "serde")]
+mod serde_impl {
+    use std::{
+        net::SocketAddrV4,
+        sync::Arc,
+        time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+    };
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::Node;
+    use crate::common::Id;
+
+    #[derive(Serialize, Deserialize)]
+    struct SerdeNode {
+        id: Id,
+        address: SocketAddrV4,
+        /// Unix timestamp in seconds.
+        last_seen: u64,
+    }
+
+    /// Serializes the node's [Id], address and the last time it was seen,
+    /// as a unix timestamp in seconds, dropping its token and client version.
+    impl Serialize for Node {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let last_seen = SystemTime::now()
+                .checked_sub(self.last_seen().elapsed())
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .unwrap_or_default()
+                .as_secs();
+
+            SerdeNode {
+                id: *self.id(),
+                address: self.address(),
+                last_seen,
+            }
+            .serialize(serializer)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Node {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let SerdeNode {
+                id,
+                address,
+                last_seen,
+            } = SerdeNode::deserialize(deserializer)?;
+
+            let mut node = Node::new(id, address);
+
+            let age = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .saturating_sub(Duration::from_secs(last_seen));
+
+            if let Some(last_seen) = Instant::now().checked_sub(age) {
+                Arc::make_mut(&mut node.0).last_seen = last_seen;
+            }
+
+            Ok(node)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn client_version_from_bytes() {
+        assert_eq!(
+            ClientVersion::from_bytes(b"LT\x01\x02"),
+            Some(ClientVersion {
+                two_char_id: *b"LT",
+                version: 0x0102,
+            })
+        );
+        assert_eq!(ClientVersion::from_bytes(b"LT\x01"), None);
+        assert_eq!(ClientVersion::from_bytes(b""), None);
+    }
+
+    #[test]
+    fn compact_bytes() {
+        let nodes = vec![Node::random(), Node::random()];
+
+        let bytes = Node::to_compact_bytes(&nodes);
+        assert_eq!(bytes.len(), 2 * COMPACT_NODE_SIZE);
+
+        let decoded = Node::from_compact_bytes(&bytes);
+        assert_eq!(decoded.len(), 2);
+        for (node, decoded) in nodes.iter().zip(&decoded) {
+            assert_eq!(node.id(), decoded.id());
+            assert_eq!(node.address(), decoded.address());
+        }
+
+        // Trailing bytes are ignored
+        assert_eq!(
+            Node::from_compact_bytes(&bytes[..COMPACT_NODE_SIZE + 3]).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn is_secure() {
+        use std::net::Ipv4Addr;
+
+        let public = Ipv4Addr::new(124, 31, 75, 21);
+
+        let secure = Node::new(Id::from_ipv4(public), SocketAddrV4::new(public, 6881));
+        assert!(secure.is_secure());
+
+        let mut bytes = *secure.id().as_bytes();
+        bytes[0] ^= 0xff;
+        let insecure = Node::new(Id::from_bytes(bytes).unwrap(), secure.address());
+        assert!(!insecure.is_secure());
+
+        for local in [
+            Ipv4Addr::new(192, 168, 1, 2),
+            Ipv4Addr::new(10, 0, 0, 1),
+            Ipv4Addr::new(169, 254, 0, 1),
+            Ipv4Addr::LOCALHOST,
+        ] {
+            let node = Node::new(*insecure.id(), SocketAddrV4::new(local, 6881));
+            assert!(node.is_secure());
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde() {
+        let mut node = Node::random();
+        Arc::make_mut(&mut node.0).last_seen = Instant::now() - Duration::from_secs(60);
+
+        let encoded = serde_bencode::to_bytes(&node).unwrap();
+        let decoded: Node = serde_bencode::from_bytes(&encoded).unwrap();
+
+        assert_eq!(decoded.id(), node.id());
+        assert_eq!(decoded.address(), node.address());
+
+        let age = decoded.last_seen().elapsed().as_secs();
+        assert!((59..=61).contains(&age));
+    }
+}
@@ -2,6 +2,7 @@
 
 use std::collections::BTreeMap;
 use std::slice::Iter;
+use std::time::{Duration, Instant};
 
 use crate::common::{Id, Node};
 use crate::rpc::ClosestNodes;
@@ -9,6 +10,29 @@ use crate::rpc::ClosestNodes;
 /// K = the default maximum size of a k-bucket.
 pub const MAX_BUCKET_SIZE_K: usize = 20;
 
+/// A summary of a non-empty bucket in a [RoutingTable], for debugging, see [RoutingTable::buckets_info].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BucketInfo {
+    /// The [distance](Id::distance) of this bucket's nodes from the routing table's [Id].
+    pub distance: u8,
+    /// All nodes in this bucket share the first [Self::prefix_bits] of this [Id].
+    pub prefix: Id,
+    /// Number of leading bits of [Self::prefix] shared by all nodes in this bucket.
+    pub prefix_bits: u8,
+    /// Maximum number of nodes in this bucket.
+    pub capacity: usize,
+    /// Number of nodes seen within the last 15 minutes.
+    pub good: usize,
+    /// Number of [stale](Node::is_stale) nodes, replaced by new nodes once this bucket is full.
+    ///
+    /// Nodes that fail to respond are removed from the routing table,
+    /// so there is no separate count of bad nodes.
+    pub questionable: usize,
+    /// The most recent time any node in this bucket was seen, which is when
+    /// this bucket was last refreshed, see [crate::DhtBuilder::bucket_refresh_interval].
+    pub last_seen: Option<Instant>,
+}
+
 #[derive(Debug, Clone)]
 /// Simplified Kademlia routing table
 pub struct RoutingTable {
@@ -58,6 +82,43 @@ impl RoutingTable {
         bucket.add(node)
     }
 
+    /// Returns the distances of non-empty buckets, where no node was seen
+    /// within `max_age`, see [crate::DhtBuilder::bucket_refresh_interval].
+    pub(crate) fn stale_buckets(&self, max_age: Duration) -> Vec<u8> {
+        self.buckets
+            .iter()
+            .filter(|(_, bucket)| {
+                !bucket.is_empty()
+                    && bucket
+                        .iter()
+                        .all(|node| node.last_seen().elapsed() > max_age)
+            })
+            .map(|(distance, _)| *distance)
+            .collect()
+    }
+
+    /// Returns a [BucketInfo] for each non-empty bucket, sorted by distance.
+    pub fn buckets_info(&self) -> Vec<BucketInfo> {
+        self.buckets
+            .iter()
+            .filter(|(_, bucket)| !bucket.is_empty())
+            .map(|(distance, bucket)| {
+                let (prefix, prefix_bits) = bucket_prefix(&self.id, *distance);
+                let questionable = bucket.iter().filter(|node| node.is_stale()).count();
+
+                BucketInfo {
+                    distance: *distance,
+                    prefix,
+                    prefix_bits,
+                    capacity: MAX_BUCKET_SIZE_K,
+                    good: bucket.nodes.len() - questionable,
+                    questionable,
+                    last_seen: bucket.iter().map(|node| node.last_seen()).max(),
+                }
+            })
+            .collect()
+    }
+
     /// Remove a node from this routing table.
     pub fn remove(&mut self, node_id: &Id) {
         let distance = self.id.distance(node_id);
@@ -112,7 +173,7 @@ impl RoutingTable {
     }
 
     /// Returns an iterator over the nodes in this routing table.
-    pub fn nodes(&self) -> RoutingTableIterator {
+    pub fn nodes(&self) -> RoutingTableIterator<'_> {
         RoutingTableIterator {
             bucket_index: 1,
             node_index: 0,
@@ -148,6 +209,22 @@ impl RoutingTable {
     }
 }
 
+/// The prefix shared by all [Id]s at `distance` (1 to 160) from `id`: the bits of `id`
+/// before the first differing bit, followed by that bit flipped, and the rest zeroed.
+fn bucket_prefix(id: &Id, distance: u8) -> (Id, u8) {
+    let prefix_bits = 160 - distance + 1;
+    let mut bytes = *id.as_bytes();
+
+    let flipped = (prefix_bits - 1) as usize;
+    bytes[flipped / 8] ^= 0x80 >> (flipped % 8);
+
+    for bit in prefix_bits as usize..160 {
+        bytes[bit / 8] &= !(0x80 >> (bit % 8));
+    }
+
+    (bytes.into(), prefix_bits)
+}
+
 pub struct RoutingTableIterator<'a> {
     bucket_index: u8,
     node_index: usize,
@@ -263,7 +340,7 @@ mod test {
     use std::net::SocketAddrV4;
     use std::str::FromStr;
     use std::sync::Arc;
-    use std::time::Instant;
+    use std::time::{Duration, Instant};
 
     use crate::common::{Id, KBucket, Node, NodeInner, RoutingTable, MAX_BUCKET_SIZE_K};
 
@@ -305,10 +382,10 @@ mod test {
 
         let node = Node::random();
 
-        assert!(!table.contains(&node.id()));
+        assert!(!table.contains(node.id()));
 
         table.add(node.clone());
-        assert!(table.contains(&node.id()));
+        assert!(table.contains(node.id()));
     }
 
     #[test]
@@ -318,10 +395,10 @@ mod test {
         let node = Node::random();
 
         table.add(node.clone());
-        assert!(table.contains(&node.id()));
+        assert!(table.contains(node.id()));
 
         table.remove(node.id());
-        assert!(!table.contains(&node.id()));
+        assert!(!table.contains(node.id()));
     }
 
     #[test]
@@ -408,6 +485,7 @@ mod test {
                 address: SocketAddrV4::new([21, 75, 31, 124].into(), 0),
                 token: None,
                 last_seen: Instant::now(),
+                client_version: None,
             }));
 
             let unsecure = Node::new(*secure.id(), SocketAddrV4::new([0, 0, 0, 0].into(), 1));
@@ -562,6 +640,7 @@ mod test {
                     address: SocketAddrV4::new((i as u32).into(), i as u16),
                     token: None,
                     last_seen: Instant::now(),
+                    client_version: None,
                 }))
             })
             .collect();
@@ -646,4 +725,62 @@ mod test {
             assert_eq!(closest_ids, expected_closest_ids);
         }
     }
+
+    #[test]
+    fn stale_buckets() {
+        let mut table = RoutingTable::new(Id::random());
+
+        let fresh = Node::new(
+            table.id().random_at_distance(150),
+            Node::unique(1).address(),
+        );
+        table.add(fresh);
+
+        let mut stale = Node::new(
+            table.id().random_at_distance(140),
+            Node::unique(2).address(),
+        );
+        Arc::make_mut(&mut stale.0).last_seen = Instant::now() - Duration::from_secs(60);
+        table.add(stale);
+
+        assert_eq!(table.stale_buckets(Duration::from_secs(30)), vec![140]);
+        assert!(table.stale_buckets(Duration::from_secs(90)).is_empty());
+    }
+
+    #[test]
+    fn buckets_info() {
+        let mut table = RoutingTable::new(Id::random());
+
+        for i in 0..100 {
+            table.add(Node::unique(i));
+        }
+
+        let mut stale = Node::new(
+            table.id().random_at_distance(1),
+            Node::unique(200).address(),
+        );
+        Arc::make_mut(&mut stale.0).last_seen = Instant::now() - Duration::from_secs(16 * 60);
+        table.add(stale);
+
+        let info = table.buckets_info();
+
+        assert_eq!(
+            info.iter().map(|b| b.good + b.questionable).sum::<usize>(),
+            table.size()
+        );
+
+        for bucket in &info {
+            assert_eq!(bucket.prefix_bits, 161 - bucket.distance);
+            assert!(!table.id().has_prefix(&bucket.prefix, bucket.prefix_bits));
+
+            for node in &table.buckets()[&bucket.distance].nodes {
+                assert!(node.id().has_prefix(&bucket.prefix, bucket.prefix_bits));
+            }
+        }
+
+        let closest = info.first().unwrap();
+        assert_eq!(closest.distance, 1);
+        assert_eq!(closest.questionable, 1);
+        assert_eq!(closest.good, 0);
+    }
 }
@@ -1,35 +1,64 @@
 //! Dht node.
 
 use std::{
-    collections::HashMap, 
-    net::{Ipv4Addr, SocketAddrV4, ToSocketAddrs, UdpSocket}, 
-    sync::Arc, 
-    thread, 
-    time::Duration,
+    collections::{HashMap, HashSet, VecDeque},
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4, ToSocketAddrs, UdpSocket},
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
 };
 
-use flume::{Receiver, Sender, TryRecvError};
+use flume::{Receiver, Sender, TryRecvError, WeakSender};
 
-use tracing::info;
+use tracing::{debug, info};
 
 use crate::{
     common::{
-        hash_immutable, AnnouncePeerRequestArguments, FindNodeRequestArguments,
-        GetPeersRequestArguments, GetValueRequestArguments, Id, MutableItem,
-        PutImmutableRequestArguments, PutMutableRequestArguments, PutRequestSpecific,
+        hash_immutable, AnnouncePeerRequestArguments, BloomFilter, ErrorSpecific,
+        FindNodeRequestArguments, GetPeersRequestArguments, GetPeersResponseArguments,
+        GetValueRequestArguments, Id, Ipv4Net, MagnetError, MessageType, MutableItem,
+        NoValuesResponseArguments, PutImmutableRequestArguments, PutMutableRequestArguments,
+        PutRequestSpecific, RequestSpecific, RequestTypeSpecific, ResponseSpecific,
+        SampleInfohashesRequestArguments, SampleInfohashesResponseArguments,
+        ScrapeRequestArguments, ScrapeResponseArguments, ValueCodec, ID_SIZE, MAX_VALUE_SIZE,
     },
     rpc::{
-        to_socket_address, ConcurrencyError, GetRequestSpecific, Info, PutError, PutQueryError,
-        Response, Rpc,
+        config::{RequestObserver, Resolver},
+        probe_from_port, to_socket_address, ConcurrencyError, GetRequestSpecific, Info,
+        NetworkConditions, PeersResponse, PutError, PutQueryError, PutReport, QueryTiming,
+        Response, RetransmitPolicy, Rpc,
     },
-    Node, ServerSettings,
+    BucketInfo, Node, RoutingTable, ServerSettings, ServerStats, SigningKey,
 };
 
 use crate::rpc::config::Config;
 
+/// Maximum number of attempts in [Dht::put_mutable_merged] before giving up
+/// and returning the last [ConcurrencyError].
+pub const MAX_MERGE_ATTEMPTS: usize = 5;
+
+/// Maximum number of chunks in [Dht::put_immutable_chunked], as many chunk
+/// [Id]s as fit in a single manifest value after its tag.
+pub const MAX_IMMUTABLE_CHUNKS: usize = (MAX_VALUE_SIZE - CHUNKS_MANIFEST_TAG.len()) / ID_SIZE;
+
+/// Prefix of the manifest values put by [Dht::put_immutable_chunked],
+/// to tell them apart from other immutable values.
+const CHUNKS_MANIFEST_TAG: &[u8] = b"mlc1";
+
+/// How often a [paused][Dht::pause] actor checks for new messages.
+const PAUSED_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
 #[derive(Debug, Clone)]
 /// Mainline Dht node.
-pub struct Dht(pub(crate) Sender<ActorMessage>);
+///
+/// Cloning a [Dht] is cheap, as all clones (and `AsyncDht`s)
+/// share the same actor thread. Once the last clone is dropped, the actor thread stops,
+/// and the UDP socket is released, so there is no need for an explicit shutdown.
+pub struct Dht(
+    pub(crate) Sender<ActorMessage>,
+    Arc<dyn ValueCodec>,
+    SocketAddrV4,
+);
 
 #[derive(Debug, Default, Clone)]
 /// A builder for the [Dht] node.
@@ -43,6 +72,68 @@ impl DhtBuilder {
         self
     }
 
+    /// Run this node in read-only mode according to [BEP_0043](https://www.bittorrent.org/beps/bep_0043.html).
+    ///
+    /// All outgoing requests are marked read-only, so other nodes shouldn't ask this node to
+    /// store anything. Incoming requests aren't answered, so no tokens are handed out,
+    /// and announce_peer or put requests are silently dropped.
+    ///
+    /// Useful for nodes behind NAT, or that shouldn't store third party data.
+    /// Takes precedence over [Self::server_mode].
+    pub fn read_only(&mut self) -> &mut Self {
+        self.0.read_only = true;
+
+        self
+    }
+
+    /// Limit outgoing UDP packets to `packets_per_second`, to avoid getting banned
+    /// by other nodes for flooding them.
+    ///
+    /// Packets exceeding the limit are queued rather than dropped, so queries take longer,
+    /// up to 1000 packets, beyond which the oldest are dropped.
+    /// See [Info::rate_limit_utilization] to tell if this node is being throttled.
+    ///
+    /// Defaults to unlimited.
+    pub fn rate_limit(&mut self, packets_per_second: u32) -> &mut Self {
+        self.0.rate_limit = Some(packets_per_second);
+
+        self
+    }
+
+    /// Retransmit requests that are not answered yet, following a [RetransmitPolicy]
+    /// of randomized exponential backoff, to recover from lost packets
+    /// without synchronized retransmission storms against busy nodes.
+    ///
+    /// Defaults to no retransmissions.
+    pub fn retransmit_policy(&mut self, retransmit_policy: RetransmitPolicy) -> &mut Self {
+        self.0.retransmit_policy = Some(retransmit_policy);
+
+        self
+    }
+
+    /// Never switch to server mode, even after running long enough with a publicly
+    /// accessible address, unlike the default
+    /// [Adaptive mode](https://github.com/pubky/mainline?tab=readme-ov-file#adaptive-mode).
+    ///
+    /// The node still reports whether or not it is [firewalled](Info::firewalled), but never
+    /// responds to other nodes' requests, so it never stores their peers or values.
+    ///
+    /// Useful for embedded clients. Takes precedence over [Self::server_mode].
+    pub fn client_only(&mut self) -> &mut Self {
+        self.0.client_only = true;
+
+        self
+    }
+
+    /// Simulate unreliable network conditions, by dropping or delaying outgoing packets.
+    ///
+    /// Only useful for testing, see [Testnet::with_packet_loss].
+    pub fn network_conditions(&mut self, network_conditions: NetworkConditions) -> &mut Self {
+        self.0.network_conditions = Some(network_conditions);
+
+        self
+    }
+
     /// Set a custom settings for the node to use at server mode.
     ///
     /// Defaults to [ServerSettings::default]
@@ -52,23 +143,109 @@ impl DhtBuilder {
         self
     }
 
+    /// Call `on_request` with the source address of every incoming request,
+    /// before it is handled, whether or not this node is running in server mode.
+    ///
+    /// Useful to log requests, or build abuse detection, rate limiting or banlists
+    /// on top, using a [ServerSettings::filter] to drop the requests accordingly.
+    ///
+    /// The callback runs on the actor thread, so it must return quickly, and
+    /// hand off any slow work to another thread, for example over a channel.
+    pub fn on_request(
+        &mut self,
+        on_request: impl Fn(SocketAddrV4, &RequestSpecific) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.0.on_request = Some(RequestObserver(Arc::new(on_request)));
+
+        self
+    }
+
+    /// Drop packets to and from addresses in these ranges, and never add
+    /// such nodes to the routing table.
+    ///
+    /// Single addresses can be converted to an [Ipv4Net] with `Ipv4Net::from(ip)`,
+    /// and CIDR ranges parsed with `"10.0.0.0/8".parse::<Ipv4Net>()`.
+    ///
+    /// See [Dht::ban] and [Dht::unban] to update the list at runtime.
+    pub fn blocklist(&mut self, blocklist: &[Ipv4Net]) -> &mut Self {
+        self.0.blocklist = blocklist.to_vec();
+
+        self
+    }
+
+    /// Detect unreachable destinations from ICMP port unreachable errors, to stop waiting
+    /// for their responses, and remove them from the routing table immediately, instead
+    /// of waiting for the [request timeout](Self::request_timeout).
+    ///
+    /// Speeds up lookups when many nodes in the routing table are stale.
+    ///
+    /// Only supported on Linux, on other platforms a warning is logged and requests
+    /// time out as usual. Counted in [Info::unreachable_destinations].
+    pub fn detect_unreachable(&mut self) -> &mut Self {
+        self.0.detect_unreachable = true;
+
+        self
+    }
+
     /// Set bootstrapping nodes.
+    ///
+    /// All the Ipv4 addresses each host resolves to are tried, not just the first one.
+    /// Ipv6 addresses are ignored, as Ipv6 is not supported yet.
     pub fn bootstrap<T: ToSocketAddrs>(&mut self, bootstrap: &[T]) -> &mut Self {
         self.0.bootstrap = Some(to_socket_address(bootstrap));
 
         self
     }
 
-    /// Add more bootstrap nodes to default bootstrapping nodes.
+    /// Add more bootstrap nodes to the [bootstrapping nodes](Self::bootstrap),
+    /// or to the [crate::DEFAULT_BOOTSTRAP_NODES] if none were set.
+    ///
+    /// Unlike [Self::bootstrap] this doesn't replace the existing nodes. Nodes are
+    /// resolved when the node starts, with the [Self::resolver] if any.
     ///
     /// Useful when you want to augment the default bootstrapping nodes with
     /// dynamic list of nodes you have seen in previous sessions.
+    pub fn add_bootstrap(&mut self, nodes: &[String]) -> &mut Self {
+        self.0.extra_bootstrap.extend_from_slice(nodes);
+
+        self
+    }
+
+    /// Same as [Self::add_bootstrap], but resolves the nodes as they are given,
+    /// like [Self::bootstrap].
     pub fn extra_bootstrap<T: ToSocketAddrs>(&mut self, extra_bootstrap: &[T]) -> &mut Self {
-        let mut bootstrap = self.0.bootstrap.clone().unwrap_or_default();
-        for address in to_socket_address(extra_bootstrap) {
-            bootstrap.push(address);
-        }
-        self.0.bootstrap = Some(bootstrap);
+        self.0.extra_bootstrap.extend(
+            to_socket_address(extra_bootstrap)
+                .iter()
+                .map(|address| address.to_string()),
+        );
+
+        self
+    }
+
+    /// Override how the [crate::DEFAULT_BOOTSTRAP_NODES] hostnames, and the nodes
+    /// passed to [Self::add_bootstrap], are resolved, for example using DNS over HTTPS
+    /// or a fixed map in restricted networks.
+    ///
+    /// Nodes passed to [Self::bootstrap] and [Self::extra_bootstrap] are resolved as they are given.
+    ///
+    /// Defaults to [ToSocketAddrs].
+    pub fn resolver(
+        &mut self,
+        resolver: impl Fn(&str) -> std::io::Result<Vec<SocketAddr>> + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.0.resolver = Some(Resolver(Arc::new(resolver)));
+
+        self
+    }
+
+    /// Bootstrap from these nodes, usually saved from the [routing table][Dht::routing_table]
+    /// of a previous session, before falling back to the bootstrapping nodes.
+    ///
+    /// This makes cold starts faster, and reduces the load on the public bootstrapping nodes.
+    /// If none of these nodes respond, the bootstrapping nodes are used as usual.
+    pub fn bootstrap_from_nodes(&mut self, nodes: &[Node]) -> &mut Self {
+        self.0.bootstrap_from_nodes = nodes.to_vec();
 
         self
     }
@@ -76,6 +253,23 @@ impl DhtBuilder {
     /// Remove the existing bootstrapping nodes, usually to create the first node in a new network.
     pub fn no_bootstrap(&mut self) -> &mut Self {
         self.0.bootstrap = Some(vec![]);
+        self.0.extra_bootstrap = vec![];
+
+        self
+    }
+
+    /// Run an isolated node that never reaches the network on its own, useful for tests.
+    ///
+    /// Binds to an ephemeral port on the loopback interface, removes all bootstrapping
+    /// nodes (including [Self::bootstrap_from_nodes]), and never switches to server mode,
+    /// see [Self::client_only].
+    pub fn offline(&mut self) -> &mut Self {
+        self.0.bind_addr = Some(SocketAddr::from((Ipv4Addr::LOCALHOST, 0)));
+        self.0.port = None;
+        self.0.bootstrap = Some(vec![]);
+        self.0.extra_bootstrap = vec![];
+        self.0.bootstrap_from_nodes = vec![];
+        self.0.client_only = true;
 
         self
     }
@@ -87,6 +281,27 @@ impl DhtBuilder {
         self
     }
 
+    /// Bind to an explicit local address, instead of all interfaces, for example
+    /// to keep DHT traffic on one of multiple network interfaces.
+    ///
+    /// Mutually exclusive with [Self::port] and [Self::ephemeral_port], otherwise
+    /// [Self::build] returns an error, as it does for Ipv6 addresses.
+    /// Use port `0` to let the OS assign a port.
+    pub fn bind_addr(&mut self, bind_addr: SocketAddr) -> &mut Self {
+        self.0.bind_addr = Some(bind_addr);
+
+        self
+    }
+
+    /// Listen on an ephemeral port assigned by the OS, same as setting [Self::port] to `0`.
+    ///
+    /// The assigned port is available from [Dht::local_addr].
+    pub fn ephemeral_port(&mut self) -> &mut Self {
+        self.0.port = Some(0);
+
+        self
+    }
+
     /// A known public IPv4 address for this node to generate
     /// a secure node Id from according to [BEP_0042](https://www.bittorrent.org/beps/bep_0042.html)
     ///
@@ -97,6 +312,46 @@ impl DhtBuilder {
         self
     }
 
+    /// A likely public IPv4 address, for example discovered with a STUN server,
+    /// counted as a strong vote when responding nodes vote on our [public address][Info::public_address].
+    ///
+    /// Unlike [Self::public_ip], the consensus of responding nodes can
+    /// still override it if enough of them disagree.
+    pub fn suggested_public_ip(&mut self, ip: Ipv4Addr) -> &mut Self {
+        self.0.suggested_public_ip = Some(ip);
+
+        self
+    }
+
+    /// Use this node [Id] instead of generating one, for example to keep a stable
+    /// identity across restarts, for reputation purposes.
+    ///
+    /// If [Self::public_ip] is also set, [Self::build] returns an error unless this Id is
+    /// secure for that ip according to [BEP_0042](https://www.bittorrent.org/beps/bep_0042.html),
+    /// see [Id::from_ipv4].
+    ///
+    /// Otherwise, if this Id turns out to be insecure for the public address reported by
+    /// other nodes, it is replaced with a secure one, just like a random Id.
+    pub fn node_id(&mut self, id: Id) -> &mut Self {
+        self.0.node_id = Some(id);
+
+        self
+    }
+
+    /// Derive the node [Id] deterministically from a `seed` instead of generating
+    /// a random one, to make tests reproducible, see [TestnetBuilder::seed].
+    ///
+    /// The derived Id is [secure](Id::from_ipv4_and_seed) for [Self::public_ip] if it is set,
+    /// and if it has to be replaced with one secure for the public address reported by
+    /// other nodes, the replacement is derived from the same seed.
+    ///
+    /// Ignored if [Self::node_id] is set.
+    pub fn node_id_seed(&mut self, seed: [u8; 32]) -> &mut Self {
+        self.0.node_id_seed = Some(seed);
+
+        self
+    }
+
     /// UDP socket request timeout duration.
     ///
     /// The longer this duration is, the longer queries take until they are deemeed "done".
@@ -110,6 +365,217 @@ impl DhtBuilder {
         self
     }
 
+    /// Interval to refresh (re-put or re-announce) stored items at, before they expire.
+    ///
+    /// Most nodes in the mainline network keep immutable and mutable items, and announced peers,
+    /// for roughly 2 hours, so the default refreshes at half that, leaving a safety margin
+    /// for failed refreshes. Shorter intervals improve durability at the cost of more traffic,
+    /// and longer intervals only make sense in private networks with longer expiry policies.
+    ///
+    /// Used as the default `interval` of [Dht::put_immutable_repeating] and
+    /// [Dht::put_mutable_repeating], and available from [Info::item_refresh_interval]
+    /// to schedule other refreshes.
+    ///
+    /// Defaults to [crate::DEFAULT_ITEM_REFRESH_INTERVAL]
+    pub fn item_refresh_interval(&mut self, item_refresh_interval: Duration) -> &mut Self {
+        self.0.item_refresh_interval = item_refresh_interval;
+
+        self
+    }
+
+    /// Interval to refresh the routing table at, by looking up this node's own [Id],
+    /// and a random [Id] in each bucket where no node was seen within the interval.
+    ///
+    /// Long running server nodes benefit from keeping their routing table fresh,
+    /// while short lived clients can use a longer interval to avoid wasteful lookups.
+    ///
+    /// The last refresh is available from [Info::last_table_refresh].
+    ///
+    /// Defaults to [crate::DEFAULT_BUCKET_REFRESH_INTERVAL]
+    pub fn bucket_refresh_interval(&mut self, bucket_refresh_interval: Duration) -> &mut Self {
+        self.0.bucket_refresh_interval = bucket_refresh_interval;
+
+        self
+    }
+
+    /// Maximum lifetime of a query, after which it is forcibly completed with whatever
+    /// results it has so far, and a warning is logged.
+    ///
+    /// Queries normally finish once all requests are responded to or timed out, so this is
+    /// a watchdog against queries that never complete, blocking their callers indefinitely.
+    ///
+    /// Defaults to [crate::DEFAULT_MAX_QUERY_LIFETIME]
+    pub fn max_query_lifetime(&mut self, max_query_lifetime: Duration) -> &mut Self {
+        self.0.max_query_lifetime = max_query_lifetime;
+
+        self
+    }
+
+    /// How long write tokens received from GET queries are considered valid,
+    /// before PUT queries (announce_peer, put_immutable, put_mutable) re-fetch
+    /// them with a new lookup.
+    ///
+    /// If a PUT query still fails because most nodes reject its tokens, it is
+    /// retried once with fresh tokens before returning an error.
+    ///
+    /// Defaults to [crate::DEFAULT_TOKEN_LIFETIME]
+    pub fn token_lifetime(&mut self, token_lifetime: Duration) -> &mut Self {
+        self.0.token_lifetime = token_lifetime;
+
+        self
+    }
+
+    /// Maximum number of immutable values to cache by their hash.
+    ///
+    /// Immutable values are verified against their hash, so caching them is always safe,
+    /// and repeated [Dht::get_immutable] calls for cached values return immediately without
+    /// hitting the network. See [Info::immutable_cache_hits] and [Info::immutable_cache_misses].
+    ///
+    /// Defaults to 0, where the cache is disabled.
+    pub fn immutable_cache_size(&mut self, immutable_cache_size: usize) -> &mut Self {
+        self.0.immutable_cache_size = immutable_cache_size;
+
+        self
+    }
+
+    /// Maximum number of iterative queries (lookups) running at the same time.
+    ///
+    /// Queries beyond this limit are queued, and started once an inflight query is done,
+    /// instead of flooding the socket with requests in constrained environments.
+    /// A limit of 0 is treated as 1.
+    ///
+    /// Defaults to no limit.
+    pub fn max_concurrent_queries(&mut self, max_concurrent_queries: usize) -> &mut Self {
+        self.0.max_concurrent_queries = Some(max_concurrent_queries);
+
+        self
+    }
+
+    /// Number of closest nodes to a target that queries track and visit concurrently,
+    /// that [Dht::find_node] returns, and that values are stored at.
+    ///
+    /// Higher values increase replication and resilience to churn and sybil attacks,
+    /// at the cost of more requests per query and more storage load on the network.
+    /// Lower values make queries cheaper, but values are stored at fewer nodes
+    /// and are more likely to be lost as nodes churn.
+    ///
+    /// Routing table buckets and nodes returned to other nodes' requests are unaffected.
+    ///
+    /// Defaults to 20 as specified in
+    /// [BEP_0005](https://www.bittorrent.org/beps/bep_0005.html).
+    pub fn k_factor(&mut self, k_factor: usize) -> &mut Self {
+        self.0.k_factor = k_factor.max(1);
+
+        self
+    }
+
+    /// Maximum number of hops an iterative query follows towards progressively closer nodes,
+    /// before it is done and returns whatever results it has so far.
+    ///
+    /// Gives latency sensitive callers a predictable upper bound on lookups, at the cost
+    /// of possibly missing the closest nodes, and values stored only at them.
+    /// The first round visits the closest nodes in the routing table, and each next round
+    /// visits the nodes returned by the previous one, see [QueryTiming::rounds].
+    ///
+    /// Lookups of this node's own [Id] to fill the routing table are not limited.
+    ///
+    /// Defaults to no limit.
+    pub fn max_lookup_rounds(&mut self, max_lookup_rounds: usize) -> &mut Self {
+        self.0.max_lookup_rounds = Some(max_lookup_rounds.max(1));
+
+        self
+    }
+
+    /// Maximum number of done queries to cache the closest responding nodes of,
+    /// which are also the samples used for the Dht size estimate.
+    ///
+    /// Lower values reduce memory usage on constrained deployments, at the cost
+    /// of less accurate size estimates and more lookups before PUT queries.
+    ///
+    /// Defaults to [crate::MAX_CACHED_ITERATIVE_QUERIES]
+    pub fn max_cached_queries(&mut self, max_cached_queries: usize) -> &mut Self {
+        self.0.max_cached_queries = max_cached_queries;
+
+        self
+    }
+
+    /// Maximum number of most recent Dht size estimates to keep,
+    /// see [Dht::dht_size_estimate_history].
+    ///
+    /// Defaults to [crate::DHT_SIZE_ESTIMATE_HISTORY]
+    pub fn max_estimate_samples(&mut self, max_estimate_samples: usize) -> &mut Self {
+        self.0.max_estimate_samples = max_estimate_samples;
+
+        self
+    }
+
+    /// Answer get_peers requests for info hashes this node [announced][Dht::announce_peer]
+    /// itself on, with its own peer address, even when not running in server mode.
+    ///
+    /// Third party announces are still ignored, and no other requests are answered,
+    /// unless this node is (or switches to) server mode.
+    ///
+    /// Each info hash is served for twice [Self::item_refresh_interval] after its
+    /// last [announcement][Dht::announce_peer], so keep reannouncing it.
+    ///
+    /// Note: nodes not in server mode are read-only, so other nodes won't add this node
+    /// to their routing tables. This only helps requesters that learned about this
+    /// node otherwise.
+    pub fn serve_own_peers(&mut self) -> &mut Self {
+        self.0.serve_own_peers = true;
+
+        self
+    }
+
+    /// Accept responses from an address other than the one the request was sent to.
+    ///
+    /// By default such responses are dropped (and counted in
+    /// [Info::response_address_mismatches]) to protect against off-path response spoofing,
+    /// but some NATs legitimately rewrite the source address of responses.
+    pub fn accept_any_response_address(&mut self) -> &mut Self {
+        self.0.accept_any_response_address = true;
+
+        self
+    }
+
+    /// Don't run the `find_node` lookup for this node's own Id, neither at startup,
+    /// on periodic routing table refresh, nor after a change of the external IP.
+    ///
+    /// The routing table will only be populated from incoming traffic and explicit
+    /// queries, which results in a weaker routing table, so this is only appropriate
+    /// for passive [server][Self::server_mode] deployments that never initiate lookups.
+    pub fn skip_self_lookup(&mut self) -> &mut Self {
+        self.0.skip_self_lookup = true;
+
+        self
+    }
+
+    /// Transparently encode immutable values before putting them, and decode them
+    /// after getting them, for example to compress them, see [ValueCodec].
+    ///
+    /// The target of an immutable value is the sha1 hash of its encoded bytes,
+    /// so all nodes reading a value should use the same codec as its writer.
+    ///
+    /// Defaults to [crate::IdentityCodec].
+    pub fn value_codec(&mut self, value_codec: Box<dyn ValueCodec>) -> &mut Self {
+        self.0.value_codec = value_codec;
+
+        self
+    }
+
+    /// Maximum time [Self::build] waits for the node to start, including binding its
+    /// socket and resolving the [default bootstrapping nodes](crate::DEFAULT_BOOTSTRAP_NODES),
+    /// before returning an error of kind [std::io::ErrorKind::TimedOut].
+    ///
+    /// The actor thread shuts down on its own once it finishes starting, if it ever does.
+    ///
+    /// Defaults to waiting indefinitely.
+    pub fn build_timeout(&mut self, build_timeout: Duration) -> &mut Self {
+        self.0.build_timeout = Some(build_timeout);
+
+        self
+    }
+
     /// Create a Dht node.
     pub fn build(&self) -> Result<Dht, std::io::Error> {
         Dht::new(self.0.clone())
@@ -123,6 +589,8 @@ impl Dht {
     /// port or other io errors while binding the udp socket.
     pub fn new(config: Config) -> Result<Self, std::io::Error> {
         let (sender, receiver) = flume::unbounded();
+        let value_codec = Arc::from(config.value_codec.clone());
+        let build_timeout = config.build_timeout;
 
         thread::Builder::new()
             .name("Mainline Dht actor thread".to_string())
@@ -134,9 +602,21 @@ impl Dht {
             .send(ActorMessage::Check(tx))
             .expect("actor thread unexpectedly shutdown");
 
-        rx.recv().expect("actor thread unexpectedly shutdown")?;
+        let local_addr = match build_timeout {
+            Some(timeout) => match rx.recv_timeout(timeout) {
+                Ok(result) => result,
+                Err(flume::RecvTimeoutError::Timeout) => Err(std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    format!("Dht node did not start within {:?}", timeout),
+                )),
+                Err(flume::RecvTimeoutError::Disconnected) => {
+                    panic!("actor thread unexpectedly shutdown")
+                }
+            },
+            None => rx.recv().expect("actor thread unexpectedly shutdown"),
+        }?;
 
-        Ok(Dht(sender))
+        Ok(Dht(sender, value_codec, local_addr))
     }
 
     /// Returns a builder to edit settings before creating a Dht node.
@@ -172,6 +652,86 @@ impl Dht {
         rx.recv().expect("actor thread unexpectedly shutdown")
     }
 
+    /// Returns this node's [Id], same as [Info::id], without computing the rest of [Info].
+    pub fn id(&self) -> Id {
+        let (tx, rx) = flume::bounded::<Id>(1);
+        self.send(ActorMessage::Id(tx));
+
+        rx.recv().expect("actor thread unexpectedly shutdown")
+    }
+
+    /// Returns the local address this node is listening on, same as [Info::local_addr],
+    /// without a round trip to the actor thread, as it never changes.
+    pub fn local_addr(&self) -> SocketAddrV4 {
+        self.2
+    }
+
+    /// Returns true if the actor thread stopped, without a round trip to it.
+    ///
+    /// The actor keeps running as long as any clone of this node exists, so this
+    /// only happens if it stopped unexpectedly, in which case every other method panics.
+    pub fn is_shutdown(&self) -> bool {
+        self.0.is_disconnected()
+    }
+
+    /// Returns a snapshot of this node's [RoutingTable].
+    ///
+    /// Useful to inspect all the nodes in the routing table, not just the
+    /// closest nodes to a target, or to persist it between runs.
+    pub fn routing_table(&self) -> RoutingTable {
+        let (tx, rx) = flume::bounded::<RoutingTable>(1);
+        self.send(ActorMessage::RoutingTable(tx));
+
+        rx.recv().expect("actor thread unexpectedly shutdown")
+    }
+
+    /// Returns a [BucketInfo] for each non-empty bucket in this node's routing table.
+    ///
+    /// Cheaper than [Dht::routing_table] when debugging why the table is sparse,
+    /// or which buckets haven't been refreshed lately.
+    pub fn debug_buckets(&self) -> Vec<BucketInfo> {
+        let (tx, rx) = flume::bounded::<Vec<BucketInfo>>(1);
+        self.send(ActorMessage::DebugBuckets(tx));
+
+        rx.recv().expect("actor thread unexpectedly shutdown")
+    }
+
+    /// Returns statistics about the peers and values stored by this node
+    /// while running in server mode, including the `top_n` info_hashes with the most peers.
+    ///
+    /// Useful to find hot info_hashes, and to size and monitor a long running node.
+    pub fn server_stats(&self, top_n: usize) -> ServerStats {
+        let (tx, rx) = flume::bounded::<ServerStats>(1);
+        self.send(ActorMessage::ServerStats(top_n, tx));
+
+        rx.recv().expect("actor thread unexpectedly shutdown")
+    }
+
+    /// Returns the (resolved) address of each configured [bootstrapping node](DhtBuilder::bootstrap),
+    /// and whether it responded to any request since startup.
+    ///
+    /// Useful to tell whether this node failed to bootstrap because
+    /// all the bootstrapping nodes are unreachable.
+    pub fn bootstrap_status(&self) -> Vec<(SocketAddrV4, bool)> {
+        let (tx, rx) = flume::bounded::<Vec<(SocketAddrV4, bool)>>(1);
+        self.send(ActorMessage::BootstrapStatus(tx));
+
+        rx.recv().expect("actor thread unexpectedly shutdown")
+    }
+
+    /// Returns up to the last `n` [Dht size estimates](Info::dht_size_estimate), oldest first,
+    /// as `(updated_at, estimate, standard_deviation)`, updated every time a query is done.
+    ///
+    /// Useful to plot how the estimate converges, and detect when this node's view of
+    /// the network is unstable. Only the most recent [DhtBuilder::max_estimate_samples]
+    /// estimates are kept.
+    pub fn dht_size_estimate_history(&self, n: usize) -> Vec<(Instant, usize, f64)> {
+        let (tx, rx) = flume::bounded::<Vec<(Instant, usize, f64)>>(1);
+        self.send(ActorMessage::DhtSizeEstimateHistory(n, tx));
+
+        rx.recv().expect("actor thread unexpectedly shutdown")
+    }
+
     /// Turn this node's routing table to a list of bootstrapping nodes.   
     pub fn to_bootstrap(&self) -> Vec<String> {
         let (tx, rx) = flume::bounded::<Vec<String>>(1);
@@ -186,46 +746,374 @@ impl Dht {
     ///
     /// Returns true if the bootstrapping was successful.
     pub fn bootstrapped(&self) -> bool {
-        let info = self.info();
-        let nodes = self.find_node(*info.id());
+        let nodes = self.find_node(self.id());
 
         !nodes.is_empty()
     }
 
-    // === Find nodes ===
-
-    /// Returns the closest 20 [secure](Node::is_secure) nodes to a target [Id].
-    ///
-    /// Mostly useful to crawl the DHT.
-    ///
-    /// The returned nodes are claims by other nodes, they may be lies, or may have churned
-    /// since they were last seen, but haven't been pinged yet.
+    /// Block until the routing table has at least `min_nodes` nodes, or `timeout` elapses.
     ///
-    /// You might need to ping them to confirm they exist, and responsive, or if you want to
-    /// learn more about them like the client they are using, or if they support a given BEP.
+    /// Unlike [Self::bootstrapped], this doesn't run a query, it only waits for the
+    /// routing table to be populated enough, to avoid racing the first lookups
+    /// against an empty routing table.
     ///
-    /// If you are trying to find the closest nodes to a target with intent to [Self::put],
-    /// a request directly to these nodes (using `extra_nodes` parameter), then you should
-    /// use [Self::get_closest_nodes] instead.
-    pub fn find_node(&self, target: Id) -> Box<[Node]> {
-        let (tx, rx) = flume::bounded::<Box<[Node]>>(1);
-        self.send(ActorMessage::Get(
-            GetRequestSpecific::FindNode(FindNodeRequestArguments { target }),
-            ResponseSender::ClosestNodes(tx),
+    /// Returns true if the routing table reached `min_nodes` before the timeout.
+    pub fn bootstrapped_with(&self, min_nodes: usize, timeout: Duration) -> bool {
+        let (tx, rx) = flume::bounded::<bool>(1);
+        self.send(ActorMessage::Bootstrapped(
+            min_nodes,
+            Instant::now() + timeout,
+            tx,
         ));
 
-        rx.recv()
-            .expect("Query was dropped before sending a response, please open an issue.")
+        rx.recv().expect("actor thread unexpectedly shutdown")
     }
 
-    // === Peers ===
-
-    /// Get peers for a given infohash.
+    /// Send a ping to `target` from a fresh UDP socket bound to `local_port`,
+    /// instead of this node's socket, and report the address `target` observed.
     ///
-    /// Note: each node of the network will only return a _random_ subset (usually 20)
-    /// of the total peers it has for a given infohash, so if you are getting responses
-    /// from 20 nodes, you can expect up to 400 peers in total, but if there are more
-    /// announced peers on that infohash, you are likely to miss some, the logic here
+    /// The socket is bound to the same Ip as this node's socket, see [DhtBuilder::bind_addr].
+    ///
+    /// Useful for testing NAT traversal, by comparing the observed addresses
+    /// of probes sent from different local ports, to find out whether or not
+    /// your NAT preserves ports or maps them consistently.
+    ///
+    /// Pass `0` as `local_port` to let the OS pick an ephemeral port.
+    ///
+    /// Blocks until `target` responds or the request times out
+    /// after [crate::DEFAULT_REQUEST_TIMEOUT].
+    pub fn probe_from_port(
+        &self,
+        local_port: u16,
+        target: SocketAddrV4,
+    ) -> Result<Observed, std::io::Error> {
+        let (local_addr, address) =
+            probe_from_port(self.probe_addr(local_port), target, self.id())?;
+
+        Ok(Observed {
+            local_addr,
+            target,
+            address,
+        })
+    }
+
+    /// Hairpin self-test: check whether or not a ping sent from this machine to our
+    /// public address reaches this node, unlike [Info::firewalled] which is a passive best guess.
+    ///
+    /// Probes up to 3 recently seen nodes from the routing table, from fresh sockets,
+    /// to learn our public Ip as they observe it, then sends a ping from another fresh
+    /// socket to our [public address][Info::public_address], and reports whether or not
+    /// it reached this node. Probes are sent from the same Ip as this node's socket,
+    /// see [DhtBuilder::bind_addr].
+    ///
+    /// KRPC has no request to ask a remote node to contact a third address, so this
+    /// only tests whether your router hairpins (loops back) packets to its own public
+    /// address; if it doesn't, a correctly forwarded port will still be reported as
+    /// unreachable, and it says nothing about filtering of packets from other hosts.
+    ///
+    /// While [paused][Self::pause], no probes are sent, and the report has no
+    /// observed addresses and is unreachable.
+    ///
+    /// Blocks for up to twice [crate::DEFAULT_REQUEST_TIMEOUT].
+    pub fn check_reachability(&self) -> ReachabilityReport {
+        let info = self.info();
+
+        if self.paused() {
+            return ReachabilityReport {
+                public_address: info.public_address(),
+                observed: vec![],
+                reachable: false,
+            };
+        }
+
+        let mut nodes = self.routing_table().to_owned_nodes();
+        nodes.retain(|node| !node.is_stale());
+        nodes.sort_by_key(|node| std::cmp::Reverse(node.last_seen()));
+        nodes.truncate(REACHABILITY_CHECK_NODES);
+
+        let observed: Vec<SocketAddrV4> = std::thread::scope(|scope| {
+            let probes: Vec<_> = nodes
+                .iter()
+                .map(|node| {
+                    scope.spawn(|| {
+                        probe_from_port(self.probe_addr(0), node.address(), *info.id())
+                            .ok()
+                            .and_then(|(_, address)| address)
+                    })
+                })
+                .collect();
+
+            probes
+                .into_iter()
+                .filter_map(|probe| probe.join().ok().flatten())
+                .collect()
+        });
+
+        let public_address = info.public_address().or_else(|| {
+            // No votes yet, assume the most commonly observed Ip, and that our port is preserved.
+            let mut ips = observed
+                .iter()
+                .map(|address| *address.ip())
+                .collect::<Vec<_>>();
+            ips.sort();
+
+            ips.chunk_by(|a, b| a == b)
+                .max_by_key(|chunk| chunk.len())
+                .map(|chunk| SocketAddrV4::new(chunk[0], self.local_addr().port()))
+        });
+
+        let reachable = public_address
+            .map(|address| {
+                let probe_id = Id::random();
+
+                // Wait for the actor to expect the probe before sending it.
+                let (tx, rx) = flume::bounded::<()>(1);
+                self.send(ActorMessage::ExpectProbe(probe_id, tx));
+                rx.recv().expect("actor thread unexpectedly shutdown");
+
+                // Our node may not respond, we only care whether or not the request arrived.
+                let _ = probe_from_port(self.probe_addr(0), address, probe_id);
+
+                let (tx, rx) = flume::bounded::<bool>(1);
+                self.send(ActorMessage::ProbeReceived(probe_id, tx));
+
+                rx.recv().expect("actor thread unexpectedly shutdown")
+            })
+            .unwrap_or(false);
+
+        ReachabilityReport {
+            public_address,
+            observed,
+            reachable,
+        }
+    }
+
+    /// Stop sending and receiving any packets, including routing table maintenance,
+    /// until [Self::resume] is called.
+    ///
+    /// The socket stays bound and the actor thread stays alive, so it is cheaper than
+    /// dropping and rebuilding the node. Queries issued while paused are not failed,
+    /// they just wait until the node is resumed, and so do new republishing values.
+    /// [Self::set_external_ip] changes the Id immediately, but only pings the new
+    /// address and refreshes the routing table after resuming.
+    ///
+    /// Inflight requests will likely time out while paused.
+    pub fn pause(&self) {
+        self.send(ActorMessage::Pause);
+    }
+
+    /// Resume the network activity stopped by [Self::pause],
+    /// and start any queries issued while paused.
+    pub fn resume(&self) {
+        self.send(ActorMessage::Resume);
+    }
+
+    /// Drop packets to and from addresses in this `range`, and remove
+    /// matching nodes from the routing table, see [DhtBuilder::blocklist].
+    pub fn ban(&self, range: impl Into<Ipv4Net>) {
+        self.send(ActorMessage::Ban(range.into()));
+    }
+
+    /// Remove a `range` previously added with [Self::ban] or [DhtBuilder::blocklist].
+    ///
+    /// Only removes an exactly matching range, so unbanning a single address
+    /// within a banned range has no effect.
+    pub fn unban(&self, range: impl Into<Ipv4Net>) {
+        self.send(ActorMessage::Unban(range.into()));
+    }
+
+    /// Tell this node its external IP changed, for example after a network change,
+    /// without recreating the [Dht].
+    ///
+    /// Regenerates a [BEP_0042](https://www.bittorrent.org/beps/bep_0042.html)
+    /// secure node [Id] for the new IP if the current one isn't valid for it,
+    /// keeping the nodes in the routing table, and refreshes the routing table.
+    /// In-flight queries still complete using the old Id.
+    ///
+    /// Returns the node's (possibly unchanged) Id.
+    pub fn set_external_ip(&self, ip: Ipv4Addr) -> Id {
+        let (tx, rx) = flume::bounded::<Id>(1);
+        self.send(ActorMessage::SetExternalIp(ip, tx));
+
+        rx.recv().expect("actor thread unexpectedly shutdown")
+    }
+
+    /// Ping a node at the given address, and return the round trip time,
+    /// or `None` if it didn't respond before the [request timeout][DhtBuilder::request_timeout].
+    ///
+    /// Useful to check the liveness of nodes, for example from [Self::find_node].
+    pub fn ping(&self, address: SocketAddrV4) -> Option<Duration> {
+        let (tx, rx) = flume::bounded::<Option<Duration>>(1);
+        self.send(ActorMessage::Ping(address, tx));
+
+        rx.recv().expect("actor thread unexpectedly shutdown")
+    }
+
+    /// Send a raw `request` to a node at the given address, and return its response.
+    ///
+    /// A low level escape hatch to experiment with the protocol, the transaction id
+    /// is assigned and the response is correlated by this node, but the response isn't
+    /// otherwise handled, so it doesn't affect the routing table or any running queries.
+    ///
+    /// Blocks until the node responds, or the [request timeout][DhtBuilder::request_timeout] elapses.
+    pub fn send_request(
+        &self,
+        address: SocketAddrV4,
+        request: RequestSpecific,
+    ) -> Result<ResponseSpecific, RequestError> {
+        let (tx, rx) = flume::bounded::<Result<ResponseSpecific, RequestError>>(1);
+        self.send(ActorMessage::Request(address, request, tx));
+
+        rx.recv().expect("actor thread unexpectedly shutdown")
+    }
+
+    /// Run an iterative GET query, and return an iterator over every response
+    /// as received from each node, see [RawResponse].
+    ///
+    /// A low level escape hatch to aggregate responses differently than the other
+    /// query methods do, the query itself still runs as usual, so it updates the
+    /// routing table, and joins any running query for the same target.
+    ///
+    /// The iterator ends once the query is done.
+    pub fn get_raw(&self, request: GetRequestSpecific) -> GetIterator<RawResponse> {
+        let target = *request.target();
+
+        let (tx, rx) = flume::unbounded::<RawResponse>();
+        self.send(ActorMessage::Get(
+            request,
+            ResponseSender::Raw(tx),
+            None,
+            None,
+        ));
+
+        GetIterator::new(rx, self.cancel_on_drop(vec![target]))
+    }
+
+    // === Find nodes ===
+
+    /// Returns the closest [k](DhtBuilder::k_factor) (20 by default) [secure](Node::is_secure)
+    /// nodes to a target [Id].
+    ///
+    /// Mostly useful to crawl the DHT.
+    ///
+    /// The returned nodes are claims by other nodes, they may be lies, or may have churned
+    /// since they were last seen, but haven't been pinged yet.
+    ///
+    /// You might need to ping them to confirm they exist, and responsive, or if you want to
+    /// learn more about them like the client they are using, or if they support a given BEP.
+    ///
+    /// This node itself is never queried or returned, even if other nodes list it,
+    /// whether by its [Id], its local address, or its [public address][Info::public_address].
+    ///
+    /// If you are trying to find the closest nodes to a target with intent to [Self::put],
+    /// a request directly to these nodes (using `extra_nodes` parameter), then you should
+    /// use [Self::get_closest_nodes] instead.
+    pub fn find_node(&self, target: Id) -> Box<[Node]> {
+        let (tx, rx) = flume::bounded::<Box<[Node]>>(1);
+        self.send(ActorMessage::Get(
+            GetRequestSpecific::FindNode(FindNodeRequestArguments { target }),
+            ResponseSender::ClosestNodes(tx),
+            None,
+            None,
+        ));
+
+        rx.recv()
+            .expect("Query was dropped before sending a response, please open an issue.")
+    }
+
+    /// Same as [Self::find_node], but pairs each node with its XOR distance
+    /// to the `target` (see [Id::xor]), sorted ascending by distance.
+    pub fn find_node_with_distance(&self, target: Id) -> Vec<(Node, Id)> {
+        with_distance(target, &self.find_node(target))
+    }
+
+    /// Discover up to `max` nodes whose [Id]s share the first `prefix_bits` bits with `prefix`.
+    ///
+    /// Useful to partition the keyspace, for example between workers of a crawler,
+    /// by issuing [Self::find_node] queries for random targets within that prefix, until
+    /// [PREFIX_DISCOVERY_IDLE_ROUNDS] consecutive queries find no new nodes.
+    pub fn find_nodes_in_prefix(&self, prefix_bits: u8, prefix: Id, max: usize) -> Vec<Node> {
+        let mut found = PrefixNodes::new(prefix_bits, prefix, max);
+
+        while !found.done() {
+            let closest_nodes = self.find_node(found.next_target());
+            found.add(&closest_nodes);
+        }
+
+        found.into_nodes()
+    }
+
+    /// Request [BEP_0051](https://www.bittorrent.org/beps/bep_0051.html) samples of the
+    /// info hashes stored by the closest nodes to `target`, and return the distinct samples,
+    /// and the suggested interval before requesting new samples from the same nodes.
+    ///
+    /// Useful to index the content on the network, by sampling random targets.
+    ///
+    /// Nodes that don't support BEP_0051 are ignored.
+    pub fn sample_infohashes(&self, target: Id) -> InfohashSamples {
+        let request = sample_infohashes_request(self.id(), target);
+
+        let receivers = self.send_requests(
+            self.find_node(target).iter().map(|node| node.address()),
+            request,
+        );
+
+        let mut samples = InfohashSamples::default();
+
+        for (_, rx) in receivers {
+            if let Ok(Ok(ResponseSpecific::SampleInfohashes(response))) = rx.recv() {
+                samples.add(response);
+            }
+        }
+
+        samples
+    }
+
+    /// Send the same `request` to all `addresses` concurrently, and return
+    /// a receiver for each response.
+    pub(crate) fn send_requests(
+        &self,
+        addresses: impl IntoIterator<Item = SocketAddrV4>,
+        request: RequestSpecific,
+    ) -> Vec<(SocketAddrV4, RequestReceiver)> {
+        addresses
+            .into_iter()
+            .map(|address| {
+                let (tx, rx) = flume::bounded::<Result<ResponseSpecific, RequestError>>(1);
+                self.send(ActorMessage::Request(address, request.clone(), tx));
+
+                (address, rx)
+            })
+            .collect()
+    }
+
+    // === Peers ===
+
+    /// Send a get_peers request for `info_hash` directly to each of the given `nodes`,
+    /// skipping the iterative lookup, and return the responses of the nodes that responded.
+    ///
+    /// Useful to verify whether specific nodes store peers for an info hash. Nodes
+    /// that responded without any peers are included with an empty list of peers.
+    ///
+    /// Ipv6 addresses are ignored.
+    pub fn get_peers_from(&self, info_hash: Id, nodes: &[SocketAddr]) -> Vec<PeersResponse> {
+        let receivers = self.send_requests(
+            ipv4_addresses(nodes),
+            get_peers_request(self.id(), info_hash),
+        );
+
+        receivers
+            .into_iter()
+            .filter_map(|(address, rx)| peers_response(address, rx.recv().ok()?.ok()?))
+            .collect()
+    }
+
+    /// Get peers for a given infohash.
+    ///
+    /// Note: each node of the network will only return a _random_ subset (usually 20)
+    /// of the total peers it has for a given infohash, so if you are getting responses
+    /// from 20 nodes, you can expect up to 400 peers in total, but if there are more
+    /// announced peers on that infohash, you are likely to miss some, the logic here
     /// for Bittorrent is that any peer will introduce you to more peers through "peer exchange"
     /// so if you are implementing something different from Bittorrent, you might want
     /// to implement your own logic for gossipping more peers after you discover the first ones.
@@ -234,9 +1122,140 @@ impl Dht {
         self.send(ActorMessage::Get(
             GetRequestSpecific::GetPeers(GetPeersRequestArguments { info_hash }),
             ResponseSender::Peers(tx),
+            None,
+            None,
+        ));
+
+        GetIterator::new(rx, self.cancel_on_drop(vec![info_hash]))
+    }
+
+    /// Same as [Self::get_peers], but overriding the [request timeout](DhtBuilder::request_timeout)
+    /// for this query only, for example a short timeout for interactive lookups.
+    ///
+    /// If a query for the same `info_hash` is already running, this call joins it,
+    /// and `request_timeout` is ignored.
+    pub fn get_peers_with_timeout(
+        &self,
+        info_hash: Id,
+        request_timeout: Duration,
+    ) -> GetIterator<Vec<SocketAddrV4>> {
+        let (tx, rx) = flume::unbounded::<Vec<SocketAddrV4>>();
+        self.send(ActorMessage::Get(
+            GetRequestSpecific::GetPeers(GetPeersRequestArguments { info_hash }),
+            ResponseSender::Peers(tx),
+            Some(request_timeout),
+            None,
+        ));
+
+        GetIterator::new(rx, self.cancel_on_drop(vec![info_hash]))
+    }
+
+    /// Same as [Self::get_peers], but each peer is only returned once,
+    /// even if multiple nodes return it.
+    ///
+    /// Peers with the same ip but different ports are distinct peers.
+    pub fn get_peers_dedup(&self, info_hash: Id) -> GetIterator<Vec<SocketAddrV4>> {
+        let (tx, rx) = flume::unbounded::<Vec<SocketAddrV4>>();
+        self.send(ActorMessage::Get(
+            GetRequestSpecific::GetPeers(GetPeersRequestArguments { info_hash }),
+            ResponseSender::PeersDedup(tx, HashSet::new()),
+            None,
+            None,
+        ));
+
+        GetIterator::new(rx, self.cancel_on_drop(vec![info_hash]))
+    }
+
+    /// Same as [Self::get_peers], but also starting the lookup from `seed_nodes`,
+    /// for example nodes learned from a tracker, to speed it up.
+    ///
+    /// Seed nodes are only used by this query, they are not added to the routing table
+    /// unless they respond, like any other node visited by a query.
+    ///
+    /// If a query for the same `info_hash` is already running, this call joins it,
+    /// and `seed_nodes` are ignored. Ipv6 addresses are ignored.
+    pub fn get_peers_with_seeds(
+        &self,
+        info_hash: Id,
+        seed_nodes: &[SocketAddr],
+    ) -> GetIterator<Vec<SocketAddrV4>> {
+        let (tx, rx) = flume::unbounded::<Vec<SocketAddrV4>>();
+        self.send(ActorMessage::Get(
+            GetRequestSpecific::GetPeers(GetPeersRequestArguments { info_hash }),
+            ResponseSender::Peers(tx),
+            None,
+            Some(ipv4_addresses(seed_nodes).into()),
+        ));
+
+        GetIterator::new(rx, self.cancel_on_drop(vec![info_hash]))
+    }
+
+    /// Same as [Self::get_peers], but each batch of peers comes with the
+    /// [Node] that returned it, see [PeersResponse].
+    pub fn get_peers_with_source(&self, info_hash: Id) -> GetIterator<PeersResponse> {
+        let (tx, rx) = flume::unbounded::<PeersResponse>();
+        self.send(ActorMessage::Get(
+            GetRequestSpecific::GetPeers(GetPeersRequestArguments { info_hash }),
+            ResponseSender::PeersWithSource(tx),
+            None,
+            None,
+        ));
+
+        GetIterator::new(rx, self.cancel_on_drop(vec![info_hash]))
+    }
+
+    /// Get peers for multiple info hashes concurrently, see [Self::get_peers].
+    ///
+    /// Returns an iterator over `(info_hash, peers)` tuples as responses arrive
+    /// from any of the queries, that ends once all queries are done.
+    pub fn get_peers_many(&self, info_hashes: &[Id]) -> GetIterator<(Id, Vec<SocketAddrV4>)> {
+        let (tx, rx) = flume::unbounded::<(Id, Vec<SocketAddrV4>)>();
+
+        for info_hash in info_hashes {
+            self.send(ActorMessage::Get(
+                GetRequestSpecific::GetPeers(GetPeersRequestArguments {
+                    info_hash: *info_hash,
+                }),
+                ResponseSender::PeersWithTarget(*info_hash, tx.clone()),
+                None,
+                None,
+            ));
+        }
+
+        GetIterator::new(rx, self.cancel_on_drop(info_hashes.into()))
+    }
+
+    /// Resolve a [magnet link](https://www.bittorrent.org/beps/bep_0009.html) to peers,
+    /// by parsing its info hash (see [Id::from_magnet]) and calling [Self::get_peers].
+    ///
+    /// Returns the unique peers found before the query is done, or the `timeout` elapses.
+    pub fn resolve_magnet(
+        &self,
+        magnet_uri: &str,
+        timeout: Duration,
+    ) -> Result<Vec<SocketAddrV4>, MagnetError> {
+        let info_hash = Id::from_magnet(magnet_uri)?;
+        let deadline = Instant::now() + timeout;
+
+        let (tx, rx) = flume::unbounded::<Vec<SocketAddrV4>>();
+        self.send(ActorMessage::Get(
+            GetRequestSpecific::GetPeers(GetPeersRequestArguments { info_hash }),
+            ResponseSender::Peers(tx),
+            None,
+            None,
         ));
 
-        GetIterator(rx.into_iter())
+        let mut peers = Vec::new();
+
+        while let Ok(response) = rx.recv_deadline(deadline) {
+            for peer in response {
+                if !peers.contains(&peer) {
+                    peers.push(peer);
+                }
+            }
+        }
+
+        Ok(peers)
     }
 
     /// Announce a peer for a given infohash.
@@ -245,31 +1264,83 @@ impl Dht {
     /// If explicit port is passed, it will be used, otherwise the port will be implicitly
     /// assumed by remote nodes to be the same ase port they received the request from.
     pub fn announce_peer(&self, info_hash: Id, port: Option<u16>) -> Result<Id, PutQueryError> {
-        let (port, implied_port) = match port {
-            Some(port) => (port, None),
-            None => (0, Some(true)),
-        };
+        self.announce_peer_report(info_hash, port)
+            .map(|report| report.info_hash)
+    }
 
-        self.put(
-            PutRequestSpecific::AnnouncePeer(AnnouncePeerRequestArguments {
-                info_hash,
-                port,
-                implied_port,
-            }),
-            None,
-        )
-        .map_err(|error| match error {
-            PutError::Query(error) => error,
-            PutError::Concurrency(_) => {
-                unreachable!("should not receive a concurrency error from announce peer query")
+    /// Same as [Self::announce_peer], but returns an [AnnounceReport]
+    /// with the nodes that accepted the announcement.
+    ///
+    /// Useful to check how many, and which, nodes acknowledged the announcement
+    /// before relying on it.
+    pub fn announce_peer_report(
+        &self,
+        info_hash: Id,
+        port: Option<u16>,
+    ) -> Result<AnnounceReport, PutQueryError> {
+        self.announce(info_hash, announce_peer_request(info_hash, port, false))
+    }
+
+    /// Same as [Self::announce_peer], but announces this peer as a seed,
+    /// so it is counted in the seeds bloom filter of [Self::scrape].
+    ///
+    /// Read [BEP_0033](https://www.bittorrent.org/beps/bep_0033.html) for more information.
+    pub fn announce_seed(&self, info_hash: Id, port: Option<u16>) -> Result<Id, PutQueryError> {
+        self.announce(info_hash, announce_peer_request(info_hash, port, true))
+            .map(|report| report.info_hash)
+    }
+
+    /// Scrape the closest nodes to `info_hash` for bloom filters of the seeds and
+    /// downloaders announced on it, and return their union.
+    ///
+    /// Useful to estimate the number of seeds and downloaders without
+    /// fetching all the peers, see [ScrapeResult::seeds_count] and [ScrapeResult::peers_count].
+    ///
+    /// Nodes that don't support [BEP_0033](https://www.bittorrent.org/beps/bep_0033.html) are ignored.
+    pub fn scrape(&self, info_hash: Id) -> ScrapeResult {
+        let receivers = self.send_requests(
+            self.find_node(info_hash).iter().map(|node| node.address()),
+            scrape_request(self.id(), info_hash),
+        );
+
+        let mut result = ScrapeResult::default();
+
+        for (_, rx) in receivers {
+            if let Ok(Ok(ResponseSpecific::Scrape(response))) = rx.recv() {
+                result.add(response);
             }
-        })
+        }
+
+        result
+    }
+
+    fn announce(
+        &self,
+        info_hash: Id,
+        request: PutRequestSpecific,
+    ) -> Result<AnnounceReport, PutQueryError> {
+        self.put_inner(request, None)
+            .recv()
+            .expect("Query was dropped before sending a response, please open an issue.")
+            .map(|report| AnnounceReport::new(info_hash, report))
+            .map_err(|error| match error {
+                PutError::Query(error) => error,
+                PutError::Concurrency(_) => {
+                    unreachable!("should not receive a concurrency error from announce peer query")
+                }
+            })
     }
 
     // === Immutable data ===
 
     /// Get an Immutable data by its sha1 hash.
+    ///
+    /// Immutable values are verified against their hash, so the query is cancelled
+    /// as soon as the first valid value is received, unless other callers are waiting on it.
     pub fn get_immutable(&self, target: Id) -> Option<Box<[u8]>> {
+        // Declared first, to be dropped after the receiver is disconnected.
+        let _cancel = self.cancel_on_drop(vec![target]);
+
         let (tx, rx) = flume::unbounded::<Box<[u8]>>();
         self.send(ActorMessage::Get(
             GetRequestSpecific::GetValue(GetValueRequestArguments {
@@ -278,20 +1349,73 @@ impl Dht {
                 salt: None,
             }),
             ResponseSender::Immutable(tx),
+            None,
+            None,
         ));
 
-        rx.recv().map(Some).unwrap_or(None)
+        self.recv_immutable(&rx)
     }
 
-    /// Put an immutable data to the DHT.
-    pub fn put_immutable(&self, value: &[u8]) -> Result<Id, PutQueryError> {
-        let target: Id = hash_immutable(value).into();
+    /// Same as [Self::get_immutable], but tells apart a value that wasn't found
+    /// after the query converged, from a query that timed out, see [ImmutableOutcome].
+    ///
+    /// Useful to decide whether or not retrying is worthwhile.
+    pub fn get_immutable_outcome(&self, target: Id) -> ImmutableOutcome {
+        let _cancel = self.cancel_on_drop(vec![target]);
 
-        self.put(
-            PutRequestSpecific::PutImmutable(PutImmutableRequestArguments {
+        let (tx, rx) = flume::unbounded::<Box<[u8]>>();
+        let timing = self.get_timed(
+            || {
+                GetRequestSpecific::GetValue(GetValueRequestArguments {
+                    target,
+                    seq: None,
+                    salt: None,
+                })
+            },
+            ResponseSender::Immutable(tx),
+        );
+
+        match self.recv_immutable(&rx) {
+            Some(value) => ImmutableOutcome::Found(value),
+            None => ImmutableOutcome::from_timing(
+                timing
+                    .recv()
+                    .expect("Query was dropped before sending a response, please open an issue."),
+            ),
+        }
+    }
+
+    /// Same as [Self::get_immutable], but overriding the [request timeout](DhtBuilder::request_timeout)
+    /// for this query only.
+    ///
+    /// If a query for the same `target` is already running, this call joins it,
+    /// and `request_timeout` is ignored.
+    pub fn get_immutable_with_timeout(
+        &self,
+        target: Id,
+        request_timeout: Duration,
+    ) -> Option<Box<[u8]>> {
+        let _cancel = self.cancel_on_drop(vec![target]);
+
+        let (tx, rx) = flume::unbounded::<Box<[u8]>>();
+        self.send(ActorMessage::Get(
+            GetRequestSpecific::GetValue(GetValueRequestArguments {
                 target,
-                v: value.into(),
+                seq: None,
+                salt: None,
             }),
+            ResponseSender::Immutable(tx),
+            Some(request_timeout),
+            None,
+        ));
+
+        self.recv_immutable(&rx)
+    }
+
+    /// Put an immutable data to the DHT.
+    pub fn put_immutable(&self, value: &[u8]) -> Result<Id, PutQueryError> {
+        self.put(
+            PutRequestSpecific::PutImmutable(self.put_immutable_request(value)),
             None,
         )
         .map_err(|error| match error {
@@ -302,14 +1426,121 @@ impl Dht {
         })
     }
 
-    // === Mutable data ===
+    /// Same as [Self::put_immutable], with [PutOptions], for example to skip storing
+    /// a value that is already stored at enough nodes.
+    pub fn put_immutable_with_options(
+        &self,
+        value: &[u8],
+        options: PutOptions,
+    ) -> Result<Id, PutQueryError> {
+        if options.skip_if_present {
+            let target = self.put_immutable_request(value).target;
+
+            let (tx, rx) = flume::unbounded::<Box<[u8]>>();
+            self.send(ActorMessage::Get(
+                GetRequestSpecific::GetValue(GetValueRequestArguments {
+                    target,
+                    seq: None,
+                    salt: None,
+                }),
+                ResponseSender::Immutable(tx),
+                None,
+                None,
+            ));
+
+            let min_present = options.min_present.max(1);
+
+            if rx.iter().take(min_present).count() >= min_present {
+                return Ok(target);
+            }
+        }
 
-    /// Get a mutable data by its `public_key` and optional `salt`.
+        self.put_immutable(value)
+    }
+
+    /// Put an immutable data to the DHT, and keep republishing it every `interval`
+    /// until the returned [RepublishHandle] is dropped.
     ///
-    /// You can ask for items `more_recent_than` than a certain `seq`,
-    /// usually one that you already have seen before, similar to `If-Modified-Since` header in HTTP.
+    /// Values expire from the nodes storing them after a while (usually 2 hours),
+    /// so `interval` should be shorter than that, and defaults to
+    /// [DhtBuilder::item_refresh_interval] if `None`.
     ///
-    /// # Order
+    /// Unlike [Self::put_immutable], this doesn't block, and errors are only logged.
+    pub fn put_immutable_repeating(
+        &self,
+        value: &[u8],
+        interval: Option<Duration>,
+    ) -> RepublishHandle {
+        self.republish(
+            PutRequestSpecific::PutImmutable(self.put_immutable_request(value)),
+            interval,
+        )
+    }
+
+    /// Put a `value` larger than [MAX_VALUE_SIZE] to the DHT, by encoding it with the
+    /// [ValueCodec], splitting the encoded value into chunks of up to [MAX_VALUE_SIZE] bytes,
+    /// each put as an immutable value as is, and putting a tagged manifest of the chunks [Id]s
+    /// as another immutable value.
+    ///
+    /// Returns the manifest's [Id], to get the value back with [Self::get_immutable_chunked].
+    ///
+    /// Chunks are put concurrently. Values whose encoding is larger than [MAX_IMMUTABLE_CHUNKS]
+    /// chunks are rejected with [PutQueryError::ValueTooLarge].
+    pub fn put_immutable_chunked(&self, value: &[u8]) -> Result<Id, PutQueryError> {
+        let encoded = self.encode_immutable(value);
+        check_chunked_size(&encoded)?;
+
+        let receivers = encoded
+            .chunks(MAX_VALUE_SIZE)
+            .map(|chunk| self.put_encoded_immutable(chunk))
+            .collect::<Vec<_>>();
+
+        let mut chunks = Vec::with_capacity(receivers.len());
+
+        for rx in receivers {
+            chunks.push(immutable_put_result(rx.recv().expect(
+                "Query was dropped before sending a response, please open an issue.",
+            ))?);
+        }
+
+        immutable_put_result(
+            self.put_encoded_immutable(&chunks_manifest(&chunks))
+                .recv()
+                .expect("Query was dropped before sending a response, please open an issue."),
+        )
+    }
+
+    /// Get a value put with [Self::put_immutable_chunked] by its manifest [Id].
+    ///
+    /// Chunks are requested concurrently, and `None` is returned if the manifest
+    /// or any of the chunks can't be found, or the value can't be decoded.
+    pub fn get_immutable_chunked(&self, manifest: Id) -> Option<Box<[u8]>> {
+        let chunks = parse_chunks_manifest(&self.get_encoded_immutable(manifest).recv().ok()?)?;
+
+        let _cancel = self.cancel_on_drop(chunks.clone());
+
+        let receivers = chunks
+            .iter()
+            .map(|target| self.get_encoded_immutable(*target))
+            .collect::<Vec<_>>();
+
+        let mut encoded = Vec::with_capacity(chunks.len() * MAX_VALUE_SIZE);
+
+        for rx in receivers {
+            encoded.extend_from_slice(&rx.recv().ok()?);
+        }
+
+        self.decode_immutable(&encoded)
+    }
+
+    // === Mutable data ===
+
+    /// Get a mutable data by its `public_key` and optional `salt`.
+    ///
+    /// You can ask for items `more_recent_than` than a certain `seq`,
+    /// usually one that you already have seen before, similar to `If-Modified-Since` header in HTTP.
+    ///
+    /// # Order
     ///
     /// The order of [MutableItem]s returned by this iterator is not guaranteed to
     /// reflect their `seq` value. You should not assume that the later items are
@@ -332,9 +1563,66 @@ impl Dht {
                 salt,
             }),
             ResponseSender::Mutable(tx),
+            None,
+            None,
+        ));
+
+        GetIterator::new(rx, self.cancel_on_drop(vec![target]))
+    }
+
+    /// Same as [Self::get_mutable], but also starting the lookup from `seed_nodes`,
+    /// see [Self::get_peers_with_seeds].
+    pub fn get_mutable_with_seeds(
+        &self,
+        public_key: &[u8; 32],
+        salt: Option<&[u8]>,
+        more_recent_than: Option<i64>,
+        seed_nodes: &[SocketAddr],
+    ) -> GetIterator<MutableItem> {
+        let salt = salt.map(|s| s.into());
+        let target = MutableItem::target_from_key(public_key, salt.as_deref());
+        let (tx, rx) = flume::unbounded::<MutableItem>();
+        self.send(ActorMessage::Get(
+            GetRequestSpecific::GetValue(GetValueRequestArguments {
+                target,
+                seq: more_recent_than,
+                salt,
+            }),
+            ResponseSender::Mutable(tx),
+            None,
+            Some(ipv4_addresses(seed_nodes).into()),
+        ));
+
+        GetIterator::new(rx, self.cancel_on_drop(vec![target]))
+    }
+
+    /// Same as [Self::get_mutable], but overriding the [request timeout](DhtBuilder::request_timeout)
+    /// for this query only.
+    ///
+    /// If a query for the same item is already running, this call joins it,
+    /// and `request_timeout` is ignored.
+    pub fn get_mutable_with_timeout(
+        &self,
+        public_key: &[u8; 32],
+        salt: Option<&[u8]>,
+        more_recent_than: Option<i64>,
+        request_timeout: Duration,
+    ) -> GetIterator<MutableItem> {
+        let salt = salt.map(|s| s.into());
+        let target = MutableItem::target_from_key(public_key, salt.as_deref());
+        let (tx, rx) = flume::unbounded::<MutableItem>();
+        self.send(ActorMessage::Get(
+            GetRequestSpecific::GetValue(GetValueRequestArguments {
+                target,
+                seq: more_recent_than,
+                salt,
+            }),
+            ResponseSender::Mutable(tx),
+            Some(request_timeout),
+            None,
         ));
 
-        GetIterator(rx.into_iter())
+        GetIterator::new(rx, self.cancel_on_drop(vec![target]))
     }
 
     /// Get the most recent [MutableItem] from the network.
@@ -346,18 +1634,111 @@ impl Dht {
         let mut most_recent: Option<MutableItem> = None;
         let iter = self.get_mutable(public_key, salt, None);
         for item in iter {
-            if let Some(mr) = &most_recent {
-                if item.seq() == mr.seq && item.value() > &mr.value {
-                    most_recent = Some(item)
-                }
-            } else {
-                most_recent = Some(item);
+            most_recent = Some(more_recent(most_recent, item));
+        }
+
+        most_recent
+    }
+
+    /// Same as [Self::get_mutable_most_recent], but returns as soon as an item
+    /// with a `seq` greater than or equal to a known `ceiling` is found, instead
+    /// of waiting for the query to be done.
+    pub fn get_mutable_most_recent_until(
+        &self,
+        public_key: &[u8; 32],
+        salt: Option<&[u8]>,
+        ceiling: i64,
+    ) -> Option<MutableItem> {
+        let mut most_recent: Option<MutableItem> = None;
+        let iter = self.get_mutable(public_key, salt, None);
+        for item in iter {
+            let item = more_recent(most_recent, item);
+            let done = item.seq() >= ceiling;
+
+            most_recent = Some(item);
+
+            if done {
+                break;
             }
         }
 
         most_recent
     }
 
+    /// Get the most recent [MutableItem] for each of the given `salts` under the same
+    /// `public_key`, querying all of them concurrently.
+    ///
+    /// Returns a map from each salt to its most recent item, once all queries are done.
+    /// Salts with no item found are missing from the map.
+    ///
+    /// # Salts
+    ///
+    /// The DHT stores mutable items at a target derived from the hash of the `public_key`
+    /// and the `salt`, so there is no way to enumerate all salts used under a key.
+    /// You need to keep track of the salts you use, and pass them here.
+    pub fn get_mutable_many(
+        &self,
+        public_key: &[u8; 32],
+        salts: &[&[u8]],
+    ) -> HashMap<Box<[u8]>, MutableItem> {
+        let (tx, rx) = flume::unbounded::<MutableItem>();
+
+        for salt in salts {
+            self.send(ActorMessage::Get(
+                get_mutable_request(public_key, Some(salt)),
+                ResponseSender::Mutable(tx.clone()),
+                None,
+                None,
+            ));
+        }
+        drop(tx);
+
+        let mut items = HashMap::new();
+        for item in rx.iter() {
+            insert_more_recent(&mut items, item);
+        }
+
+        items
+    }
+
+    /// Returns `true` if at least `quorum` of the closest nodes to the mutable item's target
+    /// responded without a value, and none of the responding nodes returned a [MutableItem].
+    ///
+    /// This is stronger than an empty [Self::get_mutable], which could just mean that the query
+    /// failed to reach the closest nodes, and is useful before claiming a `public_key` and `salt`.
+    ///
+    /// Note: this waits for the query to be done, and only counts the closest responding nodes
+    /// (usually 20), so a larger `quorum` is unlikely to be satisfied.
+    pub fn confirm_absent(
+        &self,
+        public_key: &[u8; 32],
+        salt: Option<&[u8]>,
+        quorum: usize,
+    ) -> bool {
+        let (items_tx, items_rx) = flume::unbounded::<MutableItem>();
+        self.send(ActorMessage::Get(
+            get_mutable_request(public_key, salt),
+            ResponseSender::Mutable(items_tx),
+            None,
+            None,
+        ));
+
+        // Joins the same query, to receive the closest responding nodes once it is done.
+        let (tx, rx) = flume::bounded::<Box<[Node]>>(1);
+        self.send(ActorMessage::Get(
+            get_mutable_request(public_key, salt),
+            ResponseSender::ClosestNodes(tx),
+            None,
+            None,
+        ));
+
+        let closest_nodes = rx
+            .recv()
+            .expect("Query was dropped before sending a response, please open an issue.");
+
+        items_rx.is_empty() && closest_nodes.len() >= quorum
+    }
+
     /// Put a mutable data to the DHT.
     ///
     /// # Lost Update Problem
@@ -417,6 +1798,102 @@ impl Dht {
         })
     }
 
+    /// Same as [Self::put_mutable], but only returns successfully once at least
+    /// `min_replicas` nodes acknowledged storing the `item`.
+    ///
+    /// Otherwise returns [PutQueryError::NotEnoughReplicas] with the number of nodes
+    /// that did store it, for durability sensitive data where you may want to retry
+    /// or [put][Self::put] to extra nodes.
+    ///
+    /// Values are sent to at most [k](DhtBuilder::k_factor) closest nodes (20 by default),
+    /// so a higher `min_replicas` can't be reached without extra nodes.
+    pub fn put_mutable_with_min_replicas(
+        &self,
+        item: MutableItem,
+        cas: Option<i64>,
+        min_replicas: usize,
+    ) -> Result<Id, PutMutableError> {
+        let request = PutRequestSpecific::PutMutable(PutMutableRequestArguments::from(item, cas));
+
+        let report = self
+            .put_inner(request, None)
+            .recv()
+            .expect("Query was dropped before sending a response, please open an issue.")?;
+
+        check_min_replicas(report, min_replicas)
+    }
+
+    /// Put a mutable data to the DHT, and keep republishing the same `item` every `interval`
+    /// until the returned [RepublishHandle] is dropped.
+    ///
+    /// Make sure to drop the handle before putting a more recent item for the same
+    /// key and salt, otherwise republishing the older item will keep failing.
+    ///
+    /// See [Self::put_immutable_repeating].
+    pub fn put_mutable_repeating(
+        &self,
+        item: MutableItem,
+        interval: Option<Duration>,
+    ) -> RepublishHandle {
+        self.republish(
+            PutRequestSpecific::PutMutable(PutMutableRequestArguments::from(item, None)),
+            interval,
+        )
+    }
+
+    /// Put a mutable data to the DHT, merging it with the most recent [MutableItem]
+    /// stored at the same `signer`'s public key and `salt`, if any.
+    ///
+    /// The `merge` closure receives the most recent item and the `value` you intended
+    /// to put, and returns the value to actually put, with a `seq` one higher than
+    /// the most recent item, using the most recent `seq` as a `CAS`.
+    ///
+    /// If the put fails with a [ConcurrencyError], because another writer sharing the
+    /// same key won the race, the most recent item is read again, merged, and the put
+    /// is retried, up to [MAX_MERGE_ATTEMPTS] times.
+    ///
+    /// Useful for CRDT-like records shared by multiple processes on a single mutable key.
+    pub fn put_mutable_merged(
+        &self,
+        signer: SigningKey,
+        salt: Option<&[u8]>,
+        value: &[u8],
+        merge: impl Fn(&MutableItem, &[u8]) -> Vec<u8>,
+    ) -> Result<Id, PutMutableError> {
+        let public_key = signer.verifying_key().to_bytes();
+
+        let mut attempts = 0;
+
+        loop {
+            attempts += 1;
+
+            let most_recent = self.get_mutable_most_recent(&public_key, salt);
+            let (item, cas) = merged_item(&signer, salt, value, most_recent, &merge)?;
+
+            match self.put_mutable(item, cas) {
+                Err(PutMutableError::Concurrency(_)) if attempts < MAX_MERGE_ATTEMPTS => {}
+                result => return result,
+            }
+        }
+    }
+
+    /// "Delete" a mutable item by publishing an empty value with `current_seq + 1`,
+    /// using `current_seq` as the `CAS` to avoid overwriting a concurrent update.
+    ///
+    /// The DHT has no true delete; this only publishes an empty tombstone that
+    /// supersedes the current value, until it expires like any other item.
+    pub fn clear_mutable(
+        &self,
+        signer: SigningKey,
+        salt: Option<&[u8]>,
+        current_seq: i64,
+    ) -> Result<Id, PutMutableError> {
+        self.put_mutable(
+            MutableItem::new(signer, &[], current_seq + 1, salt),
+            Some(current_seq),
+        )
+    }
+
     // === Raw ===
 
     /// Get closet nodes to a specific target, that support [BEP_0044](https://www.bittorrent.org/beps/bep_0044.html).
@@ -432,12 +1909,34 @@ impl Dht {
                 seq: None,
             }),
             ResponseSender::ClosestNodes(tx),
+            None,
+            None,
         ));
 
         rx.recv()
             .expect("Query was dropped before sending a response, please open an issue.")
     }
 
+    /// Estimate the storage pressure at the closest nodes to a `target`, before putting to it.
+    ///
+    /// Runs a probe query similar to [Self::get_closest_nodes], and returns the fraction
+    /// (between `0.0` and `1.0`) of the closest nodes that timed out or didn't respond with a token.
+    /// Overloaded nodes, that are likely full and evicting aggressively, tend to drop requests,
+    /// so this is a rough hint that a publisher may want to increase replication by putting to
+    /// [extra nodes][Self::put], or refresh more frequently.
+    ///
+    /// Returns `1.0` if no nodes were found at all.
+    pub fn estimate_storage_pressure(&self, target: Id) -> f64 {
+        self.get_closest_nodes(target);
+
+        let (tx, rx) = flume::bounded::<Option<f64>>(1);
+        self.send(ActorMessage::StoragePressure(target, tx));
+
+        rx.recv()
+            .expect("actor thread unexpectedly shutdown")
+            .unwrap_or(1.0)
+    }
+
     /// Send a PUT request to the closest nodes, and optionally some extra nodes.
     ///
     /// This is useful to put data to regions of the DHT other than the closest nodes
@@ -455,282 +1954,3285 @@ impl Dht {
         self.put_inner(request, extra_nodes)
             .recv()
             .expect("Query was dropped before sending a response, please open an issue.")
+            .map(|report| report.target)
     }
 
     /// Return the UdpSocket so it can be used externaly
     pub fn get_socket(&self) -> Arc<UdpSocket> {
         let (tx, rx) = flume::bounded(1);
-        self.0.send(ActorMessage::GetSocket(tx))
+        self.0
+            .send(ActorMessage::GetSocket(tx))
             .expect("actor thread unexpectedly shutdown");
-        rx.recv()
-            .expect("Failed to receive socket")
+        rx.recv().expect("Failed to receive socket")
     }
 
-    // === Private Methods ===
+    // === Debug ===
 
-    pub(crate) fn put_inner(
-        &self,
-        request: PutRequestSpecific,
-        extra_nodes: Option<Box<[Node]>>,
-    ) -> flume::Receiver<Result<Id, PutError>> {
-        let (tx, rx) = flume::bounded::<Result<Id, PutError>>(1);
-        self.send(ActorMessage::Put(request, tx, extra_nodes));
+    /// Subscribe to [DhtEvent]s reporting the progress of queries,
+    /// for example to show lookup progress in a UI.
+    ///
+    /// Events are only emitted for queries started through this [Dht]'s GET methods,
+    /// from the moment of subscription. The channel is unbounded, so make sure to keep
+    /// consuming it, or drop the receiver to unsubscribe.
+    pub fn subscribe_events(&self) -> Receiver<DhtEvent> {
+        let (tx, rx) = flume::unbounded::<DhtEvent>();
+        self.send(ActorMessage::SubscribeEvents(tx));
 
         rx
     }
 
-    pub(crate) fn send(&self, message: ActorMessage) {
-        self.0
-            .send(message)
-            .expect("actor thrread unexpectedly shutdown");
+    /// Same as [Self::find_node], plus the [QueryTiming] of the query.
+    pub fn find_node_timed(&self, target: Id) -> (Box<[Node]>, QueryTiming) {
+        let (tx, rx) = flume::bounded::<Box<[Node]>>(1);
+        let timing = self.get_timed(
+            || GetRequestSpecific::FindNode(FindNodeRequestArguments { target }),
+            ResponseSender::ClosestNodes(tx),
+        );
+
+        (
+            rx.recv()
+                .expect("Query was dropped before sending a response, please open an issue."),
+            timing
+                .recv()
+                .expect("Query was dropped before sending a response, please open an issue."),
+        )
     }
-}
 
-pub struct GetIterator<T>(flume::IntoIter<T>);
+    /// Same as [Self::get_peers], but waits for the query to be done, and returns
+    /// all unique peers, plus the [QueryTiming] of the query.
+    pub fn get_peers_timed(&self, info_hash: Id) -> (Vec<SocketAddrV4>, QueryTiming) {
+        let (tx, rx) = flume::unbounded::<Vec<SocketAddrV4>>();
+        let timing = self.get_timed(
+            || GetRequestSpecific::GetPeers(GetPeersRequestArguments { info_hash }),
+            ResponseSender::Peers(tx),
+        );
 
-impl<T> Iterator for GetIterator<T> {
-    type Item = T;
+        let mut peers = Vec::new();
+        for peer in rx.iter().flatten() {
+            if !peers.contains(&peer) {
+                peers.push(peer);
+            }
+        }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        self.0.next()
+        (
+            peers,
+            timing
+                .recv()
+                .expect("Query was dropped before sending a response, please open an issue."),
+        )
     }
-}
 
-fn run(config: Config, receiver: Receiver<ActorMessage>) {
-    match Rpc::new(config) {
-        Ok(mut rpc) => {
-            let address = rpc.local_addr();
-            info!(?address, "Mainline DHT listening");
+    /// Same as [Self::get_immutable], but waits for the query to be done,
+    /// and returns the [QueryTiming] of the query.
+    pub fn get_immutable_timed(&self, target: Id) -> (Option<Box<[u8]>>, QueryTiming) {
+        let (tx, rx) = flume::unbounded::<Box<[u8]>>();
+        let timing = self.get_timed(
+            || {
+                GetRequestSpecific::GetValue(GetValueRequestArguments {
+                    target,
+                    seq: None,
+                    salt: None,
+                })
+            },
+            ResponseSender::Immutable(tx),
+        );
 
-            let mut put_senders = HashMap::new();
-            let mut get_senders = HashMap::new();
+        (
+            self.recv_immutable(&rx),
+            timing
+                .recv()
+                .expect("Query was dropped before sending a response, please open an issue."),
+        )
+    }
 
-            loop {
-                match receiver.try_recv() {
-                    Ok(actor_message) => match actor_message {
-                        ActorMessage::GetSocket(sender) => {
-                            let socket = Arc::new(rpc.get_socket().get_socket().try_clone().unwrap());
-                            let _ = sender.send(socket);
-                        }
-                        ActorMessage::Check(sender) => {
-                            let _ = sender.send(Ok(()));
-                        }
-                        ActorMessage::Info(sender) => {
-                            let _ = sender.send(rpc.info());
-                        }
-                        ActorMessage::Put(request, sender, extra_nodes) => {
-                            let target = *request.target();
+    /// Same as [Self::get_mutable], but waits for the query to be done, and returns
+    /// all the [MutableItem]s, plus the [QueryTiming] of the query.
+    pub fn get_mutable_timed(
+        &self,
+        public_key: &[u8; 32],
+        salt: Option<&[u8]>,
+        more_recent_than: Option<i64>,
+    ) -> (Vec<MutableItem>, QueryTiming) {
+        let (tx, rx) = flume::unbounded::<MutableItem>();
+        let timing = self.get_timed(
+            || {
+                let mut request = get_mutable_request(public_key, salt);
+                if let GetRequestSpecific::GetValue(args) = &mut request {
+                    args.seq = more_recent_than;
+                }
+                request
+            },
+            ResponseSender::Mutable(tx),
+        );
 
-                            match rpc.put(request, extra_nodes) {
-                                Ok(()) => {
-                                    let senders = put_senders.entry(target).or_insert(vec![]);
+        (
+            rx.iter().collect(),
+            timing
+                .recv()
+                .expect("Query was dropped before sending a response, please open an issue."),
+        )
+    }
 
-                                    senders.push(sender);
-                                }
-                                Err(error) => {
-                                    let _ = sender.send(Err(error));
-                                }
-                            };
-                        }
-                        ActorMessage::Get(request, sender) => {
-                            let target = *request.target();
+    // === Private Methods ===
 
-                            if let Some(responses) = rpc.get(request, None) {
-                                for response in responses {
-                                    send(&sender, response);
-                                }
-                            };
+    /// The address to bind probing sockets to, on the same Ip as this node's socket.
+    fn probe_addr(&self, local_port: u16) -> SocketAddrV4 {
+        SocketAddrV4::new(*self.local_addr().ip(), local_port)
+    }
 
-                            let senders = get_senders.entry(target).or_insert(vec![]);
+    /// Returns true if the actor is [paused][Self::pause].
+    fn paused(&self) -> bool {
+        let (tx, rx) = flume::bounded::<bool>(1);
+        self.send(ActorMessage::Paused(tx));
 
-                            senders.push(sender);
-                        }
-                        ActorMessage::ToBootstrap(sender) => {
-                            let _ = sender.send(rpc.routing_table().to_bootstrap());
-                        }
-                    },
-                    Err(TryRecvError::Disconnected) => {
-                        // Node was dropped, kill this thread.
-                        tracing::debug!("mainline::Dht's actor thread was shutdown after Drop.");
-                        break;
-                    }
-                    Err(TryRecvError::Empty) => {
-                        // No op
-                    }
-                }
+        rx.recv().expect("actor thread unexpectedly shutdown")
+    }
 
-                let report = rpc.tick();
+    /// Register a PUT request with the actor to be republished every `interval`,
+    /// or every [DhtBuilder::item_refresh_interval] if `None`.
+    pub(crate) fn republish(
+        &self,
+        request: PutRequestSpecific,
+        interval: Option<Duration>,
+    ) -> RepublishHandle {
+        let target = *request.target();
+        let (tx, rx) = flume::bounded::<()>(1);
 
-                // Response for an ongoing GET query
-                if let Some((target, response)) = report.new_query_response {
-                    if let Some(senders) = get_senders.get(&target) {
-                        for sender in senders {
-                            send(sender, response.clone());
-                        }
-                    }
-                }
+        self.send(ActorMessage::Republish(request, interval, rx));
 
-                // Cleanup done GET queries
-                for (id, closest_nodes) in report.done_get_queries {
-                    if let Some(senders) = get_senders.remove(&id) {
-                        for sender in senders {
-                            // return closest_nodes to whoever was asking
-                            if let ResponseSender::ClosestNodes(sender) = sender {
-                                let _ = sender.send(closest_nodes.clone());
-                            }
-                        }
-                    }
-                }
+        RepublishHandle { target, _stop: tx }
+    }
 
-                // Cleanup done PUT query and send a resulting error if any.
-                for (id, error) in report.done_put_queries {
-                    if let Some(senders) = put_senders.remove(&id) {
-                        let result = if let Some(error) = error {
-                            Err(error)
-                        } else {
-                            Ok(id)
-                        };
+    /// Send a GET query, and join it to receive its [QueryTiming] once it is done.
+    pub(crate) fn get_timed(
+        &self,
+        request: impl Fn() -> GetRequestSpecific,
+        sender: ResponseSender,
+    ) -> flume::Receiver<QueryTiming> {
+        self.send(ActorMessage::Get(request(), sender, None, None));
 
-                        for sender in senders {
-                            let _ = sender.send(result.clone());
-                        }
-                    }
-                }
-            }
+        let (tx, rx) = flume::bounded::<QueryTiming>(1);
+        self.send(ActorMessage::Get(
+            request(),
+            ResponseSender::Timing(tx),
+            None,
+            None,
+        ));
+
+        rx
+    }
+
+    pub(crate) fn put_inner(
+        &self,
+        request: PutRequestSpecific,
+        extra_nodes: Option<Box<[Node]>>,
+    ) -> flume::Receiver<Result<PutReport, PutError>> {
+        let (tx, rx) = flume::bounded::<Result<PutReport, PutError>>(1);
+        self.send(ActorMessage::Put(request, tx, extra_nodes));
+
+        rx
+    }
+
+    /// Encode an immutable `value` with the [ValueCodec], and return
+    /// the arguments to put the encoded value at its target.
+    pub(crate) fn put_immutable_request(&self, value: &[u8]) -> PutImmutableRequestArguments {
+        encoded_immutable_request(&self.encode_immutable(value))
+    }
+
+    /// Encode an immutable value with the [ValueCodec].
+    pub(crate) fn encode_immutable(&self, value: &[u8]) -> Box<[u8]> {
+        self.1.encode(value)
+    }
+
+    /// Put an already `encoded` immutable value as is, bypassing the [ValueCodec].
+    pub(crate) fn put_encoded_immutable(
+        &self,
+        encoded: &[u8],
+    ) -> flume::Receiver<Result<PutReport, PutError>> {
+        self.put_inner(
+            PutRequestSpecific::PutImmutable(encoded_immutable_request(encoded)),
+            None,
+        )
+    }
+
+    /// Get the immutable values at `target` as they are stored, without decoding them.
+    pub(crate) fn get_encoded_immutable(&self, target: Id) -> flume::Receiver<Box<[u8]>> {
+        let (tx, rx) = flume::unbounded::<Box<[u8]>>();
+        self.send(ActorMessage::Get(
+            GetRequestSpecific::GetValue(GetValueRequestArguments {
+                target,
+                seq: None,
+                salt: None,
+            }),
+            ResponseSender::Immutable(tx),
+            None,
+            None,
+        ));
+
+        rx
+    }
+
+    /// Decode an immutable value with the [ValueCodec].
+    pub(crate) fn decode_immutable(&self, encoded: &[u8]) -> Option<Box<[u8]>> {
+        self.1.decode(encoded)
+    }
+
+    /// Return the first immutable value received that the [ValueCodec] can decode.
+    fn recv_immutable(&self, rx: &Receiver<Box<[u8]>>) -> Option<Box<[u8]>> {
+        rx.iter()
+            .find_map(|encoded| self.decode_immutable(&encoded))
+    }
+
+    pub(crate) fn send(&self, message: ActorMessage) {
+        self.0
+            .send(message)
+            .expect("actor thrread unexpectedly shutdown");
+    }
+
+    /// Cancel the queries for `targets` once the returned guard is dropped,
+    /// without keeping the actor alive.
+    pub(crate) fn cancel_on_drop(&self, targets: Vec<Id>) -> CancelOnDrop {
+        CancelOnDrop {
+            sender: self.0.downgrade(),
+            targets,
         }
-        Err(err) => {
-            if let Ok(ActorMessage::Check(sender)) = receiver.try_recv() {
-                let _ = sender.send(Err(err));
+    }
+}
+
+/// Returns the more recent of the `current` most recent item and a new `item`,
+/// by `seq`, breaking ties with the lexicographically greater value.
+pub(crate) fn more_recent(current: Option<MutableItem>, item: MutableItem) -> MutableItem {
+    match current {
+        Some(current)
+            if current.seq() > item.seq()
+                || (current.seq() == item.seq() && current.value() >= item.value()) =>
+        {
+            current
+        }
+        _ => item,
+    }
+}
+
+/// Keep the more recent of `item` and the item already stored under the same salt.
+pub(crate) fn insert_more_recent(items: &mut HashMap<Box<[u8]>, MutableItem>, item: MutableItem) {
+    let salt: Box<[u8]> = item.salt().unwrap_or_default().into();
+    let previous = items.remove(&salt);
+
+    items.insert(salt, more_recent(previous, item));
+}
+
+/// Create the [MutableItem] (and its `CAS`) to put in [Dht::put_mutable_merged].
+pub(crate) fn merged_item(
+    signer: &SigningKey,
+    salt: Option<&[u8]>,
+    value: &[u8],
+    most_recent: Option<MutableItem>,
+    merge: impl Fn(&MutableItem, &[u8]) -> Vec<u8>,
+) -> Result<(MutableItem, Option<i64>), PutQueryError> {
+    match most_recent {
+        Some(most_recent) => {
+            let seq = most_recent
+                .seq()
+                .checked_add(1)
+                .ok_or(PutQueryError::SeqOverflow)?;
+
+            Ok((
+                MutableItem::new(signer.clone(), &merge(&most_recent, value), seq, salt),
+                Some(most_recent.seq()),
+            ))
+        }
+        None => Ok((MutableItem::new(signer.clone(), value, 1, salt), None)),
+    }
+}
+
+/// The arguments to put an already `encoded` immutable value at its target.
+fn encoded_immutable_request(encoded: &[u8]) -> PutImmutableRequestArguments {
+    PutImmutableRequestArguments {
+        target: hash_immutable(encoded).into(),
+        v: encoded.into(),
+    }
+}
+
+/// The target of a done immutable PUT query.
+pub(crate) fn immutable_put_result(
+    result: Result<PutReport, PutError>,
+) -> Result<Id, PutQueryError> {
+    match result {
+        Ok(report) => Ok(report.target),
+        Err(PutError::Query(error)) => Err(error),
+        Err(PutError::Concurrency(_)) => {
+            unreachable!("should not receive a concurrency error from put immutable query")
+        }
+    }
+}
+
+/// Reject encoded values too large for [Dht::put_immutable_chunked].
+pub(crate) fn check_chunked_size(value: &[u8]) -> Result<(), PutQueryError> {
+    let max = MAX_IMMUTABLE_CHUNKS * MAX_VALUE_SIZE;
+
+    if value.len() > max {
+        return Err(PutQueryError::ValueTooLarge {
+            len: value.len(),
+            max,
+        });
+    }
+
+    Ok(())
+}
+
+/// Encode the manifest of a chunked immutable value, as the [CHUNKS_MANIFEST_TAG]
+/// followed by the concatenated chunk [Id]s.
+pub(crate) fn chunks_manifest(chunks: &[Id]) -> Vec<u8> {
+    CHUNKS_MANIFEST_TAG
+        .iter()
+        .copied()
+        .chain(chunks.iter().flat_map(|id| *id.as_bytes()))
+        .collect()
+}
+
+/// Decode the chunk [Id]s of a manifest encoded with [chunks_manifest].
+pub(crate) fn parse_chunks_manifest(manifest: &[u8]) -> Option<Vec<Id>> {
+    let manifest = manifest.strip_prefix(CHUNKS_MANIFEST_TAG)?;
+
+    if !manifest.len().is_multiple_of(ID_SIZE) || manifest.len() > MAX_IMMUTABLE_CHUNKS * ID_SIZE {
+        return None;
+    }
+
+    manifest
+        .chunks_exact(ID_SIZE)
+        .map(|bytes| Id::from_bytes(bytes).ok())
+        .collect()
+}
+
+/// Number of consecutive lookups in [Dht::find_nodes_in_prefix] that find
+/// no new nodes before giving up.
+pub const PREFIX_DISCOVERY_IDLE_ROUNDS: usize = 3;
+
+/// Nodes collected so far in [Dht::find_nodes_in_prefix].
+pub(crate) struct PrefixNodes {
+    prefix_bits: u8,
+    prefix: Id,
+    max: usize,
+    nodes: Vec<Node>,
+    idle_rounds: usize,
+}
+
+impl PrefixNodes {
+    pub(crate) fn new(prefix_bits: u8, prefix: Id, max: usize) -> Self {
+        Self {
+            prefix_bits,
+            prefix,
+            max,
+            nodes: Vec::new(),
+            idle_rounds: 0,
+        }
+    }
+
+    pub(crate) fn next_target(&self) -> Id {
+        Id::random_with_prefix(&self.prefix, self.prefix_bits)
+    }
+
+    pub(crate) fn add(&mut self, closest_nodes: &[Node]) {
+        let before = self.nodes.len();
+
+        for node in closest_nodes {
+            if self.nodes.len() >= self.max {
+                break;
+            }
+
+            if node.id().has_prefix(&self.prefix, self.prefix_bits)
+                && !self.nodes.iter().any(|existing| existing.id() == node.id())
+            {
+                self.nodes.push(node.clone());
             }
         }
+
+        if self.nodes.len() == before {
+            self.idle_rounds += 1;
+        } else {
+            self.idle_rounds = 0;
+        }
+    }
+
+    pub(crate) fn done(&self) -> bool {
+        self.nodes.len() >= self.max || self.idle_rounds >= PREFIX_DISCOVERY_IDLE_ROUNDS
+    }
+
+    pub(crate) fn into_nodes(self) -> Vec<Node> {
+        self.nodes
+    }
+}
+
+/// Create the [GetRequestSpecific::GetValue] request for a mutable item.
+pub(crate) fn get_mutable_request(
+    public_key: &[u8; 32],
+    salt: Option<&[u8]>,
+) -> GetRequestSpecific {
+    let salt: Option<Box<[u8]>> = salt.map(|s| s.into());
+    let target = MutableItem::target_from_key(public_key, salt.as_deref());
+
+    GetRequestSpecific::GetValue(GetValueRequestArguments {
+        target,
+        seq: None,
+        salt,
+    })
+}
+
+/// Create the [PutRequestSpecific::AnnouncePeer] request for [Dht::announce_peer].
+pub(crate) fn announce_peer_request(
+    info_hash: Id,
+    port: Option<u16>,
+    seed: bool,
+) -> PutRequestSpecific {
+    let (port, implied_port) = match port {
+        Some(port) => (port, None),
+        None => (0, Some(true)),
     };
+
+    PutRequestSpecific::AnnouncePeer(AnnouncePeerRequestArguments {
+        info_hash,
+        port,
+        implied_port,
+        seed: seed.then_some(true),
+    })
 }
 
-fn send(sender: &ResponseSender, response: Response) {
-    match (sender, response) {
-        (ResponseSender::Peers(s), Response::Peers(r)) => {
-            let _ = s.send(r);
+/// Pair each of the `nodes` with its XOR distance to `target`, sorted ascending by distance.
+pub(crate) fn with_distance(target: Id, nodes: &[Node]) -> Vec<(Node, Id)> {
+    let mut nodes = nodes
+        .iter()
+        .map(|node| (node.clone(), target.xor(node.id())))
+        .collect::<Vec<_>>();
+
+    nodes.sort_by_key(|(_, distance)| *distance);
+
+    nodes
+}
+
+pub(crate) type RequestReceiver = Receiver<Result<ResponseSpecific, RequestError>>;
+
+pub(crate) fn ipv4_addresses(addresses: &[SocketAddr]) -> Vec<SocketAddrV4> {
+    addresses
+        .iter()
+        .filter_map(|address| match address {
+            SocketAddr::V4(address) => Some(*address),
+            SocketAddr::V6(_) => None,
+        })
+        .collect()
+}
+
+pub(crate) fn get_peers_request(requester_id: Id, info_hash: Id) -> RequestSpecific {
+    RequestSpecific {
+        requester_id,
+        request_type: RequestTypeSpecific::GetPeers(GetPeersRequestArguments { info_hash }),
+    }
+}
+
+/// Convert a direct get_peers response from `address` to a [PeersResponse].
+pub(crate) fn peers_response(
+    address: SocketAddrV4,
+    response: ResponseSpecific,
+) -> Option<PeersResponse> {
+    match response {
+        ResponseSpecific::GetPeers(GetPeersResponseArguments {
+            responder_id,
+            values,
+            ..
+        }) => Some(PeersResponse {
+            from: Node::new(responder_id, address),
+            peers: values,
+        }),
+        ResponseSpecific::NoValues(NoValuesResponseArguments { responder_id, .. }) => {
+            Some(PeersResponse {
+                from: Node::new(responder_id, address),
+                peers: vec![],
+            })
         }
-        (ResponseSender::Mutable(s), Response::Mutable(r)) => {
-            let _ = s.send(r);
+        _ => None,
+    }
+}
+
+pub(crate) fn sample_infohashes_request(requester_id: Id, target: Id) -> RequestSpecific {
+    RequestSpecific {
+        requester_id,
+        request_type: RequestTypeSpecific::SampleInfohashes(SampleInfohashesRequestArguments {
+            target,
+        }),
+    }
+}
+
+pub(crate) fn scrape_request(requester_id: Id, info_hash: Id) -> RequestSpecific {
+    RequestSpecific {
+        requester_id,
+        request_type: RequestTypeSpecific::Scrape(ScrapeRequestArguments { info_hash }),
+    }
+}
+
+/// Options for [Dht::put_immutable_with_options].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PutOptions {
+    /// Look the value up first, and skip storing it if at least [Self::min_present]
+    /// nodes already have it.
+    ///
+    /// Defaults to false, since republishing is sometimes desired to refresh
+    /// the value's expiry at the nodes storing it.
+    pub skip_if_present: bool,
+    /// Minimum number of nodes returning the value to consider it present.
+    ///
+    /// Defaults to [DEFAULT_MIN_PRESENT].
+    pub min_present: usize,
+}
+
+/// Default [PutOptions::min_present].
+pub const DEFAULT_MIN_PRESENT: usize = 3;
+
+impl Default for PutOptions {
+    fn default() -> Self {
+        Self {
+            skip_if_present: false,
+            min_present: DEFAULT_MIN_PRESENT,
         }
-        (ResponseSender::Immutable(s), Response::Immutable(r)) => {
-            let _ = s.send(r);
+    }
+}
+
+/// The result of [Dht::sample_infohashes].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InfohashSamples {
+    /// Distinct info hashes sampled from all responding nodes.
+    pub samples: Vec<Id>,
+    /// Suggested interval before requesting new samples from the same nodes,
+    /// the longest interval requested by any of the responding nodes.
+    pub interval: Duration,
+    /// Total number of info hashes the responding nodes claim to store.
+    pub num: usize,
+    /// Number of nodes that responded with samples.
+    pub responders: usize,
+}
+
+impl InfohashSamples {
+    pub(crate) fn add(&mut self, response: SampleInfohashesResponseArguments) {
+        for info_hash in response.samples.iter() {
+            if !self.samples.contains(info_hash) {
+                self.samples.push(*info_hash);
+            }
         }
-        _ => {}
+
+        self.interval = self
+            .interval
+            .max(Duration::from_secs(response.interval.max(0) as u64));
+        self.num += response.num.max(0) as usize;
+        self.responders += 1;
+    }
+}
+
+/// A response to a request of a [Dht::get_raw] query, before any processing.
+#[derive(Debug, Clone)]
+pub struct RawResponse {
+    /// The target of the query.
+    pub target: Id,
+    /// The node that responded, with its [token](Node::token) if any.
+    pub from: Node,
+    /// The response as received from [Self::from].
+    pub response: ResponseSpecific,
+}
+
+/// The result of [Dht::scrape].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScrapeResult {
+    /// Union of the seeds bloom filters (`BFsd`) of all responding nodes.
+    pub seeds: BloomFilter,
+    /// Union of the downloaders bloom filters (`BFpe`) of all responding nodes.
+    pub peers: BloomFilter,
+    /// Number of nodes that responded with bloom filters.
+    pub responders: usize,
+}
+
+impl ScrapeResult {
+    /// Estimated number of seeds.
+    pub fn seeds_count(&self) -> usize {
+        self.seeds.estimate_count()
+    }
+
+    /// Estimated number of downloaders.
+    pub fn peers_count(&self) -> usize {
+        self.peers.estimate_count()
+    }
+
+    pub(crate) fn add(&mut self, response: ScrapeResponseArguments) {
+        self.seeds.union(&response.seeds);
+        self.peers.union(&response.peers);
+        self.responders += 1;
     }
 }
 
+/// The result of [Dht::announce_peer_report].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnnounceReport {
+    /// The info hash that was announced.
+    pub info_hash: Id,
+    /// Addresses of the nodes that confirmed storing the announcement.
+    pub announced_on: Vec<SocketAddrV4>,
+    /// Nodes that confirmed storing the announcement, including their ids.
+    pub stored_on: Vec<Node>,
+    /// Number of nodes the announcement was sent to.
+    pub queried: usize,
+}
+
+impl AnnounceReport {
+    pub(crate) fn new(info_hash: Id, report: PutReport) -> Self {
+        Self {
+            info_hash,
+            announced_on: report.stored_at.iter().map(|node| node.address()).collect(),
+            stored_on: report.stored_at.into(),
+            queried: report.queried,
+        }
+    }
+}
+
+/// Keeps republishing a value, see [Dht::put_immutable_repeating] and [Dht::put_mutable_repeating].
+///
+/// Republishing stops once this handle is dropped.
 #[derive(Debug)]
-pub(crate) enum ActorMessage {
-    Info(Sender<Info>),
-    Put(
-        PutRequestSpecific,
-        Sender<Result<Id, PutError>>,
-        Option<Box<[Node]>>,
-    ),
-    Get(GetRequestSpecific, ResponseSender),
-    Check(Sender<Result<(), std::io::Error>>),
-    ToBootstrap(Sender<Vec<String>>),
-    GetSocket(Sender<Arc<UdpSocket>>),
+pub struct RepublishHandle {
+    target: Id,
+    /// Disconnects the actor's receiver when dropped.
+    _stop: Sender<()>,
+}
+
+impl RepublishHandle {
+    /// Returns the target of the republished value.
+    pub fn target(&self) -> &Id {
+        &self.target
+    }
+}
+
+/// A PUT request republished by the actor until its [RepublishHandle] is dropped.
+struct Republish {
+    request: PutRequestSpecific,
+    interval: Duration,
+    last_published: Instant,
+    stop: Receiver<()>,
+}
+
+/// The terminal condition of [Dht::get_immutable_outcome].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImmutableOutcome {
+    /// The value was found.
+    Found(Box<[u8]>),
+    /// The query converged without any node returning the value,
+    /// so it most likely doesn't exist, or expired.
+    NotFound {
+        /// Number of nodes the query sent requests to.
+        nodes_queried: usize,
+    },
+    /// No node responded, or the query exceeded the
+    /// [maximum query lifetime](DhtBuilder::max_query_lifetime) before converging,
+    /// so retrying might still find the value.
+    TimedOut,
+}
+
+impl ImmutableOutcome {
+    /// The outcome of a query that didn't find the value.
+    pub(crate) fn from_timing(timing: QueryTiming) -> Self {
+        if timing.timed_out || timing.time_to_first_response.is_none() {
+            ImmutableOutcome::TimedOut
+        } else {
+            ImmutableOutcome::NotFound {
+                nodes_queried: timing.nodes_contacted,
+            }
+        }
+    }
+}
+
+/// Progress of GET queries, see [Dht::subscribe_events].
+#[derive(Debug, Clone, PartialEq)]
+pub enum DhtEvent {
+    /// A new query started looking up the `target`.
+    QueryStarted {
+        /// Target of the query.
+        target: Id,
+    },
+    /// A node responded to one of the query's requests.
+    NodeResponded {
+        /// Target of the query.
+        target: Id,
+        /// The responding node.
+        node: Node,
+    },
+    /// The query is done.
+    QueryCompleted {
+        /// Target of the query.
+        target: Id,
+        /// Number of distinct nodes the query sent requests to.
+        nodes_contacted: usize,
+    },
+}
+
+/// Number of recently seen nodes probed by [Dht::check_reachability].
+const REACHABILITY_CHECK_NODES: usize = 3;
+
+/// The result of [Dht::probe_from_port].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Observed {
+    /// The local address of the socket the probe was sent from.
+    pub local_addr: SocketAddrV4,
+    /// The address of the probed node.
+    pub target: SocketAddrV4,
+    /// Our address as observed by the probed node, or `None` if it didn't
+    /// respond in time, or didn't include it in the response.
+    pub address: Option<SocketAddrV4>,
+}
+
+/// The result of [Dht::check_reachability].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReachabilityReport {
+    /// Our public address, see [Info::public_address], or if no responding nodes
+    /// voted on it yet, the Ip most commonly observed by the probed nodes, with our local port.
+    pub public_address: Option<SocketAddrV4>,
+    /// Addresses of the probing socket, as observed by each probed node that responded.
+    pub observed: Vec<SocketAddrV4>,
+    /// Whether or not a ping sent from another socket to [Self::public_address] reached this node.
+    pub reachable: bool,
+}
+
+/// An iterator of incoming peers or mutable values.
+///
+/// Dropping it (or calling [Self::cancel]) before it ends cancels its query, unless
+/// other iterators are still waiting for the same target.
+pub struct GetIterator<T> {
+    // Dropped before `cancel`, so the actor sees this receiver as disconnected.
+    inner: flume::IntoIter<T>,
+    _cancel: CancelOnDrop,
+}
+
+impl<T> GetIterator<T> {
+    pub(crate) fn new(rx: Receiver<T>, cancel: CancelOnDrop) -> Self {
+        Self {
+            inner: rx.into_iter(),
+            _cancel: cancel,
+        }
+    }
+
+    /// Stop waiting for responses, and cancel the query if no one else is waiting for it.
+    ///
+    /// Same as dropping this iterator.
+    pub fn cancel(self) {}
+}
+
+impl<T> Iterator for GetIterator<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+}
+
+/// Sends [ActorMessage::Cancel] for each of its targets when dropped.
+#[derive(Debug)]
+pub(crate) struct CancelOnDrop {
+    sender: WeakSender<ActorMessage>,
+    targets: Vec<Id>,
+}
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        if let Some(sender) = self.sender.upgrade() {
+            for target in self.targets.drain(..) {
+                let _ = sender.send(ActorMessage::Cancel(target));
+            }
+        }
+    }
 }
 
-#[derive(Debug, Clone)]
-pub enum ResponseSender {
-    ClosestNodes(Sender<Box<[Node]>>),
-    Peers(Sender<Vec<SocketAddrV4>>),
-    Mutable(Sender<MutableItem>),
-    Immutable(Sender<Box<[u8]>>),
-}
+fn run(config: Config, receiver: Receiver<ActorMessage>) {
+    match Rpc::new(config) {
+        Ok(mut rpc) => {
+            let address = rpc.local_addr();
+            info!(?address, "Mainline DHT listening");
+
+            let mut put_senders = HashMap::new();
+            let mut get_senders = HashMap::new();
+            let mut ping_senders: HashMap<u16, (Instant, Sender<Option<Duration>>)> =
+                HashMap::new();
+            let mut request_senders: HashMap<u16, Sender<Result<ResponseSpecific, RequestError>>> =
+                HashMap::new();
+            let mut event_senders: Vec<Sender<DhtEvent>> = Vec::new();
+            let mut bootstrapped_senders: Vec<(usize, Instant, Sender<bool>)> = Vec::new();
+            let mut republishing: Vec<Republish> = Vec::new();
+
+            let mut paused = false;
+            // Queries received while paused, to be handled after resuming.
+            let mut pending = VecDeque::new();
+            // Whether an external IP change is waiting to be confirmed after resuming.
+            let mut unconfirmed_external_ip = false;
+
+            loop {
+                let next_message = if paused || pending.is_empty() {
+                    receiver.try_recv()
+                } else {
+                    Ok(pending.pop_front().expect("pending is not empty"))
+                };
+
+                match next_message {
+                    Ok(actor_message) => match actor_message {
+                        ActorMessage::Pause => {
+                            paused = true;
+                        }
+                        ActorMessage::Paused(sender) => {
+                            let _ = sender.send(paused);
+                        }
+                        ActorMessage::Resume => {
+                            paused = false;
+
+                            if std::mem::take(&mut unconfirmed_external_ip) {
+                                rpc.confirm_external_ip();
+                            }
+                        }
+                        message @ (ActorMessage::Put(..)
+                        | ActorMessage::Get(..)
+                        | ActorMessage::Cancel(..)
+                        | ActorMessage::Ping(..)
+                        | ActorMessage::Request(..)
+                        | ActorMessage::Republish(..))
+                            if paused =>
+                        {
+                            pending.push_back(message);
+                        }
+                        ActorMessage::GetSocket(sender) => {
+                            let socket = Arc::new(
+                                rpc.get_socket()
+                                    .get_socket()
+                                    .try_clone()
+                                    .expect("failed to clone the UdpSocket"),
+                            );
+                            let _ = sender.send(socket);
+                        }
+                        ActorMessage::Check(sender) => {
+                            let _ = sender.send(Ok(address));
+                        }
+                        ActorMessage::Info(sender) => {
+                            let _ = sender.send(rpc.info());
+                        }
+                        ActorMessage::Id(sender) => {
+                            let _ = sender.send(*rpc.id());
+                        }
+                        ActorMessage::Put(request, sender, extra_nodes) => {
+                            let target = *request.target();
+
+                            match rpc.put(request, extra_nodes) {
+                                Ok(()) => {
+                                    let senders = put_senders.entry(target).or_insert(vec![]);
+
+                                    senders.push(sender);
+                                }
+                                Err(error) => {
+                                    let _ = sender.send(Err(error));
+                                }
+                            };
+                        }
+                        ActorMessage::Get(request, sender, request_timeout, seed_nodes) => {
+                            let target = *request.target();
+
+                            let cached_value = match &sender {
+                                ResponseSender::Immutable(_) => rpc.cached_immutable(&target),
+                                _ => None,
+                            };
+
+                            if let (Some(value), ResponseSender::Immutable(immutable_sender)) =
+                                (cached_value, &sender)
+                            {
+                                let _ = immutable_sender.send(value);
+                            } else {
+                                if !get_senders.contains_key(&target) {
+                                    emit(&mut event_senders, DhtEvent::QueryStarted { target });
+                                }
+
+                                let mut sender = sender;
+
+                                if let Some(responses) = rpc.get_with_timeout(
+                                    request,
+                                    seed_nodes.as_deref(),
+                                    request_timeout,
+                                ) {
+                                    for response in responses {
+                                        send(&mut sender, response);
+                                    }
+                                };
+
+                                let senders = get_senders.entry(target).or_insert(vec![]);
+
+                                senders.push(sender);
+                            }
+                        }
+                        ActorMessage::Cancel(target) => {
+                            if let Some(senders) = get_senders.get_mut(&target) {
+                                senders.retain(|sender| !sender.is_disconnected());
+
+                                if senders.is_empty() {
+                                    get_senders.remove(&target);
+                                    rpc.cancel(&target);
+                                }
+                            }
+                        }
+                        ActorMessage::Ban(range) => {
+                            rpc.ban(range);
+                        }
+                        ActorMessage::Unban(range) => {
+                            rpc.unban(&range);
+                        }
+                        ActorMessage::SetExternalIp(ip, sender) => {
+                            let _ = sender.send(rpc.update_external_ip(ip));
+
+                            if paused {
+                                unconfirmed_external_ip = true;
+                            } else {
+                                rpc.confirm_external_ip();
+                            }
+                        }
+                        ActorMessage::ExpectProbe(probe_id, sender) => {
+                            rpc.expect_probe(probe_id);
+                            let _ = sender.send(());
+                        }
+                        ActorMessage::ProbeReceived(probe_id, sender) => {
+                            let _ = sender.send(rpc.probe_received(&probe_id));
+                        }
+                        ActorMessage::ToBootstrap(sender) => {
+                            let _ = sender.send(rpc.routing_table().to_bootstrap());
+                        }
+                        ActorMessage::Ping(address, sender) => {
+                            let tid = rpc.ping(address);
+                            ping_senders.insert(tid, (Instant::now(), sender));
+                        }
+                        ActorMessage::Request(address, request, sender) => {
+                            let tid = rpc.request(address, request);
+                            request_senders.insert(tid, sender);
+                        }
+                        ActorMessage::RoutingTable(sender) => {
+                            let _ = sender.send(rpc.routing_table().clone());
+                        }
+                        ActorMessage::DebugBuckets(sender) => {
+                            let _ = sender.send(rpc.routing_table().buckets_info());
+                        }
+                        ActorMessage::StoragePressure(target, sender) => {
+                            let _ = sender.send(rpc.storage_pressure(&target));
+                        }
+                        ActorMessage::SubscribeEvents(sender) => {
+                            event_senders.push(sender);
+                        }
+                        ActorMessage::ServerStats(top_n, sender) => {
+                            let _ = sender.send(rpc.server_stats(top_n));
+                        }
+                        ActorMessage::BootstrapStatus(sender) => {
+                            let _ = sender.send(rpc.bootstrap_status());
+                        }
+                        ActorMessage::DhtSizeEstimateHistory(n, sender) => {
+                            let _ = sender.send(rpc.dht_size_estimate_history(n));
+                        }
+                        ActorMessage::Bootstrapped(min_nodes, deadline, sender) => {
+                            bootstrapped_senders.push((min_nodes, deadline, sender));
+                        }
+                        ActorMessage::Republish(request, interval, stop) => {
+                            if let Err(error) = rpc.put(request.clone(), None) {
+                                debug!(?error, "Failed to republish");
+                            }
+
+                            republishing.push(Republish {
+                                request,
+                                interval: interval.unwrap_or(rpc.item_refresh_interval()),
+                                last_published: Instant::now(),
+                                stop,
+                            });
+                        }
+                    },
+                    Err(TryRecvError::Disconnected) => {
+                        // Node was dropped, kill this thread.
+                        tracing::debug!("mainline::Dht's actor thread was shutdown after Drop.");
+                        break;
+                    }
+                    Err(TryRecvError::Empty) => {
+                        // No op
+                    }
+                }
+
+                if paused {
+                    thread::sleep(PAUSED_POLL_INTERVAL);
+
+                    continue;
+                }
+
+                let report = rpc.tick();
+
+                // Response for an ongoing GET query
+                if let Some((target, response)) = report.new_query_response {
+                    if let Some(senders) = get_senders.get_mut(&target) {
+                        for sender in senders {
+                            send(sender, response.clone());
+                        }
+                    }
+                }
+
+                if let Some((target, from, response)) = report.new_query_raw_response {
+                    if let Some(senders) = get_senders.get(&target) {
+                        for sender in senders {
+                            if let ResponseSender::Raw(s) = sender {
+                                let _ = s.send(RawResponse {
+                                    target,
+                                    from: from.clone(),
+                                    response: response.clone(),
+                                });
+                            }
+                        }
+                    }
+                }
+
+                if let Some((target, node)) = report.new_query_responder {
+                    if get_senders.contains_key(&target) {
+                        emit(&mut event_senders, DhtEvent::NodeResponded { target, node });
+                    }
+                }
+
+                // Response to a raw request
+                if let Some((tid, _, message_type)) = &report.new_direct_response {
+                    if let Some(sender) = request_senders.remove(tid) {
+                        let _ = sender.send(match message_type {
+                            MessageType::Response(response) => Ok(response.clone()),
+                            MessageType::Error(error) => {
+                                Err(RequestError::ErrorResponse(error.clone()))
+                            }
+                            MessageType::Request(_) => {
+                                unreachable!("direct responses are not requests")
+                            }
+                        });
+                    }
+                }
+
+                // Response to a direct ping
+                if let Some((tid, _, MessageType::Response(_))) = report.new_direct_response {
+                    if let Some((sent_at, sender)) = ping_senders.remove(&tid) {
+                        let _ = sender.send(Some(sent_at.elapsed()));
+                    }
+                }
+
+                // Timed out raw requests
+                request_senders.retain(|tid, sender| {
+                    let inflight = rpc.inflight(*tid);
+                    if !inflight {
+                        let _ = sender.send(Err(RequestError::Timeout));
+                    }
+                    inflight
+                });
+
+                // Timed out pings
+                ping_senders.retain(|tid, (_, sender)| {
+                    let inflight = rpc.inflight(*tid);
+                    if !inflight {
+                        let _ = sender.send(None);
+                    }
+                    inflight
+                });
+
+                // Waiting for the routing table to be populated
+                if !bootstrapped_senders.is_empty() {
+                    let size = rpc.routing_table().size();
+
+                    bootstrapped_senders.retain(|(min_nodes, deadline, sender)| {
+                        if size >= *min_nodes {
+                            let _ = sender.send(true);
+                        } else if Instant::now() >= *deadline {
+                            let _ = sender.send(false);
+                        } else {
+                            return true;
+                        }
+
+                        false
+                    });
+                }
+
+                // Republish values that are due, and forget the ones whose handle was dropped.
+                republishing.retain_mut(|republish| {
+                    if republish.stop.is_disconnected() {
+                        return false;
+                    }
+
+                    if republish.last_published.elapsed() >= republish.interval {
+                        republish.last_published = Instant::now();
+
+                        if let Err(error) = rpc.put(republish.request.clone(), None) {
+                            debug!(?error, "Failed to republish");
+                        }
+                    }
+
+                    true
+                });
+
+                // Cleanup done GET queries
+                for ((id, closest_nodes), (_, timing)) in report
+                    .done_get_queries
+                    .into_iter()
+                    .zip(report.done_query_timings)
+                {
+                    if let Some(senders) = get_senders.remove(&id) {
+                        emit(
+                            &mut event_senders,
+                            DhtEvent::QueryCompleted {
+                                target: id,
+                                nodes_contacted: timing.nodes_contacted,
+                            },
+                        );
+
+                        for sender in senders {
+                            match sender {
+                                // return closest_nodes to whoever was asking
+                                ResponseSender::ClosestNodes(sender) => {
+                                    let _ = sender.send(closest_nodes.clone());
+                                }
+                                ResponseSender::Timing(sender) => {
+                                    let _ = sender.send(timing);
+                                }
+                                _ => {}
+                            }
+                        }
+                    }
+                }
+
+                // Cleanup done PUT query and send the resulting report or error.
+                for (id, result) in report.done_put_queries {
+                    if let Some(senders) = put_senders.remove(&id) {
+                        for sender in senders {
+                            let _ = sender.send(result.clone());
+                        }
+                    }
+                }
+            }
+        }
+        Err(err) => {
+            if let Ok(ActorMessage::Check(sender)) = receiver.try_recv() {
+                let _ = sender.send(Err(err));
+            }
+        }
+    };
+}
+
+/// Send an event to all subscribers, dropping the ones that unsubscribed.
+fn emit(senders: &mut Vec<Sender<DhtEvent>>, event: DhtEvent) {
+    senders.retain(|sender| sender.send(event.clone()).is_ok());
+}
+
+fn send(sender: &mut ResponseSender, response: Response) {
+    match (sender, response) {
+        (ResponseSender::Peers(s), Response::Peers(r)) => {
+            let _ = s.send(r.peers);
+        }
+        (ResponseSender::PeersDedup(s, seen), Response::Peers(r)) => {
+            let peers = r
+                .peers
+                .into_iter()
+                .filter(|peer| seen.insert(*peer))
+                .collect::<Vec<_>>();
+
+            if !peers.is_empty() {
+                let _ = s.send(peers);
+            }
+        }
+        (ResponseSender::PeersWithSource(s), Response::Peers(r)) => {
+            let _ = s.send(r);
+        }
+        (ResponseSender::PeersWithTarget(target, s), Response::Peers(r)) => {
+            let _ = s.send((*target, r.peers));
+        }
+        (ResponseSender::Mutable(s), Response::Mutable(r)) => {
+            let _ = s.send(r);
+        }
+        (ResponseSender::Immutable(s), Response::Immutable(r)) => {
+            let _ = s.send(r);
+        }
+        _ => {}
+    }
+}
+
+#[derive(Debug)]
+pub(crate) enum ActorMessage {
+    Info(Sender<Info>),
+    Id(Sender<Id>),
+    Put(
+        PutRequestSpecific,
+        Sender<Result<PutReport, PutError>>,
+        Option<Box<[Node]>>,
+    ),
+    /// A GET query, with an optional request timeout, and optional seed nodes.
+    Get(
+        GetRequestSpecific,
+        ResponseSender,
+        Option<Duration>,
+        Option<Box<[SocketAddrV4]>>,
+    ),
+    /// Stop a query if all its [ResponseSender]s are disconnected.
+    Cancel(Id),
+    Check(Sender<Result<SocketAddrV4, std::io::Error>>),
+    ToBootstrap(Sender<Vec<String>>),
+    RoutingTable(Sender<RoutingTable>),
+    DebugBuckets(Sender<Vec<BucketInfo>>),
+    Ping(SocketAddrV4, Sender<Option<Duration>>),
+    StoragePressure(Id, Sender<Option<f64>>),
+    SubscribeEvents(Sender<DhtEvent>),
+    ServerStats(usize, Sender<ServerStats>),
+    BootstrapStatus(Sender<Vec<(SocketAddrV4, bool)>>),
+    DhtSizeEstimateHistory(usize, Sender<Vec<(Instant, usize, f64)>>),
+    Bootstrapped(usize, Instant, Sender<bool>),
+    Republish(PutRequestSpecific, Option<Duration>, Receiver<()>),
+    Request(
+        SocketAddrV4,
+        RequestSpecific,
+        Sender<Result<ResponseSpecific, RequestError>>,
+    ),
+    Pause,
+    Paused(Sender<bool>),
+    Resume,
+    Ban(Ipv4Net),
+    Unban(Ipv4Net),
+    SetExternalIp(Ipv4Addr, Sender<Id>),
+    ExpectProbe(Id, Sender<()>),
+    ProbeReceived(Id, Sender<bool>),
+    GetSocket(Sender<Arc<UdpSocket>>),
+}
+
+#[derive(Debug, Clone)]
+pub enum ResponseSender {
+    ClosestNodes(Sender<Box<[Node]>>),
+    Peers(Sender<Vec<SocketAddrV4>>),
+    /// Peers not sent to this sender before.
+    PeersDedup(Sender<Vec<SocketAddrV4>>, HashSet<SocketAddrV4>),
+    PeersWithSource(Sender<PeersResponse>),
+    PeersWithTarget(Id, Sender<(Id, Vec<SocketAddrV4>)>),
+    Mutable(Sender<MutableItem>),
+    Immutable(Sender<Box<[u8]>>),
+    Raw(Sender<RawResponse>),
+    Timing(Sender<QueryTiming>),
+}
+
+impl ResponseSender {
+    /// Returns true if the receiving side was dropped.
+    fn is_disconnected(&self) -> bool {
+        match self {
+            ResponseSender::ClosestNodes(sender) => sender.is_disconnected(),
+            ResponseSender::Peers(sender) => sender.is_disconnected(),
+            ResponseSender::PeersDedup(sender, _) => sender.is_disconnected(),
+            ResponseSender::PeersWithSource(sender) => sender.is_disconnected(),
+            ResponseSender::PeersWithTarget(_, sender) => sender.is_disconnected(),
+            ResponseSender::Mutable(sender) => sender.is_disconnected(),
+            ResponseSender::Immutable(sender) => sender.is_disconnected(),
+            ResponseSender::Raw(sender) => sender.is_disconnected(),
+            ResponseSender::Timing(sender) => sender.is_disconnected(),
+        }
+    }
+}
+
+/// Create a testnet of Dht nodes to run tests against instead of the real mainline network.
+#[derive(Debug)]
+pub struct Testnet {
+    /// bootstrapping nodes for this testnet.
+    pub bootstrap: Vec<String>,
+    /// all nodes in this testnet
+    pub nodes: Vec<Dht>,
+}
+
+impl Testnet {
+    /// Create a new testnet with a certain size.
+    ///
+    /// Note: this network will be shutdown as soon as this struct
+    /// gets dropped, if you want the network to be `'static`, then
+    /// you should call [Self::leak].
+    pub fn new(count: usize) -> Result<Testnet, std::io::Error> {
+        Self::builder(count).build()
+    }
+
+    /// Create a new testnet with a certain size, where every node drops
+    /// a `loss_rate` fraction (between `0.0` and `1.0`) of its outgoing packets.
+    ///
+    /// Useful to test that queries still converge over an unreliable network.
+    pub fn with_packet_loss(count: usize, loss_rate: f64) -> Result<Testnet, std::io::Error> {
+        Self::builder(count)
+            .configure(move |_, builder| {
+                builder.network_conditions(NetworkConditions {
+                    packet_loss: loss_rate,
+                    ..Default::default()
+                });
+            })
+            .build()
+    }
+
+    /// Create a [TestnetBuilder] to customize the settings of each node
+    /// in a testnet with a certain size.
+    pub fn builder(count: usize) -> TestnetBuilder {
+        TestnetBuilder {
+            count,
+            configure: None,
+            seed: None,
+        }
+    }
+
+    /// By default as soon as this testnet gets dropped,
+    /// all the nodes get dropped and the entire network is shutdown.
+    ///
+    /// This method uses [Box::leak] to keep nodes running, which is
+    /// useful if you need to keep running the testnet in the process
+    /// even if this struct gets dropped.
+    pub fn leak(&self) {
+        for node in self.nodes.clone() {
+            Box::leak(Box::new(node));
+        }
+    }
+}
+
+/// Customizes the [DhtBuilder] of a [Testnet] node by its index.
+type ConfigureNode = Box<dyn Fn(usize, &mut DhtBuilder)>;
+
+/// A builder for a [Testnet], see [Testnet::builder].
+pub struct TestnetBuilder {
+    count: usize,
+    configure: Option<ConfigureNode>,
+    seed: Option<[u8; 32]>,
+}
+
+impl std::fmt::Debug for TestnetBuilder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TestnetBuilder")
+            .field("count", &self.count)
+            .field("configure", &self.configure.is_some())
+            .field("seed", &self.seed)
+            .finish()
+    }
+}
+
+impl TestnetBuilder {
+    /// Customize the [DhtBuilder] of each node, given its index in the testnet,
+    /// for example to run some nodes in [read-only][DhtBuilder::read_only] mode.
+    ///
+    /// Nodes start in [server mode][DhtBuilder::server_mode], and their bootstrapping
+    /// nodes are set after calling `configure`, so the testnet remains connected.
+    pub fn configure(&mut self, configure: impl Fn(usize, &mut DhtBuilder) + 'static) -> &mut Self {
+        self.configure = Some(Box::new(configure));
+
+        self
+    }
+
+    /// Derive each node's [Id] deterministically from this `seed` and the node's index,
+    /// see [DhtBuilder::node_id_seed], so failures can be reproduced with the same seed.
+    pub fn seed(&mut self, seed: [u8; 32]) -> &mut Self {
+        self.seed = Some(seed);
+
+        self
+    }
+
+    /// Create the [Testnet].
+    pub fn build(&self) -> Result<Testnet, std::io::Error> {
+        let mut nodes: Vec<Dht> = vec![];
+        let mut bootstrap = vec![];
+
+        for i in 0..self.count {
+            let mut builder = Dht::builder();
+            builder.server_mode();
+
+            if let Some(seed) = self.seed {
+                builder.node_id_seed(node_seed(seed, i));
+            }
+
+            if let Some(configure) = &self.configure {
+                configure(i, &mut builder);
+            }
+
+            if i == 0 {
+                let node = builder.no_bootstrap().build()?;
+
+                let addr = node.local_addr();
+
+                bootstrap.push(format!("127.0.0.1:{}", addr.port()));
+
+                nodes.push(node)
+            } else {
+                let node = builder.bootstrap(&bootstrap).build()?;
+                nodes.push(node)
+            }
+        }
+
+        Ok(Testnet { bootstrap, nodes })
+    }
+}
+
+/// Derive a distinct seed for the node at `index` of a [Testnet].
+fn node_seed(seed: [u8; 32], index: usize) -> [u8; 32] {
+    let mut seed = seed;
+    let tail = u64::from_be_bytes(seed[24..].try_into().expect("infallible"));
+    seed[24..].copy_from_slice(&tail.wrapping_add(index as u64).to_be_bytes());
+
+    seed
+}
+
+#[derive(thiserror::Error, Debug, Clone)]
+/// [Dht::send_request] errors.
+pub enum RequestError {
+    /// The node didn't respond before the request timeout.
+    #[error("Request timed out")]
+    Timeout,
+
+    /// The node responded with an error.
+    #[error("Error response: {0:?}")]
+    ErrorResponse(ErrorSpecific),
+}
+
+/// Return the report's target if it was stored at at least `min_replicas` nodes.
+pub(crate) fn check_min_replicas(
+    report: PutReport,
+    min_replicas: usize,
+) -> Result<Id, PutMutableError> {
+    if report.stored_at.len() < min_replicas {
+        return Err(PutQueryError::NotEnoughReplicas {
+            stored: report.stored_at.len(),
+            min: min_replicas,
+        }
+        .into());
+    }
+
+    Ok(report.target)
+}
+
+impl From<PutError> for PutMutableError {
+    fn from(error: PutError) -> Self {
+        match error {
+            PutError::Query(err) => PutMutableError::Query(err),
+            PutError::Concurrency(err) => PutMutableError::Concurrency(err),
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+/// Put MutableItem errors.
+pub enum PutMutableError {
+    #[error(transparent)]
+    /// Common PutQuery errors
+    Query(#[from] PutQueryError),
+
+    #[error(transparent)]
+    /// PutQuery for [crate::MutableItem] errors
+    Concurrency(#[from] ConcurrencyError),
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use ed25519_dalek::SigningKey;
+
+    use crate::common::{PingResponseArguments, RequestTypeSpecific, MAX_VALUE_SIZE};
+    use crate::rpc::ConcurrencyError;
+
+    use super::*;
+
+    #[test]
+    fn bind_twice() {
+        let a = Dht::client().unwrap();
+        let result = Dht::builder()
+            .port(a.local_addr().port())
+            .server_mode()
+            .build();
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn probe_from_port() {
+        let testnet = Testnet::new(1).unwrap();
+        let target = SocketAddrV4::new(Ipv4Addr::LOCALHOST, testnet.nodes[0].local_addr().port());
+
+        let client = Dht::builder().no_bootstrap().build().unwrap();
+
+        let observed = client.probe_from_port(0, target).unwrap();
+
+        assert_eq!(observed.target, target);
+        assert_ne!(observed.local_addr.port(), client.local_addr().port());
+        assert_eq!(
+            observed.address,
+            Some(SocketAddrV4::new(
+                Ipv4Addr::LOCALHOST,
+                observed.local_addr.port()
+            ))
+        );
+    }
+
+    #[test]
+    fn check_reachability() {
+        let testnet = Testnet::new(3).unwrap();
+
+        let report = testnet.nodes[1].check_reachability();
+
+        assert!(!report.observed.is_empty());
+        assert!(report
+            .observed
+            .iter()
+            .all(|address| *address.ip() == Ipv4Addr::LOCALHOST));
+        assert_eq!(
+            report.public_address.map(|address| *address.ip()),
+            Some(Ipv4Addr::LOCALHOST)
+        );
+        assert!(report.reachable);
+
+        let lonely = Dht::builder().no_bootstrap().build().unwrap();
+        let report = lonely.check_reachability();
+
+        assert!(report.observed.is_empty());
+        assert_eq!(report.public_address, None);
+        assert!(!report.reachable);
+    }
+
+    #[test]
+    fn check_reachability_paused() {
+        let testnet = Testnet::new(3).unwrap();
+
+        let dht = &testnet.nodes[1];
+        dht.pause();
+
+        let report = dht.check_reachability();
+
+        assert!(report.observed.is_empty());
+        assert!(!report.reachable);
+    }
+
+    #[test]
+    fn probe_from_bind_addr() {
+        let testnet = Testnet::new(1).unwrap();
+        let target = SocketAddrV4::new(Ipv4Addr::LOCALHOST, testnet.nodes[0].local_addr().port());
+
+        let client = Dht::builder()
+            .no_bootstrap()
+            .bind_addr(SocketAddr::from(([127, 0, 0, 1], 0)))
+            .build()
+            .unwrap();
+
+        let observed = client.probe_from_port(0, target).unwrap();
+
+        assert_eq!(*observed.local_addr.ip(), Ipv4Addr::LOCALHOST);
+        assert!(observed.address.is_some());
+    }
+
+    #[test]
+    fn max_cached_queries() {
+        let testnet = Testnet::new(10).unwrap();
+
+        let dht = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .max_cached_queries(2)
+            .build()
+            .unwrap();
+
+        for _ in 0..3 {
+            dht.find_node(Id::random());
+        }
+
+        let report = dht.info().memory_report();
+
+        assert_eq!(report.cached_queries, 2);
+        assert!(report.routing_table_nodes > 0);
+        assert!(report.approximate_bytes > 0);
+    }
+
+    #[test]
+    fn find_node_with_distance() {
+        let testnet = Testnet::new(10).unwrap();
+
+        let dht = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+
+        let target = Id::random();
+        let nodes = dht.find_node_with_distance(target);
+
+        assert!(!nodes.is_empty());
+
+        for (node, distance) in &nodes {
+            assert_eq!(*distance, target.xor(node.id()));
+        }
+
+        assert!(nodes.windows(2).all(|w| w[0].1 <= w[1].1));
+    }
+
+    #[test]
+    fn get_peers_from() {
+        let testnet = Testnet::new(10).unwrap();
+
+        let a = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+
+        let info_hash = Id::random();
+
+        let report = a
+            .announce_peer_report(info_hash, Some(45555))
+            .expect("failed to announce");
+
+        let b = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+
+        let stored_at = report.announced_on[0];
+        let unknown = SocketAddr::from(([127, 0, 0, 1], 1));
+
+        let responses = b.get_peers_from(info_hash, &[stored_at.into(), unknown]);
+
+        assert_eq!(responses.len(), 1);
+        assert_eq!(responses[0].from.address(), stored_at);
+        assert_eq!(responses[0].peers.len(), 1);
+        assert_eq!(responses[0].peers[0].port(), 45555);
+    }
+
+    #[test]
+    fn get_peers_dedup() {
+        let (tx, rx) = flume::unbounded();
+        let mut sender = ResponseSender::PeersDedup(tx, HashSet::new());
+
+        let a = SocketAddrV4::new(Ipv4Addr::new(1, 2, 3, 4), 45555);
+        // Same ip, different port.
+        let b = SocketAddrV4::new(Ipv4Addr::new(1, 2, 3, 4), 45556);
+
+        for peers in [vec![a], vec![a, b], vec![b]] {
+            send(
+                &mut sender,
+                Response::Peers(PeersResponse {
+                    from: Node::random(),
+                    peers,
+                }),
+            );
+        }
+        drop(sender);
+
+        assert_eq!(rx.iter().collect::<Vec<_>>(), vec![vec![a], vec![b]]);
+    }
+
+    #[test]
+    fn get_peers_with_seeds() {
+        let testnet = Testnet::new(3).unwrap();
+        // Unknown to the testnet.
+        let island = Testnet::new(1).unwrap();
+
+        let info_hash = Id::random();
+
+        Dht::builder()
+            .bootstrap(&island.bootstrap)
+            .build()
+            .unwrap()
+            .announce_peer(info_hash, Some(45555))
+            .expect("failed to announce");
+
+        let client = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+
+        assert_eq!(client.get_peers(info_hash).count(), 0);
+
+        let seed = SocketAddr::from(([127, 0, 0, 1], island.nodes[0].local_addr().port()));
+
+        let peers = client
+            .get_peers_with_seeds(info_hash, &[seed])
+            .flatten()
+            .collect::<Vec<_>>();
+
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers[0].port(), 45555);
+    }
+
+    #[test]
+    fn dht_size_estimate_history() {
+        let testnet = Testnet::new(10).unwrap();
+
+        let dht = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+
+        dht.find_node(Id::random());
+        dht.find_node(Id::random());
+
+        let history = dht.dht_size_estimate_history(100);
+        assert!(history.len() >= 2);
+        assert!(history.windows(2).all(|w| w[0].0 <= w[1].0));
+
+        assert!(history.iter().all(|(_, estimate, _)| *estimate > 0));
+
+        assert_eq!(dht.dht_size_estimate_history(1).len(), 1);
+    }
+
+    #[test]
+    fn max_estimate_samples() {
+        let testnet = Testnet::new(10).unwrap();
+
+        let dht = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .max_estimate_samples(2)
+            .build()
+            .unwrap();
+
+        for _ in 0..3 {
+            dht.find_node(Id::random());
+        }
+
+        assert_eq!(dht.dht_size_estimate_history(100).len(), 2);
+        assert_eq!(dht.info().memory_report().estimate_samples, 2);
+    }
+
+    #[test]
+    fn node_id() {
+        let id = Id::random();
+
+        let dht = Dht::builder().node_id(id).build().unwrap();
+        assert_eq!(*dht.info().id(), id);
+
+        let public_ip = Ipv4Addr::new(21, 75, 31, 124);
+        let secure_id = Id::from_ipv4(public_ip);
+
+        let dht = Dht::builder()
+            .public_ip(public_ip)
+            .node_id(secure_id)
+            .build()
+            .unwrap();
+        assert_eq!(*dht.info().id(), secure_id);
+
+        let insecure_id = Id::from_ipv4(Ipv4Addr::new(43, 213, 53, 83));
+
+        let error = Dht::builder()
+            .public_ip(public_ip)
+            .node_id(insecure_id)
+            .build()
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn sample_infohashes() {
+        let testnet = Testnet::new(10).unwrap();
+
+        let a = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+
+        let info_hash = Id::random();
+
+        a.announce_peer(info_hash, Some(45555))
+            .expect("failed to announce");
+
+        let samples = a.sample_infohashes(info_hash);
+
+        assert!(samples.responders > 0);
+        assert!(samples.num > 0);
+        assert_eq!(samples.samples, vec![info_hash]);
+        assert_eq!(
+            samples.interval,
+            Duration::from_secs(crate::rpc::server::SAMPLE_INFOHASHES_INTERVAL as u64)
+        );
+    }
+
+    #[test]
+    fn scrape() {
+        let testnet = Testnet::new(10).unwrap();
+
+        let a = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+
+        let info_hash = Id::random();
+
+        let empty = a.scrape(info_hash);
+        assert!(empty.responders > 0);
+        assert_eq!(empty.seeds_count(), 0);
+        assert_eq!(empty.peers_count(), 0);
+
+        a.announce_seed(info_hash, Some(45555))
+            .expect("failed to announce");
+
+        let result = a.scrape(info_hash);
+
+        assert!(result.responders > 0);
+        assert_eq!(result.seeds_count(), 1);
+        assert_eq!(result.peers_count(), 0);
+        assert!(result.seeds.contains(Ipv4Addr::LOCALHOST));
+    }
+
+    #[test]
+    fn announce_get_peer() {
+        let testnet = Testnet::new(10).unwrap();
+
+        let a = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+        let b = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+
+        let info_hash = Id::random();
+
+        a.announce_peer(info_hash, Some(45555))
+            .expect("failed to announce");
+
+        let peers = b.get_peers(info_hash).next().expect("No peers");
+
+        assert_eq!(peers.first().unwrap().port(), 45555);
+    }
+
+    #[test]
+    fn testnet_builder() {
+        let testnet = Testnet::builder(5)
+            .configure(|i, builder| {
+                if i % 2 == 1 {
+                    builder.read_only();
+                }
+            })
+            .build()
+            .unwrap();
+
+        let server_mode = testnet
+            .nodes
+            .iter()
+            .map(|node| node.info().server_mode())
+            .collect::<Vec<_>>();
+
+        assert_eq!(server_mode, vec![true, false, true, false, true]);
+    }
+
+    #[test]
+    fn testnet_seed() {
+        let ids = |seed: [u8; 32]| {
+            Testnet::builder(3)
+                .seed(seed)
+                .build()
+                .unwrap()
+                .nodes
+                .iter()
+                .map(|node| *node.info().id())
+                .collect::<Vec<_>>()
+        };
+
+        let first = ids([7; 32]);
+
+        assert_eq!(first, ids([7; 32]));
+        assert_ne!(first, ids([8; 32]));
+        assert_ne!(first[0], first[1]);
+    }
+
+    #[test]
+    fn send_request() {
+        let testnet = Testnet::new(10).unwrap();
+
+        let dht = Dht::client().unwrap();
+        let node = &testnet.nodes[1];
+        let address = SocketAddrV4::new([127, 0, 0, 1].into(), node.local_addr().port());
+
+        let response = dht.send_request(
+            address,
+            RequestSpecific {
+                requester_id: Id::random(),
+                request_type: RequestTypeSpecific::Ping,
+            },
+        );
+
+        assert!(matches!(
+            response,
+            Ok(ResponseSpecific::Ping(PingResponseArguments { responder_id }))
+                if responder_id == *node.info().id()
+        ));
+    }
+
+    #[test]
+    fn send_request_timeout() {
+        // A node that never responds.
+        let silent = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = match silent.local_addr().unwrap() {
+            SocketAddr::V4(address) => address,
+            SocketAddr::V6(_) => unreachable!(),
+        };
+
+        let dht = Dht::builder()
+            .no_bootstrap()
+            .request_timeout(Duration::from_millis(100))
+            .build()
+            .unwrap();
+
+        let response = dht.send_request(
+            address,
+            RequestSpecific {
+                requester_id: Id::random(),
+                request_type: RequestTypeSpecific::Ping,
+            },
+        );
+
+        assert!(matches!(response, Err(RequestError::Timeout)));
+    }
+
+    #[test]
+    fn put_immutable_repeating() {
+        let testnet = Testnet::new(10).unwrap();
+
+        let a = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+        let b = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+
+        let value = b"Hello World!";
+
+        let handle = a.put_immutable_repeating(value, Some(Duration::from_millis(100)));
+        let target = *handle.target();
+
+        assert_eq!(target, Id::from(hash_immutable(value)));
+
+        // Let it republish a few times, while the value is retrievable.
+        thread::sleep(Duration::from_millis(300));
+
+        assert_eq!(b.get_immutable(target), Some(value.to_vec().into()));
+    }
+
+    #[test]
+    fn put_repeating_defaults_to_item_refresh_interval() {
+        let testnet = Testnet::new(10).unwrap();
+
+        let (tx, rx) = flume::unbounded();
+
+        let server = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .server_mode()
+            .on_request(move |_, request| {
+                if matches!(request.request_type, RequestTypeSpecific::Put(_)) {
+                    let _ = tx.send(());
+                }
+            })
+            .build()
+            .unwrap();
+
+        let mut bootstrap = testnet.bootstrap.clone();
+        bootstrap.push(format!("127.0.0.1:{}", server.local_addr().port()));
+
+        let dht = Dht::builder()
+            .bootstrap(&bootstrap)
+            .item_refresh_interval(Duration::from_millis(100))
+            .build()
+            .unwrap();
+
+        let _handle = dht.put_immutable_repeating(b"Hello World!", None);
+
+        // Published, then republished after the configured item refresh interval.
+        for _ in 0..2 {
+            rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        }
+    }
+
+    #[test]
+    fn client_version() {
+        let testnet = Testnet::new(10).unwrap();
+
+        let dht = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+
+        assert!(dht.bootstrapped_with(1, Duration::from_secs(5)));
+
+        let expected = Some(crate::ClientVersion {
+            two_char_id: *b"RS",
+            version: 4,
+        });
+
+        let routing_table = dht.routing_table();
+
+        assert!(!routing_table.is_empty());
+        assert!(routing_table
+            .nodes()
+            .all(|node| node.client_version() == expected));
+    }
+
+    #[test]
+    fn get_immutable_outcome() {
+        let testnet = Testnet::new(10).unwrap();
+
+        let dht = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+
+        let value = b"Hello World!";
+        let target = dht.put_immutable(value).unwrap();
+
+        assert_eq!(
+            dht.get_immutable_outcome(target),
+            ImmutableOutcome::Found(value.to_vec().into())
+        );
+
+        assert!(matches!(
+            dht.get_immutable_outcome(Id::random()),
+            ImmutableOutcome::NotFound { nodes_queried } if nodes_queried > 0
+        ));
+    }
+
+    #[test]
+    fn get_immutable_outcome_timed_out() {
+        // A node that never responds.
+        let silent = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+
+        let dht = Dht::builder()
+            .bootstrap(&[silent.local_addr().unwrap()])
+            .max_query_lifetime(Duration::from_millis(100))
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            dht.get_immutable_outcome(Id::random()),
+            ImmutableOutcome::TimedOut
+        );
+    }
+
+    #[test]
+    fn resolver() {
+        let testnet = Testnet::new(10).unwrap();
+
+        let bootstrap = testnet.nodes[0].local_addr();
+        let resolved = Arc::new(std::sync::Mutex::new(Vec::new()));
+
+        let dht = Dht::builder()
+            .resolver({
+                let resolved = resolved.clone();
+
+                move |host| {
+                    resolved.lock().unwrap().push(host.to_string());
+
+                    Ok(vec![SocketAddr::from(([127, 0, 0, 1], bootstrap.port()))])
+                }
+            })
+            .build()
+            .unwrap();
+
+        assert!(dht.bootstrapped_with(1, Duration::from_secs(5)));
+        assert_eq!(
+            *resolved.lock().unwrap(),
+            crate::DEFAULT_BOOTSTRAP_NODES.to_vec()
+        );
+    }
+
+    #[test]
+    fn build_timeout() {
+        let start = Instant::now();
+
+        let result = Dht::builder()
+            .resolver(|_| {
+                thread::sleep(Duration::from_secs(2));
+
+                Ok(vec![])
+            })
+            .build_timeout(Duration::from_millis(100))
+            .build();
+
+        assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::TimedOut);
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        assert!(Dht::builder()
+            .no_bootstrap()
+            .build_timeout(Duration::from_secs(5))
+            .build()
+            .is_ok());
+    }
+
+    #[test]
+    fn add_bootstrap() {
+        let default = SocketAddrV4::new(Ipv4Addr::LOCALHOST, 6881);
+        let extra = SocketAddrV4::new(Ipv4Addr::LOCALHOST, 6882);
+
+        let mut builder = Dht::builder();
+        // Extra nodes are resolved with the resolver set after them.
+        builder
+            .add_bootstrap(&["extra.example:6882".to_string()])
+            .extra_bootstrap(&[SocketAddrV4::new(Ipv4Addr::LOCALHOST, 6883)])
+            .resolver(move |host| {
+                Ok(vec![match host {
+                    "extra.example:6882" => extra.into(),
+                    _ => host.parse().unwrap_or(default.into()),
+                }])
+            });
+
+        let rpc = Rpc::new(builder.0.clone()).unwrap();
+
+        assert_eq!(
+            rpc.bootstrap_status()
+                .into_iter()
+                .map(|(address, _)| address)
+                .collect::<Vec<_>>(),
+            vec![default, extra, SocketAddrV4::new(Ipv4Addr::LOCALHOST, 6883)]
+        );
+
+        assert_eq!(
+            crate::bootstrap_defaults(),
+            crate::DEFAULT_BOOTSTRAP_NODES
+                .iter()
+                .map(|node| node.to_string())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn bootstrapped_with() {
+        let testnet = Testnet::new(10).unwrap();
+
+        let dht = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+
+        assert!(dht.bootstrapped_with(1, Duration::from_secs(5)));
+        assert!(!dht.bootstrapped_with(1000, Duration::from_millis(100)));
+    }
+
+    #[test]
+    fn bootstrap_status() {
+        let testnet = Testnet::new(10).unwrap();
+
+        // A bootstrapping node that never responds.
+        let silent = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = match silent.local_addr().unwrap() {
+            SocketAddr::V4(address) => address,
+            SocketAddr::V6(_) => unreachable!(),
+        };
+
+        let mut bootstrap = testnet.bootstrap.clone();
+        bootstrap.push(address.to_string());
+
+        let dht = Dht::builder().bootstrap(&bootstrap).build().unwrap();
+
+        assert!(dht.bootstrapped_with(1, Duration::from_secs(5)));
+
+        let status = dht.bootstrap_status();
+
+        assert_eq!(status.len(), bootstrap.len());
+        assert!(status.contains(&(address, false)));
+        assert!(status.iter().any(|(_, responded)| *responded));
+    }
+
+    #[test]
+    fn cancel_on_drop() {
+        // A bootstrapping node that never responds, so queries stay inflight.
+        let silent = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+
+        let dht = Dht::builder()
+            .bootstrap(&[silent.local_addr().unwrap().to_string()])
+            .request_timeout(Duration::from_secs(10))
+            .build()
+            .unwrap();
+
+        let events = dht.subscribe_events();
+        let info_hash = Id::random();
+
+        let peers = dht.get_peers(info_hash);
+        let other = dht.get_peers(info_hash);
+
+        assert_eq!(
+            events.recv_timeout(Duration::from_secs(1)),
+            Ok(DhtEvent::QueryStarted { target: info_hash })
+        );
+
+        // Another iterator is still waiting for the same query.
+        drop(peers);
+        let joined = dht.get_peers(info_hash);
+        assert!(events.recv_timeout(Duration::from_millis(200)).is_err());
+
+        other.cancel();
+        drop(joined);
+
+        // The query was cancelled, so a new one is started.
+        let _peers = dht.get_peers(info_hash);
+        assert_eq!(
+            events.recv_timeout(Duration::from_secs(1)),
+            Ok(DhtEvent::QueryStarted { target: info_hash })
+        );
+    }
+
+    #[test]
+    fn get_immutable_cancels_query() {
+        let testnet = Testnet::new(1).unwrap();
+
+        let writer = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+        let target = writer.put_immutable(b"Hello World!").unwrap();
+
+        // A bootstrapping node that never responds, so queries can't complete.
+        let silent = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+
+        let mut bootstrap = testnet.bootstrap.clone();
+        bootstrap.push(silent.local_addr().unwrap().to_string());
+
+        let reader = Dht::builder()
+            .bootstrap(&bootstrap)
+            .request_timeout(Duration::from_secs(10))
+            .build()
+            .unwrap();
+
+        let events = reader.subscribe_events();
+
+        assert!(reader.get_immutable(target).is_some());
+        assert_eq!(
+            events.recv_timeout(Duration::from_secs(1)),
+            Ok(DhtEvent::QueryStarted { target })
+        );
+
+        // The first query was cancelled, so a new one is started instead of joining it.
+        assert!(reader.get_immutable(target).is_some());
+        assert!(events
+            .try_iter()
+            .any(|event| event == DhtEvent::QueryStarted { target }));
+    }
+
+    #[test]
+    fn get_peers_many() {
+        let testnet = Testnet::new(10).unwrap();
+
+        let a = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+        let b = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+
+        let info_hashes = [Id::random(), Id::random()];
+
+        a.announce_peer(info_hashes[0], Some(45555))
+            .expect("failed to announce");
+        a.announce_peer(info_hashes[1], Some(45556))
+            .expect("failed to announce");
+
+        let mut found = b
+            .get_peers_many(&info_hashes)
+            .flat_map(|(info_hash, peers)| {
+                peers.into_iter().map(move |peer| (info_hash, peer.port()))
+            })
+            .collect::<Vec<_>>();
+        found.sort();
+        found.dedup();
+
+        let mut expected = vec![(info_hashes[0], 45555), (info_hashes[1], 45556)];
+        expected.sort();
+
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn server_stats() {
+        let testnet = Testnet::new(10).unwrap();
+
+        let a = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+
+        let info_hash = Id::random();
+
+        a.announce_peer(info_hash, Some(45555))
+            .expect("failed to announce");
+
+        let stats = testnet
+            .nodes
+            .iter()
+            .map(|node| node.server_stats(1))
+            .find(|stats| stats.info_hashes > 0)
+            .expect("no node stored the announced peer");
+
+        assert_eq!(stats.peers, 1);
+        assert_eq!(stats.top_info_hashes, vec![(info_hash, 1)]);
+    }
+
+    #[test]
+    fn subscribe_events() {
+        let testnet = Testnet::new(10).unwrap();
+
+        let dht = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+
+        let events = dht.subscribe_events();
+
+        let target = Id::random();
+        dht.find_node(target);
+
+        let events = events.try_iter().collect::<Vec<_>>();
+
+        assert_eq!(events.first(), Some(&DhtEvent::QueryStarted { target }));
+        assert!(events.iter().any(
+            |event| matches!(event, DhtEvent::NodeResponded { target: t, .. } if *t == target)
+        ));
+        assert!(matches!(
+            events.last(),
+            Some(DhtEvent::QueryCompleted { target: t, nodes_contacted }) if *t == target && *nodes_contacted > 0
+        ));
+    }
+
+    #[test]
+    fn get_peers_with_source() {
+        let testnet = Testnet::new(10).unwrap();
+
+        let a = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+        let b = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+
+        let info_hash = Id::random();
+
+        let report = a
+            .announce_peer_report(info_hash, Some(45555))
+            .expect("failed to announce");
+
+        let response = b.get_peers_with_source(info_hash).next().expect("No peers");
+
+        assert_eq!(response.peers.first().unwrap().port(), 45555);
+        assert!(report.announced_on.contains(&response.from.address()));
+    }
+
+    #[test]
+    fn find_nodes_in_prefix() {
+        let testnet = Testnet::new(20).unwrap();
+
+        let dht = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+
+        let prefix = *testnet.nodes[0].info().id();
+
+        let nodes = dht.find_nodes_in_prefix(1, prefix, 5);
+
+        assert!(!nodes.is_empty());
+        assert!(nodes.len() <= 5);
+        assert!(nodes.iter().all(|node| node.id().has_prefix(&prefix, 1)));
+    }
+
+    #[test]
+    fn skip_self_lookup() {
+        let testnet = Testnet::new(10).unwrap();
+
+        let dht = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .skip_self_lookup()
+            .build()
+            .unwrap();
+
+        thread::sleep(Duration::from_millis(100));
+
+        assert_eq!(dht.info().memory_report().routing_table_nodes, 0);
+
+        dht.find_node(Id::random());
+
+        assert!(dht.info().memory_report().routing_table_nodes > 0);
+    }
+
+    #[test]
+    fn estimate_storage_pressure() {
+        let testnet = Testnet::new(10).unwrap();
+
+        let dht = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+
+        // All testnet nodes are responsive.
+        assert_eq!(dht.estimate_storage_pressure(Id::random()), 0.0);
+
+        let offline = Dht::builder().no_bootstrap().build().unwrap();
+
+        assert_eq!(offline.estimate_storage_pressure(Id::random()), 1.0);
+    }
+
+    #[test]
+    fn ephemeral_port() {
+        let dht = Dht::builder()
+            .ephemeral_port()
+            .no_bootstrap()
+            .build()
+            .unwrap();
+
+        let port = dht.local_addr().port();
+        assert_ne!(port, 0);
+
+        drop(dht);
+
+        // Wait for the actor thread to notice the drop and release the socket.
+        let mut rebound = false;
+        for _ in 0..100 {
+            if UdpSocket::bind(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, port)).is_ok() {
+                rebound = true;
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        assert!(rebound, "port should be bindable again after shutdown");
+    }
+
+    #[test]
+    fn local_addr() {
+        let dht = Dht::builder()
+            .ephemeral_port()
+            .no_bootstrap()
+            .build()
+            .unwrap();
+
+        assert_ne!(dht.local_addr().port(), 0);
+        assert_eq!(dht.local_addr(), dht.info().local_addr());
+    }
+
+    #[test]
+    fn offline() {
+        let dht = Dht::builder().offline().build().unwrap();
+
+        assert_eq!(*dht.local_addr().ip(), Ipv4Addr::LOCALHOST);
+        assert_ne!(dht.local_addr().port(), 0);
+        assert!(dht.bootstrap_status().is_empty());
+        assert!(dht.routing_table().is_empty());
+        assert!(!dht.info().server_mode());
+    }
+
+    #[test]
+    fn is_shutdown() {
+        let dht = Dht::builder().no_bootstrap().build().unwrap();
+        assert!(!dht.is_shutdown());
+
+        // Simulate an actor thread that stopped unexpectedly.
+        let (tx, rx) = flume::unbounded();
+        drop(rx);
+        let stopped = Dht(tx, Arc::new(crate::IdentityCodec), dht.local_addr());
+
+        assert!(stopped.is_shutdown());
+    }
+
+    #[test]
+    fn shutdown_on_last_clone_drop() {
+        let dht = Dht::builder()
+            .ephemeral_port()
+            .no_bootstrap()
+            .build()
+            .unwrap();
+        let clone = dht.clone();
+
+        let port = dht.local_addr().port();
+
+        drop(dht);
+
+        // The remaining clone keeps the actor running.
+        assert_eq!(clone.info().local_addr().port(), port);
+        assert!(UdpSocket::bind(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, port)).is_err());
+
+        drop(clone);
+
+        let mut rebound = false;
+        for _ in 0..100 {
+            if UdpSocket::bind(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, port)).is_ok() {
+                rebound = true;
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        assert!(
+            rebound,
+            "port should be bindable again after the last clone is dropped"
+        );
+    }
+
+    #[test]
+    fn id() {
+        let dht = Dht::builder().no_bootstrap().build().unwrap();
+
+        assert_eq!(&dht.id(), dht.info().id());
+
+        let new_id = dht.set_external_ip(Ipv4Addr::new(93, 184, 216, 34));
+        assert_eq!(dht.id(), new_id);
+    }
+
+    #[test]
+    fn item_refresh_interval() {
+        let dht = Dht::builder().no_bootstrap().build().unwrap();
+
+        assert_eq!(
+            dht.info().item_refresh_interval(),
+            crate::DEFAULT_ITEM_REFRESH_INTERVAL
+        );
+
+        let dht = Dht::builder()
+            .no_bootstrap()
+            .item_refresh_interval(Duration::from_secs(10 * 60))
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            dht.info().item_refresh_interval(),
+            Duration::from_secs(10 * 60)
+        );
+    }
+
+    #[test]
+    fn resolve_magnet() {
+        let testnet = Testnet::new(10).unwrap();
+
+        let a = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+        let b = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+
+        let info_hash = Id::random();
+
+        a.announce_peer(info_hash, Some(45555))
+            .expect("failed to announce");
+
+        let peers = b
+            .resolve_magnet(
+                &format!("magnet:?xt=urn:btih:{}&dn=example", info_hash),
+                Duration::from_secs(5),
+            )
+            .unwrap();
+
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers[0].port(), 45555);
+
+        assert_eq!(
+            b.resolve_magnet("magnet:?dn=example", Duration::from_secs(5)),
+            Err(MagnetError::MissingInfoHash)
+        );
+    }
+
+    #[test]
+    fn max_lookup_rounds() {
+        let testnet = Testnet::new(30).unwrap();
+
+        for max in [1, 2] {
+            let dht = Dht::builder()
+                .bootstrap(&testnet.bootstrap)
+                .max_lookup_rounds(max)
+                .build()
+                .unwrap();
+
+            for _ in 0..3 {
+                let (nodes, timing) = dht.find_node_timed(Id::random());
+
+                assert!(!nodes.is_empty());
+                assert!(timing.rounds >= 1);
+                assert!(timing.rounds <= max);
+            }
+        }
+    }
+
+    #[test]
+    fn query_timing() {
+        let testnet = Testnet::new(10).unwrap();
+
+        let a = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+
+        let (nodes, timing) = a.find_node_timed(Id::random());
+
+        assert!(!nodes.is_empty());
+        assert!(timing.rounds > 0);
+        let time_to_first_response = timing.time_to_first_response.unwrap();
+        assert!(time_to_first_response <= timing.time_to_convergence);
+        let (_, slowest) = timing.slowest_response.unwrap();
+        assert!(slowest <= timing.time_to_convergence);
+        assert!(timing.requests_sent >= timing.nodes_contacted);
+        assert!(timing.rounds <= timing.nodes_contacted);
+        assert!(timing.responses_received >= timing.unique_responders);
+        assert!(timing.unique_responders > 0);
+        assert!(timing.unique_responders <= timing.nodes_contacted);
+        assert_eq!(timing.replication_estimate(), 0.0);
+
+        let value = b"timed";
+        let target = a.put_immutable(value).unwrap();
+
+        let b = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+
+        let (response, timing) = b.get_immutable_timed(target);
+
+        assert_eq!(response.as_deref(), Some(value.as_ref()));
+        assert!(timing.time_to_first_response.is_some());
+        assert!(timing.closest_with_value > 0);
+        assert!(timing.closest_with_value <= timing.closest_responders);
+        assert!(timing.replication_estimate() > 0.0);
+    }
+
+    #[test]
+    fn set_external_ip() {
+        let testnet = Testnet::new(10).unwrap();
+
+        let dht = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+
+        dht.find_node(Id::random());
+        let old_id = *dht.info().id();
+
+        let ip = Ipv4Addr::new(93, 184, 216, 34);
+        let new_id = dht.set_external_ip(ip);
+
+        assert_ne!(new_id, old_id);
+        assert!(new_id.is_valid_for_ip(ip));
+        assert_eq!(dht.info().id(), &new_id);
+        assert!(!dht.routing_table().is_empty());
+
+        // Queries still work with the new Id.
+        assert!(!dht.find_node(Id::random()).is_empty());
+    }
+
+    #[test]
+    fn get_raw() {
+        let testnet = Testnet::new(10).unwrap();
+
+        let a = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+        let b = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+
+        let info_hash = Id::random();
+        a.announce_peer(info_hash, Some(45555)).unwrap();
+
+        let responses = b
+            .get_raw(GetRequestSpecific::GetPeers(GetPeersRequestArguments {
+                info_hash,
+            }))
+            .collect::<Vec<_>>();
+
+        assert!(!responses.is_empty());
+        assert!(responses.iter().all(|r| r.target == info_hash));
+        // Every get_peers response carries a token.
+        assert!(responses.iter().all(|r| r.from.token().is_some()));
+
+        let peers = responses
+            .iter()
+            .filter_map(|r| match &r.response {
+                ResponseSpecific::GetPeers(args) => Some(args.values.clone()),
+                _ => None,
+            })
+            .flatten()
+            .collect::<Vec<_>>();
+
+        assert_eq!(peers, vec![SocketAddrV4::new(Ipv4Addr::LOCALHOST, 45555)]);
+    }
+
+    #[test]
+    fn find_node_excludes_self() {
+        let testnet = Testnet::new(10).unwrap();
+
+        let dht = Dht::builder()
+            .server_mode()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+
+        let id = *dht.info().id();
+        let address = SocketAddr::from(([127, 0, 0, 1], dht.local_addr().port()));
+
+        // Let other nodes add this node to their routing tables.
+        for node in &testnet.nodes {
+            node.get_peers_with_seeds(Id::random(), &[address]).count();
+        }
+
+        for _ in 0..3 {
+            let closest = dht.find_node(id);
+
+            assert!(!closest.is_empty());
+            assert!(closest
+                .iter()
+                .all(|node| node.id() != &id && node.address().port() != dht.local_addr().port()));
+        }
+    }
+
+    #[test]
+    fn debug_buckets() {
+        let testnet = Testnet::new(10).unwrap();
+
+        let dht = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+
+        dht.find_node(Id::random());
+
+        let buckets = dht.debug_buckets();
+
+        assert_eq!(
+            buckets
+                .iter()
+                .map(|b| b.good + b.questionable)
+                .sum::<usize>(),
+            dht.routing_table().size()
+        );
+        assert!(buckets.iter().all(|b| b.last_seen.is_some()));
+    }
+
+    #[test]
+    fn routing_table() {
+        let testnet = Testnet::new(10).unwrap();
+
+        let dht = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+
+        dht.find_node(Id::random());
+
+        let routing_table = dht.routing_table();
+
+        assert_eq!(routing_table.id(), dht.info().id());
+        assert_eq!(
+            routing_table.size(),
+            dht.info().memory_report().routing_table_nodes
+        );
+        assert!(routing_table
+            .nodes()
+            .all(|node| node.last_seen() <= Instant::now()));
+    }
+
+    #[test]
+    fn bootstrap_from_nodes() {
+        let testnet = Testnet::new(10).unwrap();
+
+        let a = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+        a.find_node(Id::random());
+
+        let saved = a.routing_table().to_owned_nodes();
+        assert!(!saved.is_empty());
+
+        // Bootstrap only from the saved nodes.
+        let b = Dht::builder()
+            .no_bootstrap()
+            .bootstrap_from_nodes(&saved)
+            .build()
+            .unwrap();
+
+        assert!(!b.find_node(Id::random()).is_empty());
+
+        // Fall back to the bootstrapping nodes if the saved nodes are unreachable.
+        let c = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .bootstrap_from_nodes(&[Node::new(
+                Id::random(),
+                SocketAddrV4::new(Ipv4Addr::LOCALHOST, 1),
+            )])
+            .request_timeout(Duration::from_millis(100))
+            .build()
+            .unwrap();
+
+        let mut populated = false;
+        for _ in 0..100 {
+            if !c.routing_table().is_empty() {
+                populated = true;
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        assert!(populated);
+    }
+
+    #[test]
+    fn ping() {
+        let testnet = Testnet::new(1).unwrap();
+
+        let dht = Dht::builder()
+            .no_bootstrap()
+            .request_timeout(Duration::from_millis(200))
+            .build()
+            .unwrap();
+
+        let port = testnet.nodes[0].local_addr().port();
+        let rtt = dht.ping(SocketAddrV4::new(Ipv4Addr::LOCALHOST, port));
+
+        assert!(rtt.unwrap() < Duration::from_millis(200));
+
+        // A node that never responds.
+        let silent = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let port = silent.local_addr().unwrap().port();
+
+        assert_eq!(dht.ping(SocketAddrV4::new(Ipv4Addr::LOCALHOST, port)), None);
+    }
+
+    #[test]
+    fn on_request() {
+        let (tx, rx) = flume::unbounded();
+
+        let server = Dht::builder()
+            .no_bootstrap()
+            .server_mode()
+            .on_request(move |from, request| {
+                let _ = tx.send((from, request.request_type.clone()));
+            })
+            .build()
+            .unwrap();
+
+        let client = Dht::builder().no_bootstrap().build().unwrap();
+
+        let port = server.local_addr().port();
+        client
+            .ping(SocketAddrV4::new(Ipv4Addr::LOCALHOST, port))
+            .unwrap();
+
+        let (from, request_type) = rx.recv().unwrap();
+        assert_eq!(from.port(), client.local_addr().port());
+        assert!(matches!(request_type, RequestTypeSpecific::Ping));
+    }
+
+    #[test]
+    fn blocklist() {
+        let client = Dht::builder()
+            .no_bootstrap()
+            .request_timeout(Duration::from_millis(200))
+            .build()
+            .unwrap();
+
+        let server = Dht::builder()
+            .no_bootstrap()
+            .server_mode()
+            .blocklist(&["127.0.0.0/8".parse().unwrap()])
+            .build()
+            .unwrap();
+        let address = SocketAddrV4::new(Ipv4Addr::LOCALHOST, server.local_addr().port());
+
+        assert_eq!(client.ping(address), None);
+
+        server.unban("127.0.0.0/8".parse::<Ipv4Net>().unwrap());
+        // Wait for the actor to handle the message.
+        server.info();
+        assert!(client.ping(address).is_some());
+
+        server.ban(Ipv4Addr::LOCALHOST);
+        server.info();
+        assert_eq!(client.ping(address), None);
+    }
+
+    #[test]
+    fn announce_peer_report() {
+        let testnet = Testnet::new(10).unwrap();
+
+        let dht = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+
+        let info_hash = Id::random();
+
+        let report = dht
+            .announce_peer_report(info_hash, Some(45555))
+            .expect("failed to announce");
+
+        assert_eq!(report.info_hash, info_hash);
+        assert!(!report.announced_on.is_empty());
+        assert!(report.announced_on.len() <= report.queried);
+        assert_eq!(
+            report
+                .stored_on
+                .iter()
+                .map(|node| node.address())
+                .collect::<Vec<_>>(),
+            report.announced_on
+        );
+    }
+
+    #[test]
+    fn success_rates() {
+        let testnet = Testnet::new(10).unwrap();
+
+        let a = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+        let b = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+
+        assert_eq!(a.info().put_success_rate(), None);
+        assert_eq!(b.info().get_success_rate(), None);
+
+        let info_hash = Id::random();
+
+        a.announce_peer(info_hash, Some(45555))
+            .expect("failed to announce");
+
+        let _ = b.get_peers(info_hash).count();
+        let _ = b.get_peers(Id::random()).count();
+
+        assert_eq!(a.info().put_success_rate(), Some(1.0));
+        assert_eq!(b.info().get_success_rate(), Some(0.5));
+    }
+
+    #[test]
+    fn pause_resume() {
+        let testnet = Testnet::new(10).unwrap();
+
+        let dht = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+
+        dht.pause();
+
+        let (tx, rx) = flume::bounded(1);
+        let client = dht.clone();
+        thread::spawn(move || {
+            let _ = tx.send(client.find_node(Id::random()));
+        });
+
+        // Other messages are still handled while paused.
+        dht.info();
+
+        assert!(rx.recv_timeout(Duration::from_millis(200)).is_err());
+
+        dht.resume();
+
+        let nodes = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        assert!(!nodes.is_empty());
+    }
+
+    #[test]
+    fn pause_defers_republish_and_external_ip() {
+        let testnet = Testnet::new(10).unwrap();
+
+        let dht = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+
+        dht.find_node(Id::random());
+        dht.pause();
+
+        // Let the actor finish its current tick.
+        thread::sleep(Duration::from_millis(50));
+        let packets_sent = dht.info().packets_sent();
+
+        let _handle = dht.put_immutable_repeating(b"Hello", None);
+
+        let ip = Ipv4Addr::new(93, 184, 216, 34);
+        let new_id = dht.set_external_ip(ip);
+        assert!(new_id.is_valid_for_ip(ip));
+        assert_eq!(dht.info().id(), &new_id);
+
+        thread::sleep(Duration::from_millis(200));
+        assert_eq!(dht.info().packets_sent(), packets_sent);
+
+        dht.resume();
+        thread::sleep(Duration::from_millis(200));
+        assert!(dht.info().packets_sent() > packets_sent);
+    }
+
+    #[test]
+    fn put_get_immutable() {
+        let testnet = Testnet::new(10).unwrap();
+
+        let a = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+        let b = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+
+        let value = b"Hello World!";
+        let expected_target = Id::from_str("e5f96f6f38320f0f33959cb4d3d656452117aadb").unwrap();
+
+        let target = a.put_immutable(value).unwrap();
+        assert_eq!(target, expected_target);
+
+        let response = b.get_immutable(target).unwrap();
+
+        assert_eq!(response, value.to_vec().into_boxed_slice());
+    }
+
+    /// Run-length encoding, as a stand-in for a real compression codec.
+    #[derive(Debug, Clone)]
+    struct RunLengthCodec;
+
+    impl ValueCodec for RunLengthCodec {
+        fn encode(&self, value: &[u8]) -> Box<[u8]> {
+            let mut encoded = Vec::new();
+
+            for byte in value {
+                match encoded.len() {
+                    len if len >= 2 && encoded[len - 1] == *byte && encoded[len - 2] < u8::MAX => {
+                        encoded[len - 2] += 1
+                    }
+                    _ => encoded.extend([1, *byte]),
+                }
+            }
+
+            encoded.into()
+        }
+
+        fn decode(&self, encoded: &[u8]) -> Option<Box<[u8]>> {
+            if !encoded.len().is_multiple_of(2) {
+                return None;
+            }
+
+            Some(
+                encoded
+                    .chunks(2)
+                    .flat_map(|pair| std::iter::repeat_n(pair[1], pair[0] as usize))
+                    .collect(),
+            )
+        }
+    }
+
+    #[test]
+    fn put_get_immutable_with_codec() {
+        let testnet = Testnet::new(10).unwrap();
+
+        let a = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .value_codec(Box::new(RunLengthCodec))
+            .build()
+            .unwrap();
+        let b = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .value_codec(Box::new(RunLengthCodec))
+            .build()
+            .unwrap();
+        let plain = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+
+        // Too large to store without encoding.
+        let value = [7; MAX_VALUE_SIZE * 2];
+        let encoded = RunLengthCodec.encode(&value);
+
+        let target = a.put_immutable(&value).unwrap();
+        assert_eq!(target, Id::from(hash_immutable(&encoded)));
+
+        assert_eq!(b.get_immutable(target), Some(value.into()));
+        assert_eq!(plain.get_immutable(target), Some(encoded));
+    }
+
+    #[test]
+    fn put_get_immutable_chunked() {
+        let testnet = Testnet::new(10).unwrap();
+
+        let a = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+        let b = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+
+        let value = (0..MAX_VALUE_SIZE * 3 + 10)
+            .map(|i| i as u8)
+            .collect::<Vec<_>>();
+
+        let manifest = a.put_immutable_chunked(&value).unwrap();
+
+        let chunks = parse_chunks_manifest(&b.get_immutable(manifest).unwrap()).unwrap();
+        assert_eq!(chunks.len(), 4);
+
+        assert_eq!(b.get_immutable_chunked(manifest), Some(value.into()));
+        assert_eq!(b.get_immutable_chunked(Id::random()), None);
+
+        // Not a manifest.
+        let target = a.put_immutable(&[0; ID_SIZE * 2]).unwrap();
+        assert_eq!(b.get_immutable_chunked(target), None);
+    }
+
+    #[test]
+    fn put_get_immutable_chunked_with_codec() {
+        let testnet = Testnet::new(10).unwrap();
+
+        let a = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .value_codec(Box::new(RunLengthCodec))
+            .build()
+            .unwrap();
+        let b = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .value_codec(Box::new(RunLengthCodec))
+            .build()
+            .unwrap();
+        let plain = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+
+        // Doubles in size when run-length encoded.
+        let value = (0..MAX_VALUE_SIZE * 2).map(|i| i as u8).collect::<Vec<_>>();
+
+        let manifest = a.put_immutable_chunked(&value).unwrap();
+
+        // Chunks are split from the encoded value, and the manifest isn't encoded.
+        let chunks = parse_chunks_manifest(&plain.get_immutable(manifest).unwrap()).unwrap();
+        assert_eq!(chunks.len(), 4);
+
+        assert_eq!(b.get_immutable_chunked(manifest), Some(value.into()));
+    }
+
+    #[test]
+    fn chunks_manifest() {
+        let chunks = vec![Id::random(), Id::random()];
+
+        let manifest = super::chunks_manifest(&chunks);
+        assert_eq!(manifest.len(), CHUNKS_MANIFEST_TAG.len() + 2 * ID_SIZE);
+        assert_eq!(parse_chunks_manifest(&manifest), Some(chunks));
+
+        assert_eq!(parse_chunks_manifest(CHUNKS_MANIFEST_TAG), Some(vec![]));
+        assert_eq!(parse_chunks_manifest(&[]), None);
+        assert_eq!(parse_chunks_manifest(&manifest[..manifest.len() - 1]), None);
+        assert_eq!(
+            parse_chunks_manifest(&manifest[CHUNKS_MANIFEST_TAG.len()..]),
+            None
+        );
+
+        let too_large = [0; MAX_VALUE_SIZE * MAX_IMMUTABLE_CHUNKS + 1];
+        assert!(matches!(
+            check_chunked_size(&too_large),
+            Err(PutQueryError::ValueTooLarge {
+                len: 49001,
+                max: 49000
+            })
+        ));
+        assert!(check_chunked_size(&too_large[1..]).is_ok());
+    }
+
+    #[test]
+    fn find_node_no_values() {
+        let client = Dht::builder().no_bootstrap().build().unwrap();
+
+        client.find_node(Id::random());
+    }
+
+    #[test]
+    fn immutable_cache() {
+        let testnet = Testnet::new(10).unwrap();
+
+        let a = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+        let b = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .immutable_cache_size(10)
+            .build()
+            .unwrap();
+
+        let value = b"Hello World!";
+        let target = a.put_immutable(value).unwrap();
 
-/// Create a testnet of Dht nodes to run tests against instead of the real mainline network.
-#[derive(Debug)]
-pub struct Testnet {
-    /// bootstrapping nodes for this testnet.
-    pub bootstrap: Vec<String>,
-    /// all nodes in this testnet
-    pub nodes: Vec<Dht>,
-}
+        assert_eq!(b.get_immutable(target).as_deref(), Some(value.as_ref()));
+        assert_eq!(b.info().immutable_cache_misses(), 1);
+        assert_eq!(b.info().immutable_cache_hits(), 0);
 
-impl Testnet {
-    /// Create a new testnet with a certain size.
-    ///
-    /// Note: this network will be shutdown as soon as this struct
-    /// gets dropped, if you want the network to be `'static`, then
-    /// you should call [Self::leak].
-    pub fn new(count: usize) -> Result<Testnet, std::io::Error> {
-        let mut nodes: Vec<Dht> = vec![];
-        let mut bootstrap = vec![];
+        assert_eq!(b.get_immutable(target).as_deref(), Some(value.as_ref()));
+        assert_eq!(b.info().immutable_cache_misses(), 1);
+        assert_eq!(b.info().immutable_cache_hits(), 1);
+    }
 
-        for i in 0..count {
-            if i == 0 {
-                let node = Dht::builder().server_mode().no_bootstrap().build()?;
+    #[test]
+    fn put_get_immutable_no_values() {
+        let client = Dht::builder().no_bootstrap().build().unwrap();
 
-                let info = node.info();
-                let addr = info.local_addr();
+        assert_eq!(client.get_immutable(Id::random()), None);
+    }
 
-                bootstrap.push(format!("127.0.0.1:{}", addr.port()));
+    #[test]
+    fn put_value_too_large() {
+        let dht = Dht::builder().no_bootstrap().build().unwrap();
 
-                nodes.push(node)
-            } else {
-                let node = Dht::builder().server_mode().bootstrap(&bootstrap).build()?;
-                nodes.push(node)
-            }
-        }
+        let value = [0; MAX_VALUE_SIZE + 1];
 
-        let testnet = Self { bootstrap, nodes };
+        assert!(matches!(
+            dht.put_immutable(&value),
+            Err(PutQueryError::ValueTooLarge {
+                len: 1001,
+                max: 1000
+            })
+        ));
 
-        Ok(testnet)
-    }
+        let item = MutableItem::new(SigningKey::from_bytes(&[0; 32]), &value, 1, None);
 
-    /// By default as soon as this testnet gets dropped,
-    /// all the nodes get dropped and the entire network is shutdown.
-    ///
-    /// This method uses [Box::leak] to keep nodes running, which is
-    /// useful if you need to keep running the testnet in the process
-    /// even if this struct gets dropped.
-    pub fn leak(&self) {
-        for node in self.nodes.clone() {
-            Box::leak(Box::new(node));
-        }
+        assert!(matches!(
+            dht.put_mutable(item, None),
+            Err(PutMutableError::Query(PutQueryError::ValueTooLarge {
+                len: 1001,
+                max: 1000
+            }))
+        ));
     }
-}
 
-#[derive(thiserror::Error, Debug)]
-/// Put MutableItem errors.
-pub enum PutMutableError {
-    #[error(transparent)]
-    /// Common PutQuery errors
-    Query(#[from] PutQueryError),
+    #[test]
+    fn put_get_mutable() {
+        let testnet = Testnet::new(10).unwrap();
 
-    #[error(transparent)]
-    /// PutQuery for [crate::MutableItem] errors
-    Concurrency(#[from] ConcurrencyError),
-}
+        let a = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+        let b = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
 
-#[cfg(test)]
-mod test {
-    use std::str::FromStr;
+        let signer = SigningKey::from_bytes(&[
+            56, 171, 62, 85, 105, 58, 155, 209, 189, 8, 59, 109, 137, 84, 84, 201, 221, 115, 7,
+            228, 127, 70, 4, 204, 182, 64, 77, 98, 92, 215, 27, 103,
+        ]);
 
-    use ed25519_dalek::SigningKey;
+        let seq = 1000;
+        let value = b"Hello World!";
 
-    use crate::rpc::ConcurrencyError;
+        let item = MutableItem::new(signer.clone(), value, seq, None);
 
-    use super::*;
+        a.put_mutable(item.clone(), None).unwrap();
 
-    #[test]
-    fn bind_twice() {
-        let a = Dht::client().unwrap();
-        let result = Dht::builder()
-            .port(a.info().local_addr().port())
-            .server_mode()
-            .build();
+        let response = b
+            .get_mutable(signer.verifying_key().as_bytes(), None, None)
+            .next()
+            .expect("No mutable values");
 
-        assert!(result.is_err());
+        assert_eq!(&response, &item);
     }
 
     #[test]
-    fn announce_get_peer() {
+    fn get_mutable_many() {
         let testnet = Testnet::new(10).unwrap();
 
         let a = Dht::builder()
@@ -742,18 +5244,24 @@ mod test {
             .build()
             .unwrap();
 
-        let info_hash = Id::random();
+        let signer = SigningKey::from_bytes(&[7; 32]);
 
-        a.announce_peer(info_hash, Some(45555))
-            .expect("failed to announce");
+        let first = MutableItem::new(signer.clone(), b"first", 1, Some(b"a"));
+        let second = MutableItem::new(signer.clone(), b"second", 2, Some(b"b"));
 
-        let peers = b.get_peers(info_hash).next().expect("No peers");
+        a.put_mutable(first.clone(), None).unwrap();
+        a.put_mutable(second.clone(), None).unwrap();
 
-        assert_eq!(peers.first().unwrap().port(), 45555);
+        let items =
+            b.get_mutable_many(signer.verifying_key().as_bytes(), &[b"a", b"b", b"missing"]);
+
+        assert_eq!(items.len(), 2);
+        assert_eq!(items.get(&b"a"[..]), Some(&first));
+        assert_eq!(items.get(&b"b"[..]), Some(&second));
     }
 
     #[test]
-    fn put_get_immutable() {
+    fn clear_mutable() {
         let testnet = Testnet::new(10).unwrap();
 
         let a = Dht::builder()
@@ -765,33 +5273,83 @@ mod test {
             .build()
             .unwrap();
 
-        let value = b"Hello World!";
-        let expected_target = Id::from_str("e5f96f6f38320f0f33959cb4d3d656452117aadb").unwrap();
+        let signer = SigningKey::from_bytes(&[
+            56, 171, 62, 85, 105, 58, 155, 209, 189, 8, 59, 109, 137, 84, 84, 201, 221, 115, 7,
+            228, 127, 70, 4, 204, 182, 64, 77, 98, 92, 215, 27, 103,
+        ]);
 
-        let target = a.put_immutable(value).unwrap();
-        assert_eq!(target, expected_target);
+        let seq = 1000;
+        let item = MutableItem::new(signer.clone(), b"Hello World!", seq, None);
 
-        let response = b.get_immutable(target).unwrap();
+        a.put_mutable(item, None).unwrap();
+        a.clear_mutable(signer.clone(), None, seq).unwrap();
 
-        assert_eq!(response, value.to_vec().into_boxed_slice());
+        let response = b
+            .get_mutable(signer.verifying_key().as_bytes(), None, Some(seq))
+            .next()
+            .expect("No mutable values");
+
+        assert_eq!(response.seq(), seq + 1);
+        assert!(response.value().is_empty());
     }
 
     #[test]
-    fn find_node_no_values() {
-        let client = Dht::builder().no_bootstrap().build().unwrap();
+    fn confirm_absent() {
+        let testnet = Testnet::new(10).unwrap();
 
-        client.find_node(Id::random());
+        let dht = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+
+        let signer = SigningKey::from_bytes(&[
+            56, 171, 62, 85, 105, 58, 155, 209, 189, 8, 59, 109, 137, 84, 84, 201, 221, 115, 7,
+            228, 127, 70, 4, 204, 182, 64, 77, 98, 92, 215, 27, 103,
+        ]);
+        let public_key = signer.verifying_key().to_bytes();
+        let salt = Some(b"name".as_ref());
+
+        assert!(dht.confirm_absent(&public_key, salt, 1));
+        assert!(!dht.confirm_absent(&public_key, salt, 100));
+
+        let item = MutableItem::new(signer, b"claimed", 1, salt);
+        dht.put_mutable(item, None).unwrap();
+
+        assert!(!dht.confirm_absent(&public_key, salt, 1));
     }
 
     #[test]
-    fn put_get_immutable_no_values() {
-        let client = Dht::builder().no_bootstrap().build().unwrap();
+    fn put_mutable_with_min_replicas() {
+        let testnet = Testnet::new(10).unwrap();
 
-        assert_eq!(client.get_immutable(Id::random()), None);
+        let dht = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+
+        let signer = SigningKey::from_bytes(&[
+            56, 171, 62, 85, 105, 58, 155, 209, 189, 8, 59, 109, 137, 84, 84, 201, 221, 115, 7,
+            228, 127, 70, 4, 204, 182, 64, 77, 98, 92, 215, 27, 103,
+        ]);
+
+        let item = MutableItem::new(signer.clone(), b"Hello", 1, None);
+        let target = dht
+            .put_mutable_with_min_replicas(item.clone(), None, 1)
+            .unwrap();
+        assert_eq!(target, *item.target());
+
+        let item = MutableItem::new(signer, b"Hello again", 2, None);
+        let result = dht.put_mutable_with_min_replicas(item, Some(1), 100);
+
+        assert!(matches!(
+            result,
+            Err(PutMutableError::Query(PutQueryError::NotEnoughReplicas { stored, min: 100 }))
+                if stored >= 1
+        ));
     }
 
     #[test]
-    fn put_get_mutable() {
+    fn put_mutable_merged() {
         let testnet = Testnet::new(10).unwrap();
 
         let a = Dht::builder()
@@ -807,20 +5365,146 @@ mod test {
             56, 171, 62, 85, 105, 58, 155, 209, 189, 8, 59, 109, 137, 84, 84, 201, 221, 115, 7,
             228, 127, 70, 4, 204, 182, 64, 77, 98, 92, 215, 27, 103,
         ]);
+        let salt = Some(b"shared".as_ref());
 
-        let seq = 1000;
-        let value = b"Hello World!";
+        let merge =
+            |most_recent: &MutableItem, value: &[u8]| [most_recent.value(), b",", value].concat();
 
-        let item = MutableItem::new(signer.clone(), value, seq, None);
+        a.put_mutable_merged(signer.clone(), salt, b"a", merge)
+            .unwrap();
+        b.put_mutable_merged(signer.clone(), salt, b"b", merge)
+            .unwrap();
 
-        a.put_mutable(item.clone(), None).unwrap();
+        let item = a
+            .get_mutable_most_recent(signer.verifying_key().as_bytes(), salt)
+            .expect("No mutable values");
 
-        let response = b
-            .get_mutable(signer.verifying_key().as_bytes(), None, None)
-            .next()
+        assert_eq!(item.seq(), 2);
+        assert_eq!(item.value(), b"a,b");
+    }
+
+    #[test]
+    fn put_mutable_merged_conflicting_writer() {
+        let testnet = Testnet::new(10).unwrap();
+
+        let a = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+        let b = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+
+        let signer = SigningKey::from_bytes(&[
+            56, 171, 62, 85, 105, 58, 155, 209, 189, 8, 59, 109, 137, 84, 84, 201, 221, 115, 7,
+            228, 127, 70, 4, 204, 182, 64, 77, 98, 92, 215, 27, 103,
+        ]);
+        let salt = Some(b"conflict".as_ref());
+
+        a.put_mutable(MutableItem::new(signer.clone(), b"0", 1, salt), None)
+            .unwrap();
+
+        // `b` wins the race for the first `conflicts` attempts, by putting
+        // a more recent item between `a`'s read and write.
+        let merge_with_conflicts = |conflicts: usize| {
+            let attempts = std::cell::Cell::new(0);
+            let signer = signer.clone();
+            let b = b.clone();
+
+            move |most_recent: &MutableItem, value: &[u8]| {
+                attempts.set(attempts.get() + 1);
+
+                if attempts.get() <= conflicts {
+                    b.put_mutable(
+                        MutableItem::new(signer.clone(), b"b", most_recent.seq() + 1, salt),
+                        Some(most_recent.seq()),
+                    )
+                    .unwrap();
+                }
+
+                [most_recent.value(), b",", value].concat()
+            }
+        };
+
+        a.put_mutable_merged(signer.clone(), salt, b"a", merge_with_conflicts(2))
+            .unwrap();
+
+        let item = a
+            .get_mutable_most_recent(signer.verifying_key().as_bytes(), salt)
             .expect("No mutable values");
 
-        assert_eq!(&response, &item);
+        // Retried on top of both conflicting writes.
+        assert_eq!(item.seq(), 4);
+        assert_eq!(item.value(), b"b,a");
+
+        // Gives up after MAX_MERGE_ATTEMPTS.
+        assert!(matches!(
+            a.put_mutable_merged(
+                signer.clone(),
+                salt,
+                b"a",
+                merge_with_conflicts(MAX_MERGE_ATTEMPTS)
+            ),
+            Err(PutMutableError::Concurrency(_))
+        ));
+
+        let item = a
+            .get_mutable_most_recent(signer.verifying_key().as_bytes(), salt)
+            .expect("No mutable values");
+
+        assert_eq!(item.seq(), 4 + MAX_MERGE_ATTEMPTS as i64);
+        assert_eq!(item.value(), b"b");
+    }
+
+    #[test]
+    fn merged_item_seq_overflow() {
+        let signer = SigningKey::from_bytes(&[0; 32]);
+        let most_recent = MutableItem::new(signer.clone(), b"a", i64::MAX, None);
+
+        assert!(matches!(
+            merged_item(&signer, None, b"b", Some(most_recent), |_, value| value
+                .to_vec()),
+            Err(PutQueryError::SeqOverflow)
+        ));
+    }
+
+    #[test]
+    fn more_recent_mutable() {
+        let signer = SigningKey::from_bytes(&[0; 32]);
+
+        let old = MutableItem::new(signer.clone(), b"b", 1, None);
+        let new = MutableItem::new(signer.clone(), b"a", 2, None);
+        let tie = MutableItem::new(signer, b"c", 2, None);
+
+        assert_eq!(more_recent(None, old.clone()), old);
+        assert_eq!(more_recent(Some(old.clone()), new.clone()), new);
+        assert_eq!(more_recent(Some(new.clone()), old), new);
+        assert_eq!(more_recent(Some(new.clone()), tie.clone()), tie);
+        assert_eq!(more_recent(Some(tie.clone()), new), tie);
+    }
+
+    #[test]
+    fn get_mutable_most_recent_until() {
+        let testnet = Testnet::new(10).unwrap();
+
+        let dht = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+
+        let signer = SigningKey::from_bytes(&[
+            56, 171, 62, 85, 105, 58, 155, 209, 189, 8, 59, 109, 137, 84, 84, 201, 221, 115, 7,
+            228, 127, 70, 4, 204, 182, 64, 77, 98, 92, 215, 27, 103,
+        ]);
+
+        let item = MutableItem::new(signer.clone(), b"Hello World!", 5, None);
+        dht.put_mutable(item.clone(), None).unwrap();
+
+        let most_recent =
+            dht.get_mutable_most_recent_until(signer.verifying_key().as_bytes(), None, 5);
+
+        assert_eq!(most_recent, Some(item));
     }
 
     #[test]
@@ -855,6 +5539,38 @@ mod test {
         assert!(&response.is_none());
     }
 
+    #[test]
+    fn put_immutable_skip_if_present() {
+        let testnet = Testnet::new(10).unwrap();
+
+        let a = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+        let b = Dht::builder()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+
+        let id = a.put_immutable(&[1, 2, 3]).unwrap();
+
+        let options = PutOptions {
+            skip_if_present: true,
+            min_present: 1,
+        };
+
+        assert_eq!(
+            b.put_immutable_with_options(&[1, 2, 3], options).unwrap(),
+            id
+        );
+        // Skipped the PUT query.
+        assert_eq!(b.info().put_success_rate(), None);
+
+        // Not present yet.
+        b.put_immutable_with_options(&[4, 5, 6], options).unwrap();
+        assert_eq!(b.info().put_success_rate(), Some(1.0));
+    }
+
     #[test]
     fn repeated_put_query() {
         let testnet = Testnet::new(10).unwrap();
@@ -972,7 +5688,7 @@ mod test {
             let handle = std::thread::spawn(move || {
                 let result = client.put_mutable(item, None);
                 if i == 0 {
-                    assert!(matches!(result, Ok(_)))
+                    assert!(result.is_ok())
                 } else {
                     assert!(matches!(
                         result,
@@ -1007,7 +5723,7 @@ mod test {
         {
             let item = MutableItem::new(signer.clone(), &[], 1000, None);
 
-            let (sender, _) = flume::bounded::<Result<Id, PutError>>(1);
+            let (sender, _) = flume::bounded::<Result<PutReport, PutError>>(1);
             let request =
                 PutRequestSpecific::PutMutable(PutMutableRequestArguments::from(item, None));
             client
@@ -16,15 +16,34 @@ mod rpc;
 #[cfg(feature = "async")]
 pub mod async_dht;
 
-pub use common::{Id, MutableItem, Node, RoutingTable};
+pub use common::{
+    BloomFilter, BucketInfo, ClientVersion, Id, IdentityCodec, Ipv4Net, MutableItem,
+    MutableItemBuilder, Node, RoutingTable, ValueCodec, BLOOM_FILTER_SIZE, MAX_VALUE_SIZE,
+};
 
 #[cfg(feature = "node")]
-pub use dht::{Dht, DhtBuilder, Testnet};
+pub use dht::{
+    AnnounceReport, Dht, DhtBuilder, DhtEvent, ImmutableOutcome, InfohashSamples, Observed,
+    PutOptions, RawResponse, ReachabilityReport, RepublishHandle, ScrapeResult, Testnet,
+    TestnetBuilder, DEFAULT_MIN_PRESENT, MAX_IMMUTABLE_CHUNKS, MAX_MERGE_ATTEMPTS,
+    PREFIX_DISCOVERY_IDLE_ROUNDS,
+};
 #[cfg(feature = "node")]
 pub use rpc::{
-    messages::{MessageType, PutRequestSpecific, RequestSpecific},
-    server::{RequestFilter, ServerSettings, MAX_INFO_HASHES, MAX_PEERS, MAX_VALUES},
-    ClosestNodes, DEFAULT_REQUEST_TIMEOUT,
+    bootstrap_defaults,
+    messages::{
+        FindNodeRequestArguments, GetPeersRequestArguments, GetValueRequestArguments, MessageType,
+        PutRequestSpecific, RequestSpecific, RequestTypeSpecific, ResponseSpecific,
+    },
+    server::{
+        PeerStorage, RequestFilter, ServerSettings, ServerStats, MAX_INFOHASH_SAMPLES,
+        MAX_INFO_HASHES, MAX_PEERS, MAX_VALUES, SAMPLE_INFOHASHES_INTERVAL,
+    },
+    ClosestNodes, GetRequestSpecific, Info, InfoChange, MemoryReport, NetworkConditions,
+    PeersResponse, PutReport, QueryTiming, RetransmitPolicy, DEFAULT_BOOTSTRAP_NODES,
+    DEFAULT_BUCKET_REFRESH_INTERVAL, DEFAULT_ITEM_REFRESH_INTERVAL, DEFAULT_MAX_QUERY_LIFETIME,
+    DEFAULT_REQUEST_TIMEOUT, DEFAULT_TOKEN_LIFETIME, DHT_SIZE_ESTIMATE_HISTORY,
+    MAX_CACHED_ITERATIVE_QUERIES,
 };
 
 pub use ed25519_dalek::SigningKey;
@@ -34,10 +53,12 @@ pub mod errors {
     #[cfg(feature = "node")]
     pub use super::common::ErrorSpecific;
     #[cfg(feature = "node")]
-    pub use super::dht::PutMutableError;
+    pub use super::dht::{PutMutableError, RequestError};
     #[cfg(feature = "node")]
     pub use super::rpc::{ConcurrencyError, PutError, PutQueryError};
 
     pub use super::common::DecodeIdError;
+    pub use super::common::DecodeIpv4NetError;
+    pub use super::common::MagnetError;
     pub use super::common::MutableError;
 }
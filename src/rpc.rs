@@ -7,63 +7,139 @@ mod iterative_query;
 mod put_query;
 pub(crate) mod server;
 mod socket;
+#[cfg(feature = "node")]
+mod unreachable;
 
-use std::collections::HashMap;
-use std::net::{SocketAddr, SocketAddrV4, ToSocketAddrs};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, ToSocketAddrs};
 use std::num::NonZeroUsize;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime};
 
 use lru::LruCache;
-use tracing::{debug, error, info};
+use tracing::{debug, debug_span, error, info, warn, Span};
 
 use iterative_query::IterativeQuery;
 use put_query::PutQuery;
 
 use crate::common::{
-    validate_immutable, ErrorSpecific, FindNodeRequestArguments, GetImmutableResponseArguments,
-    GetMutableResponseArguments, GetPeersResponseArguments, GetValueRequestArguments, Id, Message,
-    MessageType, MutableItem, NoMoreRecentValueResponseArguments, NoValuesResponseArguments, Node,
+    validate_immutable, AnnouncePeerRequestArguments, ErrorSpecific, FindNodeRequestArguments,
+    GetImmutableResponseArguments, GetMutableResponseArguments, GetPeersResponseArguments,
+    GetValueRequestArguments, Id, Ipv4Net, Message, MessageType, MutableItem,
+    NoMoreRecentValueResponseArguments, NoValuesResponseArguments, Node, PingResponseArguments,
     PutRequestSpecific, RequestSpecific, RequestTypeSpecific, ResponseSpecific, RoutingTable,
-    MAX_BUCKET_SIZE_K,
+    MAX_BUCKET_SIZE_K, MAX_VALUE_SIZE, TOKEN_ROTATE_INTERVAL,
 };
 use server::Server;
 
-use self::messages::{GetPeersRequestArguments, PutMutableRequestArguments};
-use server::ServerSettings;
+use self::messages::{
+    GetPeersRequestArguments, PutImmutableRequestArguments, PutMutableRequestArguments,
+};
+use server::{ServerSettings, ServerStats};
 use socket::KrpcSocket;
 
 pub use crate::common::messages;
 pub use closest_nodes::ClosestNodes;
-pub use info::Info;
-pub use iterative_query::GetRequestSpecific;
-pub use put_query::{ConcurrencyError, PutError, PutQueryError};
-pub use socket::DEFAULT_REQUEST_TIMEOUT;
-
-pub const DEFAULT_BOOTSTRAP_NODES: [&str; 4] = [
+#[cfg(feature = "node")]
+pub use info::InfoChange;
+pub use info::{Info, MemoryReport};
+pub use iterative_query::{GetRequestSpecific, QueryTiming};
+pub use put_query::{ConcurrencyError, PutError, PutQueryError, PutReport};
+pub use socket::{NetworkConditions, RetransmitPolicy, DEFAULT_REQUEST_TIMEOUT};
+
+/// Default bootstrapping nodes, used unless [crate::DhtBuilder::bootstrap] is set.
+pub const DEFAULT_BOOTSTRAP_NODES: &[&str] = &[
     "router.bittorrent.com:6881",
     "dht.transmissionbt.com:6881",
     "dht.libtorrent.org:25401",
     "relay.pkarr.org:6881",
 ];
 
-const REFRESH_TABLE_INTERVAL: Duration = Duration::from_secs(15 * 60);
+/// Returns the [DEFAULT_BOOTSTRAP_NODES] as owned strings, for example
+/// to filter or extend them before passing them to [crate::DhtBuilder::bootstrap].
+///
+/// To only add nodes to the defaults, use [crate::DhtBuilder::add_bootstrap] instead.
+pub fn bootstrap_defaults() -> Vec<String> {
+    DEFAULT_BOOTSTRAP_NODES
+        .iter()
+        .map(|node| node.to_string())
+        .collect()
+}
+
+/// Interval to check if an adaptive node should switch to server mode.
+const ADAPTIVE_MODE_INTERVAL: Duration = Duration::from_secs(15 * 60);
 const PING_TABLE_INTERVAL: Duration = Duration::from_secs(5 * 60);
 
-const MAX_CACHED_ITERATIVE_QUERIES: usize = 1000;
+/// Number of most recent GET and PUT queries used to calculate their success rates.
+const SUCCESS_RATE_WINDOW: usize = 100;
+
+/// Default number of most recent Dht size estimates kept,
+/// see [crate::DhtBuilder::max_estimate_samples].
+pub const DHT_SIZE_ESTIMATE_HISTORY: usize = 100;
+
+/// Default interval to refresh (re-put or re-announce) stored items at,
+/// see [crate::DhtBuilder::item_refresh_interval].
+///
+/// Half the roughly 2 hours that most nodes in the network keep items, and
+/// announced peers, for before they expire.
+pub const DEFAULT_ITEM_REFRESH_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Default interval to refresh the routing table at, see [crate::DhtBuilder::bucket_refresh_interval].
+pub const DEFAULT_BUCKET_REFRESH_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+/// Default maximum lifetime of a query, see [crate::DhtBuilder::max_query_lifetime].
+pub const DEFAULT_MAX_QUERY_LIFETIME: Duration = Duration::from_secs(60);
+
+/// Default lifetime of write tokens, see [crate::DhtBuilder::token_lifetime].
+///
+/// Most nodes rotate their token secret every 5 minutes, and accept tokens
+/// generated with the current or previous secret.
+pub const DEFAULT_TOKEN_LIFETIME: Duration = TOKEN_ROTATE_INTERVAL;
+
+/// Default maximum number of cached done queries, see [crate::DhtBuilder::max_cached_queries].
+pub const MAX_CACHED_ITERATIVE_QUERIES: usize = 1000;
 
 #[derive(Debug)]
 /// Internal Rpc called in the Dht thread loop, useful to create your own actor setup.
 pub struct Rpc {
     // Options
     bootstrap: Box<[SocketAddrV4]>,
+    /// [Self::bootstrap] nodes that responded to any request since startup.
+    bootstrap_responded: HashSet<SocketAddrV4>,
+    /// Nodes to try before the [Self::bootstrap] nodes, until the first
+    /// routing table population is done, see [config::Config::bootstrap_from_nodes].
+    bootstrap_from_nodes: Option<Box<[Node]>>,
+    /// See [config::Config::skip_self_lookup].
+    skip_self_lookup: bool,
+    /// See [config::Config::item_refresh_interval].
+    item_refresh_interval: Duration,
+    /// See [config::Config::bucket_refresh_interval].
+    bucket_refresh_interval: Duration,
+    /// See [config::Config::max_query_lifetime].
+    max_query_lifetime: Duration,
+    /// See [config::Config::max_concurrent_queries].
+    max_concurrent_queries: Option<usize>,
+    /// See [config::Config::token_lifetime].
+    token_lifetime: Duration,
+    /// See [config::Config::k_factor].
+    k: usize,
+    /// See [config::Config::max_lookup_rounds].
+    max_lookup_rounds: Option<usize>,
+    /// See [config::Config::node_id_seed].
+    node_id_seed: Option<[u8; 32]>,
+    /// See [config::Config::on_request].
+    on_request: Option<config::RequestObserver>,
 
     socket: KrpcSocket,
 
     // Routing
     /// Closest nodes to this node
     routing_table: RoutingTable,
-    /// Last time we refreshed the routing table with a find_node query.
+    /// Last time we refreshed the routing table with find_node queries.
     last_table_refresh: Instant,
+    /// Wall clock time of [Self::last_table_refresh], if any refresh happened yet.
+    last_table_refresh_time: Option<SystemTime>,
+    /// Last time we checked if an adaptive node should switch to server mode.
+    last_adaptive_mode_check: Instant,
     /// Last time we pinged nodes in the routing table.
     last_table_ping: Instant,
     /// Closest responding nodes to specific target
@@ -73,12 +149,20 @@ pub struct Rpc {
     /// 2. dht size estimate based on closest responding nodes.
     /// 3. number of subnets with unique 6 bits prefix in ipv4
     cached_iterative_queries: LruCache<Id, CachedIterativeQuery>,
+    /// Immutable values by their hash, see [config::Config::immutable_cache_size].
+    immutable_cache: Option<LruCache<Id, Box<[u8]>>>,
+    immutable_cache_hits: usize,
+    immutable_cache_misses: usize,
 
     // Active IterativeQueries
     iterative_queries: HashMap<Id, IterativeQuery>,
     /// Put queries are special, since they have to wait for a corresponding
     /// get query to finish, update the closest_nodes, then `query_all` these.
     put_queries: HashMap<Id, PutQuery>,
+    /// Queries waiting for a free slot, see [config::Config::max_concurrent_queries].
+    queued_queries: VecDeque<QueuedQuery>,
+    /// Id of the next query's tracing span.
+    next_query_id: u64,
 
     /// Sum of Dht size estimates from closest nodes from get queries.
     dht_size_estimates_sum: f64,
@@ -94,36 +178,108 @@ pub struct Rpc {
 
     public_address: Option<SocketAddrV4>,
     firewalled: bool,
+    /// See [config::Config::suggested_public_ip].
+    suggested_public_ip: Option<Ipv4Addr>,
+    /// Requester ids of pending reachability probes, and whether or not
+    /// a ping with that id was received, see [Self::expect_probe].
+    reachability_probes: HashMap<Id, bool>,
+
+    /// Info hashes we announced ourselves on, the explicit port if any,
+    /// and when we last did, see [config::Config::serve_own_peers].
+    own_peers: Option<HashMap<Id, (Option<u16>, Instant)>>,
+    /// See [config::Config::read_only].
+    read_only: bool,
+    /// See [config::Config::client_only].
+    client_only: bool,
+
+    /// Whether or not each of the most recent GET queries returned any values.
+    get_results: VecDeque<bool>,
+    /// Whether or not each of the most recent PUT queries stored at any nodes.
+    put_results: VecDeque<bool>,
+    /// The most recent [Self::dht_size_estimate]s, and when they were updated.
+    dht_size_estimate_history: VecDeque<(Instant, usize, f64)>,
+    /// Maximum length of [Self::dht_size_estimate_history].
+    max_estimate_samples: usize,
 }
 
 impl Rpc {
     /// Create a new Rpc
     pub fn new(config: config::Config) -> Result<Self, std::io::Error> {
-        let id = if let Some(ip) = config.public_ip {
-            Id::from_ip(ip.into())
-        } else {
-            Id::random()
+        let id = match (config.node_id, config.public_ip) {
+            (Some(id), Some(ip)) => {
+                if !id.is_valid_for_ip(ip) {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        format!("node_id {} is not a secure Id for public_ip {}", id, ip),
+                    ));
+                }
+
+                id
+            }
+            (Some(id), None) => id,
+            (None, Some(ip)) => match &config.node_id_seed {
+                Some(seed) => Id::from_ipv4_and_seed(ip, seed),
+                None => Id::from_ip(ip.into()),
+            },
+            (None, None) => config
+                .node_id_seed
+                .as_ref()
+                .map(Id::from_seed)
+                .unwrap_or_else(Id::random),
         };
 
         let socket = KrpcSocket::new(&config)?;
 
+        let mut bootstrap = config
+            .bootstrap
+            .unwrap_or_else(|| default_bootstrap(config.resolver.as_ref()));
+
+        if !config.extra_bootstrap.is_empty() {
+            let extra = config
+                .extra_bootstrap
+                .iter()
+                .map(String::as_str)
+                .collect::<Vec<_>>();
+
+            bootstrap.extend(resolve_bootstrap(&extra, config.resolver.as_ref()));
+            bootstrap = dedup(bootstrap);
+        }
+
         Ok(Rpc {
-            bootstrap: config
-                .bootstrap
-                .unwrap_or(to_socket_address(&DEFAULT_BOOTSTRAP_NODES))
-                .into(),
+            bootstrap: bootstrap.into(),
+            bootstrap_responded: HashSet::new(),
+            bootstrap_from_nodes: (!config.bootstrap_from_nodes.is_empty())
+                .then(|| config.bootstrap_from_nodes.into()),
+            skip_self_lookup: config.skip_self_lookup,
+            item_refresh_interval: config.item_refresh_interval,
+            bucket_refresh_interval: config.bucket_refresh_interval,
+            max_query_lifetime: config.max_query_lifetime,
+            // A limit of 0 would queue every query forever.
+            max_concurrent_queries: config.max_concurrent_queries.map(|max| max.max(1)),
+            token_lifetime: config.token_lifetime,
+            k: config.k_factor.max(1),
+            max_lookup_rounds: config.max_lookup_rounds,
+            node_id_seed: config.node_id_seed,
+            on_request: config.on_request,
             socket,
 
             routing_table: RoutingTable::new(id),
             iterative_queries: HashMap::new(),
             put_queries: HashMap::new(),
+            queued_queries: VecDeque::new(),
+            next_query_id: 0,
 
             cached_iterative_queries: LruCache::new(
-                NonZeroUsize::new(MAX_CACHED_ITERATIVE_QUERIES)
-                    .expect("MAX_CACHED_BUCKETS is NonZeroUsize"),
+                NonZeroUsize::new(config.max_cached_queries.max(1))
+                    .expect("max_cached_queries is at least 1"),
             ),
+            immutable_cache: NonZeroUsize::new(config.immutable_cache_size).map(LruCache::new),
+            immutable_cache_hits: 0,
+            immutable_cache_misses: 0,
 
             last_table_refresh: Instant::now(),
+            last_table_refresh_time: None,
+            last_adaptive_mode_check: Instant::now(),
             last_table_ping: Instant::now(),
 
             dht_size_estimates_sum: 0.0,
@@ -137,6 +293,17 @@ impl Rpc {
 
             public_address: None,
             firewalled: true,
+            suggested_public_ip: config.suggested_public_ip,
+            reachability_probes: HashMap::new(),
+
+            own_peers: config.serve_own_peers.then(HashMap::new),
+            read_only: config.read_only,
+            client_only: config.client_only,
+
+            get_results: VecDeque::with_capacity(SUCCESS_RATE_WINDOW),
+            put_results: VecDeque::with_capacity(SUCCESS_RATE_WINDOW),
+            dht_size_estimate_history: VecDeque::with_capacity(config.max_estimate_samples),
+            max_estimate_samples: config.max_estimate_samples,
         })
     }
 
@@ -176,10 +343,74 @@ impl Rpc {
         self.socket.server_mode
     }
 
+    /// Returns whether or not this node is read-only, see [config::Config::read_only].
+    pub fn read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Returns the interval to refresh stored items at, see [config::Config::item_refresh_interval].
+    pub fn item_refresh_interval(&self) -> Duration {
+        self.item_refresh_interval
+    }
+
+    /// Returns the last time the routing table was refreshed, see
+    /// [config::Config::bucket_refresh_interval].
+    pub fn last_table_refresh(&self) -> Option<SystemTime> {
+        self.last_table_refresh_time
+    }
+
+    /// Returns the number of responses received from an address other than
+    /// the one the corresponding request was sent to.
+    pub fn response_address_mismatches(&self) -> usize {
+        self.socket.response_address_mismatches()
+    }
+
+    /// Returns the cumulative counters of packets sent and received, and socket IO errors.
+    pub(crate) fn socket_stats(&self) -> socket::SocketStats {
+        self.socket.stats()
+    }
+
+    /// Returns the fraction of the outbound rate limit currently used,
+    /// or `None` if there is no rate limit.
+    pub(crate) fn rate_limit_utilization(&self) -> Option<f64> {
+        self.socket.rate_limit_utilization()
+    }
+
+    /// Returns the number of outgoing packets waiting for the rate limiter.
+    pub(crate) fn throttled_packets(&self) -> usize {
+        self.socket.throttled_packets()
+    }
+
+    /// Returns the number of requests waiting for a response or a timeout.
+    pub(crate) fn inflight_requests(&self) -> usize {
+        self.socket.inflight_requests()
+    }
+
+    /// Returns the number of lookups in the immutable values cache that
+    /// were found, and that were not, see [config::Config::immutable_cache_size].
+    pub fn immutable_cache_stats(&self) -> (usize, usize) {
+        (self.immutable_cache_hits, self.immutable_cache_misses)
+    }
+
+    /// Returns statistics about data stored by this node's [Server],
+    /// see [Server::stats].
+    pub fn server_stats(&self, top_n: usize) -> ServerStats {
+        self.server.stats(top_n)
+    }
+
     pub fn routing_table(&self) -> &RoutingTable {
         &self.routing_table
     }
 
+    /// Returns each bootstrapping node's address, and whether it responded
+    /// to any request since startup.
+    pub fn bootstrap_status(&self) -> Vec<(SocketAddrV4, bool)> {
+        self.bootstrap
+            .iter()
+            .map(|address| (*address, self.bootstrap_responded.contains(address)))
+            .collect()
+    }
+
     /// Returns:
     ///  1. Normal Dht size estimate based on all closer `nodes` in query responses.
     ///  2. Standard deviaiton as a function of the number of samples used in this estimate.
@@ -195,6 +426,84 @@ impl Rpc {
         (normal, std_dev)
     }
 
+    /// Returns up to the last `n` [Self::dht_size_estimate]s, oldest first,
+    /// each with the time it was updated after a query was done.
+    ///
+    /// Only the most recent [Config::max_estimate_samples] estimates are kept.
+    pub fn dht_size_estimate_history(&self, n: usize) -> Vec<(Instant, usize, f64)> {
+        self.dht_size_estimate_history
+            .iter()
+            .skip(self.dht_size_estimate_history.len().saturating_sub(n))
+            .copied()
+            .collect()
+    }
+
+    /// Returns the fraction of the most recent GET queries (for peers or values)
+    /// that returned at least one value, or `None` if no GET queries are done yet.
+    pub fn get_success_rate(&self) -> Option<f64> {
+        success_rate(&self.get_results)
+    }
+
+    /// Returns the fraction of the most recent PUT queries that stored
+    /// at least at one node, or `None` if no PUT queries are done yet.
+    pub fn put_success_rate(&self) -> Option<f64> {
+        success_rate(&self.put_results)
+    }
+
+    /// Returns the fraction of the closest nodes to `target` that didn't respond
+    /// with a token in the most recent cached query, or `None` if there is no cached query
+    /// for this target.
+    ///
+    /// Nodes that are overloaded or evicting aggressively tend to time out or drop requests,
+    /// so a higher value suggests a more contended region of the keyspace.
+    pub fn storage_pressure(&self, target: &Id) -> Option<f64> {
+        self.cached_iterative_queries
+            .peek(target)
+            .map(|cached| cached.storage_pressure)
+    }
+
+    /// Returns a summary of the approximate memory held by the routing table,
+    /// the cached queries used for the Dht size estimate, and the history of
+    /// that estimate.
+    pub fn memory_report(&self) -> MemoryReport {
+        let routing_table_nodes = self.routing_table.size();
+
+        let mut cached_nodes = 0;
+        let mut approximate_bytes = self
+            .routing_table
+            .nodes()
+            .map(|node| node.approximate_size())
+            .sum::<usize>();
+
+        for (_, cached) in self.cached_iterative_queries.iter() {
+            cached_nodes += cached.closest_responding_nodes.len();
+            approximate_bytes += std::mem::size_of::<(Id, CachedIterativeQuery)>()
+                + cached
+                    .closest_responding_nodes
+                    .iter()
+                    .map(|node| node.approximate_size())
+                    .sum::<usize>();
+        }
+
+        if let Some(cache) = &self.immutable_cache {
+            approximate_bytes += cache
+                .iter()
+                .map(|(_, value)| std::mem::size_of::<(Id, Box<[u8]>)>() + value.len())
+                .sum::<usize>();
+        }
+
+        approximate_bytes +=
+            self.dht_size_estimate_history.len() * std::mem::size_of::<(Instant, usize, f64)>();
+
+        MemoryReport {
+            routing_table_nodes,
+            cached_queries: self.cached_iterative_queries.len(),
+            cached_nodes,
+            estimate_samples: self.dht_size_estimate_history.len(),
+            approximate_bytes,
+        }
+    }
+
     /// Returns a thread safe and lightweight summary of this node's
     /// information and statistics.
     pub fn info(&self) -> Info {
@@ -208,18 +517,30 @@ impl Rpc {
     /// to happen at every tick.
     pub fn tick(&mut self) -> RpcTickReport {
         let mut done_get_queries = Vec::with_capacity(self.iterative_queries.len());
+        let mut done_query_timings = Vec::with_capacity(self.iterative_queries.len());
         let mut done_put_queries = Vec::with_capacity(self.put_queries.len());
 
         // === Tick Queries ===
 
         for (id, query) in self.put_queries.iter_mut() {
             match query.tick(&self.socket) {
-                Ok(done) => {
-                    if done {
-                        done_put_queries.push((*id, None));
+                Ok(Some(report)) => done_put_queries.push((*id, Ok(report))),
+                Ok(None) => {
+                    if query
+                        .age()
+                        .map(|age| age > self.max_query_lifetime)
+                        .unwrap_or(false)
+                    {
+                        let _entered = query.span().enter();
+                        warn!(
+                            ?id,
+                            "PutQuery exceeded the maximum query lifetime, forcibly completing it"
+                        );
+
+                        done_put_queries.push((*id, query.timed_out()))
                     }
                 }
-                Err(error) => done_put_queries.push((*id, Some(error))),
+                Err(error) => done_put_queries.push((*id, Err(error))),
             };
         }
 
@@ -230,35 +551,58 @@ impl Rpc {
         let average_subnets = self.average_subnets();
 
         for (id, query) in self.iterative_queries.iter_mut() {
-            let is_done = query.tick(&mut self.socket);
+            let mut is_done = query.tick(&mut self.socket);
+
+            if !is_done && query.age() > self.max_query_lifetime {
+                let _entered = query.span().clone().entered();
+                warn!(
+                    ?id,
+                    "Query exceeded the maximum query lifetime, forcibly completing it"
+                );
+
+                query.set_timed_out();
+                is_done = true;
+            }
 
             if is_done {
-                let closest_nodes =
-                    if let RequestTypeSpecific::FindNode(_) = query.request.request_type {
-                        if *id == self_id {
-                            if table_size == 0 {
-                                error!("Could not bootstrap the routing table");
+                let closest_nodes = if let RequestTypeSpecific::FindNode(_) =
+                    query.request.request_type
+                {
+                    if *id == self_id {
+                        if table_size == 0 {
+                            if self.bootstrap_from_nodes.is_some() {
+                                info!("Could not bootstrap the routing table from saved nodes, falling back to bootstrapping nodes");
                             } else {
-                                debug!(?self_id, table_size, "Populated the routing table");
+                                error!("Could not bootstrap the routing table");
                             }
-                        };
-
-                        query
-                            .closest()
-                            .nodes()
-                            .iter()
-                            .take(MAX_BUCKET_SIZE_K)
-                            .cloned()
-                            .collect::<Box<[_]>>()
-                    } else {
-                        query
-                            .responders()
-                            .take_until_secure(responders_based_dht_size_estimate, average_subnets)
-                            .to_vec()
-                            .into_boxed_slice()
+                        } else {
+                            debug!(?self_id, table_size, "Populated the routing table");
+                        }
+
+                        self.bootstrap_from_nodes = None;
                     };
 
+                    query
+                        .closest()
+                        .nodes()
+                        .iter()
+                        .take(self.k)
+                        .cloned()
+                        .collect::<Box<[_]>>()
+                } else {
+                    query
+                        .responders()
+                        .take_until_secure_with_k(
+                            self.k,
+                            responders_based_dht_size_estimate,
+                            average_subnets,
+                        )
+                        .to_vec()
+                        .into_boxed_slice()
+                };
+
                 done_get_queries.push((*id, closest_nodes));
+                done_query_timings.push((*id, query.timing()));
             };
         }
 
@@ -275,46 +619,137 @@ impl Rpc {
                     if let Some(put_query) = self.put_queries.get_mut(id) {
                         if !put_query.started() {
                             if let Err(error) = put_query.start(&mut self.socket, closest_nodes) {
-                                done_put_queries.push((*id, Some(error)))
+                                done_put_queries.push((*id, Err(error)))
                             }
                         }
+                    } else {
+                        record_result(&mut self.get_results, !query.responses().is_empty());
                     }
                 }
             };
         }
 
-        for (id, _) in &done_put_queries {
+        // Retry PUT queries rejected for stale tokens once, with fresh tokens.
+        done_put_queries.retain(|(id, _)| {
+            let should_retry = self
+                .put_queries
+                .get(id)
+                .map(|query| query.should_retry())
+                .unwrap_or(false);
+
+            if should_retry {
+                if let Some(query) = self.put_queries.remove(id) {
+                    debug!(?id, "PutQuery rejected for stale tokens, retrying");
+
+                    let cached = self.cached_iterative_queries.pop(id);
+                    self.decrement_cached_iterative_query_stats(cached);
+
+                    self.lookup_put_target(&query.request);
+                    self.put_queries.insert(*id, query.retry());
+                }
+            }
+
+            !should_retry
+        });
+
+        for (id, result) in &done_put_queries {
             self.put_queries.remove(id);
+            record_result(&mut self.put_results, result.is_ok());
+        }
+
+        // === Start queued queries ===
+        while self.has_free_query_slot() {
+            match self.queued_queries.pop_front() {
+                Some(queued) => {
+                    self.get_with_timeout(
+                        queued.request,
+                        queued.extra_nodes.as_deref(),
+                        queued.request_timeout,
+                    );
+                }
+                None => break,
+            }
         }
 
         // === Periodic node maintaenance ===
         self.periodic_node_maintaenance();
 
+        let mut new_direct_response = None;
+        let mut new_query_responder = None;
+        let mut new_query_raw_response = None;
+
         // Handle new incoming message
-        let new_query_response = self
-            .socket
-            .recv_from()
-            .and_then(|(message, from)| match message.message_type {
+        let new_query_response = match self.socket.recv_from() {
+            Some((message, from)) => match message.message_type {
                 MessageType::Request(request_specific) => {
                     self.handle_request(from, message.transaction_id, request_specific);
 
                     None
                 }
-                _ => self.handle_response(from, message),
-            });
+                _ => {
+                    if self.bootstrap.contains(&from) {
+                        self.bootstrap_responded.insert(from);
+                    }
+
+                    if !self.is_query_response(message.transaction_id) {
+                        new_direct_response =
+                            Some((message.transaction_id, from, message.message_type.clone()));
+                    } else if !matches!(
+                        message.message_type,
+                        MessageType::Response(ResponseSpecific::Ping(_))
+                    ) {
+                        if let Some((target, id)) = self
+                            .iterative_queries
+                            .values()
+                            .find(|query| query.inflight(message.transaction_id))
+                            .map(|query| query.target())
+                            .zip(message.get_author_id())
+                        {
+                            let node = match message.get_token() {
+                                Some((_, token)) => Node::new_with_token(id, from, token.into()),
+                                None => Node::new(id, from),
+                            }
+                            .with_client_version(message.client_version());
+
+                            if let MessageType::Response(response) = &message.message_type {
+                                new_query_raw_response =
+                                    Some((target, node.clone(), response.clone()));
+                            }
+
+                            new_query_responder = Some((target, node));
+                        }
+                    }
+
+                    self.handle_response(from, message)
+                }
+            },
+            None => None,
+        };
 
         RpcTickReport {
             done_get_queries,
+            done_query_timings,
             done_put_queries,
             new_query_response,
+            new_query_responder,
+            new_query_raw_response,
+            new_direct_response,
         }
     }
 
     /// Send a request to the given address and return the transaction_id
+    ///
+    /// The response, if any, will be in [RpcTickReport::new_direct_response].
     pub fn request(&mut self, address: SocketAddrV4, request: RequestSpecific) -> u16 {
         self.socket.request(address, request)
     }
 
+    /// Returns true if a request with this transaction_id is still waiting for a response,
+    /// and didn't time out yet.
+    pub fn inflight(&self, transaction_id: u16) -> bool {
+        self.socket.inflight(&transaction_id)
+    }
+
     /// Send a response to the given address.
     pub fn response(
         &mut self,
@@ -341,6 +776,39 @@ impl Rpc {
     ) -> Result<(), PutError> {
         let target = *request.target();
 
+        if let PutRequestSpecific::PutImmutable(PutImmutableRequestArguments { v, .. })
+        | PutRequestSpecific::PutMutable(PutMutableRequestArguments { v, .. }) = &request
+        {
+            if v.len() > MAX_VALUE_SIZE {
+                Err(PutQueryError::ValueTooLarge {
+                    len: v.len(),
+                    max: MAX_VALUE_SIZE,
+                })?;
+            }
+        }
+
+        let own_peer_lifetime = self.own_peer_lifetime();
+
+        if let (
+            Some(own_peers),
+            PutRequestSpecific::AnnouncePeer(AnnouncePeerRequestArguments {
+                info_hash,
+                port,
+                implied_port,
+                ..
+            }),
+        ) = (&mut self.own_peers, &request)
+        {
+            own_peers.retain(|_, (_, announced_at)| announced_at.elapsed() < own_peer_lifetime);
+            own_peers.insert(
+                *info_hash,
+                (
+                    (*implied_port != Some(true)).then_some(*port),
+                    Instant::now(),
+                ),
+            );
+        }
+
         if let PutRequestSpecific::PutMutable(PutMutableRequestArguments {
             sig, cas, seq, ..
         }) = &request
@@ -372,31 +840,28 @@ impl Rpc {
             };
         }
 
+        let span = self.query_span(target, request.name());
+        let _entered = span.enter();
+
         let mut query = PutQuery::new(target, request.clone(), extra_nodes);
+        query.set_span(span.clone());
 
         if let Some(closest_nodes) = self
             .cached_iterative_queries
             .get(&target)
+            // FindNode responses don't carry tokens to store values with.
+            .filter(|cached| !cached.is_find_node)
             .map(|cached| cached.closest_responding_nodes.clone())
             .filter(|closest_nodes| {
-                !closest_nodes.is_empty() && closest_nodes.iter().any(|n| n.valid_token())
+                !closest_nodes.is_empty()
+                    && closest_nodes
+                        .iter()
+                        .any(|n| n.last_seen().elapsed() <= self.token_lifetime)
             })
         {
             query.start(&mut self.socket, &closest_nodes)?
         } else {
-            let salt = match request {
-                PutRequestSpecific::PutMutable(args) => args.salt,
-                _ => None,
-            };
-
-            self.get(
-                GetRequestSpecific::GetValue(GetValueRequestArguments {
-                    target,
-                    seq: None,
-                    salt,
-                }),
-                None,
-            );
+            self.lookup_put_target(&request);
         };
 
         self.put_queries.insert(target, query);
@@ -404,6 +869,46 @@ impl Rpc {
         Ok(())
     }
 
+    /// Stop the iterative query for this target, and forget it if it is queued,
+    /// unless a put query is waiting for it.
+    ///
+    /// Returns true if an inflight query was stopped.
+    pub fn cancel(&mut self, target: &Id) -> bool {
+        if self.put_queries.contains_key(target) {
+            return false;
+        }
+
+        self.queued_queries
+            .retain(|queued| queued.request.target() != target);
+
+        let cancelled = self.iterative_queries.remove(target).is_some();
+
+        if cancelled {
+            debug!(?target, "Cancelled query");
+        }
+
+        cancelled
+    }
+
+    /// Returns the cached immutable value for this target, if any,
+    /// see [config::Config::immutable_cache_size].
+    pub fn cached_immutable(&mut self, target: &Id) -> Option<Box<[u8]>> {
+        let cache = self.immutable_cache.as_mut()?;
+
+        match cache.get(target) {
+            Some(value) => {
+                self.immutable_cache_hits += 1;
+
+                Some(value.clone())
+            }
+            None => {
+                self.immutable_cache_misses += 1;
+
+                None
+            }
+        }
+    }
+
     /// Send a message to closer and closer nodes until we can't find any more nodes.
     ///
     /// Queries take few seconds to fully traverse the network, once it is done, it will be removed from
@@ -416,13 +921,24 @@ impl Rpc {
     /// to traverse it.
     ///
     /// - `request` [RequestTypeSpecific], except [RequestTypeSpecific::Ping] and
-    ///     [RequestTypeSpecific::Put] which will be ignored.
+    ///   [RequestTypeSpecific::Put] which will be ignored.
     /// - `extra_nodes` option allows the query to visit specific nodes, that won't necessesarily be visited
-    ///     through the query otherwise.
+    ///   through the query otherwise.
     pub fn get(
         &mut self,
         request: GetRequestSpecific,
         extra_nodes: Option<&[SocketAddrV4]>,
+    ) -> Option<Vec<Response>> {
+        self.get_with_timeout(request, extra_nodes, None)
+    }
+
+    /// Same as [Rpc::get], but overriding the socket's request timeout for the
+    /// requests of this query, if a new query is started for it.
+    pub fn get_with_timeout(
+        &mut self,
+        request: GetRequestSpecific,
+        extra_nodes: Option<&[SocketAddrV4]>,
+        request_timeout: Option<Duration>,
     ) -> Option<Vec<Response>> {
         let target = match request {
             GetRequestSpecific::FindNode(FindNodeRequestArguments { target }) => target,
@@ -450,13 +966,45 @@ impl Rpc {
             return Some(responses);
         }
 
+        if !self.has_free_query_slot() {
+            if !self
+                .queued_queries
+                .iter()
+                .any(|queued| *queued.request.target() == target)
+            {
+                debug!(?target, "Too many concurrent queries, queuing query");
+
+                self.queued_queries.push_back(QueuedQuery {
+                    request,
+                    extra_nodes: extra_nodes.map(|nodes| nodes.into()),
+                    request_timeout,
+                });
+            }
+
+            return response_from_inflight_put_mutable_request.map(|response| vec![response]);
+        }
+
         let node_id = self.routing_table.id();
 
         if target == *node_id {
             debug!(?node_id, "Bootstrapping the routing table");
         }
 
+        let span = self.query_span(target, request.name());
+        let _entered = span.enter();
+
         let mut query = IterativeQuery::new(*self.id(), target, request);
+        query.set_span(span.clone());
+        query.set_k(self.k);
+
+        // Bootstrapping lookups are not limited, to fill the routing table.
+        if let Some(max_lookup_rounds) = self.max_lookup_rounds.filter(|_| target != *self.id()) {
+            query.set_max_rounds(max_lookup_rounds);
+        }
+
+        if let Some(request_timeout) = request_timeout {
+            query.set_request_timeout(request_timeout);
+        }
 
         // Seed the query either with the closest nodes from the routing table, or the
         // bootstrapping nodes if the closest nodes are not enough.
@@ -467,8 +1015,15 @@ impl Rpc {
             self.average_subnets(),
         );
 
-        // If we don't have enough or any closest nodes, call the bootstrapping nodes.
-        if routing_table_closest.is_empty() || routing_table_closest.len() < self.bootstrap.len() {
+        if let Some(nodes) = &self.bootstrap_from_nodes {
+            // Try the saved nodes first, until the routing table is populated.
+            for node in nodes.iter() {
+                query.add_candidate(node.clone())
+            }
+        } else if routing_table_closest.is_empty()
+            || routing_table_closest.len() < self.bootstrap.len()
+        {
+            // If we don't have enough or any closest nodes, call the bootstrapping nodes.
             for bootstrapping_node in self.bootstrap.clone() {
                 query.visit(&mut self.socket, bootstrapping_node);
             }
@@ -516,9 +1071,26 @@ impl Rpc {
         transaction_id: u16,
         request_specific: RequestSpecific,
     ) {
+        if let Some(on_request) = &self.on_request {
+            on_request.observe(from, &request_specific);
+        }
+
         let is_ping = matches!(request_specific.request_type, RequestTypeSpecific::Ping);
 
-        if self.server_mode() {
+        if is_ping {
+            if let Some(received) = self
+                .reachability_probes
+                .get_mut(&request_specific.requester_id)
+            {
+                *received = true;
+            }
+        }
+
+        if self.read_only {
+            // Read-only nodes don't answer any requests, so they never hand out tokens,
+            // and store requests are dropped.
+            debug!(?from, "Read-only node ignoring request");
+        } else if self.server_mode() {
             let server = &mut self.server;
 
             match server.handle_request(&self.routing_table, from, request_specific) {
@@ -530,36 +1102,109 @@ impl Rpc {
                 }
                 _ => {}
             };
+        } else if let Some(peer) = self.own_peer(&request_specific) {
+            if let Some(MessageType::Response(response)) = self.server.handle_own_peers_request(
+                &self.routing_table,
+                from,
+                &request_specific,
+                peer,
+            ) {
+                self.response(from, transaction_id, response);
+            }
         }
 
         if let Some(our_address) = self.public_address {
             if from == our_address && is_ping {
                 self.firewalled = false;
 
-                let ipv4 = our_address.ip();
-
                 // Restarting our routing table with new secure Id if necessary.
-                if !self.id().is_valid_for_ip(*ipv4) {
-                    let new_id = Id::from_ipv4(*ipv4);
-
-                    info!(
-                        "Our current id {} is not valid for adrsess {}. Using new id {}",
-                        self.id(),
-                        our_address,
-                        new_id
-                    );
-
-                    self.get(
-                        GetRequestSpecific::FindNode(FindNodeRequestArguments { target: new_id }),
-                        None,
-                    );
-
-                    self.routing_table = RoutingTable::new(new_id);
+                if self.secure_id_for(*our_address.ip()) {
+                    self.self_lookup();
                 }
             }
         }
     }
 
+    /// Our external IP changed to `ip`, regenerate a [BEP_0042](https://www.bittorrent.org/beps/bep_0042.html)
+    /// secure Id for it if necessary, and refresh the routing table around it.
+    ///
+    /// Nodes already in the routing table are kept, and in-flight queries
+    /// complete using the Id they started with.
+    ///
+    /// Returns the (possibly unchanged) Id.
+    pub fn set_external_ip(&mut self, ip: Ipv4Addr) -> Id {
+        let id = self.update_external_ip(ip);
+        self.confirm_external_ip();
+
+        id
+    }
+
+    /// The local half of [Self::set_external_ip], updates our public address
+    /// and Id without sending any packets.
+    ///
+    /// Follow it with [Self::confirm_external_ip] once the network is available.
+    pub fn update_external_ip(&mut self, ip: Ipv4Addr) -> Id {
+        let port = self
+            .public_address
+            .map(|address| address.port())
+            .unwrap_or(self.local_addr().port());
+        let new_address = SocketAddrV4::new(ip, port);
+
+        if self.public_address != Some(new_address) {
+            // Unconfirmed until we ping ourselves at the new address.
+            self.firewalled = true;
+            self.public_address = Some(new_address);
+        }
+
+        self.secure_id_for(ip);
+
+        *self.id()
+    }
+
+    /// The network half of [Self::set_external_ip], pings our unconfirmed
+    /// public address, looks up our Id and refreshes stale buckets.
+    pub fn confirm_external_ip(&mut self) {
+        if let Some(address) = self.public_address.filter(|_| self.firewalled) {
+            self.ping(address);
+        }
+
+        self.self_lookup();
+
+        self.last_table_refresh = Instant::now();
+        self.last_table_refresh_time = Some(SystemTime::now());
+        self.refresh_stale_buckets();
+    }
+
+    /// Replace our Id with a secure one for `ip` if it isn't already valid for it,
+    /// keeping the nodes in the routing table.
+    ///
+    /// Returns whether the Id changed.
+    fn secure_id_for(&mut self, ip: Ipv4Addr) -> bool {
+        if self.id().is_valid_for_ip(ip) {
+            return false;
+        }
+
+        let new_id = match &self.node_id_seed {
+            Some(seed) => Id::from_ipv4_and_seed(ip, seed),
+            None => Id::from_ipv4(ip),
+        };
+
+        info!(
+            "Our current id {} is not valid for address {}. Using new id {}",
+            self.id(),
+            ip,
+            new_id
+        );
+
+        let mut routing_table = RoutingTable::new(new_id);
+        for node in self.routing_table.nodes() {
+            routing_table.add(node.clone());
+        }
+        self.routing_table = routing_table;
+
+        true
+    }
+
     fn handle_response(&mut self, from: SocketAddrV4, message: Message) -> Option<(Id, Response)> {
         // If someone claims to be readonly, then let's not store anything even if they respond.
         if message.read_only {
@@ -572,10 +1217,17 @@ impl Rpc {
             .values_mut()
             .find(|query| query.inflight(message.transaction_id))
         {
+            let span = query.span().clone();
+            let _entered = span.enter();
+
             match message.message_type {
-                MessageType::Response(ResponseSpecific::Ping(_)) => {
+                MessageType::Response(ResponseSpecific::Ping(PingResponseArguments {
+                    responder_id,
+                })) => {
                     // Mark storage at that node as a success.
-                    query.success();
+                    query.success(
+                        Node::new(responder_id, from).with_client_version(message.client_version()),
+                    );
                 }
                 MessageType::Error(error) => query.error(error),
                 _ => {}
@@ -587,6 +1239,15 @@ impl Rpc {
         let mut should_add_node = false;
         let author_id = message.get_author_id();
         let from_version = message.version.to_owned();
+        let client_version = message.client_version();
+
+        let closer_nodes = message.get_closer_nodes().map(|nodes| {
+            nodes
+                .iter()
+                .filter(|node| !self.socket.is_blocked(node.address().ip()) && !self.is_self(node))
+                .cloned()
+                .collect::<Vec<_>>()
+        });
 
         // Get corresponding query for message.transaction_id
         if let Some(query) = self
@@ -594,17 +1255,23 @@ impl Rpc {
             .values_mut()
             .find(|query| query.inflight(message.transaction_id))
         {
+            let span = query.span().clone();
+            let _entered = span.enter();
+
             // KrpcSocket would not give us a response from the wrong address for the transaction_id
             should_add_node = true;
 
-            if let Some(nodes) = message.get_closer_nodes() {
-                for node in nodes {
-                    query.add_candidate(node.clone());
-                }
+            query.record_response_time(message.transaction_id);
+
+            for node in closer_nodes.into_iter().flatten() {
+                query.add_closer_node(node, from);
             }
 
             if let Some((responder_id, token)) = message.get_token() {
-                query.add_responding_node(Node::new_with_token(responder_id, from, token.into()));
+                query.add_responding_node(
+                    Node::new_with_token(responder_id, from, token.into())
+                        .with_client_version(client_version),
+                );
             }
 
             if let Some(proposed_ip) = message.requester_ip {
@@ -615,10 +1282,14 @@ impl Rpc {
 
             match message.message_type {
                 MessageType::Response(ResponseSpecific::GetPeers(GetPeersResponseArguments {
+                    responder_id,
                     values,
                     ..
                 })) => {
-                    let response = Response::Peers(values);
+                    let response = Response::Peers(PeersResponse {
+                        from: Node::new(responder_id, from).with_client_version(client_version),
+                        peers: values,
+                    });
                     query.response(from, response.clone());
 
                     return Some((target, response));
@@ -629,6 +1300,10 @@ impl Rpc {
                     },
                 )) => {
                     if validate_immutable(&v, query.target()) {
+                        if let Some(cache) = &mut self.immutable_cache {
+                            cache.put(target, v.clone());
+                        }
+
                         let response = Response::Immutable(v);
                         query.response(from, response.clone());
 
@@ -696,6 +1371,8 @@ impl Rpc {
                         ?from_version,
                         "No more recent value"
                     );
+
+                    query.no_more_recent_value(from);
                 }
                 MessageType::Response(ResponseSpecific::NoValues(NoValuesResponseArguments {
                     responder_id,
@@ -719,8 +1396,11 @@ impl Rpc {
                 // Ping response is already handled in add_node()
                 // FindNode response is already handled in query.add_candidate()
                 // Requests are handled elsewhere
+                // SampleInfohashes and Scrape responses are only expected as direct requests
                 MessageType::Response(ResponseSpecific::Ping(_))
                 | MessageType::Response(ResponseSpecific::FindNode(_))
+                | MessageType::Response(ResponseSpecific::SampleInfohashes(_))
+                | MessageType::Response(ResponseSpecific::Scrape(_))
                 | MessageType::Request(_) => {}
             };
         };
@@ -729,30 +1409,97 @@ impl Rpc {
             // Add a node to our routing table on any expected incoming response.
 
             if let Some(id) = author_id {
-                self.routing_table.add(Node::new(id, from));
+                self.routing_table
+                    .add(Node::new(id, from).with_client_version(client_version));
             }
         }
 
         None
     }
 
+    /// Returns true if this node has our Id or one of our addresses,
+    /// so we don't query ourselves or return ourselves in query results.
+    fn is_self(&self, node: &Node) -> bool {
+        let address = node.address();
+        let local_addr = self.local_addr();
+
+        node.id() == self.id()
+            || Some(address) == self.public_address
+            || (address.port() == local_addr.port()
+                && (address.ip() == local_addr.ip()
+                    || (address.ip().is_loopback() && local_addr.ip().is_unspecified())))
+    }
+
+    /// Returns our own peer address if this is a get_peers request for an info hash
+    /// we announced ourselves on, see [config::Config::serve_own_peers].
+    fn own_peer(&self, request: &RequestSpecific) -> Option<SocketAddrV4> {
+        if let RequestTypeSpecific::GetPeers(GetPeersRequestArguments { info_hash }) =
+            &request.request_type
+        {
+            let (port, _) = self
+                .own_peers
+                .as_ref()?
+                .get(info_hash)
+                .filter(|(_, announced_at)| announced_at.elapsed() < self.own_peer_lifetime())?;
+            let public_address = self.public_address?;
+
+            return Some(SocketAddrV4::new(
+                *public_address.ip(),
+                port.unwrap_or(public_address.port()),
+            ));
+        }
+
+        None
+    }
+
+    /// How long to keep serving our own peer after announcing it, like other nodes
+    /// would before it expires, see [config::Config::serve_own_peers].
+    fn own_peer_lifetime(&self) -> Duration {
+        self.item_refresh_interval.saturating_mul(2)
+    }
+
+    /// Adaptive mode: switch to server mode once the node has been running long enough
+    /// without being firewalled, unless it is read-only or client-only.
+    fn switch_to_server_mode_if_accessible(&mut self) {
+        if !self.server_mode() && !self.read_only && !self.client_only && !self.firewalled() {
+            info!("Adaptive mode: have been running long enough (not firewalled), switching to server mode");
+
+            self.socket.server_mode = true;
+        }
+    }
+
     fn periodic_node_maintaenance(&mut self) {
+        // Remove nodes that ICMP errors reported as unreachable.
+        for address in self.socket.take_unreachable() {
+            let unreachable = self
+                .routing_table
+                .nodes()
+                .filter(|node| node.address() == address)
+                .map(|node| *node.id())
+                .collect::<Vec<_>>();
+
+            for id in unreachable {
+                self.routing_table.remove(&id);
+            }
+        }
+
         // Bootstrap if necessary
         if self.routing_table.is_empty() {
             self.populate();
         }
 
-        // Every 15 minutes refresh the routing table.
-        if self.last_table_refresh.elapsed() > REFRESH_TABLE_INTERVAL {
-            self.last_table_refresh = Instant::now();
+        if self.last_adaptive_mode_check.elapsed() > ADAPTIVE_MODE_INTERVAL {
+            self.last_adaptive_mode_check = Instant::now();
 
-            if !self.server_mode() && !self.firewalled() {
-                info!("Adaptive mode: have been running long enough (not firewalled), switching to server mode");
+            self.switch_to_server_mode_if_accessible();
+        }
 
-                self.socket.server_mode = true;
-            }
+        if self.last_table_refresh.elapsed() > self.bucket_refresh_interval {
+            self.last_table_refresh = Instant::now();
+            self.last_table_refresh_time = Some(SystemTime::now());
 
             self.populate();
+            self.refresh_stale_buckets();
         }
 
         if self.last_table_ping.elapsed() > PING_TABLE_INTERVAL {
@@ -781,7 +1528,16 @@ impl Rpc {
 
     /// Ping bootstrap nodes, add them to the routing table with closest query.
     fn populate(&mut self) {
-        if self.bootstrap.is_empty() {
+        if self.bootstrap.is_empty() && self.bootstrap_from_nodes.is_none() {
+            return;
+        }
+
+        self.self_lookup();
+    }
+
+    /// Look up our own Id, unless [config::Config::skip_self_lookup] is set.
+    fn self_lookup(&mut self) {
+        if self.skip_self_lookup {
             return;
         }
 
@@ -791,20 +1547,110 @@ impl Rpc {
         );
     }
 
-    fn ping(&mut self, address: SocketAddrV4) {
-        self.socket.request(
-            address,
-            RequestSpecific {
-                requester_id: *self.id(),
-                request_type: RequestTypeSpecific::Ping,
-            },
-        );
+    /// Look up a random Id in each bucket where no node was seen
+    /// since the last refresh, to discover fresh nodes for it.
+    fn refresh_stale_buckets(&mut self) {
+        for distance in self
+            .routing_table
+            .stale_buckets(self.bucket_refresh_interval)
+        {
+            let target = self.id().random_at_distance(distance);
+
+            debug!(?distance, ?target, "Refreshing stale bucket");
+
+            self.get(
+                GetRequestSpecific::FindNode(FindNodeRequestArguments { target }),
+                None,
+            );
+        }
     }
 
-    fn update_address_votes_from_iterative_query(&mut self, query: &IterativeQuery) {
-        if let Some(new_address) = query.best_address() {
-            if self.public_address.is_none()
-                || new_address
+    /// Drop packets to and from addresses in this `range`, and remove
+    /// matching nodes from the routing table, see [config::Config::blocklist].
+    pub fn ban(&mut self, range: Ipv4Net) {
+        let banned = self
+            .routing_table
+            .nodes()
+            .filter(|node| range.contains(node.address().ip()))
+            .map(|node| *node.id())
+            .collect::<Vec<_>>();
+
+        for id in banned {
+            self.routing_table.remove(&id);
+        }
+
+        self.socket.ban(range);
+    }
+
+    /// Remove a `range` previously added with [Self::ban] or [config::Config::blocklist].
+    pub fn unban(&mut self, range: &Ipv4Net) {
+        self.socket.unban(range);
+    }
+
+    /// Start waiting for a ping request with `probe_id` as its requester id,
+    /// sent to our public address from another socket, see [crate::Dht::check_reachability].
+    pub fn expect_probe(&mut self, probe_id: Id) {
+        self.reachability_probes.insert(probe_id, false);
+    }
+
+    /// Stop waiting for the probe started with [Self::expect_probe],
+    /// and return true if it was received.
+    pub fn probe_received(&mut self, probe_id: &Id) -> bool {
+        self.reachability_probes.remove(probe_id).unwrap_or(false)
+    }
+
+    /// Send a ping request to the given address and return the transaction_id
+    pub fn ping(&mut self, address: SocketAddrV4) -> u16 {
+        self.socket.request(
+            address,
+            RequestSpecific {
+                requester_id: *self.id(),
+                request_type: RequestTypeSpecific::Ping,
+            },
+        )
+    }
+
+    /// Start a GET query to find the closest nodes to a PUT request's target,
+    /// and their write tokens.
+    fn lookup_put_target(&mut self, request: &PutRequestSpecific) {
+        let salt = match request {
+            PutRequestSpecific::PutMutable(args) => args.salt.clone(),
+            _ => None,
+        };
+
+        self.get(
+            GetRequestSpecific::GetValue(GetValueRequestArguments {
+                target: *request.target(),
+                seq: None,
+                salt,
+            }),
+            None,
+        );
+    }
+
+    /// Whether or not a new iterative query can start without exceeding
+    /// the [config::Config::max_concurrent_queries].
+    fn has_free_query_slot(&self) -> bool {
+        self.max_concurrent_queries
+            .map(|max| self.iterative_queries.len() < max)
+            .unwrap_or(true)
+    }
+
+    /// Returns true if this transaction_id belongs to a PUT or GET query.
+    fn is_query_response(&self, transaction_id: u16) -> bool {
+        self.put_queries
+            .values()
+            .any(|query| query.inflight(transaction_id))
+            || self
+                .iterative_queries
+                .values()
+                .any(|query| query.inflight(transaction_id))
+    }
+
+    fn update_address_votes_from_iterative_query(&mut self, query: &IterativeQuery) {
+        if let Some(new_address) = query.best_address(self.suggested_public_ip) {
+            if self.public_address.is_none()
+                || new_address
                     != self
                         .public_address
                         .expect("self.public_address is not None")
@@ -822,8 +1668,16 @@ impl Rpc {
         }
     }
 
+    /// Create a tracing span for a new query, with a unique id, its target, and request type.
+    fn query_span(&mut self, target: Id, request_type: &'static str) -> Span {
+        let id = self.next_query_id;
+        self.next_query_id = self.next_query_id.wrapping_add(1);
+
+        debug_span!("query", id, ?target, request_type)
+    }
+
     fn cache_iterative_query(&mut self, query: &IterativeQuery, closest_responding_nodes: &[Node]) {
-        if self.cached_iterative_queries.len() >= MAX_CACHED_ITERATIVE_QUERIES {
+        if self.cached_iterative_queries.len() >= self.cached_iterative_queries.cap().get() {
             let q = self.cached_iterative_queries.pop_lru();
             self.decrement_cached_iterative_query_stats(q.map(|q| q.1));
         }
@@ -840,6 +1694,14 @@ impl Rpc {
         let responders_dht_size_estimate = responders.dht_size_estimate();
         let subnets_count = closest.subnets_count();
 
+        // Fraction of the closest nodes that didn't respond (with a token).
+        let closest_k = closest.nodes().iter().take(MAX_BUCKET_SIZE_K);
+        let unresponsive = closest_k
+            .clone()
+            .filter(|node| !responders.nodes().iter().any(|r| r.id() == node.id()))
+            .count();
+        let storage_pressure = unresponsive as f64 / closest_k.count() as f64;
+
         let previous = self.cached_iterative_queries.put(
             query.target(),
             CachedIterativeQuery {
@@ -847,6 +1709,7 @@ impl Rpc {
                 dht_size_estimate,
                 responders_dht_size_estimate,
                 subnets: subnets_count,
+                storage_pressure,
 
                 is_find_node: matches!(
                     query.request.request_type,
@@ -861,6 +1724,14 @@ impl Rpc {
         self.responders_based_dht_size_estimates_sum += responders_dht_size_estimate;
         self.subnets_sum += subnets_count as usize;
         self.responders_based_dht_size_estimates_count += 1;
+
+        let (estimate, std_dev) = self.dht_size_estimate();
+        self.dht_size_estimate_history
+            .push_back((Instant::now(), estimate, std_dev));
+
+        while self.dht_size_estimate_history.len() > self.max_estimate_samples {
+            self.dht_size_estimate_history.pop_front();
+        }
     }
 
     fn responders_based_dht_size_estimate(&self) -> usize {
@@ -890,7 +1761,7 @@ impl Rpc {
             }
         };
     }
-    
+
     pub(crate) fn get_socket(&self) -> &KrpcSocket {
         &self.socket
     }
@@ -901,6 +1772,8 @@ struct CachedIterativeQuery {
     dht_size_estimate: f64,
     responders_dht_size_estimate: f64,
     subnets: u8,
+    /// See [Rpc::storage_pressure].
+    storage_pressure: f64,
 
     /// Keeping track of find_node queries, because they shouldn't
     /// be counted in `responders_based_dht_size_estimates_count`
@@ -914,33 +1787,850 @@ struct CachedIterativeQuery {
 pub struct RpcTickReport {
     /// All the [Id]s of the done [Rpc::get] queries.
     pub done_get_queries: Vec<(Id, Box<[Node]>)>,
+    /// [QueryTiming] of each of the [Self::done_get_queries], in the same order.
+    pub done_query_timings: Vec<(Id, QueryTiming)>,
     /// All the [Id]s of the done [Rpc::put] queries,
-    /// and optional [PutError] if the query failed.
-    pub done_put_queries: Vec<(Id, Option<PutError>)>,
+    /// and either a [PutReport] or a [PutError] if the query failed.
+    pub done_put_queries: Vec<(Id, Result<PutReport, PutError>)>,
     /// Received GET query response.
     pub new_query_response: Option<(Id, Response)>,
+    /// Node that responded to a request of an ongoing GET query, and the query's target.
+    pub new_query_responder: Option<(Id, Node)>,
+    /// Unprocessed response to a request of an ongoing GET query, the query's target,
+    /// and the responding node, with its token if any.
+    pub new_query_raw_response: Option<(Id, Node, ResponseSpecific)>,
+    /// Response or error to a request sent directly with [Rpc::request]
+    /// or [Rpc::ping], not as part of a query, by its transaction_id.
+    pub new_direct_response: Option<(u16, SocketAddrV4, MessageType)>,
+}
+
+#[derive(Debug)]
+/// A query waiting for a free slot, see [config::Config::max_concurrent_queries].
+struct QueuedQuery {
+    request: GetRequestSpecific,
+    extra_nodes: Option<Box<[SocketAddrV4]>>,
+    request_timeout: Option<Duration>,
 }
 
 #[derive(Debug, Clone)]
 pub enum Response {
-    Peers(Vec<SocketAddrV4>),
+    Peers(PeersResponse),
     Immutable(Box<[u8]>),
     Mutable(MutableItem),
 }
 
+#[derive(Debug, Clone, PartialEq)]
+/// Peers returned by a single node in response to a `get_peers` query.
+pub struct PeersResponse {
+    /// The node that returned these peers.
+    pub from: Node,
+    /// Peers announced on the info hash, according to [Self::from].
+    pub peers: Vec<SocketAddrV4>,
+}
+
+/// Push a query result to a window of the most recent [SUCCESS_RATE_WINDOW] results.
+fn record_result(results: &mut VecDeque<bool>, success: bool) {
+    if results.len() >= SUCCESS_RATE_WINDOW {
+        results.pop_front();
+    }
+
+    results.push_back(success);
+}
+
+fn success_rate(results: &VecDeque<bool>) -> Option<f64> {
+    if results.is_empty() {
+        return None;
+    }
+
+    Some(results.iter().filter(|success| **success).count() as f64 / results.len() as f64)
+}
+
+/// Resolve the [DEFAULT_BOOTSTRAP_NODES], using a custom `resolver` if any.
+pub(crate) fn default_bootstrap(resolver: Option<&config::Resolver>) -> Vec<SocketAddrV4> {
+    resolve_bootstrap(DEFAULT_BOOTSTRAP_NODES, resolver)
+}
+
+/// Resolve bootstrapping `hosts`, using a custom `resolver` if any.
+fn resolve_bootstrap(hosts: &[&str], resolver: Option<&config::Resolver>) -> Vec<SocketAddrV4> {
+    match resolver {
+        Some(resolver) => dedup(resolver.resolve(hosts)),
+        None => to_socket_address(hosts),
+    }
+}
+
+/// Resolve `bootstrap` hosts to all their Ipv4 addresses, not just the first one,
+/// so every address of a host is tried while bootstrapping.
 pub(crate) fn to_socket_address<T: ToSocketAddrs>(bootstrap: &[T]) -> Vec<SocketAddrV4> {
-    bootstrap
-        .iter()
-        .flat_map(|s| {
-            s.to_socket_addrs().map(|addrs| {
-                addrs
-                    .filter_map(|addr| match addr {
-                        SocketAddr::V4(addr_v4) => Some(addr_v4),
-                        _ => None,
-                    })
-                    .collect::<Box<[_]>>()
+    dedup(
+        bootstrap
+            .iter()
+            .flat_map(|s| {
+                s.to_socket_addrs().map(|addrs| {
+                    addrs
+                        .filter_map(|addr| match addr {
+                            SocketAddr::V4(addr_v4) => Some(addr_v4),
+                            _ => None,
+                        })
+                        .collect::<Box<[_]>>()
+                })
             })
-        })
-        .flatten()
+            .flatten()
+            .collect(),
+    )
+}
+
+/// Remove duplicate addresses, for hosts resolving to the same addresses, keeping the order.
+fn dedup(addresses: Vec<SocketAddrV4>) -> Vec<SocketAddrV4> {
+    let mut seen = HashSet::with_capacity(addresses.len());
+
+    addresses
+        .into_iter()
+        .filter(|address| seen.insert(*address))
         .collect()
 }
+
+/// Send a ping to `target` from a fresh socket bound to `local_addr`.
+///
+/// Returns the local address of that socket, and the address `target`
+/// observed the ping coming from ([BEP_0042](https://www.bittorrent.org/beps/bep_0042.html)),
+/// if it responded before the request timed out.
+pub(crate) fn probe_from_port(
+    local_addr: SocketAddrV4,
+    target: SocketAddrV4,
+    requester_id: Id,
+) -> Result<(SocketAddrV4, Option<SocketAddrV4>), std::io::Error> {
+    let mut socket = KrpcSocket::new(&config::Config {
+        bind_addr: Some(local_addr.into()),
+        ..Default::default()
+    })?;
+
+    let tid = socket.request(
+        target,
+        RequestSpecific {
+            requester_id,
+            request_type: RequestTypeSpecific::Ping,
+        },
+    );
+
+    while socket.inflight(&tid) {
+        if let Some((message, _)) = socket.recv_from() {
+            if message.transaction_id == tid
+                && !matches!(message.message_type, MessageType::Request(_))
+            {
+                return Ok((socket.local_addr(), message.requester_ip));
+            }
+        }
+    }
+
+    Ok((socket.local_addr(), None))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::common::PutRequest;
+    use crate::Testnet;
+
+    use super::*;
+
+    #[test]
+    fn bootstrap_all_resolved_addresses() {
+        let a = SocketAddrV4::new(std::net::Ipv4Addr::LOCALHOST, 6881);
+        let b = SocketAddrV4::new(std::net::Ipv4Addr::LOCALHOST, 6882);
+
+        let rpc = Rpc::new(config::Config {
+            resolver: Some(config::Resolver(std::sync::Arc::new(move |_| {
+                Ok(vec![a.into(), "[::1]:6881".parse().unwrap(), b.into()])
+            }))),
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert_eq!(rpc.bootstrap_status(), vec![(a, false), (b, false)]);
+    }
+
+    #[test]
+    fn max_query_lifetime() {
+        // A node that never responds.
+        let silent = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+
+        let mut rpc = Rpc::new(config::Config {
+            bootstrap: Some(to_socket_address(&[silent.local_addr().unwrap()])),
+            max_query_lifetime: Duration::from_millis(50),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let target = Id::random();
+        rpc.get(
+            GetRequestSpecific::FindNode(FindNodeRequestArguments { target }),
+            None,
+        );
+
+        let start = Instant::now();
+
+        while !rpc
+            .tick()
+            .done_get_queries
+            .iter()
+            .any(|(id, _)| *id == target)
+        {}
+
+        // Much sooner than the request timeout.
+        assert!(start.elapsed() < DEFAULT_REQUEST_TIMEOUT);
+    }
+
+    #[test]
+    fn put_max_query_lifetime_partial_report() {
+        let testnet = Testnet::new(10).unwrap();
+        // A node that never responds.
+        let silent = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let silent_address = match silent.local_addr().unwrap() {
+            SocketAddr::V4(address) => address,
+            _ => unreachable!(),
+        };
+
+        let mut rpc = Rpc::new(config::Config {
+            bootstrap: Some(to_socket_address(&testnet.bootstrap)),
+            max_query_lifetime: Duration::from_millis(500),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let info_hash = Id::random();
+
+        rpc.put(
+            PutRequestSpecific::AnnouncePeer(AnnouncePeerRequestArguments {
+                info_hash,
+                port: 45555,
+                implied_port: None,
+                seed: None,
+            }),
+            Some(
+                [Node::new_with_token(
+                    Id::random(),
+                    silent_address,
+                    [0; 4].into(),
+                )]
+                .into(),
+            ),
+        )
+        .unwrap();
+
+        let start = Instant::now();
+
+        let result = loop {
+            if let Some((_, result)) = rpc
+                .tick()
+                .done_put_queries
+                .into_iter()
+                .find(|(id, _)| *id == info_hash)
+            {
+                break result;
+            }
+        };
+
+        // Finished before the silent node's request timed out, with the nodes that did store it.
+        assert!(start.elapsed() < DEFAULT_REQUEST_TIMEOUT);
+        let report = result.unwrap();
+        assert!(!report.stored_at.is_empty());
+        assert!(report
+            .stored_at
+            .iter()
+            .all(|node| node.address() != silent_address));
+    }
+
+    #[test]
+    fn set_external_ip() {
+        let mut rpc = Rpc::new(config::Config {
+            bootstrap: Some(vec![]),
+            ..Default::default()
+        })
+        .unwrap();
+
+        for i in 0..20 {
+            rpc.routing_table.add(Node::unique(i));
+        }
+        let size = rpc.routing_table().size();
+
+        let old_id = *rpc.id();
+        let target = Id::random();
+        rpc.get(
+            GetRequestSpecific::FindNode(FindNodeRequestArguments { target }),
+            None,
+        );
+
+        let ip = Ipv4Addr::new(93, 184, 216, 34);
+        let new_id = rpc.set_external_ip(ip);
+
+        assert_ne!(new_id, old_id);
+        assert!(new_id.is_valid_for_ip(ip));
+        assert_eq!(rpc.id(), &new_id);
+        assert_eq!(rpc.public_address().map(|a| *a.ip()), Some(ip));
+        assert!(rpc.firewalled());
+
+        // Existing nodes are kept, and the new Id is looked up.
+        assert!(rpc.routing_table().size() >= size - 1);
+        assert!(rpc.iterative_queries.contains_key(&new_id));
+
+        // In-flight queries keep running.
+        assert!(rpc.iterative_queries.contains_key(&target));
+
+        // Already valid Ids are kept.
+        assert_eq!(rpc.set_external_ip(ip), new_id);
+    }
+
+    #[test]
+    fn set_external_ip_skip_self_lookup() {
+        let mut rpc = Rpc::new(config::Config {
+            bootstrap: Some(vec![]),
+            skip_self_lookup: true,
+            ..Default::default()
+        })
+        .unwrap();
+
+        for i in 0..20 {
+            rpc.routing_table.add(Node::unique(i));
+        }
+
+        let new_id = rpc.set_external_ip(Ipv4Addr::new(93, 184, 216, 34));
+
+        assert!(!rpc.iterative_queries.contains_key(&new_id));
+    }
+
+    #[test]
+    fn update_external_ip_sends_nothing() {
+        let mut rpc = Rpc::new(config::Config {
+            bootstrap: Some(vec![]),
+            ..Default::default()
+        })
+        .unwrap();
+
+        for i in 0..20 {
+            rpc.routing_table.add(Node::unique(i));
+        }
+
+        let ip = Ipv4Addr::new(93, 184, 216, 34);
+        let new_id = rpc.update_external_ip(ip);
+
+        assert!(new_id.is_valid_for_ip(ip));
+        assert_eq!(rpc.public_address().map(|a| *a.ip()), Some(ip));
+        assert_eq!(rpc.socket_stats().packets_sent, 0);
+        assert!(rpc.iterative_queries.is_empty());
+
+        rpc.confirm_external_ip();
+
+        assert!(rpc.iterative_queries.contains_key(&new_id));
+    }
+
+    #[test]
+    fn bucket_refresh_interval() {
+        let mut rpc = Rpc::new(config::Config {
+            bootstrap: Some(vec![]),
+            bucket_refresh_interval: Duration::from_millis(10),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let mut stale = Node::new(rpc.id().random_at_distance(150), Node::unique(1).address());
+        std::sync::Arc::make_mut(&mut stale.0).last_seen = Instant::now() - Duration::from_secs(1);
+        rpc.routing_table.add(stale);
+
+        assert!(rpc.last_table_refresh().is_none());
+
+        std::thread::sleep(Duration::from_millis(20));
+        rpc.tick();
+
+        assert!(rpc.last_table_refresh().is_some());
+        assert!(rpc
+            .iterative_queries
+            .keys()
+            .any(|target| rpc.id().distance(target) == 150));
+    }
+
+    #[test]
+    fn get_with_timeout() {
+        // A node that never responds.
+        let silent = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+
+        let mut rpc = Rpc::new(config::Config {
+            bootstrap: Some(to_socket_address(&[silent.local_addr().unwrap()])),
+            skip_self_lookup: true,
+            ..Default::default()
+        })
+        .unwrap();
+
+        let target = Id::random();
+        rpc.get_with_timeout(
+            GetRequestSpecific::FindNode(FindNodeRequestArguments { target }),
+            None,
+            Some(Duration::from_millis(50)),
+        );
+
+        let start = Instant::now();
+
+        while !rpc
+            .tick()
+            .done_get_queries
+            .iter()
+            .any(|(id, _)| *id == target)
+        {}
+
+        // Much sooner than the default request timeout.
+        assert!(start.elapsed() < DEFAULT_REQUEST_TIMEOUT);
+    }
+
+    #[test]
+    fn query_spans() {
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::DEBUG)
+            .with_writer(std::io::sink)
+            .finish();
+
+        tracing::subscriber::with_default(subscriber, || {
+            let mut rpc = Rpc::new(config::Config {
+                bootstrap: Some(vec![]),
+                skip_self_lookup: true,
+                ..Default::default()
+            })
+            .unwrap();
+
+            let a = Id::random();
+            let b = Id::random();
+
+            rpc.get(
+                GetRequestSpecific::FindNode(FindNodeRequestArguments { target: a }),
+                None,
+            );
+            rpc.get(
+                GetRequestSpecific::FindNode(FindNodeRequestArguments { target: b }),
+                None,
+            );
+
+            let span_a = rpc.iterative_queries[&a].span();
+            let span_b = rpc.iterative_queries[&b].span();
+
+            assert_eq!(span_a.metadata().map(|m| m.name()), Some("query"));
+            assert!(span_a.id().is_some());
+            assert_ne!(span_a.id(), span_b.id());
+            assert_eq!(rpc.next_query_id, 2);
+        });
+    }
+
+    #[test]
+    fn max_concurrent_queries() {
+        // A node that never responds.
+        let silent = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+
+        let mut rpc = Rpc::new(config::Config {
+            bootstrap: Some(to_socket_address(&[silent.local_addr().unwrap()])),
+            request_timeout: Duration::from_millis(50),
+            skip_self_lookup: true,
+            max_concurrent_queries: Some(1),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let first = Id::random();
+        let second = Id::random();
+
+        for target in [first, second] {
+            rpc.get(
+                GetRequestSpecific::FindNode(FindNodeRequestArguments { target }),
+                None,
+            );
+        }
+
+        assert_eq!(rpc.iterative_queries.len(), 1);
+        assert_eq!(rpc.queued_queries.len(), 1);
+
+        let mut done = vec![];
+        while done.len() < 2 {
+            for (id, _) in rpc.tick().done_get_queries {
+                done.push(id);
+            }
+
+            assert!(rpc.iterative_queries.len() <= 1);
+        }
+
+        assert_eq!(done, vec![first, second]);
+    }
+
+    #[test]
+    fn zero_max_concurrent_queries() {
+        // A node that never responds.
+        let silent = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+
+        let mut rpc = Rpc::new(config::Config {
+            bootstrap: Some(to_socket_address(&[silent.local_addr().unwrap()])),
+            request_timeout: Duration::from_millis(50),
+            skip_self_lookup: true,
+            max_concurrent_queries: Some(0),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let target = Id::random();
+        rpc.get(
+            GetRequestSpecific::FindNode(FindNodeRequestArguments { target }),
+            None,
+        );
+
+        // Treated as a limit of 1, instead of queueing forever.
+        assert_eq!(rpc.iterative_queries.len(), 1);
+        assert!(rpc.queued_queries.is_empty());
+
+        while !rpc
+            .tick()
+            .done_get_queries
+            .iter()
+            .any(|(id, _)| *id == target)
+        {}
+    }
+
+    #[test]
+    fn cancel() {
+        // A node that never responds.
+        let silent = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+
+        let mut rpc = Rpc::new(config::Config {
+            bootstrap: Some(to_socket_address(&[silent.local_addr().unwrap()])),
+            skip_self_lookup: true,
+            max_concurrent_queries: Some(1),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let first = Id::random();
+        let second = Id::random();
+
+        for target in [first, second] {
+            rpc.get(
+                GetRequestSpecific::FindNode(FindNodeRequestArguments { target }),
+                None,
+            );
+        }
+
+        assert!(!rpc.cancel(&second));
+        assert!(rpc.queued_queries.is_empty());
+
+        assert!(rpc.cancel(&first));
+        assert!(rpc.iterative_queries.is_empty());
+        assert!(!rpc.cancel(&first));
+    }
+
+    #[test]
+    fn retry_put_with_stale_tokens() {
+        let testnet = Testnet::new(10).unwrap();
+
+        let mut rpc = Rpc::new(config::Config {
+            bootstrap: Some(to_socket_address(&testnet.bootstrap)),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let info_hash = Id::random();
+
+        // Cache the bootstrapping node with a token it will reject.
+        rpc.cached_iterative_queries.put(
+            info_hash,
+            CachedIterativeQuery {
+                closest_responding_nodes: [Node::new_with_token(
+                    Id::random(),
+                    rpc.bootstrap[0],
+                    [0; 4].into(),
+                )]
+                .into(),
+                dht_size_estimate: 0.0,
+                responders_dht_size_estimate: 0.0,
+                subnets: 0,
+                storage_pressure: 0.0,
+                is_find_node: false,
+            },
+        );
+        rpc.responders_based_dht_size_estimates_count += 1;
+
+        rpc.put(
+            PutRequestSpecific::AnnouncePeer(AnnouncePeerRequestArguments {
+                info_hash,
+                port: 45555,
+                implied_port: None,
+                seed: None,
+            }),
+            None,
+        )
+        .unwrap();
+
+        let result = loop {
+            if let Some((_, result)) = rpc
+                .tick()
+                .done_put_queries
+                .into_iter()
+                .find(|(id, _)| *id == info_hash)
+            {
+                break result;
+            }
+        };
+
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn put_after_find_node_looks_up_tokens() {
+        let testnet = Testnet::new(10).unwrap();
+
+        let mut rpc = Rpc::new(config::Config {
+            bootstrap: Some(to_socket_address(&testnet.bootstrap)),
+            ..Default::default()
+        })
+        .unwrap();
+
+        while rpc.routing_table.is_empty() {
+            rpc.tick();
+        }
+
+        let info_hash = Id::random();
+
+        rpc.get(
+            GetRequestSpecific::FindNode(FindNodeRequestArguments { target: info_hash }),
+            None,
+        );
+
+        while !rpc
+            .tick()
+            .done_get_queries
+            .iter()
+            .any(|(id, _)| *id == info_hash)
+        {}
+
+        assert!(rpc
+            .cached_iterative_queries
+            .peek(&info_hash)
+            .is_some_and(|cached| cached.is_find_node));
+
+        rpc.put(
+            PutRequestSpecific::AnnouncePeer(AnnouncePeerRequestArguments {
+                info_hash,
+                port: 45555,
+                implied_port: None,
+                seed: None,
+            }),
+            None,
+        )
+        .unwrap();
+
+        // The cached find_node responders have no tokens, so look them up instead.
+        assert!(rpc.iterative_queries.contains_key(&info_hash));
+
+        let result = loop {
+            if let Some((_, result)) = rpc
+                .tick()
+                .done_put_queries
+                .into_iter()
+                .find(|(id, _)| *id == info_hash)
+            {
+                break result;
+            }
+        };
+
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn serve_own_peers() {
+        let testnet = Testnet::new(10).unwrap();
+
+        let mut rpc = Rpc::new(config::Config {
+            bootstrap: Some(to_socket_address(&testnet.bootstrap)),
+            serve_own_peers: true,
+            ..Default::default()
+        })
+        .unwrap();
+
+        let info_hash = Id::random();
+
+        rpc.put(
+            PutRequestSpecific::AnnouncePeer(AnnouncePeerRequestArguments {
+                info_hash,
+                port: 45555,
+                implied_port: None,
+                seed: None,
+            }),
+            None,
+        )
+        .unwrap();
+
+        while !rpc
+            .tick()
+            .done_put_queries
+            .iter()
+            .any(|(id, _)| *id == info_hash)
+        {}
+
+        let mut client = KrpcSocket::client().unwrap();
+        let rpc_address = SocketAddrV4::new([127, 0, 0, 1].into(), rpc.local_addr().port());
+
+        let tid = client.request(
+            rpc_address,
+            RequestSpecific {
+                requester_id: Id::random(),
+                request_type: RequestTypeSpecific::GetPeers(GetPeersRequestArguments { info_hash }),
+            },
+        );
+
+        while client.inflight(&tid) {
+            rpc.tick();
+
+            if let Some((message, _)) = client.recv_from() {
+                assert_eq!(
+                    message.message_type,
+                    MessageType::Response(ResponseSpecific::GetPeers(GetPeersResponseArguments {
+                        responder_id: *rpc.id(),
+                        token: message.get_token().unwrap().1.into(),
+                        nodes: None,
+                        values: vec![SocketAddrV4::new([127, 0, 0, 1].into(), 45555)],
+                    }))
+                );
+
+                return;
+            }
+        }
+
+        panic!("Expected a get_peers response");
+    }
+
+    #[test]
+    fn expire_own_peers() {
+        let mut rpc = Rpc::new(config::Config {
+            bootstrap: Some(vec![]),
+            serve_own_peers: true,
+            item_refresh_interval: Duration::from_millis(50),
+            ..Default::default()
+        })
+        .unwrap();
+        rpc.public_address = Some(SocketAddrV4::new([127, 0, 0, 1].into(), 6881));
+
+        let announce = |rpc: &mut Rpc, info_hash| {
+            let _ = rpc.put(
+                PutRequestSpecific::AnnouncePeer(AnnouncePeerRequestArguments {
+                    info_hash,
+                    port: 45555,
+                    implied_port: None,
+                    seed: None,
+                }),
+                None,
+            );
+        };
+        let get_peers = |info_hash| RequestSpecific {
+            requester_id: Id::random(),
+            request_type: RequestTypeSpecific::GetPeers(GetPeersRequestArguments { info_hash }),
+        };
+
+        let first = Id::random();
+        announce(&mut rpc, first);
+
+        assert!(rpc.own_peer(&get_peers(first)).is_some());
+
+        std::thread::sleep(Duration::from_millis(150));
+
+        assert!(rpc.own_peer(&get_peers(first)).is_none());
+
+        // Expired entries are pruned on the next announcement.
+        let second = Id::random();
+        announce(&mut rpc, second);
+
+        assert!(rpc.own_peer(&get_peers(second)).is_some());
+        assert_eq!(
+            rpc.own_peers.as_ref().map(|own_peers| own_peers.len()),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn client_only() {
+        let mut rpc = Rpc::new(config::Config {
+            bootstrap: Some(vec![]),
+            server_mode: true,
+            client_only: true,
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert!(!rpc.server_mode());
+
+        // Publicly accessible, but never promoted to server mode.
+        rpc.firewalled = false;
+        rpc.switch_to_server_mode_if_accessible();
+
+        assert!(!rpc.firewalled());
+        assert!(!rpc.server_mode());
+
+        let mut adaptive = Rpc::new(config::Config {
+            bootstrap: Some(vec![]),
+            ..Default::default()
+        })
+        .unwrap();
+
+        adaptive.firewalled = false;
+        adaptive.switch_to_server_mode_if_accessible();
+
+        assert!(adaptive.server_mode());
+    }
+
+    #[test]
+    fn read_only() {
+        let mut rpc = Rpc::new(config::Config {
+            bootstrap: Some(vec![]),
+            server_mode: true,
+            read_only: true,
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert!(!rpc.server_mode());
+
+        let mut client = KrpcSocket::client().unwrap();
+        let rpc_address = SocketAddrV4::new([127, 0, 0, 1].into(), rpc.local_addr().port());
+        let info_hash = Id::random();
+
+        // Doesn't answer any requests, nor hand out tokens.
+        let get_peers = client.request_with_timeout(
+            rpc_address,
+            RequestSpecific {
+                requester_id: Id::random(),
+                request_type: RequestTypeSpecific::GetPeers(GetPeersRequestArguments { info_hash }),
+            },
+            Duration::from_millis(200),
+        );
+
+        // Silently drops store requests.
+        let announce_peer = client.request_with_timeout(
+            rpc_address,
+            RequestSpecific {
+                requester_id: Id::random(),
+                request_type: RequestTypeSpecific::Put(PutRequest {
+                    token: vec![0; 4].into(),
+                    put_request_type: PutRequestSpecific::AnnouncePeer(
+                        AnnouncePeerRequestArguments {
+                            info_hash,
+                            port: 45555,
+                            implied_port: None,
+                            seed: None,
+                        },
+                    ),
+                }),
+            },
+            Duration::from_millis(200),
+        );
+
+        while client.inflight(&get_peers) || client.inflight(&announce_peer) {
+            rpc.tick();
+
+            assert!(
+                client.recv_from().is_none(),
+                "Read-only node answered a request"
+            );
+        }
+
+        assert_eq!(rpc.server_stats(1).info_hashes, 0);
+    }
+}
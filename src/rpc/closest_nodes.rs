@@ -67,26 +67,40 @@ impl ClosestNodes {
         }
     }
 
+    /// Same as [Self::take_until_secure_with_k] with the default `k` (20).
+    pub fn take_until_secure(
+        &self,
+        previous_dht_size_estimate: usize,
+        average_subnets: usize,
+    ) -> &[Node] {
+        self.take_until_secure_with_k(
+            MAX_BUCKET_SIZE_K,
+            previous_dht_size_estimate,
+            average_subnets,
+        )
+    }
+
     /// Take enough nodes closest to the target, until the following are satisfied:
-    /// 1. At least the closest `k` nodes (20).
-    /// 2. The last node should be at a distance `edk` which is the expected distance of the 20th
+    /// 1. At least the closest `k` nodes.
+    /// 2. The last node should be at a distance `edk` which is the expected distance of the `k`th
     ///    node given previous estimations of the DHT size.
     /// 3. The number of subnets with unique 6 bits prefix in nodes ipv4 addresses match or exceeds
-    ///     the average from previous queries.
+    ///    the average from previous queries.
     ///
     /// If one or more of these conditions are not met, then we just take all responding nodes
     /// and store data at them.
-    pub fn take_until_secure(
+    pub fn take_until_secure_with_k(
         &self,
+        k: usize,
         previous_dht_size_estimate: usize,
         average_subnets: usize,
     ) -> &[Node] {
         let mut until_secure = 0;
 
-        // 20 / dht_size_estimate == expected_dk / ID space
-        // so expected_dk = 20 * ID space / dht_size_estimate
+        // k / dht_size_estimate == expected_dk / ID space
+        // so expected_dk = k * ID space / dht_size_estimate
         let expected_dk =
-            (20.0 * u128::MAX as f64 / (previous_dht_size_estimate as f64 + 1.0)) as u128;
+            (k as f64 * u128::MAX as f64 / (previous_dht_size_estimate as f64 + 1.0)) as u128;
 
         let mut subnets = HashSet::new();
 
@@ -102,7 +116,7 @@ impl ClosestNodes {
             until_secure += 1;
         }
 
-        &self.nodes[0..until_secure.max(MAX_BUCKET_SIZE_K).min(self.nodes().len())]
+        &self.nodes[0..until_secure.max(k).min(self.nodes().len())]
     }
 
     /// Count the number of subnets with unique 6 bits prefix in ipv4
@@ -210,6 +224,7 @@ mod tests {
             address: SocketAddrV4::new([21, 75, 31, 124].into(), 0),
             token: None,
             last_seen: Instant::now(),
+            client_version: None,
         }));
 
         let mut closest_nodes = ClosestNodes::new(*unsecure.id());
@@ -250,6 +265,38 @@ mod tests {
         assert!((closest.len() - sybil.nodes().len()) > 10);
     }
 
+    #[test]
+    fn take_until_secure_with_k() {
+        let target = Id::random();
+
+        let mut closest_nodes = ClosestNodes::new(target);
+
+        for i in 0..100 {
+            closest_nodes.add(Node::unique(i));
+        }
+
+        // A huge Dht size estimate makes the expected distance trivially reached,
+        // so only the minimum `k` nodes are taken.
+        let dht_size_estimate = usize::MAX;
+
+        assert_eq!(
+            closest_nodes
+                .take_until_secure_with_k(5, dht_size_estimate, 0)
+                .len(),
+            5
+        );
+        assert_eq!(
+            closest_nodes
+                .take_until_secure_with_k(40, dht_size_estimate, 0)
+                .len(),
+            40
+        );
+        assert_eq!(
+            closest_nodes.take_until_secure(dht_size_estimate, 0).len(),
+            20
+        );
+    }
+
     #[test]
     fn simulation() {
         let lookups = 4;
@@ -281,10 +328,10 @@ mod tests {
                 let mut closest_nodes = ClosestNodes::new(target);
 
                 for (_, node) in nodes.range(target..).take(100) {
-                    closest_nodes.add(node.clone().into())
+                    closest_nodes.add(node.clone())
                 }
                 for (_, node) in nodes.range(..target).rev().take(100) {
-                    closest_nodes.add(node.clone().into())
+                    closest_nodes.add(node.clone())
                 }
 
                 let estimate = closest_nodes.dht_size_estimate();
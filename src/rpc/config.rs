@@ -1,9 +1,20 @@
 use std::{
-    net::{Ipv4Addr, SocketAddrV4},
+    fmt::Debug,
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    sync::Arc,
     time::Duration,
 };
 
-use super::{ServerSettings, DEFAULT_REQUEST_TIMEOUT};
+use crate::{
+    common::{Ipv4Net, RequestSpecific, MAX_BUCKET_SIZE_K},
+    Id, IdentityCodec, Node, ValueCodec,
+};
+
+use super::{
+    NetworkConditions, RetransmitPolicy, ServerSettings, DEFAULT_BUCKET_REFRESH_INTERVAL,
+    DEFAULT_ITEM_REFRESH_INTERVAL, DEFAULT_MAX_QUERY_LIFETIME, DEFAULT_REQUEST_TIMEOUT,
+    DEFAULT_TOKEN_LIFETIME, DHT_SIZE_ESTIMATE_HISTORY, MAX_CACHED_ITERATIVE_QUERIES,
+};
 
 #[derive(Debug, Clone)]
 /// Dht Configurations
@@ -12,10 +23,27 @@ pub struct Config {
     ///
     /// Defaults to [super::DEFAULT_BOOTSTRAP_NODES]
     pub bootstrap: Option<Vec<SocketAddrV4>>,
+    /// More bootstrapping nodes to add to [Self::bootstrap], or to the
+    /// [super::DEFAULT_BOOTSTRAP_NODES] if it is None, resolved when the node starts.
+    ///
+    /// Defaults to an empty list
+    pub extra_bootstrap: Vec<String>,
+    /// Nodes, usually saved from a previous session's routing table, to bootstrap from
+    /// before falling back to the [Self::bootstrap] nodes.
+    ///
+    /// Defaults to an empty list
+    pub bootstrap_from_nodes: Vec<Node>,
     /// Explicit port to listen on.
     ///
     /// Defaults to None
     pub port: Option<u16>,
+    /// Explicit local address to bind to, for example to choose a specific
+    /// interface on a multi-homed host.
+    ///
+    /// Mutually exclusive with [Self::port], and only Ipv4 addresses are supported.
+    ///
+    /// Defaults to None, where the socket binds to all interfaces (`0.0.0.0`).
+    pub bind_addr: Option<SocketAddr>,
     /// UDP socket request timeout duration.
     ///
     /// The longer this duration is, the longer queries take until they are deemeed "done".
@@ -35,17 +63,246 @@ pub struct Config {
     ///
     /// Defaults to None, where we depend on suggestions from responding nodes.
     pub public_ip: Option<Ipv4Addr>,
+    /// A likely public IPv4 address, for example discovered with STUN, counted as
+    /// a strong vote when responding nodes vote on our public address, so the
+    /// consensus can still override it if it disagrees.
+    ///
+    /// Defaults to None.
+    pub suggested_public_ip: Option<Ipv4Addr>,
+    /// Explicit node Id to use instead of generating one, for example to keep
+    /// a stable identity across restarts.
+    ///
+    /// If [Self::public_ip] is also set, this Id has to be secure for it according to
+    /// [BEP_0042](https://www.bittorrent.org/beps/bep_0042.html).
+    ///
+    /// Defaults to None, where the Id is generated from [Self::public_ip], or randomly.
+    pub node_id: Option<Id>,
+    /// Seed to deterministically derive the node Id from, if [Self::node_id] is not set,
+    /// see [Id::from_seed] and [Id::from_ipv4_and_seed].
+    ///
+    /// Defaults to None, where the Id is generated randomly.
+    pub node_id_seed: Option<[u8; 32]>,
+    /// Maximum number of done queries to cache the closest responding nodes of.
+    ///
+    /// These cached queries are also the samples used for the Dht size estimate,
+    /// so this bounds both.
+    ///
+    /// Defaults to [MAX_CACHED_ITERATIVE_QUERIES]
+    pub max_cached_queries: usize,
+    /// Maximum number of most recent Dht size estimates to keep,
+    /// see [crate::Dht::dht_size_estimate_history].
+    ///
+    /// Defaults to [DHT_SIZE_ESTIMATE_HISTORY]
+    pub max_estimate_samples: usize,
+    /// Answer get_peers requests for info hashes this node announced itself on,
+    /// even when not running in server mode.
+    ///
+    /// Each info hash is served for twice [Self::item_refresh_interval] after its last
+    /// announcement, roughly as long as other nodes keep it.
+    ///
+    /// Defaults to false
+    pub serve_own_peers: bool,
+    /// Accept responses from an address other than the one the request was sent to,
+    /// for NATs that legitimately rewrite the source address of responses.
+    ///
+    /// Defaults to false, where such responses are dropped to protect against
+    /// off-path response spoofing.
+    pub accept_any_response_address: bool,
+    /// Don't run the `find_node` lookup for this node's own Id, neither at startup,
+    /// on periodic routing table refresh, nor after a change of the external IP.
+    ///
+    /// Defaults to false
+    pub skip_self_lookup: bool,
+    /// Interval to refresh the routing table at, looking up this node's own Id
+    /// and a random Id in each bucket where no node was seen within the interval.
+    ///
+    /// Defaults to [DEFAULT_BUCKET_REFRESH_INTERVAL]
+    pub bucket_refresh_interval: Duration,
+    /// Interval to refresh (re-put or re-announce) stored items at, before they expire.
+    ///
+    /// Defaults to [DEFAULT_ITEM_REFRESH_INTERVAL]
+    pub item_refresh_interval: Duration,
+    /// Maximum lifetime of a query, after which it is forcibly completed
+    /// with whatever results it has so far.
+    ///
+    /// Defaults to [DEFAULT_MAX_QUERY_LIFETIME]
+    pub max_query_lifetime: Duration,
+    /// Maximum number of iterative queries running at the same time, new queries
+    /// beyond this limit are queued until an inflight query is done.
+    ///
+    /// A limit of 0 is treated as 1.
+    ///
+    /// Defaults to None, where there is no limit.
+    pub max_concurrent_queries: Option<usize>,
+    /// Number of closest nodes queries visit concurrently, [Dht::find_node](crate::Dht::find_node)
+    /// returns, and values are stored at, see [DhtBuilder::k_factor](crate::DhtBuilder::k_factor).
+    ///
+    /// Defaults to 20
+    pub k_factor: usize,
+    /// Maximum number of hops an iterative query follows towards closer nodes,
+    /// before it is done with whatever results it has so far,
+    /// see [DhtBuilder::max_lookup_rounds](crate::DhtBuilder::max_lookup_rounds).
+    ///
+    /// Defaults to None, where there is no limit.
+    pub max_lookup_rounds: Option<usize>,
+    /// How long write tokens received from GET queries are considered valid,
+    /// before PUT queries re-fetch them with a new lookup.
+    ///
+    /// Defaults to [DEFAULT_TOKEN_LIFETIME]
+    pub token_lifetime: Duration,
+    /// Maximum number of immutable values to cache by their hash, so repeated
+    /// `get_immutable` calls for the same target don't hit the network.
+    ///
+    /// Defaults to 0, where the cache is disabled.
+    pub immutable_cache_size: usize,
+    /// Mark all outgoing requests as read-only (`ro=1`) according to
+    /// [BEP_0043](https://www.bittorrent.org/beps/bep_0043.html), and don't
+    /// answer any incoming requests, silently dropping store requests (announce_peer and put).
+    ///
+    /// Overrides [Self::server_mode], and prevents switching to server mode in adaptive mode.
+    ///
+    /// Defaults to false
+    pub read_only: bool,
+    /// Never switch to server mode, even if this node has been running long enough
+    /// with a publicly accessible address, nor respond to any requests.
+    ///
+    /// Overrides [Self::server_mode].
+    ///
+    /// Defaults to false, where the node runs in
+    /// [Adaptive mode](https://github.com/pubky/mainline?tab=readme-ov-file#adaptive-mode).
+    pub client_only: bool,
+    /// Simulated network conditions (packet loss and latency) applied to outgoing packets,
+    /// only useful for testing.
+    ///
+    /// Defaults to None
+    pub network_conditions: Option<NetworkConditions>,
+    /// Maximum number of outgoing UDP packets per second, throttled using a token bucket.
+    ///
+    /// Packets exceeding the limit are queued and sent later instead of being dropped,
+    /// and queued requests only start timing out once they are sent. Up to 1000 packets
+    /// are queued, beyond which the oldest are dropped.
+    ///
+    /// Defaults to None (or `Some(0)`), where outgoing packets are not limited.
+    pub rate_limit: Option<u32>,
+    /// Retransmit requests that are not answered yet, with a randomized exponential backoff.
+    ///
+    /// Defaults to None, where requests are sent once, and abandoned after the [Self::request_timeout].
+    pub retransmit_policy: Option<RetransmitPolicy>,
+    /// Custom name resolution for the [default bootstrapping nodes](super::DEFAULT_BOOTSTRAP_NODES),
+    /// and the [Self::extra_bootstrap] nodes.
+    ///
+    /// Defaults to None, where [std::net::ToSocketAddrs] is used.
+    pub resolver: Option<Resolver>,
+    /// Encoding applied to immutable values before putting them,
+    /// and decoding applied after getting them, see [ValueCodec].
+    ///
+    /// Defaults to [IdentityCodec]
+    pub value_codec: Box<dyn ValueCodec>,
+    /// Called with every incoming request, before it is handled, see [crate::DhtBuilder::on_request].
+    ///
+    /// Defaults to None
+    pub on_request: Option<RequestObserver>,
+    /// Addresses to drop packets to and from, and never add to the routing table,
+    /// see [crate::DhtBuilder::blocklist].
+    ///
+    /// Defaults to an empty list
+    pub blocklist: Vec<Ipv4Net>,
+    /// Detect unreachable destinations from ICMP errors, to stop waiting for their
+    /// responses, and remove them from the routing table, see [crate::DhtBuilder::detect_unreachable].
+    ///
+    /// Defaults to false
+    pub detect_unreachable: bool,
+    /// Maximum time [crate::Dht::new] waits for the actor thread to bind its socket
+    /// and start, see [crate::DhtBuilder::build_timeout].
+    ///
+    /// Defaults to None, where it waits indefinitely.
+    pub build_timeout: Option<Duration>,
+}
+
+type ResolveFn = dyn Fn(&str) -> std::io::Result<Vec<SocketAddr>> + Send + Sync;
+
+#[derive(Clone)]
+/// Resolves a `host:port` string to socket addresses, see [crate::DhtBuilder::resolver].
+pub struct Resolver(pub(crate) Arc<ResolveFn>);
+
+impl Resolver {
+    /// Resolve `hosts` to their Ipv4 socket addresses, ignoring the ones that fail to resolve.
+    pub(crate) fn resolve(&self, hosts: &[&str]) -> Vec<SocketAddrV4> {
+        hosts
+            .iter()
+            .filter_map(|host| (self.0)(host).ok())
+            .flatten()
+            .filter_map(|addr| match addr {
+                SocketAddr::V4(addr_v4) => Some(addr_v4),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl Debug for Resolver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Resolver")
+    }
+}
+
+type OnRequestFn = dyn Fn(SocketAddrV4, &RequestSpecific) + Send + Sync;
+
+#[derive(Clone)]
+/// Observes incoming requests before they are handled, see [crate::DhtBuilder::on_request].
+pub struct RequestObserver(pub(crate) Arc<OnRequestFn>);
+
+impl RequestObserver {
+    pub(crate) fn observe(&self, from: SocketAddrV4, request: &RequestSpecific) {
+        (self.0)(from, request)
+    }
+}
+
+impl Debug for RequestObserver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("RequestObserver")
+    }
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
             bootstrap: None,
+            extra_bootstrap: Vec::new(),
+            bootstrap_from_nodes: Vec::new(),
             port: None,
+            bind_addr: None,
             request_timeout: DEFAULT_REQUEST_TIMEOUT,
             server_settings: Default::default(),
             server_mode: false,
             public_ip: None,
+            suggested_public_ip: None,
+            node_id: None,
+            node_id_seed: None,
+            max_cached_queries: MAX_CACHED_ITERATIVE_QUERIES,
+            max_estimate_samples: DHT_SIZE_ESTIMATE_HISTORY,
+            serve_own_peers: false,
+            accept_any_response_address: false,
+            skip_self_lookup: false,
+            item_refresh_interval: DEFAULT_ITEM_REFRESH_INTERVAL,
+            bucket_refresh_interval: DEFAULT_BUCKET_REFRESH_INTERVAL,
+            max_query_lifetime: DEFAULT_MAX_QUERY_LIFETIME,
+            max_concurrent_queries: None,
+            k_factor: MAX_BUCKET_SIZE_K,
+            max_lookup_rounds: None,
+            token_lifetime: DEFAULT_TOKEN_LIFETIME,
+            immutable_cache_size: 0,
+            read_only: false,
+            client_only: false,
+            network_conditions: None,
+            rate_limit: None,
+            retransmit_policy: None,
+            resolver: None,
+            on_request: None,
+            blocklist: Vec::new(),
+            detect_unreachable: false,
+            build_timeout: None,
+            value_codec: Box::new(IdentityCodec),
         }
     }
 }
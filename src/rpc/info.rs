@@ -1,11 +1,14 @@
-use std::net::SocketAddrV4;
+use std::{
+    net::SocketAddrV4,
+    time::{Duration, SystemTime},
+};
 
 use crate::Id;
 
 use super::Rpc;
 
 /// Information and statistics about this mainline node.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Info {
     id: Id,
     local_addr: SocketAddrV4,
@@ -13,6 +16,23 @@ pub struct Info {
     firewalled: bool,
     dht_size_estimate: (usize, f64),
     server_mode: bool,
+    memory_report: MemoryReport,
+    get_success_rate: Option<f64>,
+    put_success_rate: Option<f64>,
+    response_address_mismatches: usize,
+    item_refresh_interval: Duration,
+    last_table_refresh: Option<SystemTime>,
+    immutable_cache_hits: usize,
+    immutable_cache_misses: usize,
+    packets_sent: usize,
+    packets_received: usize,
+    send_errors: usize,
+    recv_errors: usize,
+    rate_limit_utilization: Option<f64>,
+    throttled_packets: usize,
+    inflight_requests: usize,
+    unknown_transaction_responses: usize,
+    unreachable_destinations: usize,
 }
 
 impl Info {
@@ -21,6 +41,9 @@ impl Info {
         &self.id
     }
     /// Local UDP Ipv4 socket address that this node is listening on.
+    ///
+    /// The port is the one actually bound, including when it was assigned by the OS
+    /// using [crate::DhtBuilder::ephemeral_port].
     pub fn local_addr(&self) -> SocketAddrV4 {
         self.local_addr
     }
@@ -54,6 +77,212 @@ impl Info {
     pub fn dht_size_estimate(&self) -> (usize, f64) {
         self.dht_size_estimate
     }
+
+    /// Returns the fraction of the most recent (up to 100) GET queries, for peers
+    /// or values, that returned at least one value.
+    ///
+    /// Returns `None` if no GET queries are done yet.
+    pub fn get_success_rate(&self) -> Option<f64> {
+        self.get_success_rate
+    }
+
+    /// Returns the fraction of the most recent (up to 100) PUT queries
+    /// that stored at least at one node.
+    ///
+    /// Returns `None` if no PUT queries are done yet.
+    pub fn put_success_rate(&self) -> Option<f64> {
+        self.put_success_rate
+    }
+
+    /// Returns the number of responses received from an address other than
+    /// the one the corresponding request was sent to.
+    ///
+    /// These responses are dropped unless [crate::DhtBuilder::accept_any_response_address] is set.
+    pub fn response_address_mismatches(&self) -> usize {
+        self.response_address_mismatches
+    }
+
+    /// Returns the interval to refresh (re-put or re-announce) stored items at,
+    /// see [crate::DhtBuilder::item_refresh_interval].
+    pub fn item_refresh_interval(&self) -> Duration {
+        self.item_refresh_interval
+    }
+
+    /// Returns the last time the routing table was refreshed, or `None` if it wasn't yet,
+    /// see [crate::DhtBuilder::bucket_refresh_interval].
+    pub fn last_table_refresh(&self) -> Option<SystemTime> {
+        self.last_table_refresh
+    }
+
+    /// Returns the number of `get_immutable` calls answered from the immutable values cache,
+    /// see [crate::DhtBuilder::immutable_cache_size].
+    pub fn immutable_cache_hits(&self) -> usize {
+        self.immutable_cache_hits
+    }
+
+    /// Returns the number of `get_immutable` calls not found in the immutable values cache,
+    /// see [crate::DhtBuilder::immutable_cache_size].
+    pub fn immutable_cache_misses(&self) -> usize {
+        self.immutable_cache_misses
+    }
+
+    /// Returns the number of UDP packets sent since this node started.
+    pub fn packets_sent(&self) -> usize {
+        self.packets_sent
+    }
+
+    /// Returns the number of UDP packets received since this node started.
+    pub fn packets_received(&self) -> usize {
+        self.packets_received
+    }
+
+    /// Returns the number of errors sending UDP packets since this node started.
+    pub fn send_errors(&self) -> usize {
+        self.send_errors
+    }
+
+    /// Returns the number of errors receiving UDP packets since this node started,
+    /// for example after ICMP port unreachable messages, not counting read timeouts.
+    pub fn recv_errors(&self) -> usize {
+        self.recv_errors
+    }
+
+    /// Returns the fraction of the [outbound rate limit](crate::DhtBuilder::rate_limit)
+    /// currently used, between `0.0` and `1.0`, or `None` if there is no rate limit.
+    ///
+    /// Values close to `1.0` mean this node is being throttled.
+    pub fn rate_limit_utilization(&self) -> Option<f64> {
+        self.rate_limit_utilization
+    }
+
+    /// Returns the number of outgoing packets currently queued by the
+    /// [outbound rate limit](crate::DhtBuilder::rate_limit).
+    pub fn throttled_packets(&self) -> usize {
+        self.throttled_packets
+    }
+
+    /// Returns the number of outstanding requests (transaction ids) waiting
+    /// for a response or a timeout.
+    pub fn inflight_requests(&self) -> usize {
+        self.inflight_requests
+    }
+
+    /// Returns the number of responses received since this node started, with a
+    /// transaction id that isn't inflight, usually late responses to timed out requests.
+    ///
+    /// Useful to debug dropped responses, for example due to transaction id reuse.
+    pub fn unknown_transaction_responses(&self) -> usize {
+        self.unknown_transaction_responses
+    }
+
+    /// Returns the number of ICMP destination unreachable errors received since this node started,
+    /// see [crate::DhtBuilder::detect_unreachable].
+    pub fn unreachable_destinations(&self) -> usize {
+        self.unreachable_destinations
+    }
+
+    /// Returns a summary of the approximate memory held by the routing table,
+    /// the cached queries used for the Dht size estimate, and the history of that estimate.
+    ///
+    /// See [crate::DhtBuilder::max_cached_queries] and [crate::DhtBuilder::max_estimate_samples]
+    /// to bound the latter two.
+    pub fn memory_report(&self) -> MemoryReport {
+        self.memory_report
+    }
+
+    /// Returns the meaningful changes from an `older` snapshot to this one.
+    ///
+    /// Continuously changing statistics, like [Self::dht_size_estimate] and the success
+    /// rates, are not included; compare them directly if needed.
+    pub fn diff(&self, older: &Info) -> Vec<InfoChange> {
+        let mut changes = Vec::new();
+
+        if self.id != older.id {
+            changes.push(InfoChange::Id {
+                from: older.id,
+                to: self.id,
+            });
+        }
+        if self.local_addr != older.local_addr {
+            changes.push(InfoChange::LocalAddr {
+                from: older.local_addr,
+                to: self.local_addr,
+            });
+        }
+        if self.public_address != older.public_address {
+            changes.push(InfoChange::PublicAddress {
+                from: older.public_address,
+                to: self.public_address,
+            });
+        }
+        if self.firewalled != older.firewalled {
+            changes.push(InfoChange::Firewalled(self.firewalled));
+        }
+        if self.server_mode != older.server_mode {
+            changes.push(InfoChange::ServerMode(self.server_mode));
+        }
+        if self.memory_report.routing_table_nodes != older.memory_report.routing_table_nodes {
+            changes.push(InfoChange::RoutingTableSize {
+                from: older.memory_report.routing_table_nodes,
+                to: self.memory_report.routing_table_nodes,
+            });
+        }
+
+        changes
+    }
+}
+
+/// A change between two [Info] snapshots, see [Info::diff].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InfoChange {
+    /// This node's [Id] changed.
+    Id {
+        /// Previous Id.
+        from: Id,
+        /// Current Id.
+        to: Id,
+    },
+    /// The local address changed.
+    LocalAddr {
+        /// Previous local address.
+        from: SocketAddrV4,
+        /// Current local address.
+        to: SocketAddrV4,
+    },
+    /// The best guess for the public address changed.
+    PublicAddress {
+        /// Previous public address.
+        from: Option<SocketAddrV4>,
+        /// Current public address.
+        to: Option<SocketAddrV4>,
+    },
+    /// Whether or not this node is firewalled changed to this value.
+    Firewalled(bool),
+    /// Whether or not this node is running in server mode changed to this value.
+    ServerMode(bool),
+    /// The number of nodes in the routing table changed.
+    RoutingTableSize {
+        /// Previous number of nodes.
+        from: usize,
+        /// Current number of nodes.
+        to: usize,
+    },
+}
+
+/// Approximate memory held by a node's routing table and caches.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryReport {
+    /// Number of nodes in the routing table.
+    pub routing_table_nodes: usize,
+    /// Number of cached done queries, each is a sample in the Dht size estimate.
+    pub cached_queries: usize,
+    /// Number of closest responding nodes held by the cached queries.
+    pub cached_nodes: usize,
+    /// Number of Dht size estimates kept in the [crate::Dht::dht_size_estimate_history].
+    pub estimate_samples: usize,
+    /// Approximate number of bytes held by the routing table, the cached queries,
+    /// the Dht size estimate history, and the cached immutable values.
+    pub approximate_bytes: usize,
 }
 
 impl From<&Rpc> for Info {
@@ -65,6 +294,87 @@ impl From<&Rpc> for Info {
             public_address: rpc.public_address(),
             firewalled: rpc.firewalled(),
             server_mode: rpc.server_mode(),
+            memory_report: rpc.memory_report(),
+            get_success_rate: rpc.get_success_rate(),
+            put_success_rate: rpc.put_success_rate(),
+            response_address_mismatches: rpc.response_address_mismatches(),
+            item_refresh_interval: rpc.item_refresh_interval(),
+            last_table_refresh: rpc.last_table_refresh(),
+            immutable_cache_hits: rpc.immutable_cache_stats().0,
+            immutable_cache_misses: rpc.immutable_cache_stats().1,
+            packets_sent: rpc.socket_stats().packets_sent,
+            packets_received: rpc.socket_stats().packets_received,
+            send_errors: rpc.socket_stats().send_errors,
+            recv_errors: rpc.socket_stats().recv_errors,
+            rate_limit_utilization: rpc.rate_limit_utilization(),
+            throttled_packets: rpc.throttled_packets(),
+            inflight_requests: rpc.inflight_requests(),
+            unknown_transaction_responses: rpc.socket_stats().unknown_transaction_responses,
+            unreachable_destinations: rpc.socket_stats().unreachable_destinations,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn info() -> Info {
+        Info {
+            id: Id::random(),
+            local_addr: SocketAddrV4::new([0, 0, 0, 0].into(), 6881),
+            public_address: None,
+            firewalled: true,
+            dht_size_estimate: (0, 0.0),
+            server_mode: false,
+            memory_report: MemoryReport::default(),
+            get_success_rate: None,
+            put_success_rate: None,
+            response_address_mismatches: 0,
+            item_refresh_interval: Duration::from_secs(60 * 60),
+            last_table_refresh: None,
+            immutable_cache_hits: 0,
+            immutable_cache_misses: 0,
+            packets_sent: 0,
+            packets_received: 0,
+            send_errors: 0,
+            recv_errors: 0,
+            rate_limit_utilization: None,
+            throttled_packets: 0,
+            inflight_requests: 0,
+            unknown_transaction_responses: 0,
+            unreachable_destinations: 0,
         }
     }
+
+    #[test]
+    fn diff() {
+        let older = info();
+
+        assert_eq!(older, older.clone());
+        assert!(older.diff(&older).is_empty());
+
+        let public_address = SocketAddrV4::new([1, 1, 1, 1].into(), 6881);
+
+        let mut newer = older.clone();
+        newer.public_address = Some(public_address);
+        newer.firewalled = false;
+        newer.server_mode = true;
+        newer.memory_report.routing_table_nodes = 20;
+        newer.dht_size_estimate = (1000, 0.1);
+
+        assert_ne!(older, newer);
+        assert_eq!(
+            newer.diff(&older),
+            vec![
+                InfoChange::PublicAddress {
+                    from: None,
+                    to: Some(public_address)
+                },
+                InfoChange::Firewalled(false),
+                InfoChange::ServerMode(true),
+                InfoChange::RoutingTableSize { from: 0, to: 20 },
+            ]
+        );
+    }
 }
@@ -2,9 +2,10 @@
 
 use std::collections::HashMap;
 use std::collections::HashSet;
-use std::net::SocketAddrV4;
+use std::net::{Ipv4Addr, SocketAddrV4};
+use std::time::{Duration, Instant};
 
-use tracing::{debug, trace};
+use tracing::{debug, trace, Span};
 
 use super::{socket::KrpcSocket, ClosestNodes};
 use crate::common::{FindNodeRequestArguments, GetPeersRequestArguments, GetValueRequestArguments};
@@ -13,6 +14,9 @@ use crate::{
     rpc::Response,
 };
 
+/// Extra votes for addresses with the [crate::DhtBuilder::suggested_public_ip].
+const SUGGESTED_IP_VOTES: u16 = 3;
+
 /// An iterative process of concurrently sending a request to the closest known nodes to
 /// the target, updating the routing table with closer nodes discovered in the responses, and
 /// repeating this process until no closer nodes (that aren't already queried) are found.
@@ -25,16 +29,104 @@ pub(crate) struct IterativeQuery {
     visited: HashSet<SocketAddrV4>,
     responses: Vec<Response>,
     public_address_votes: HashMap<SocketAddrV4, u16>,
+    /// Overrides the socket's default request timeout for this query's requests.
+    request_timeout: Option<Duration>,
+    /// Entered while sending requests and handling responses, to correlate them in logs.
+    span: Span,
+    /// Number of closest nodes to visit, see [crate::DhtBuilder::k_factor].
+    k: usize,
+    /// Maximum hop depth of nodes to visit, see [crate::DhtBuilder::max_lookup_rounds].
+    max_rounds: Option<usize>,
+    /// Hop depth of each candidate or visited node, `1` for the nodes this query
+    /// started with, and one more than the shallowest node that returned it otherwise.
+    depths: HashMap<SocketAddrV4, usize>,
+
+    // Timing
+    started_at: Instant,
+    first_response_at: Option<Instant>,
+    /// Deepest hop depth visited so far.
+    rounds: usize,
+    /// Address and sent time of requests not responded to yet.
+    sent_at: HashMap<u16, (SocketAddrV4, Instant)>,
+    slowest_response: Option<(SocketAddrV4, Duration)>,
+    /// Whether this query was forcibly completed after exceeding the maximum query lifetime.
+    timed_out: bool,
+    requests_sent: usize,
+    responses_received: usize,
+    /// Addresses of the nodes that responded.
+    responded: HashSet<SocketAddrV4>,
+    /// Addresses of the nodes that responded with a value.
+    stored_at: HashSet<SocketAddrV4>,
+}
+
+/// Timing breakdown and request statistics of a done query, useful for performance analysis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueryTiming {
+    /// Time from starting the query until the first response, or `None` if no node responded.
+    pub time_to_first_response: Option<Duration>,
+    /// Time from starting the query until it was done.
+    pub time_to_convergence: Duration,
+    /// Hop depth of the deepest node requests were sent to, where the nodes the query
+    /// started with are at depth `1`, and the nodes they returned are at depth `2`,
+    /// roughly the number of hops it took to converge.
+    pub rounds: usize,
+    /// Number of distinct nodes requests were sent to.
+    pub nodes_contacted: usize,
+    /// Address of the slowest responding node, and how long it took to respond.
+    pub slowest_response: Option<(SocketAddrV4, Duration)>,
+    /// Whether the query was forcibly completed after exceeding the
+    /// [maximum query lifetime](crate::DhtBuilder::max_query_lifetime), before converging.
+    pub timed_out: bool,
+    /// Number of requests sent, not counting [retransmissions](crate::DhtBuilder::retransmit_policy).
+    pub requests_sent: usize,
+    /// Number of responses (or errors) received.
+    pub responses_received: usize,
+    /// Number of distinct nodes that responded.
+    pub unique_responders: usize,
+    /// Number of the closest [k](crate::DhtBuilder::k_factor) nodes to the target that
+    /// responded with a write token, always `0` for find_node queries.
+    pub closest_responders: usize,
+    /// Number of the [Self::closest_responders] that returned a value (or peers),
+    /// see [Self::replication_estimate].
+    pub closest_with_value: usize,
+}
+
+impl QueryTiming {
+    /// Fraction, between 0 and 1, of the closest responding nodes to the target that
+    /// returned a value (or peers), as an estimate of how widely it is replicated.
+    ///
+    /// Useful to decide whether or not to republish or re-announce.
+    pub fn replication_estimate(&self) -> f64 {
+        if self.closest_responders == 0 {
+            return 0.0;
+        }
+
+        self.closest_with_value as f64 / self.closest_responders as f64
+    }
 }
 
 #[derive(Debug)]
+/// The request sent to each node in an iterative GET query.
 pub enum GetRequestSpecific {
+    /// `find_node` request.
     FindNode(FindNodeRequestArguments),
+    /// `get_peers` request.
     GetPeers(GetPeersRequestArguments),
+    /// `get` request for an immutable or mutable value.
     GetValue(GetValueRequestArguments),
 }
 
 impl GetRequestSpecific {
+    /// The name of the request, as it is sent over the wire.
+    pub fn name(&self) -> &'static str {
+        match self {
+            GetRequestSpecific::FindNode(_) => "find_node",
+            GetRequestSpecific::GetPeers(_) => "get_peers",
+            GetRequestSpecific::GetValue(_) => "get",
+        }
+    }
+
+    /// The target of the query.
     pub fn target(&self) -> &Id {
         match self {
             GetRequestSpecific::FindNode(args) => &args.target,
@@ -69,6 +161,22 @@ impl IterativeQuery {
             responses: Vec::new(),
 
             public_address_votes: HashMap::new(),
+            request_timeout: None,
+            span: Span::none(),
+            k: MAX_BUCKET_SIZE_K,
+            max_rounds: None,
+            depths: HashMap::new(),
+
+            started_at: Instant::now(),
+            first_response_at: None,
+            rounds: 0,
+            sent_at: HashMap::new(),
+            slowest_response: None,
+            timed_out: false,
+            requests_sent: 0,
+            responses_received: 0,
+            responded: HashSet::new(),
+            stored_at: HashSet::new(),
         }
     }
 
@@ -92,13 +200,55 @@ impl IterativeQuery {
         &self.responses
     }
 
-    pub fn best_address(&self) -> Option<SocketAddrV4> {
+    /// The tracing span of this query.
+    pub fn span(&self) -> &Span {
+        &self.span
+    }
+
+    /// Time since this query was created.
+    pub fn age(&self) -> Duration {
+        self.started_at.elapsed()
+    }
+
+    /// Timing breakdown of this query so far.
+    pub fn timing(&self) -> QueryTiming {
+        let closest_responders = &self.responders.nodes()[..self.responders.len().min(self.k)];
+
+        QueryTiming {
+            time_to_first_response: self
+                .first_response_at
+                .map(|at| at.duration_since(self.started_at)),
+            time_to_convergence: self.started_at.elapsed(),
+            rounds: self.rounds,
+            nodes_contacted: self.visited.len(),
+            slowest_response: self.slowest_response,
+            timed_out: self.timed_out,
+            requests_sent: self.requests_sent,
+            responses_received: self.responses_received,
+            unique_responders: self.responded.len(),
+            closest_responders: closest_responders.len(),
+            closest_with_value: closest_responders
+                .iter()
+                .filter(|node| self.stored_at.contains(&node.address()))
+                .count(),
+        }
+    }
+
+    /// The address most responding nodes observed us at, with extra votes
+    /// for addresses with the `suggested_ip`, see [crate::DhtBuilder::suggested_public_ip].
+    pub fn best_address(&self, suggested_ip: Option<Ipv4Addr>) -> Option<SocketAddrV4> {
         let mut max = 0_u16;
         let mut best_addr = None;
 
         for (addr, count) in self.public_address_votes.iter() {
-            if *count > max {
-                max = *count;
+            let count = if Some(*addr.ip()) == suggested_ip {
+                count.saturating_add(SUGGESTED_IP_VOTES)
+            } else {
+                *count
+            };
+
+            if count > max {
+                max = count;
                 best_addr = Some(*addr);
             };
         }
@@ -113,10 +263,48 @@ impl IterativeQuery {
         self.visit_closest(socket);
     }
 
+    /// Override the socket's default request timeout for requests sent by this query.
+    pub fn set_request_timeout(&mut self, request_timeout: Duration) {
+        self.request_timeout = Some(request_timeout);
+    }
+
+    /// Set the tracing span to enter while sending requests and handling responses.
+    pub fn set_span(&mut self, span: Span) {
+        self.span = span;
+    }
+
+    /// Override the default number of closest nodes to visit.
+    pub fn set_k(&mut self, k: usize) {
+        self.k = k;
+    }
+
+    /// Don't visit nodes deeper than this many hops, see [QueryTiming::rounds].
+    pub fn set_max_rounds(&mut self, max_rounds: usize) {
+        self.max_rounds = Some(max_rounds);
+    }
+
+    /// Mark this query as forcibly completed after exceeding the maximum query lifetime.
+    pub fn set_timed_out(&mut self) {
+        self.timed_out = true;
+    }
+
     /// Add a candidate node to query on next tick if it is among the closest nodes.
     pub fn add_candidate(&mut self, node: Node) {
-        // ready for a ipv6 routing table?
-        self.closest.add(node);
+        self.add_candidate_at_depth(node, 1);
+    }
+
+    /// Add a candidate node returned by the node at `from`, one hop deeper than it.
+    ///
+    /// Ignored if that is deeper than the [maximum rounds](Self::set_max_rounds).
+    pub fn add_closer_node(&mut self, node: Node, from: SocketAddrV4) {
+        let depth = self
+            .depths
+            .get(&from)
+            .copied()
+            .unwrap_or(self.rounds.max(1))
+            + 1;
+
+        self.add_candidate_at_depth(node, depth);
     }
 
     /// Add a vote for this node's address.
@@ -130,10 +318,15 @@ impl IterativeQuery {
     /// Visit explicitly given addresses, and add them to the visited set.
     /// only used from the Rpc when calling bootstrapping nodes.
     pub fn visit(&mut self, socket: &mut KrpcSocket, address: SocketAddrV4) {
-        let tid = socket.request(address, self.request.clone());
+        let depth = *self.depths.entry(address).or_insert(1);
+        self.rounds = self.rounds.max(depth);
+
+        let tid = self.request(socket, address, self.request.clone());
         self.inflight_requests.push(tid);
+        self.sent_at.insert(tid, (address, Instant::now()));
 
-        let tid = socket.request(
+        let tid = self.request(
+            socket,
             address,
             RequestSpecific {
                 requester_id: Id::random(),
@@ -150,6 +343,31 @@ impl IterativeQuery {
         self.inflight_requests.contains(&tid)
     }
 
+    /// Record the time it took to receive a response to a request by its transaction_id.
+    pub fn record_response_time(&mut self, tid: u16) {
+        let now = Instant::now();
+
+        if self.first_response_at.is_none() {
+            self.first_response_at = Some(now);
+        }
+
+        self.responses_received += 1;
+
+        if let Some((address, sent_at)) = self.sent_at.remove(&tid) {
+            self.responded.insert(address);
+
+            let elapsed = now.duration_since(sent_at);
+
+            if self
+                .slowest_response
+                .map(|(_, slowest)| elapsed > slowest)
+                .unwrap_or(true)
+            {
+                self.slowest_response = Some((address, elapsed));
+            }
+        }
+    }
+
     /// Add a node that responded with a token as a probable storage node.
     pub fn add_responding_node(&mut self, node: Node) {
         self.responders.add(node)
@@ -161,13 +379,22 @@ impl IterativeQuery {
 
         debug!(?target, ?response, ?from, "Query got response");
 
+        self.stored_at.insert(from);
         self.responses.push(response.to_owned());
     }
 
+    /// Record that a node has a value, but not a more recent one than requested.
+    pub fn no_more_recent_value(&mut self, from: SocketAddrV4) {
+        self.stored_at.insert(from);
+    }
+
     /// Query closest nodes for this query's target and message.
     ///
     /// Returns true if it is done.
     pub fn tick(&mut self, socket: &mut KrpcSocket) -> bool {
+        let span = self.span.clone();
+        let _entered = span.enter();
+
         // Visit closest nodes
         self.visit_closest(socket);
 
@@ -187,13 +414,46 @@ impl IterativeQuery {
 
     // === Private Methods ===
 
+    /// Send a request respecting this query's request timeout, if any.
+    fn request(
+        &mut self,
+        socket: &mut KrpcSocket,
+        address: SocketAddrV4,
+        request: RequestSpecific,
+    ) -> u16 {
+        self.requests_sent += 1;
+
+        let _entered = self.span.enter();
+
+        match self.request_timeout {
+            Some(timeout) => socket.request_with_timeout(address, request, timeout),
+            None => socket.request(address, request),
+        }
+    }
+
+    /// Add a candidate at this hop `depth`, unless it is too deep, keeping the shallowest
+    /// depth if it was already known.
+    fn add_candidate_at_depth(&mut self, node: Node, depth: usize) {
+        if self.max_rounds.is_some_and(|max| depth > max) {
+            return;
+        }
+
+        self.depths
+            .entry(node.address())
+            .and_modify(|known| *known = (*known).min(depth))
+            .or_insert(depth);
+
+        // ready for a ipv6 routing table?
+        self.closest.add(node);
+    }
+
     /// Visit the closest candidates and remove them as candidates
     fn visit_closest(&mut self, socket: &mut KrpcSocket) {
         let to_visit = self
             .closest
             .nodes()
             .iter()
-            .take(MAX_BUCKET_SIZE_K)
+            .take(self.k)
             .filter(|node| !self.visited.contains(&node.address()))
             .map(|node| node.address())
             .collect::<Vec<_>>();
@@ -203,3 +463,73 @@ impl IterativeQuery {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn max_rounds() {
+        let mut socket = KrpcSocket::client().unwrap();
+
+        let target = Id::random();
+        let mut query = IterativeQuery::new(
+            Id::random(),
+            target,
+            GetRequestSpecific::FindNode(FindNodeRequestArguments { target }),
+        );
+        query.set_max_rounds(2);
+
+        let first = Node::unique(1);
+        query.add_candidate(first.clone());
+        query.tick(&mut socket);
+
+        // Candidates the query started with are all in the first hop,
+        // no matter when they are added.
+        query.add_candidate(Node::unique(2));
+        query.tick(&mut socket);
+        assert_eq!(query.timing().rounds, 1);
+
+        let second = Node::unique(3);
+        query.add_closer_node(second.clone(), first.address());
+        query.tick(&mut socket);
+
+        // Too deep.
+        query.add_closer_node(Node::unique(4), second.address());
+        query.tick(&mut socket);
+
+        let timing = query.timing();
+        assert_eq!(timing.rounds, 2);
+        assert_eq!(timing.nodes_contacted, 3);
+        // A request and a ping for each node.
+        assert_eq!(timing.requests_sent, 6);
+    }
+
+    #[test]
+    fn best_address_suggested_ip() {
+        let target = Id::random();
+        let mut query = IterativeQuery::new(
+            Id::random(),
+            target,
+            GetRequestSpecific::FindNode(FindNodeRequestArguments { target }),
+        );
+
+        let consensus = SocketAddrV4::new(Ipv4Addr::new(1, 1, 1, 1), 6881);
+        let suggested = SocketAddrV4::new(Ipv4Addr::new(2, 2, 2, 2), 6881);
+
+        for _ in 0..3 {
+            query.add_address_vote(consensus);
+        }
+        query.add_address_vote(suggested);
+
+        assert_eq!(query.best_address(None), Some(consensus));
+        assert_eq!(query.best_address(Some(*suggested.ip())), Some(suggested));
+
+        // Consensus still overrides the suggestion if it disagrees strongly enough.
+        for _ in 0..SUGGESTED_IP_VOTES {
+            query.add_address_vote(consensus);
+        }
+
+        assert_eq!(query.best_address(Some(*suggested.ip())), Some(consensus));
+    }
+}
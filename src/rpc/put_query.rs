@@ -1,4 +1,6 @@
-use tracing::{debug, trace};
+use std::time::{Duration, Instant};
+
+use tracing::{debug, trace, Span};
 
 use crate::{
     common::{
@@ -16,22 +18,43 @@ use super::socket::KrpcSocket;
 pub struct PutQuery {
     pub target: Id,
     /// Nodes that confirmed success
-    stored_at: u8,
+    stored_at: Vec<Node>,
     inflight_requests: Vec<u16>,
     pub request: PutRequestSpecific,
     errors: Vec<(u8, ErrorSpecific)>,
     extra_nodes: Box<[Node]>,
+    started_at: Option<Instant>,
+    /// Whether this query is already a retry of a query that failed with stale tokens.
+    retried: bool,
+    /// Entered while sending requests and handling responses, to correlate them in logs.
+    span: Span,
 }
 
 impl PutQuery {
     pub fn new(target: Id, request: PutRequestSpecific, extra_nodes: Option<Box<[Node]>>) -> Self {
         Self {
             target,
-            stored_at: 0,
+            stored_at: Vec::new(),
             inflight_requests: Vec::new(),
             request,
             errors: Vec::new(),
             extra_nodes: extra_nodes.unwrap_or(Box::new([])),
+            started_at: None,
+            retried: false,
+            span: Span::none(),
+        }
+    }
+
+    /// Create a fresh query for the same request, to retry it with new tokens.
+    pub fn retry(&self) -> Self {
+        Self {
+            retried: true,
+            span: self.span.clone(),
+            ..Self::new(
+                self.target,
+                self.request.clone(),
+                Some(self.extra_nodes.clone()),
+            )
         }
     }
 
@@ -44,9 +67,14 @@ impl PutQuery {
             panic!("should not call PutQuery::start() twice");
         };
 
+        let span = self.span.clone();
+        let _entered = span.enter();
+
         let target = self.target;
         trace!(?target, "PutQuery start");
 
+        self.started_at = Some(Instant::now());
+
         if closest_nodes.is_empty() {
             Err(PutQueryError::NoClosestNodes)?;
         }
@@ -76,17 +104,44 @@ impl PutQuery {
         Ok(())
     }
 
+    /// The tracing span of this query.
+    pub fn span(&self) -> &Span {
+        &self.span
+    }
+
+    /// Set the tracing span to enter while sending requests and handling responses.
+    pub fn set_span(&mut self, span: Span) {
+        self.span = span;
+    }
+
     pub fn started(&self) -> bool {
         !self.inflight_requests.is_empty()
     }
 
+    /// Time since this query started sending requests, if it did.
+    pub fn age(&self) -> Option<Duration> {
+        self.started_at.map(|started_at| started_at.elapsed())
+    }
+
     pub fn inflight(&self, tid: u16) -> bool {
         self.inflight_requests.contains(&tid)
     }
 
-    pub fn success(&mut self) {
-        debug!(target = ?self.target, "PutQuery got success response");
-        self.stored_at += 1
+    /// Returns true if no node stored the value, mostly rejecting it with a
+    /// `203` (bad token) error, and this query wasn't retried already.
+    pub fn should_retry(&self) -> bool {
+        !self.retried
+            && self.stored_at.is_empty()
+            && self
+                .errors
+                .first()
+                .map(|(_, error)| error.code == 203)
+                .unwrap_or(false)
+    }
+
+    pub fn success(&mut self, node: Node) {
+        debug!(target = ?self.target, ?node, "PutQuery got success response");
+        self.stored_at.push(node)
     }
 
     pub fn error(&mut self, error: ErrorSpecific) {
@@ -112,18 +167,21 @@ impl PutQuery {
         }
     }
 
-    /// Check if the query is done, and if so send the query target to the receiver if any.
-    pub fn tick(&mut self, socket: &KrpcSocket) -> Result<bool, PutError> {
+    /// Check if the query is done, and if so return a [PutReport].
+    pub fn tick(&mut self, socket: &KrpcSocket) -> Result<Option<PutReport>, PutError> {
         // Didn't start yet.
         if self.inflight_requests.is_empty() {
-            return Ok(false);
+            return Ok(None);
         }
 
+        let span = self.span.clone();
+        let _entered = span.enter();
+
         // And all queries got responses or timedout
         if self.is_done(socket) {
             let target = self.target;
 
-            if self.stored_at == 0 {
+            if self.stored_at.is_empty() {
                 let most_common_error = self.most_common_error();
 
                 debug!(
@@ -138,9 +196,13 @@ impl PutQuery {
                     .unwrap_or(PutQueryError::Timeout.into()));
             }
 
-            debug!(?target, stored_at = ?self.stored_at, "PutQuery Done successfully");
+            debug!(?target, stored_at = ?self.stored_at.len(), "PutQuery Done successfully");
 
-            return Ok(true);
+            return Ok(Some(PutReport {
+                target,
+                stored_at: self.stored_at.clone().into(),
+                queried: self.inflight_requests.len(),
+            }));
         } else if let Some(most_common_error) = self.majority_nodes_rejected_put_mutable() {
             let target = self.target;
 
@@ -154,7 +216,21 @@ impl PutQuery {
             return Err(most_common_error)?;
         }
 
-        Ok(false)
+        Ok(None)
+    }
+
+    /// Result of a query that exceeded its maximum lifetime: a partial [PutReport]
+    /// if any node stored the value, or [PutQueryError::Timeout] otherwise.
+    pub fn timed_out(&self) -> Result<PutReport, PutError> {
+        if self.stored_at.is_empty() {
+            return Err(PutQueryError::Timeout.into());
+        }
+
+        Ok(PutReport {
+            target: self.target,
+            stored_at: self.stored_at.clone().into(),
+            queried: self.inflight_requests.len(),
+        })
     }
 
     fn is_done(&self, socket: &KrpcSocket) -> bool {
@@ -185,16 +261,31 @@ impl PutQuery {
     }
 
     fn most_common_error(&self) -> Option<(u8, PutError)> {
-        self.errors
-            .first()
-            .and_then(|(count, error)| match error.code {
-                301 => Some((*count, PutError::from(ConcurrencyError::CasFailed))),
-                302 => Some((*count, PutError::from(ConcurrencyError::NotMostRecent))),
-                _ => None,
-            })
+        self.errors.first().map(|(count, error)| {
+            let error = match error.code {
+                203 => PutQueryError::InvalidToken(error.clone()).into(),
+                206 => PutQueryError::InvalidSignature(error.clone()).into(),
+                301 => ConcurrencyError::CasFailed.into(),
+                302 => ConcurrencyError::NotMostRecent.into(),
+                _ => PutQueryError::ErrorResponse(error.clone()).into(),
+            };
+
+            (*count, error)
+        })
     }
 }
 
+#[derive(Debug, Clone)]
+/// Details of a successful PUT query.
+pub struct PutReport {
+    /// The target of the PUT query.
+    pub target: Id,
+    /// Nodes that confirmed storing the value.
+    pub stored_at: Box<[Node]>,
+    /// Number of nodes the request was sent to.
+    pub queried: usize,
+}
+
 #[derive(thiserror::Error, Debug, Clone)]
 /// PutQuery errors
 pub enum PutError {
@@ -216,14 +307,57 @@ pub enum PutQueryError {
     #[error("Failed to find any nodes close to store value at")]
     NoClosestNodes,
 
-    /// Either Put Query faild to store at any nodes, and most nodes responded
-    /// with a non `301` nor `302` errors.
+    /// Put Query failed to store at any nodes, and most nodes rejected the request
+    /// with a `203` (invalid token) error.
+    ///
+    /// Tokens are already refreshed and the query retried once, so retrying again
+    /// is unlikely to help until the routing table is refreshed.
+    #[error("Nodes rejected the PutQuery with an invalid token error ({})", .0.code)]
+    InvalidToken(ErrorSpecific),
+
+    /// Put Query failed to store at any nodes, and most nodes rejected the request
+    /// with a `206` (invalid signature) error.
+    ///
+    /// The [crate::MutableItem] should be re-signed before trying again.
+    #[error("Nodes rejected the PutQuery with an invalid signature error ({})", .0.code)]
+    InvalidSignature(ErrorSpecific),
+
+    /// Put Query failed to store at any nodes, and most nodes responded
+    /// with an error other than `203`, `206`, `301` or `302`.
     ///
-    /// Either way; contains the most common error response.
+    /// Contains the most common error response.
     #[error("Query Error Response")]
     ErrorResponse(ErrorSpecific),
 
-    /// PutQuery timed out with no responses neither success or errors
+    /// The value (`v` field) is larger than [crate::MAX_VALUE_SIZE], so the request
+    /// was rejected locally before sending it to any node.
+    #[error("Value is too large ({len} bytes), max is {max} bytes")]
+    ValueTooLarge {
+        /// The size of the value in bytes.
+        len: usize,
+        /// The maximum size of a value in bytes.
+        max: usize,
+    },
+
+    /// Put Query stored the value at fewer nodes than the minimum required,
+    /// see [crate::Dht::put_mutable_with_min_replicas].
+    ///
+    /// The value is still stored at the nodes that acknowledged it.
+    #[error("Stored at {stored} nodes, fewer than the minimum of {min}")]
+    NotEnoughReplicas {
+        /// Number of nodes that confirmed storing the value.
+        stored: usize,
+        /// The minimum number of nodes required to store the value.
+        min: usize,
+    },
+
+    /// The most recent [crate::MutableItem::seq] is already [i64::MAX], so
+    /// [crate::Dht::put_mutable_merged] can't put a more recent item.
+    #[error("MutableItem::seq can't be incremented past i64::MAX")]
+    SeqOverflow,
+
+    /// PutQuery timed out with no responses neither success or errors,
+    /// meaning no nodes responded.
     #[error("PutQuery timed out with no responses neither success or errors")]
     Timeout,
 }
@@ -253,3 +387,63 @@ pub enum ConcurrencyError {
     #[error("CAS check failed, try reading most recent item before writing again.")]
     CasFailed,
 }
+
+#[cfg(test)]
+mod test {
+    use crate::common::PutImmutableRequestArguments;
+
+    use super::*;
+
+    fn query() -> PutQuery {
+        PutQuery::new(
+            Id::random(),
+            PutRequestSpecific::PutImmutable(PutImmutableRequestArguments {
+                target: Id::random(),
+                v: b"Hello".to_vec().into(),
+            }),
+            None,
+        )
+    }
+
+    fn error(code: i32) -> ErrorSpecific {
+        ErrorSpecific {
+            code,
+            description: String::new(),
+        }
+    }
+
+    #[test]
+    fn most_common_error() {
+        let mut query = query();
+        assert!(query.most_common_error().is_none());
+
+        query.error(error(203));
+        assert!(matches!(
+            query.most_common_error(),
+            Some((1, PutError::Query(PutQueryError::InvalidToken(e)))) if e.code == 203
+        ));
+
+        query.error(error(206));
+        query.error(error(206));
+        assert!(matches!(
+            query.most_common_error(),
+            Some((2, PutError::Query(PutQueryError::InvalidSignature(e)))) if e.code == 206
+        ));
+
+        query.error(error(301));
+        query.error(error(301));
+        query.error(error(301));
+        assert!(matches!(
+            query.most_common_error(),
+            Some((3, PutError::Concurrency(ConcurrencyError::CasFailed)))
+        ));
+
+        for _ in 0..4 {
+            query.error(error(205));
+        }
+        assert!(matches!(
+            query.most_common_error(),
+            Some((4, PutError::Query(PutQueryError::ErrorResponse(e)))) if e.code == 205
+        ));
+    }
+}
@@ -3,19 +3,22 @@
 pub mod peers;
 pub mod tokens;
 
-use std::{fmt::Debug, net::SocketAddrV4, num::NonZeroUsize};
+use std::{fmt::Debug, net::SocketAddrV4, num::NonZeroUsize, time::Duration};
 
 use dyn_clone::DynClone;
 use lru::LruCache;
 use tracing::debug;
 
 use crate::common::{
-    validate_immutable, AnnouncePeerRequestArguments, ErrorSpecific, FindNodeRequestArguments,
-    FindNodeResponseArguments, GetImmutableResponseArguments, GetMutableResponseArguments,
-    GetPeersRequestArguments, GetPeersResponseArguments, GetValueRequestArguments, Id, MutableItem,
-    NoMoreRecentValueResponseArguments, NoValuesResponseArguments, PingResponseArguments,
-    PutImmutableRequestArguments, PutMutableRequestArguments, PutRequest, PutRequestSpecific,
-    RequestTypeSpecific, ResponseSpecific, RoutingTable,
+    validate_immutable, AnnouncePeerRequestArguments, BloomFilter, ErrorSpecific,
+    FindNodeRequestArguments, FindNodeResponseArguments, GetImmutableResponseArguments,
+    GetMutableResponseArguments, GetPeersRequestArguments, GetPeersResponseArguments,
+    GetValueRequestArguments, Id, MutableItem, NoMoreRecentValueResponseArguments,
+    NoValuesResponseArguments, PingResponseArguments, PutImmutableRequestArguments,
+    PutMutableRequestArguments, PutRequest, PutRequestSpecific, RequestTypeSpecific,
+    ResponseSpecific, RoutingTable, SampleInfohashesRequestArguments,
+    SampleInfohashesResponseArguments, ScrapeRequestArguments, ScrapeResponseArguments,
+    MAX_VALUE_SIZE, TOKEN_ROTATE_INTERVAL,
 };
 
 use peers::PeersStore;
@@ -29,6 +32,11 @@ pub const MAX_INFO_HASHES: usize = 2000;
 pub const MAX_PEERS: usize = 500;
 /// Default maximum number of Immutable and Mutable items to store.
 pub const MAX_VALUES: usize = 1000;
+/// Maximum number of info hashes in a [BEP_0051](https://www.bittorrent.org/beps/bep_0051.html)
+/// sample_infohashes response.
+pub const MAX_INFOHASH_SAMPLES: usize = 20;
+/// Seconds a requester should wait before requesting a new sample of our info hashes.
+pub const SAMPLE_INFOHASHES_INTERVAL: i64 = 6 * 60 * 60;
 
 /// A trait for filtering incoming requests to a DHT node and
 /// decide whether to allow handling it or rate limit or ban
@@ -40,6 +48,47 @@ pub trait RequestFilter: Send + Sync + Debug + DynClone {
 
 dyn_clone::clone_trait_object!(RequestFilter);
 
+/// A trait for storing peers announced on info hashes, to back the server
+/// with a custom (for example persistent) store, see [ServerSettings::peer_storage].
+///
+/// Read [BEP_0005](https://www.bittorrent.org/beps/bep_0005.html) for more information.
+pub trait PeerStorage: Send + Sync + Debug + DynClone {
+    /// Add a `peer` (its id and address) announced on an `info_hash`.
+    fn add_peer(&mut self, info_hash: Id, peer: (&Id, SocketAddrV4));
+
+    /// Add a `peer` that announced itself as a seed on an `info_hash`.
+    ///
+    /// Defaults to [Self::add_peer], for stores that don't distinguish seeds.
+    fn add_seed(&mut self, info_hash: Id, peer: (&Id, SocketAddrV4)) {
+        self.add_peer(info_hash, peer)
+    }
+
+    /// Returns up to 20 random peers announced on an `info_hash`, or `None` if there are none.
+    fn get_random_peers(&mut self, info_hash: &Id) -> Option<Vec<SocketAddrV4>>;
+
+    /// Returns the number of peers stored for each info hash, used for [ServerStats].
+    fn peers_counts(&self) -> Vec<(Id, usize)>;
+
+    /// Returns up to `max` random info hashes with stored peers,
+    /// used to answer [BEP_0051](https://www.bittorrent.org/beps/bep_0051.html) requests.
+    fn random_info_hashes(&self, max: usize) -> Vec<Id>;
+
+    /// Returns the number of info hashes with stored peers.
+    fn info_hashes_count(&self) -> usize {
+        self.peers_counts().len()
+    }
+
+    /// Returns the bloom filters of seeds and downloaders announced on an `info_hash`,
+    /// used to answer [BEP_0033](https://www.bittorrent.org/beps/bep_0033.html) scrape requests.
+    ///
+    /// Defaults to `None`, responding with empty bloom filters.
+    fn scrape(&mut self, _info_hash: &Id) -> Option<(BloomFilter, BloomFilter)> {
+        None
+    }
+}
+
+dyn_clone::clone_trait_object!(PeerStorage);
+
 #[derive(Debug, Clone)]
 struct DefaultFilter;
 
@@ -59,7 +108,7 @@ pub struct Server {
     /// Tokens generator
     tokens: Tokens,
     /// Peers store
-    peers: PeersStore,
+    peers: Box<dyn PeerStorage>,
     /// Immutable values store
     immutable_values: LruCache<Id, Box<[u8]>>,
     /// Mutable values store
@@ -76,27 +125,66 @@ impl Default for Server {
 
 #[derive(Debug, Clone)]
 /// Settings for the default dht server.
+///
+/// All stores are bounded LRU caches; once a limit is reached, storing a new
+/// entry evicts the least recently used (stored or served) one, so memory usage
+/// stays bounded no matter how many announces or puts a node receives.
+///
+/// A limit of `0` falls back to its default.
 pub struct ServerSettings {
     /// The maximum info_hashes for which to store peers.
     ///
+    /// Peers of the least recently used info_hash are evicted beyond this limit.
+    ///
     /// Defaults to [MAX_INFO_HASHES]
     pub max_info_hashes: usize,
     /// The maximum peers to store per info_hash.
     ///
+    /// The least recently announced peer is evicted beyond this limit.
+    ///
     /// Defaults to [MAX_PEERS]
     pub max_peers_per_info_hash: usize,
     /// Maximum number of immutable values to store.
     ///
+    /// The least recently used value is evicted beyond this limit.
+    ///
     /// Defaults to [MAX_VALUES]
     pub max_immutable_values: usize,
     /// Maximum number of mutable values to store.
     ///
+    /// The least recently used value is evicted beyond this limit.
+    ///
     /// Defaults to [MAX_VALUES]
     pub max_mutable_values: usize,
     /// Filter requests before handling them.
     ///
     /// Defaults to a function that always returns true.
     pub filter: Box<dyn RequestFilter>,
+    /// Custom storage for announced peers, for example backed by a database.
+    ///
+    /// [Self::max_info_hashes] and [Self::max_peers_per_info_hash] are ignored
+    /// if this is set, and it is up to the storage to bound its size.
+    ///
+    /// Defaults to None, where peers are stored in memory in LRU caches.
+    pub peer_storage: Option<Box<dyn PeerStorage>>,
+    /// How often to rotate the secret that write tokens are derived from.
+    ///
+    /// Tokens generated with the previous secret are still accepted, so a token
+    /// issued just before a rotation remains valid until the next one.
+    ///
+    /// Defaults to 5 minutes, a zero duration falls back to the default.
+    pub token_rotation: Duration,
+    /// Size in bytes of the randomly generated token secrets.
+    ///
+    /// Defaults to 20 bytes, `0` falls back to the default.
+    pub token_secret_size: usize,
+    /// A fixed secret to derive write tokens from, instead of rotating random ones,
+    /// so tokens are reproducible across runs, useful for testing.
+    ///
+    /// [Self::token_rotation] and [Self::token_secret_size] are ignored if this is set.
+    ///
+    /// Defaults to None.
+    pub token_secret: Option<Box<[u8]>>,
 }
 
 impl Default for ServerSettings {
@@ -108,24 +196,61 @@ impl Default for ServerSettings {
             max_immutable_values: MAX_VALUES,
 
             filter: Box::new(DefaultFilter),
+            peer_storage: None,
+
+            token_rotation: TOKEN_ROTATE_INTERVAL,
+            token_secret_size: tokens::SECRET_SIZE,
+            token_secret: None,
         }
     }
 }
 
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// Statistics about the data stored by a node running in server mode.
+pub struct ServerStats {
+    /// Number of info_hashes with stored peers.
+    pub info_hashes: usize,
+    /// Total number of stored peers across all info_hashes.
+    pub peers: usize,
+    /// Number of stored immutable values.
+    pub immutable_values: usize,
+    /// Number of stored mutable values.
+    pub mutable_values: usize,
+    /// Info_hashes with the most stored peers, and their peers count, sorted descending.
+    pub top_info_hashes: Vec<(Id, usize)>,
+}
+
 impl Server {
     /// Creates a new [Server]
     pub fn new(settings: ServerSettings) -> Self {
-        let tokens = Tokens::new();
+        let tokens = match &settings.token_secret {
+            Some(secret) => Tokens::with_secret(secret),
+            None => Tokens::with_rotation(
+                if settings.token_rotation.is_zero() {
+                    TOKEN_ROTATE_INTERVAL
+                } else {
+                    settings.token_rotation
+                },
+                if settings.token_secret_size == 0 {
+                    tokens::SECRET_SIZE
+                } else {
+                    settings.token_secret_size
+                },
+            ),
+        };
 
         Self {
             tokens,
-            peers: PeersStore::new(
-                NonZeroUsize::new(settings.max_info_hashes).unwrap_or(
-                    NonZeroUsize::new(MAX_INFO_HASHES).expect("MAX_PEERS is NonZeroUsize"),
-                ),
-                NonZeroUsize::new(settings.max_peers_per_info_hash)
-                    .unwrap_or(NonZeroUsize::new(MAX_PEERS).expect("MAX_PEERS is NonZeroUsize")),
-            ),
+            peers: settings.peer_storage.unwrap_or_else(|| {
+                Box::new(PeersStore::new(
+                    NonZeroUsize::new(settings.max_info_hashes).unwrap_or(
+                        NonZeroUsize::new(MAX_INFO_HASHES).expect("MAX_PEERS is NonZeroUsize"),
+                    ),
+                    NonZeroUsize::new(settings.max_peers_per_info_hash).unwrap_or(
+                        NonZeroUsize::new(MAX_PEERS).expect("MAX_PEERS is NonZeroUsize"),
+                    ),
+                ))
+            }),
 
             immutable_values: LruCache::new(
                 NonZeroUsize::new(settings.max_immutable_values)
@@ -139,6 +264,55 @@ impl Server {
         }
     }
 
+    /// Returns statistics about stored data, including the `top_n` info_hashes by peers count.
+    pub fn stats(&self, top_n: usize) -> ServerStats {
+        let mut peers_counts = self.peers.peers_counts();
+
+        let info_hashes = peers_counts.len();
+        let peers = peers_counts.iter().map(|(_, count)| count).sum();
+
+        peers_counts.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        peers_counts.truncate(top_n);
+
+        ServerStats {
+            info_hashes,
+            peers,
+            immutable_values: self.immutable_values.len(),
+            mutable_values: self.mutable_values.len(),
+            top_info_hashes: peers_counts,
+        }
+    }
+
+    /// Returns a response to a get_peers request for an `info_hash` that this node
+    /// announced itself on, containing only this node's own `peer` address.
+    ///
+    /// Used to answer such requests while not running in server mode,
+    /// see [crate::DhtBuilder::serve_own_peers].
+    pub(crate) fn handle_own_peers_request(
+        &mut self,
+        routing_table: &RoutingTable,
+        from: SocketAddrV4,
+        request: &RequestSpecific,
+        peer: SocketAddrV4,
+    ) -> Option<MessageType> {
+        if !self.filter.allow_request(request, from) {
+            return None;
+        }
+
+        if self.tokens.should_update() {
+            self.tokens.rotate()
+        }
+
+        Some(MessageType::Response(ResponseSpecific::GetPeers(
+            GetPeersResponseArguments {
+                responder_id: *routing_table.id(),
+                token: self.tokens.generate_token(from).into(),
+                nodes: None,
+                values: vec![peer],
+            },
+        )))
+    }
+
     /// Returns an optional response or an error for a request.
     ///
     /// Passed to the Rpc to send back to the requester.
@@ -202,6 +376,28 @@ impl Server {
                     MessageType::Response(self.handle_get_mutable(routing_table, from, target, seq))
                 }
             }
+            RequestTypeSpecific::SampleInfohashes(SampleInfohashesRequestArguments { target }) => {
+                MessageType::Response(ResponseSpecific::SampleInfohashes(
+                    SampleInfohashesResponseArguments {
+                        responder_id: *routing_table.id(),
+                        interval: SAMPLE_INFOHASHES_INTERVAL,
+                        num: self.peers.info_hashes_count() as i64,
+                        nodes: Some(routing_table.closest(target)),
+                        samples: self.peers.random_info_hashes(MAX_INFOHASH_SAMPLES).into(),
+                    },
+                ))
+            }
+            RequestTypeSpecific::Scrape(ScrapeRequestArguments { info_hash }) => {
+                let (seeds, peers) = self.peers.scrape(&info_hash).unwrap_or_default();
+
+                MessageType::Response(ResponseSpecific::Scrape(ScrapeResponseArguments {
+                    responder_id: *routing_table.id(),
+                    token: self.tokens.generate_token(from).into(),
+                    nodes: Some(routing_table.closest(info_hash)),
+                    seeds,
+                    peers,
+                }))
+            }
             RequestTypeSpecific::Put(PutRequest {
                 token,
                 put_request_type,
@@ -210,7 +406,7 @@ impl Server {
                     info_hash,
                     port,
                     implied_port,
-                    ..
+                    seed,
                 }) => {
                     if !self.tokens.validate(from, &token) {
                         debug!(
@@ -232,8 +428,13 @@ impl Server {
                         _ => SocketAddrV4::new(*from.ip(), port),
                     };
 
-                    self.peers
-                        .add_peer(info_hash, (&request.requester_id, peer));
+                    if seed == Some(true) {
+                        self.peers
+                            .add_seed(info_hash, (&request.requester_id, peer));
+                    } else {
+                        self.peers
+                            .add_peer(info_hash, (&request.requester_id, peer));
+                    }
 
                     return Some(MessageType::Response(ResponseSpecific::Ping(
                         PingResponseArguments {
@@ -261,7 +462,7 @@ impl Server {
                         }));
                     }
 
-                    if v.len() > 1000 {
+                    if v.len() > MAX_VALUE_SIZE {
                         debug!(?target, ?requester_id, ?from, size = ?v.len(), "Message (v field) too big.");
 
                         return Some(MessageType::Error(ErrorSpecific {
@@ -310,7 +511,7 @@ impl Server {
                             description: "Bad token".to_string(),
                         }));
                     }
-                    if v.len() > 1000 {
+                    if v.len() > MAX_VALUE_SIZE {
                         return Some(MessageType::Error(ErrorSpecific {
                             code: 205,
                             description: "Message (v field) too big.".to_string(),
@@ -419,3 +620,207 @@ impl Server {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::common::hash_immutable;
+
+    use super::*;
+
+    fn put_immutable(
+        server: &mut Server,
+        routing_table: &RoutingTable,
+        from: SocketAddrV4,
+        v: &[u8],
+    ) -> Id {
+        let target = Id::from_bytes(hash_immutable(v)).unwrap();
+        let token = server.tokens.generate_token(from).into();
+
+        server.handle_request(
+            routing_table,
+            from,
+            RequestSpecific {
+                requester_id: Id::random(),
+                request_type: RequestTypeSpecific::Put(PutRequest {
+                    token,
+                    put_request_type: PutRequestSpecific::PutImmutable(
+                        PutImmutableRequestArguments {
+                            target,
+                            v: v.into(),
+                        },
+                    ),
+                }),
+            },
+        );
+
+        target
+    }
+
+    fn get_immutable(
+        server: &mut Server,
+        routing_table: &RoutingTable,
+        from: SocketAddrV4,
+        target: Id,
+    ) -> Option<Box<[u8]>> {
+        match server.handle_request(
+            routing_table,
+            from,
+            RequestSpecific {
+                requester_id: Id::random(),
+                request_type: RequestTypeSpecific::GetValue(GetValueRequestArguments {
+                    target,
+                    seq: None,
+                    salt: None,
+                }),
+            },
+        ) {
+            Some(MessageType::Response(ResponseSpecific::GetImmutable(
+                GetImmutableResponseArguments { v, .. },
+            ))) => Some(v),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn evict_least_recently_used_immutable_values() {
+        let mut server = Server::new(ServerSettings {
+            max_immutable_values: 2,
+            ..Default::default()
+        });
+        let routing_table = RoutingTable::new(Id::random());
+        let from = SocketAddrV4::new([127, 0, 0, 1].into(), 6881);
+
+        let a = put_immutable(&mut server, &routing_table, from, b"a");
+        let b = put_immutable(&mut server, &routing_table, from, b"b");
+
+        // Serving `a` makes `b` the least recently used.
+        assert_eq!(
+            get_immutable(&mut server, &routing_table, from, a),
+            Some(b"a".as_ref().into())
+        );
+
+        let c = put_immutable(&mut server, &routing_table, from, b"c");
+
+        assert_eq!(server.immutable_values.len(), 2);
+        assert!(server.immutable_values.contains(&a));
+        assert!(!server.immutable_values.contains(&b));
+        assert!(server.immutable_values.contains(&c));
+    }
+
+    #[derive(Debug, Clone, Default)]
+    struct VecStorage(Vec<(Id, SocketAddrV4)>);
+
+    impl PeerStorage for VecStorage {
+        fn add_peer(&mut self, info_hash: Id, peer: (&Id, SocketAddrV4)) {
+            self.0.push((info_hash, peer.1));
+        }
+
+        fn get_random_peers(&mut self, info_hash: &Id) -> Option<Vec<SocketAddrV4>> {
+            let peers = self
+                .0
+                .iter()
+                .filter(|(id, _)| id == info_hash)
+                .map(|(_, peer)| *peer)
+                .collect::<Vec<_>>();
+
+            (!peers.is_empty()).then_some(peers)
+        }
+
+        fn peers_counts(&self) -> Vec<(Id, usize)> {
+            self.0
+                .iter()
+                .map(|(info_hash, _)| (*info_hash, 1))
+                .collect()
+        }
+
+        fn random_info_hashes(&self, max: usize) -> Vec<Id> {
+            self.0.iter().take(max).map(|(id, _)| *id).collect()
+        }
+    }
+
+    #[test]
+    fn custom_peer_storage() {
+        let mut server = Server::new(ServerSettings {
+            peer_storage: Some(Box::new(VecStorage::default())),
+            ..Default::default()
+        });
+        let routing_table = RoutingTable::new(Id::random());
+        let from = SocketAddrV4::new([127, 0, 0, 1].into(), 6881);
+        let info_hash = Id::random();
+        let token = server.tokens.generate_token(from).into();
+
+        server.handle_request(
+            &routing_table,
+            from,
+            RequestSpecific {
+                requester_id: Id::random(),
+                request_type: RequestTypeSpecific::Put(PutRequest {
+                    token,
+                    put_request_type: PutRequestSpecific::AnnouncePeer(
+                        AnnouncePeerRequestArguments {
+                            info_hash,
+                            port: 45555,
+                            implied_port: None,
+                            seed: None,
+                        },
+                    ),
+                }),
+            },
+        );
+
+        let response = server.handle_request(
+            &routing_table,
+            from,
+            RequestSpecific {
+                requester_id: Id::random(),
+                request_type: RequestTypeSpecific::GetPeers(GetPeersRequestArguments { info_hash }),
+            },
+        );
+
+        assert!(matches!(
+            response,
+            Some(MessageType::Response(ResponseSpecific::GetPeers(GetPeersResponseArguments {
+                values,
+                ..
+            }))) if values == vec![SocketAddrV4::new([127, 0, 0, 1].into(), 45555)]
+        ));
+        assert_eq!(server.stats(0).info_hashes, 1);
+    }
+
+    #[test]
+    fn stats() {
+        let mut server = Server::default();
+        let routing_table = RoutingTable::new(Id::random());
+        let from = SocketAddrV4::new([127, 0, 0, 1].into(), 6881);
+
+        let hot = Id::random();
+        let cold = Id::random();
+
+        for port in 1..=3 {
+            server.peers.add_peer(
+                hot,
+                (
+                    &Id::random(),
+                    SocketAddrV4::new([127, 0, 0, 1].into(), port),
+                ),
+            );
+        }
+        server.peers.add_peer(
+            cold,
+            (&Id::random(), SocketAddrV4::new([127, 0, 0, 1].into(), 1)),
+        );
+
+        put_immutable(&mut server, &routing_table, from, b"a");
+
+        assert_eq!(
+            server.stats(1),
+            ServerStats {
+                info_hashes: 2,
+                peers: 4,
+                immutable_values: 1,
+                mutable_values: 0,
+                top_info_hashes: vec![(hot, 3)],
+            }
+        );
+    }
+}
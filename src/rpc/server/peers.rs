@@ -2,7 +2,9 @@
 
 use std::{net::SocketAddrV4, num::NonZeroUsize};
 
-use crate::common::Id;
+use crate::common::{BloomFilter, Id};
+
+use super::PeerStorage;
 
 use getrandom::getrandom;
 use lru::LruCache;
@@ -14,7 +16,8 @@ const CHANCE_SCALE: f32 = 2.0 * (1u32 << 31) as f32;
 ///
 /// Read [BEP_0005](https://www.bittorrent.org/beps/bep_0005.html) for more information.
 pub struct PeersStore {
-    info_hashes: LruCache<Id, LruCache<Id, SocketAddrV4>>,
+    /// Peers' addresses and whether or not they announced themselves as seeds.
+    info_hashes: LruCache<Id, LruCache<Id, (SocketAddrV4, bool)>>,
     max_peers: NonZeroUsize,
 }
 
@@ -29,13 +32,74 @@ impl PeersStore {
 
     /// Add a peer for an info hash.
     pub fn add_peer(&mut self, info_hash: Id, peer: (&Id, SocketAddrV4)) {
-        if let Some(info_hash_lru) = self.info_hashes.get_mut(&info_hash) {
-            info_hash_lru.put(*peer.0, peer.1);
-        } else {
-            let mut info_hash_lru = LruCache::new(self.max_peers);
-            info_hash_lru.put(*peer.0, peer.1);
-            self.info_hashes.put(info_hash, info_hash_lru);
-        };
+        self.insert(info_hash, peer, false)
+    }
+
+    /// Add a peer that announced itself as a seed for an info hash.
+    ///
+    /// Read [BEP_0033](https://www.bittorrent.org/beps/bep_0033.html) for more information.
+    pub fn add_seed(&mut self, info_hash: Id, peer: (&Id, SocketAddrV4)) {
+        self.insert(info_hash, peer, true)
+    }
+
+    /// Returns the bloom filters of seeds and downloaders of an info hash,
+    /// or `None` if there are no peers for it, without affecting their recency.
+    pub fn scrape(&self, info_hash: &Id) -> Option<(BloomFilter, BloomFilter)> {
+        let info_hash_lru = self.info_hashes.peek(info_hash)?;
+
+        let mut seeds = BloomFilter::new();
+        let mut peers = BloomFilter::new();
+
+        for (_, (address, seed)) in info_hash_lru.iter() {
+            if *seed {
+                seeds.insert(*address.ip());
+            } else {
+                peers.insert(*address.ip());
+            }
+        }
+
+        Some((seeds, peers))
+    }
+
+    /// Returns the number of peers stored for each info hash, without
+    /// affecting their recency.
+    pub fn peers_counts(&self) -> impl Iterator<Item = (&Id, usize)> {
+        self.info_hashes
+            .iter()
+            .map(|(info_hash, peers)| (info_hash, peers.len()))
+    }
+
+    /// Returns the number of info hashes with stored peers.
+    pub fn info_hashes_count(&self) -> usize {
+        self.info_hashes.len()
+    }
+
+    /// Returns up to `max` random info hashes with stored peers, without
+    /// affecting their recency.
+    pub fn random_info_hashes(&self, max: usize) -> Vec<Id> {
+        let mut info_hashes = self
+            .info_hashes
+            .iter()
+            .map(|(info_hash, _)| *info_hash)
+            .collect::<Vec<_>>();
+
+        if info_hashes.len() <= max {
+            return info_hashes;
+        }
+
+        let mut chunk = vec![0_u8; max * 4];
+        getrandom(chunk.as_mut_slice()).expect("getrandom");
+
+        // Partial Fisher-Yates shuffle of the first `max` items.
+        for (i, bytes) in chunk.chunks_exact(4).enumerate() {
+            let rand_int = u32::from_le_bytes(bytes.try_into().expect("infallible")) as usize;
+            let j = i + rand_int % (info_hashes.len() - i);
+
+            info_hashes.swap(i, j);
+        }
+
+        info_hashes.truncate(max);
+        info_hashes
     }
 
     /// Returns a random set of peers per an info hash.
@@ -51,7 +115,7 @@ impl PeersStore {
                 return Some(
                     info_hash_lru
                         .iter()
-                        .map(|n| n.1.to_owned())
+                        .map(|(_, (address, _))| *address)
                         .collect::<Vec<_>>(),
                 );
             }
@@ -61,7 +125,7 @@ impl PeersStore {
             let mut chunk = vec![0_u8; info_hash_lru.iter().len() * 4];
             getrandom(chunk.as_mut_slice()).expect("getrandom");
 
-            for (index, (_, addr)) in info_hash_lru.iter().enumerate() {
+            for (index, (_, (addr, _))) in info_hash_lru.iter().enumerate() {
                 // Calculate the chance of adding the current item based on remaining items and slots
                 let remaining_slots = target_size - results.len();
                 let remaining_items = info_hash_lru.len() - index;
@@ -86,12 +150,80 @@ impl PeersStore {
 
         None
     }
+
+    fn insert(&mut self, info_hash: Id, peer: (&Id, SocketAddrV4), seed: bool) {
+        if let Some(info_hash_lru) = self.info_hashes.get_mut(&info_hash) {
+            info_hash_lru.put(*peer.0, (peer.1, seed));
+        } else {
+            let mut info_hash_lru = LruCache::new(self.max_peers);
+            info_hash_lru.put(*peer.0, (peer.1, seed));
+            self.info_hashes.put(info_hash, info_hash_lru);
+        };
+    }
+}
+
+impl PeerStorage for PeersStore {
+    fn add_peer(&mut self, info_hash: Id, peer: (&Id, SocketAddrV4)) {
+        PeersStore::add_peer(self, info_hash, peer)
+    }
+
+    fn add_seed(&mut self, info_hash: Id, peer: (&Id, SocketAddrV4)) {
+        PeersStore::add_seed(self, info_hash, peer)
+    }
+
+    fn scrape(&mut self, info_hash: &Id) -> Option<(BloomFilter, BloomFilter)> {
+        PeersStore::scrape(self, info_hash)
+    }
+
+    fn get_random_peers(&mut self, info_hash: &Id) -> Option<Vec<SocketAddrV4>> {
+        PeersStore::get_random_peers(self, info_hash)
+    }
+
+    fn peers_counts(&self) -> Vec<(Id, usize)> {
+        PeersStore::peers_counts(self)
+            .map(|(info_hash, count)| (*info_hash, count))
+            .collect()
+    }
+
+    fn random_info_hashes(&self, max: usize) -> Vec<Id> {
+        PeersStore::random_info_hashes(self, max)
+    }
+
+    fn info_hashes_count(&self) -> usize {
+        PeersStore::info_hashes_count(self)
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
 
+    #[test]
+    fn random_info_hashes() {
+        let mut store = PeersStore::new(
+            NonZeroUsize::new(100).unwrap(),
+            NonZeroUsize::new(100).unwrap(),
+        );
+
+        for _ in 0..30 {
+            let info_hash = Id::random();
+            store.add_peer(
+                info_hash,
+                (&info_hash, SocketAddrV4::new([127, 0, 1, 1].into(), 0)),
+            );
+        }
+
+        let mut sample = store.random_info_hashes(20);
+        assert_eq!(sample.len(), 20);
+
+        sample.sort();
+        sample.dedup();
+        assert_eq!(sample.len(), 20);
+
+        assert_eq!(store.random_info_hashes(50).len(), 30);
+        assert_eq!(store.info_hashes_count(), 30);
+    }
+
     #[test]
     fn max_info_hashes() {
         let mut store = PeersStore::new(
@@ -174,4 +306,32 @@ mod test {
 
         assert_eq!(sample.len(), 20);
     }
+
+    #[test]
+    fn scrape() {
+        let mut store = PeersStore::new(
+            NonZeroUsize::new(1).unwrap(),
+            NonZeroUsize::new(100).unwrap(),
+        );
+
+        let info_hash = Id::random();
+        assert_eq!(store.scrape(&info_hash), None);
+
+        store.add_seed(
+            info_hash,
+            (&Id::random(), SocketAddrV4::new([127, 0, 1, 1].into(), 0)),
+        );
+        store.add_peer(
+            info_hash,
+            (&Id::random(), SocketAddrV4::new([127, 0, 1, 2].into(), 0)),
+        );
+
+        let (seeds, peers) = store.scrape(&info_hash).unwrap();
+
+        assert!(seeds.contains([127, 0, 1, 1].into()));
+        assert!(peers.contains([127, 0, 1, 2].into()));
+        assert_eq!(seeds.estimate_count(), 1);
+        assert_eq!(peers.estimate_count(), 1);
+        assert_eq!(store.get_random_peers(&info_hash).unwrap().len(), 2);
+    }
 }
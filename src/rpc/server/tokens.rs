@@ -5,12 +5,13 @@ use getrandom::getrandom;
 use std::{
     fmt::{self, Debug, Formatter},
     net::SocketAddrV4,
-    time::Instant,
+    time::{Duration, Instant},
 };
 
 use tracing::trace;
 
-const SECRET_SIZE: usize = 20;
+/// Default size of the randomly generated secrets.
+pub const SECRET_SIZE: usize = 20;
 const TOKEN_SIZE: usize = 4;
 const CASTAGNOLI: Crc<u32> = Crc::<u32>::new(&CRC_32_ISCSI);
 
@@ -19,9 +20,13 @@ const CASTAGNOLI: Crc<u32> = Crc::<u32>::new(&CRC_32_ISCSI);
 /// Read [BEP_0005](https://www.bittorrent.org/beps/bep_0005.html) for more information.
 #[derive(Clone)]
 pub struct Tokens {
-    prev_secret: [u8; SECRET_SIZE],
-    curr_secret: [u8; SECRET_SIZE],
+    prev_secret: Box<[u8]>,
+    curr_secret: Box<[u8]>,
     last_updated: Instant,
+    rotation: Duration,
+    secret_size: usize,
+    /// Fixed secret, never rotated.
+    fixed: bool,
 }
 
 impl Debug for Tokens {
@@ -33,10 +38,32 @@ impl Debug for Tokens {
 impl Tokens {
     /// Create a Tokens generator.
     pub fn new() -> Self {
+        Self::with_rotation(crate::common::TOKEN_ROTATE_INTERVAL, SECRET_SIZE)
+    }
+
+    /// Create a Tokens generator, rotating random secrets of `secret_size` bytes
+    /// every `rotation` interval.
+    pub fn with_rotation(rotation: Duration, secret_size: usize) -> Self {
+        Tokens {
+            prev_secret: random(secret_size),
+            curr_secret: random(secret_size),
+            last_updated: Instant::now(),
+            rotation,
+            secret_size,
+            fixed: false,
+        }
+    }
+
+    /// Create a Tokens generator with a fixed `secret` that is never rotated,
+    /// so tokens are reproducible, useful for testing.
+    pub fn with_secret(secret: &[u8]) -> Self {
         Tokens {
-            prev_secret: random(),
-            curr_secret: random(),
+            prev_secret: secret.into(),
+            curr_secret: secret.into(),
             last_updated: Instant::now(),
+            rotation: Duration::MAX,
+            secret_size: secret.len(),
+            fixed: true,
         }
     }
 
@@ -44,13 +71,14 @@ impl Tokens {
 
     /// Returns `true` if the current secret needs to be updated after an interval.
     pub fn should_update(&self) -> bool {
-        self.last_updated.elapsed() > crate::common::TOKEN_ROTATE_INTERVAL
+        !self.fixed && self.last_updated.elapsed() > self.rotation
     }
 
-    /// Validate that the token was generated within the past 10 minutes
+    /// Validate that the token was generated with the current or the previous secret,
+    /// so tokens issued just before a rotation remain valid until the next one.
     pub fn validate(&mut self, address: SocketAddrV4, token: &[u8]) -> bool {
-        let prev = self.internal_generate_token(address, self.prev_secret);
-        let curr = self.internal_generate_token(address, self.curr_secret);
+        let prev = generate(address, &self.prev_secret);
+        let curr = generate(address, &self.curr_secret);
 
         token == curr || token == prev
     }
@@ -59,35 +87,28 @@ impl Tokens {
     pub fn rotate(&mut self) {
         trace!("Rotating secrets");
 
-        self.prev_secret = self.curr_secret;
-        self.curr_secret = random();
+        self.prev_secret = std::mem::replace(&mut self.curr_secret, random(self.secret_size));
 
         self.last_updated = Instant::now();
     }
 
     /// Generates a new token for a remote peer.
     pub fn generate_token(&mut self, address: SocketAddrV4) -> [u8; 4] {
-        self.internal_generate_token(address, self.curr_secret)
+        generate(address, &self.curr_secret)
     }
+}
 
-    // === Private Methods ===
-
-    fn internal_generate_token(
-        &mut self,
-        address: SocketAddrV4,
-        secret: [u8; SECRET_SIZE],
-    ) -> [u8; TOKEN_SIZE] {
-        let mut digest = CASTAGNOLI.digest();
+fn generate(address: SocketAddrV4, secret: &[u8]) -> [u8; TOKEN_SIZE] {
+    let mut digest = CASTAGNOLI.digest();
 
-        let octets: Box<[u8]> = address.ip().octets().into();
+    let octets: Box<[u8]> = address.ip().octets().into();
 
-        digest.update(&octets);
-        digest.update(&secret);
+    digest.update(&octets);
+    digest.update(secret);
 
-        let checksum = digest.finalize();
+    let checksum = digest.finalize();
 
-        checksum.to_be_bytes()
-    }
+    checksum.to_be_bytes()
 }
 
 impl Default for Tokens {
@@ -96,11 +117,11 @@ impl Default for Tokens {
     }
 }
 
-fn random() -> [u8; SECRET_SIZE] {
-    let mut bytes = [0_u8; SECRET_SIZE];
+fn random(size: usize) -> Box<[u8]> {
+    let mut bytes = vec![0_u8; size];
     getrandom(&mut bytes).expect("getrandom");
 
-    bytes
+    bytes.into()
 }
 
 #[cfg(test)]
@@ -117,4 +138,32 @@ mod test {
 
         assert!(tokens.validate(address, &token))
     }
+
+    #[test]
+    fn rotation_grace_period() {
+        let mut tokens = Tokens::with_rotation(Duration::from_millis(1), 32);
+
+        let address = SocketAddrV4::new([127, 0, 0, 1].into(), 6881);
+        let token = tokens.generate_token(address);
+
+        std::thread::sleep(Duration::from_millis(2));
+        assert!(tokens.should_update());
+
+        tokens.rotate();
+        assert!(tokens.validate(address, &token));
+
+        tokens.rotate();
+        assert!(!tokens.validate(address, &token));
+    }
+
+    #[test]
+    fn fixed_secret() {
+        let address = SocketAddrV4::new([127, 0, 0, 1].into(), 6881);
+
+        let mut a = Tokens::with_secret(b"secret");
+        let mut b = Tokens::with_secret(b"secret");
+
+        assert_eq!(a.generate_token(address), b.generate_token(address));
+        assert!(!a.should_update());
+    }
 }
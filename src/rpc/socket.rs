@@ -1,13 +1,19 @@
 //! UDP socket layer managing incoming/outgoing requests and responses.
 
-use std::cmp::Ordering;
-use std::net::{SocketAddr, SocketAddrV4, UdpSocket};
+use std::collections::VecDeque;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
 use std::time::{Duration, Instant};
-use tracing::{debug, error, trace};
 
-use crate::common::{ErrorSpecific, Message, MessageType, RequestSpecific, ResponseSpecific};
+use getrandom::getrandom;
+use tracing::{debug, trace, warn, Span};
+
+use crate::common::{
+    ErrorSpecific, Ipv4Net, Message, MessageType, RequestSpecific, ResponseSpecific,
+};
 
 use super::config::Config;
+#[cfg(feature = "node")]
+use super::unreachable;
 
 const VERSION: [u8; 4] = [82, 83, 0, 4]; // "RS" version 04
 const MTU: usize = 2048;
@@ -16,6 +22,8 @@ pub const DEFAULT_PORT: u16 = 6881;
 /// Default request timeout before abandoning an inflight request to a non-responding node.
 pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_millis(2000); // 2 seconds
 pub const READ_TIMEOUT: Duration = Duration::from_millis(10);
+/// Maximum number of packets waiting for the rate limiter, older packets are dropped beyond it.
+const MAX_THROTTLED_PACKETS: usize = 1000;
 
 /// A UdpSocket wrapper that formats and correlates DHT requests and responses.
 #[derive(Debug)]
@@ -27,15 +35,190 @@ pub struct KrpcSocket {
     /// We don't need a HashMap, since we know the capacity is `65536` requests.
     /// Requests are also ordered by their transaction_id and thus sent_at, so lookup is fast.
     inflight_requests: Vec<InflightRequest>,
+    /// Accept responses from addresses other than the one the request was sent to.
+    accept_any_response_address: bool,
+    /// Number of responses received from an address other than the one the request was sent to.
+    response_address_mismatches: usize,
+    /// Simulated network conditions applied to outgoing packets, for testing.
+    network_conditions: Option<NetworkConditions>,
+    /// Outgoing packets delayed by [NetworkConditions::latency], ordered by their due time,
+    /// with the transaction_id of the request they carry, if any.
+    delayed_packets: VecDeque<(Instant, SocketAddrV4, Vec<u8>, Option<u16>)>,
+    /// Cumulative packets and errors counters.
+    stats: SocketStats,
+    /// Outbound rate limiter, see [Config::rate_limit].
+    rate_limiter: Option<RateLimiter>,
+    /// Outgoing packets waiting for the [Self::rate_limiter] to allow sending them,
+    /// with the transaction_id of the request they carry, if any.
+    ///
+    /// Bounded by [MAX_THROTTLED_PACKETS].
+    throttled_packets: VecDeque<(SocketAddrV4, Vec<u8>, Option<u16>)>,
+    /// Retransmission of unanswered requests, see [Config::retransmit_policy].
+    retransmit_policy: Option<RetransmitPolicy>,
+    /// Addresses to drop packets to and from, see [Config::blocklist].
+    blocklist: Vec<Ipv4Net>,
+    /// Whether ICMP errors are queued on the socket, see [Config::detect_unreachable].
+    detect_unreachable: bool,
+    /// Unreachable destinations not taken by [Self::take_unreachable] yet.
+    unreachable: Vec<SocketAddrV4>,
 
     local_addr: SocketAddrV4,
 }
 
+/// Cumulative counters of packets sent and received by a [KrpcSocket], and IO errors.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct SocketStats {
+    pub packets_sent: usize,
+    pub packets_received: usize,
+    pub send_errors: usize,
+    /// Errors other than the read timeout, for example ICMP port unreachable.
+    pub recv_errors: usize,
+    /// Responses with a transaction_id that isn't inflight, either timed out or never sent.
+    pub unknown_transaction_responses: usize,
+    /// ICMP destination unreachable errors, see [Config::detect_unreachable].
+    pub unreachable_destinations: usize,
+}
+
+/// Simulated network conditions applied to a node's outgoing packets,
+/// to test the robustness of queries against unreliable networks.
+///
+/// See [crate::DhtBuilder::network_conditions] and [crate::Testnet::with_packet_loss].
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct NetworkConditions {
+    /// Fraction of outgoing packets to drop, between `0.0` and `1.0`.
+    pub packet_loss: f64,
+    /// Delay added before sending each outgoing packet.
+    pub latency: Duration,
+}
+
+impl NetworkConditions {
+    /// Randomly decide whether or not to drop a packet according to [Self::packet_loss].
+    fn should_drop(&self) -> bool {
+        if self.packet_loss <= 0.0 {
+            return false;
+        }
+
+        let mut bytes = [0_u8; 4];
+        getrandom(&mut bytes).expect("getrandom");
+
+        (u32::from_le_bytes(bytes) as f64 / u32::MAX as f64) < self.packet_loss
+    }
+}
+
+/// Policy for retransmitting requests that are not answered yet, with a randomized
+/// exponential backoff, to avoid synchronized retransmissions against busy nodes.
+///
+/// Requests are only retransmitted until their request timeout, after which they
+/// are abandoned as usual, so the intervals should add up to less than that timeout.
+///
+/// See [crate::DhtBuilder::retransmit_policy].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetransmitPolicy {
+    /// Interval between the original request and the first retransmission.
+    pub initial_interval: Duration,
+    /// Factor by which the interval grows after each retransmission.
+    pub multiplier: f64,
+    /// Maximum number of retransmissions of the same request.
+    pub max_attempts: u32,
+    /// Fraction of each interval to randomly add or subtract, between `0.0` and `1.0`.
+    pub jitter: f64,
+}
+
+impl Default for RetransmitPolicy {
+    fn default() -> Self {
+        Self {
+            initial_interval: Duration::from_millis(500),
+            multiplier: 2.0,
+            max_attempts: 2,
+            jitter: 0.2,
+        }
+    }
+}
+
+impl RetransmitPolicy {
+    /// Randomized interval to wait after the `attempt`th transmission
+    /// (`0` being the original request) before retransmitting.
+    fn interval(&self, attempt: u32) -> Duration {
+        let base =
+            self.initial_interval.as_secs_f64() * self.multiplier.max(1.0).powi(attempt as i32);
+        let jitter = self.jitter.clamp(0.0, 1.0);
+
+        let mut bytes = [0_u8; 4];
+        getrandom(&mut bytes).expect("getrandom");
+        // Uniformly distributed between -1.0 and 1.0
+        let random = (u32::from_le_bytes(bytes) as f64 / u32::MAX as f64) * 2.0 - 1.0;
+
+        Duration::from_secs_f64(base * (1.0 + jitter * random))
+    }
+}
+
+/// A token bucket limiting outbound packets to a number of packets per second,
+/// allowing bursts up to that same number of packets.
+#[derive(Debug)]
+struct RateLimiter {
+    packets_per_second: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    fn new(packets_per_second: u32) -> Self {
+        Self {
+            packets_per_second: packets_per_second as f64,
+            tokens: packets_per_second as f64,
+            last_refill: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+
+        self.tokens =
+            (self.tokens + elapsed * self.packets_per_second).min(self.packets_per_second);
+        self.last_refill = now;
+    }
+
+    /// Take a token if one is available.
+    fn try_acquire(&mut self) -> bool {
+        self.refill();
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Fraction of the bucket currently consumed, between `0.0` and `1.0`.
+    fn utilization(&self) -> f64 {
+        let elapsed = self.last_refill.elapsed().as_secs_f64();
+        let tokens = (self.tokens + elapsed * self.packets_per_second).min(self.packets_per_second);
+
+        1.0 - tokens / self.packets_per_second
+    }
+}
+
 #[derive(Debug)]
 pub struct InflightRequest {
     tid: u16,
     to: SocketAddrV4,
-    sent_at: Instant,
+    /// None while the request is waiting for the rate limiter,
+    /// so it doesn't time out before it is sent.
+    sent_at: Option<Instant>,
+    timeout: Duration,
+    /// Only set if there is a [RetransmitPolicy].
+    retransmit: Option<Retransmit>,
+    /// The span of the query that sent this request, if any.
+    span: Span,
+}
+
+#[derive(Debug)]
+struct Retransmit {
+    bytes: Vec<u8>,
+    attempts: u32,
+    next_at: Instant,
 }
 
 impl KrpcSocket {
@@ -43,7 +226,23 @@ impl KrpcSocket {
         let request_timeout = config.request_timeout;
         let port = config.port;
 
-        let socket = if let Some(port) = port {
+        let socket = if let Some(bind_addr) = config.bind_addr {
+            if port.is_some() {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "bind_addr and port are mutually exclusive",
+                ));
+            }
+
+            if bind_addr.is_ipv6() {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::Unsupported,
+                    "KrpcSocket does not support Ipv6",
+                ));
+            }
+
+            UdpSocket::bind(bind_addr)?
+        } else if let Some(port) = port {
             UdpSocket::bind(SocketAddr::from(([0, 0, 0, 0], port)))?
         } else {
             match UdpSocket::bind(SocketAddr::from(([0, 0, 0, 0], DEFAULT_PORT))) {
@@ -54,17 +253,44 @@ impl KrpcSocket {
 
         let local_addr = match socket.local_addr()? {
             SocketAddr::V4(addr) => addr,
-            SocketAddr::V6(_) => unimplemented!("KrpcSocket does not support Ipv6"),
+            SocketAddr::V6(_) => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::Unsupported,
+                    "KrpcSocket does not support Ipv6",
+                ))
+            }
         };
 
         socket.set_read_timeout(Some(READ_TIMEOUT))?;
 
+        #[cfg(feature = "node")]
+        let detect_unreachable = config.detect_unreachable
+            && unreachable::enable(&socket)
+                .inspect_err(|error| warn!(?error, "Failed to detect unreachable destinations"))
+                .is_ok();
+        #[cfg(not(feature = "node"))]
+        let detect_unreachable = false;
+
         Ok(Self {
             socket,
             next_tid: 0,
-            server_mode: config.server_mode,
+            server_mode: config.server_mode && !config.read_only && !config.client_only,
             request_timeout,
             inflight_requests: Vec::with_capacity(u16::MAX as usize),
+            accept_any_response_address: config.accept_any_response_address,
+            response_address_mismatches: 0,
+            network_conditions: config.network_conditions,
+            delayed_packets: VecDeque::new(),
+            stats: SocketStats::default(),
+            rate_limiter: config
+                .rate_limit
+                .filter(|packets_per_second| *packets_per_second > 0)
+                .map(RateLimiter::new),
+            throttled_packets: VecDeque::new(),
+            retransmit_policy: config.retransmit_policy,
+            blocklist: config.blocklist.clone(),
+            detect_unreachable,
+            unreachable: Vec::new(),
 
             local_addr,
         })
@@ -91,8 +317,60 @@ impl KrpcSocket {
         self.local_addr
     }
 
+    /// Returns the number of responses received from an address other than
+    /// the one the corresponding request was sent to.
+    pub fn response_address_mismatches(&self) -> usize {
+        self.response_address_mismatches
+    }
+
+    /// Returns the cumulative counters of packets sent and received, and IO errors.
+    pub(crate) fn stats(&self) -> SocketStats {
+        self.stats
+    }
+
+    /// Returns the fraction of the outbound rate limit currently used, between `0.0` and `1.0`,
+    /// or `None` if there is no rate limit.
+    pub(crate) fn rate_limit_utilization(&self) -> Option<f64> {
+        self.rate_limiter
+            .as_ref()
+            .map(|limiter| limiter.utilization())
+    }
+
+    /// Returns the number of requests waiting for a response or a timeout.
+    pub(crate) fn inflight_requests(&self) -> usize {
+        self.inflight_requests.len()
+    }
+
+    /// Returns the number of outgoing packets waiting for the rate limiter.
+    pub(crate) fn throttled_packets(&self) -> usize {
+        self.throttled_packets.len()
+    }
+
+    /// Returns true if packets to and from this `ip` are dropped.
+    pub(crate) fn is_blocked(&self, ip: &Ipv4Addr) -> bool {
+        self.blocklist.iter().any(|range| range.contains(ip))
+    }
+
     // === Public Methods ===
 
+    /// Returns the destinations found unreachable since the last call,
+    /// see [Config::detect_unreachable].
+    pub(crate) fn take_unreachable(&mut self) -> Vec<SocketAddrV4> {
+        std::mem::take(&mut self.unreachable)
+    }
+
+    /// Drop packets to and from addresses in this `range`.
+    pub(crate) fn ban(&mut self, range: Ipv4Net) {
+        if !self.blocklist.contains(&range) {
+            self.blocklist.push(range);
+        }
+    }
+
+    /// Remove a `range` previously added with [Self::ban] or [Config::blocklist].
+    pub(crate) fn unban(&mut self, range: &Ipv4Net) {
+        self.blocklist.retain(|blocked| blocked != range);
+    }
+
     /// Returns true if this message's transaction_id is still inflight
     pub fn inflight(&self, transaction_id: &u16) -> bool {
         self.inflight_requests
@@ -102,13 +380,35 @@ impl KrpcSocket {
 
     /// Send a request to the given address and return the transaction_id
     pub fn request(&mut self, address: SocketAddrV4, request: RequestSpecific) -> u16 {
+        self.request_with_timeout(address, request, self.request_timeout)
+    }
+
+    /// Same as [Self::request], but overriding the request timeout for this request.
+    pub fn request_with_timeout(
+        &mut self,
+        address: SocketAddrV4,
+        request: RequestSpecific,
+        timeout: Duration,
+    ) -> u16 {
         let message = self.request_message(request);
         trace!(context = "socket_message_sending", message = ?message);
 
+        let sent_at = Instant::now();
+        let retransmit = self.retransmit_policy.and_then(|policy| {
+            Some(Retransmit {
+                bytes: message.to_bytes().ok()?,
+                attempts: 0,
+                next_at: sent_at + policy.interval(0),
+            })
+        });
+
         self.inflight_requests.push(InflightRequest {
             tid: message.transaction_id,
             to: address,
-            sent_at: Instant::now(),
+            sent_at: Some(sent_at),
+            timeout,
+            retransmit,
+            span: Span::current(),
         });
 
         let tid = message.transaction_id;
@@ -147,24 +447,39 @@ impl KrpcSocket {
     pub fn recv_from(&mut self) -> Option<(Message, SocketAddrV4)> {
         let mut buf = [0u8; MTU];
 
+        self.send_delayed_packets();
+        self.send_throttled_packets();
+
         // Cleanup timed-out transaction_ids.
-        // Find the first timedout request, and delete all earlier requests.
-        match self.inflight_requests.binary_search_by(|request| {
-            if request.sent_at.elapsed() > self.request_timeout {
-                Ordering::Less
-            } else {
-                Ordering::Greater
-            }
-        }) {
-            Ok(index) => {
-                self.inflight_requests.drain(..index);
-            }
-            Err(index) => {
-                self.inflight_requests.drain(..index);
+        // Requests can have different timeouts, so they are not ordered by expiry.
+        self.inflight_requests.retain(|request| {
+            request
+                .sent_at
+                .is_none_or(|sent_at| sent_at.elapsed() <= request.timeout)
+        });
+
+        self.retransmit();
+
+        let received = self.socket.recv_from(&mut buf);
+
+        if self.detect_unreachable {
+            self.handle_unreachable();
+        }
+
+        match &received {
+            Ok(_) => self.stats.packets_received += 1,
+            Err(error)
+                if matches!(
+                    error.kind(),
+                    std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                ) => {}
+            Err(error) => {
+                self.stats.recv_errors += 1;
+                trace!(context = "socket_error", ?error, "Failed to receive packet");
             }
-        };
+        }
 
-        if let Ok((amt, SocketAddr::V4(from))) = self.socket.recv_from(&mut buf) {
+        if let Ok((amt, SocketAddr::V4(from))) = received {
             let bytes = &buf[..amt];
 
             if from.port() == 0 {
@@ -175,6 +490,11 @@ impl KrpcSocket {
                 return None;
             }
 
+            if self.is_blocked(from.ip()) {
+                trace!(context = "socket_validation", ?from, "Blocked address");
+                return None;
+            }
+
             match Message::from_bytes(bytes) {
                 Ok(message) => {
                     // Parsed correctly.
@@ -238,19 +558,29 @@ impl KrpcSocket {
                     .get(index)
                     .expect("should be infallible");
 
-                if compare_socket_addr(&inflight_request.to, from) {
-                    // Confirm that it is a response we actually sent.
-                    self.inflight_requests.remove(index);
+                let expected_address = compare_socket_addr(&inflight_request.to, from);
+
+                if !expected_address {
+                    self.response_address_mismatches += 1;
 
-                    return true;
-                } else {
                     trace!(
                         context = "socket_validation",
+                        to = ?inflight_request.to,
+                        ?from,
                         message = "Response from wrong address"
                     );
                 }
+
+                if expected_address || self.accept_any_response_address {
+                    // Confirm that it is a response we actually sent.
+                    self.inflight_requests.remove(index);
+
+                    return true;
+                }
             }
             Err(_) => {
+                self.stats.unknown_transaction_responses += 1;
+
                 trace!(
                     context = "socket_validation",
                     message = "Unexpected response id"
@@ -268,6 +598,15 @@ impl KrpcSocket {
         // of 65535 ids in 2 seconds.
         let tid = self.next_tid;
         self.next_tid = self.next_tid.wrapping_add(1);
+
+        if self.inflight(&tid) {
+            warn!(
+                ?tid,
+                inflight = self.inflight_requests.len(),
+                "Reusing a transaction_id that is still inflight"
+            );
+        }
+
         tid
     }
 
@@ -301,13 +640,238 @@ impl KrpcSocket {
         }
     }
 
+    /// Retransmit unanswered requests that are due according to the [RetransmitPolicy].
+    fn retransmit(&mut self) {
+        if let Some(policy) = self.retransmit_policy {
+            let now = Instant::now();
+            let mut due = Vec::new();
+
+            for request in self.inflight_requests.iter_mut() {
+                if request.sent_at.is_none() {
+                    continue;
+                }
+
+                if let Some(retransmit) = &mut request.retransmit {
+                    if retransmit.next_at <= now && retransmit.attempts < policy.max_attempts {
+                        retransmit.attempts += 1;
+                        retransmit.next_at = now + policy.interval(retransmit.attempts);
+
+                        due.push((
+                            request.to,
+                            retransmit.bytes.clone(),
+                            request.tid,
+                            request.span.clone(),
+                        ));
+                    }
+                }
+            }
+
+            for (address, bytes, tid, span) in due {
+                let _entered = span.enter();
+
+                trace!(
+                    context = "socket_message_sending",
+                    ?address,
+                    "Retransmitting request"
+                );
+
+                let _ = self.send_bytes(address, bytes, Some(tid)).map_err(|e| {
+                    debug!(?e, "Error retransmitting request message");
+                });
+            }
+        }
+    }
+
+    /// Stop waiting for responses from destinations that ICMP errors reported as unreachable.
+    fn handle_unreachable(&mut self) {
+        #[cfg(feature = "node")]
+        for address in unreachable::drain(&self.socket) {
+            debug!(?address, "Destination unreachable");
+
+            self.stats.unreachable_destinations += 1;
+            self.inflight_requests
+                .retain(|request| request.to != address);
+            self.unreachable.push(address);
+        }
+    }
+
     /// Send a raw dht message
     fn send(&mut self, address: SocketAddrV4, message: Message) -> Result<(), SendMessageError> {
-        self.socket.send_to(&message.to_bytes()?, address)?;
+        let bytes = message.to_bytes().inspect_err(|_| {
+            self.stats.send_errors += 1;
+        })?;
+
+        let tid = matches!(message.message_type, MessageType::Request(_))
+            .then_some(message.transaction_id);
+
+        self.send_bytes(address, bytes, tid)?;
         trace!(context = "socket_message_sending", message = ?message);
         Ok(())
     }
 
+    /// Send an encoded dht message, applying simulated network conditions and rate limiting.
+    ///
+    /// `tid` is the transaction_id of the request in `bytes`, if it is a request.
+    fn send_bytes(
+        &mut self,
+        address: SocketAddrV4,
+        bytes: Vec<u8>,
+        tid: Option<u16>,
+    ) -> std::io::Result<()> {
+        if self.is_blocked(address.ip()) {
+            trace!(
+                context = "socket_message_sending",
+                ?address,
+                "Blocked address"
+            );
+            return Ok(());
+        }
+
+        if let Some(conditions) = self.network_conditions {
+            if conditions.should_drop() {
+                trace!(
+                    context = "socket_message_sending",
+                    ?address,
+                    "Simulated packet loss"
+                );
+                return Ok(());
+            }
+
+            if !conditions.latency.is_zero() {
+                self.delayed_packets.push_back((
+                    Instant::now() + conditions.latency,
+                    address,
+                    bytes,
+                    tid,
+                ));
+                return Ok(());
+            }
+        }
+
+        if !self.throttle(address, &bytes, tid) {
+            self.send_to(&bytes, address)?;
+        }
+
+        Ok(())
+    }
+
+    /// Queue the packet if the rate limit doesn't allow sending it now,
+    /// or packets are already waiting to be sent, and return true if it was queued.
+    ///
+    /// A queued request doesn't start timing out until it is sent, and if the
+    /// queue is full, the oldest packet is dropped, and its request abandoned.
+    fn throttle(&mut self, address: SocketAddrV4, bytes: &[u8], tid: Option<u16>) -> bool {
+        if let Some(rate_limiter) = &mut self.rate_limiter {
+            if !self.throttled_packets.is_empty() || !rate_limiter.try_acquire() {
+                trace!(context = "socket_message_sending", "Throttled packet");
+
+                if self.throttled_packets.len() >= MAX_THROTTLED_PACKETS {
+                    if let Some((_, _, Some(dropped))) = self.throttled_packets.pop_front() {
+                        debug!(tid = dropped, "Dropped oldest throttled request");
+
+                        self.inflight_requests
+                            .retain(|request| request.tid != dropped || request.sent_at.is_some());
+                    }
+                }
+
+                if let Some(request) = tid.and_then(|tid| self.inflight_request_mut(tid)) {
+                    if request.retransmit.as_ref().is_none_or(|r| r.attempts == 0) {
+                        request.sent_at = None;
+                    }
+                }
+
+                self.throttled_packets
+                    .push_back((address, bytes.to_vec(), tid));
+                return true;
+            }
+        }
+
+        false
+    }
+
+    fn inflight_request_mut(&mut self, tid: u16) -> Option<&mut InflightRequest> {
+        self.inflight_requests
+            .binary_search_by(|request| request.tid.cmp(&tid))
+            .ok()
+            .map(|index| &mut self.inflight_requests[index])
+    }
+
+    /// Start the timeout and retransmission clocks of a request once its packet is sent.
+    fn mark_sent(&mut self, tid: u16) {
+        let policy = self.retransmit_policy;
+
+        if let Some(request) = self.inflight_request_mut(tid) {
+            let now = Instant::now();
+
+            request.sent_at.get_or_insert(now);
+
+            if let (Some(retransmit), Some(policy)) = (&mut request.retransmit, policy) {
+                retransmit.next_at = now + policy.interval(retransmit.attempts);
+            }
+        }
+    }
+
+    /// Send packets queued by the rate limiter, as long as it allows.
+    fn send_throttled_packets(&mut self) {
+        while !self.throttled_packets.is_empty() {
+            let allowed = self
+                .rate_limiter
+                .as_mut()
+                .map(|limiter| limiter.try_acquire())
+                .unwrap_or(true);
+
+            if !allowed {
+                break;
+            }
+
+            let (address, bytes, tid) = self.throttled_packets.pop_front().expect("front is some");
+
+            let _ = self.send_to(&bytes, address).map_err(|e| {
+                debug!(?e, "Error sending throttled message");
+            });
+
+            if let Some(tid) = tid {
+                self.mark_sent(tid);
+            }
+        }
+    }
+
+    /// Send bytes on the socket, counting sent packets and errors.
+    fn send_to(&mut self, bytes: &[u8], address: SocketAddrV4) -> std::io::Result<()> {
+        match self.socket.send_to(bytes, address) {
+            Ok(_) => {
+                self.stats.packets_sent += 1;
+                Ok(())
+            }
+            Err(error) => {
+                self.stats.send_errors += 1;
+                Err(error)
+            }
+        }
+    }
+
+    /// Send packets delayed by simulated latency, that are due.
+    fn send_delayed_packets(&mut self) {
+        let now = Instant::now();
+
+        while self
+            .delayed_packets
+            .front()
+            .map(|(due, _, _, _)| *due <= now)
+            .unwrap_or(false)
+        {
+            let (_, address, bytes, tid) = self.delayed_packets.pop_front().expect("front is some");
+
+            if self.throttle(address, &bytes, tid) {
+                continue;
+            }
+
+            let _ = self.send_to(&bytes, address).map_err(|e| {
+                debug!(?e, "Error sending delayed message");
+            });
+        }
+    }
+
     pub fn get_socket(&self) -> &UdpSocket {
         &self.socket
     }
@@ -346,6 +910,378 @@ mod test {
 
     use super::*;
 
+    #[test]
+    fn simulated_packet_loss() {
+        let mut server = KrpcSocket::server().unwrap();
+        let server_address = server.local_addr();
+
+        let mut client = KrpcSocket::new(&Config {
+            network_conditions: Some(NetworkConditions {
+                packet_loss: 1.0,
+                ..Default::default()
+            }),
+            ..Default::default()
+        })
+        .unwrap();
+
+        client.request(
+            server_address,
+            RequestSpecific {
+                requester_id: Id::random(),
+                request_type: RequestTypeSpecific::Ping,
+            },
+        );
+
+        let started_at = Instant::now();
+        while started_at.elapsed() < Duration::from_millis(100) {
+            assert!(
+                server.recv_from().is_none(),
+                "All packets should be dropped"
+            );
+        }
+    }
+
+    #[test]
+    fn simulated_latency() {
+        let latency = Duration::from_millis(100);
+
+        let mut server = KrpcSocket::server().unwrap();
+        let server_address = server.local_addr();
+
+        let mut client = KrpcSocket::new(&Config {
+            network_conditions: Some(NetworkConditions {
+                latency,
+                ..Default::default()
+            }),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let sent_at = Instant::now();
+        client.request(
+            server_address,
+            RequestSpecific {
+                requester_id: Id::random(),
+                request_type: RequestTypeSpecific::Ping,
+            },
+        );
+
+        loop {
+            // Delayed packets are sent while receiving.
+            client.recv_from();
+
+            if server.recv_from().is_some() {
+                assert!(sent_at.elapsed() >= latency);
+                break;
+            }
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn detect_unreachable() {
+        // A closed port.
+        let closed = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = match closed.local_addr().unwrap() {
+            SocketAddr::V4(address) => address,
+            SocketAddr::V6(_) => unreachable!(),
+        };
+        drop(closed);
+
+        let mut client = KrpcSocket::new(&Config {
+            detect_unreachable: true,
+            ..Default::default()
+        })
+        .unwrap();
+
+        let tid = client.request(
+            address,
+            RequestSpecific {
+                requester_id: Id::random(),
+                request_type: RequestTypeSpecific::Ping,
+            },
+        );
+
+        let sent_at = Instant::now();
+
+        while client.inflight(&tid) {
+            client.recv_from();
+        }
+
+        // Much sooner than the request timeout.
+        assert!(sent_at.elapsed() < DEFAULT_REQUEST_TIMEOUT / 2);
+        assert_eq!(client.stats().unreachable_destinations, 1);
+        assert_eq!(client.take_unreachable(), vec![address]);
+    }
+
+    #[test]
+    fn stats() {
+        let mut server = KrpcSocket::server().unwrap();
+        let server_address = server.local_addr();
+
+        let mut client = KrpcSocket::client().unwrap();
+
+        client.request(
+            server_address,
+            RequestSpecific {
+                requester_id: Id::random(),
+                request_type: RequestTypeSpecific::Ping,
+            },
+        );
+
+        while server.recv_from().is_none() {}
+
+        assert_eq!(client.stats().packets_sent, 1);
+        assert_eq!(server.stats().packets_received, 1);
+        assert_eq!(server.stats().recv_errors, 0);
+    }
+
+    #[test]
+    fn rate_limit() {
+        let mut server = KrpcSocket::server().unwrap();
+        let server_address = server.local_addr();
+
+        let mut client = KrpcSocket::new(&Config {
+            rate_limit: Some(2),
+            ..Default::default()
+        })
+        .unwrap();
+
+        for _ in 0..4 {
+            client.request(
+                server_address,
+                RequestSpecific {
+                    requester_id: Id::random(),
+                    request_type: RequestTypeSpecific::Ping,
+                },
+            );
+        }
+
+        assert_eq!(client.stats().packets_sent, 2);
+        assert_eq!(client.throttled_packets(), 2);
+        assert!(client.rate_limit_utilization().unwrap() > 0.9);
+
+        let mut received = 0;
+        let start = Instant::now();
+
+        while received < 4 && start.elapsed() < Duration::from_secs(3) {
+            client.recv_from();
+
+            if server.recv_from().is_some() {
+                received += 1;
+            }
+        }
+
+        assert_eq!(received, 4);
+        assert_eq!(client.throttled_packets(), 0);
+    }
+
+    #[test]
+    fn throttled_request_timeout() {
+        // Never responds.
+        let silent = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let silent_address = match silent.local_addr().unwrap() {
+            SocketAddr::V4(address) => address,
+            _ => unreachable!(),
+        };
+
+        let timeout = Duration::from_millis(200);
+
+        let mut client = KrpcSocket::new(&Config {
+            rate_limit: Some(2),
+            request_timeout: timeout,
+            ..Default::default()
+        })
+        .unwrap();
+
+        let start = Instant::now();
+
+        let tids = (0..4)
+            .map(|_| {
+                client.request(
+                    silent_address,
+                    RequestSpecific {
+                        requester_id: Id::random(),
+                        request_type: RequestTypeSpecific::Ping,
+                    },
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let last = tids[3];
+
+        // The last request is sent after about a second, and still gets its full timeout.
+        while client.inflight(&last) {
+            client.recv_from();
+        }
+
+        assert_eq!(client.throttled_packets(), 0);
+        assert!(start.elapsed() >= Duration::from_secs(1) + timeout);
+    }
+
+    #[test]
+    fn throttled_packets_cap() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let server_address = match server.local_addr().unwrap() {
+            SocketAddr::V4(address) => address,
+            _ => unreachable!(),
+        };
+
+        let mut client = KrpcSocket::new(&Config {
+            rate_limit: Some(1),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let tids = (0..MAX_THROTTLED_PACKETS + 10)
+            .map(|_| {
+                client.request(
+                    server_address,
+                    RequestSpecific {
+                        requester_id: Id::random(),
+                        request_type: RequestTypeSpecific::Ping,
+                    },
+                )
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(client.throttled_packets(), MAX_THROTTLED_PACKETS);
+
+        // The first request was sent, and the oldest throttled ones were abandoned.
+        assert!(client.inflight(&tids[0]));
+        assert!((1..10).all(|i| !client.inflight(&tids[i])));
+        assert!(client.inflight(&tids[10]));
+        assert_eq!(client.inflight_requests(), MAX_THROTTLED_PACKETS + 1);
+    }
+
+    #[test]
+    fn retransmit() {
+        let mut server = KrpcSocket::server().unwrap();
+        let server_address = server.local_addr();
+
+        let mut client = KrpcSocket::new(&Config {
+            retransmit_policy: Some(RetransmitPolicy {
+                initial_interval: Duration::from_millis(50),
+                multiplier: 2.0,
+                max_attempts: 2,
+                jitter: 0.0,
+            }),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let tid = client.request(
+            server_address,
+            RequestSpecific {
+                requester_id: Id::random(),
+                request_type: RequestTypeSpecific::Ping,
+            },
+        );
+
+        let mut received = 0;
+        let start = Instant::now();
+
+        // The server never responds, so the client retransmits after 50ms and 100ms more.
+        while start.elapsed() < Duration::from_millis(500) {
+            client.recv_from();
+
+            if let Some((message, _)) = server.recv_from() {
+                assert_eq!(message.transaction_id, tid);
+                received += 1;
+            }
+        }
+
+        assert_eq!(received, 3);
+        assert_eq!(client.stats().packets_sent, 3);
+        assert!(client.inflight(&tid));
+    }
+
+    #[test]
+    fn retransmit_interval() {
+        let mut policy = RetransmitPolicy {
+            initial_interval: Duration::from_millis(100),
+            multiplier: 2.0,
+            max_attempts: 3,
+            jitter: 0.0,
+        };
+
+        assert_eq!(policy.interval(0), Duration::from_millis(100));
+        assert_eq!(policy.interval(1), Duration::from_millis(200));
+        assert_eq!(policy.interval(2), Duration::from_millis(400));
+
+        policy.jitter = 0.5;
+
+        for _ in 0..100 {
+            let interval = policy.interval(1);
+
+            assert!(interval >= Duration::from_millis(100));
+            assert!(interval <= Duration::from_millis(300));
+        }
+    }
+
+    #[test]
+    fn unknown_transaction_responses() {
+        let mut server = KrpcSocket::server().unwrap();
+        let server_address = server.local_addr();
+
+        let mut client = KrpcSocket::client().unwrap();
+        let client_address = client.local_addr();
+
+        let tid = client.request(
+            server_address,
+            RequestSpecific {
+                requester_id: Id::random(),
+                request_type: RequestTypeSpecific::Ping,
+            },
+        );
+
+        assert_eq!(client.inflight_requests(), 1);
+
+        server.response(
+            SocketAddrV4::new([127, 0, 0, 1].into(), client_address.port()),
+            tid.wrapping_add(1),
+            ResponseSpecific::Ping(PingResponseArguments {
+                responder_id: Id::random(),
+            }),
+        );
+
+        while client.stats().packets_received == 0 {
+            assert!(client.recv_from().is_none());
+        }
+
+        assert_eq!(client.stats().unknown_transaction_responses, 1);
+        assert_eq!(client.inflight_requests(), 1);
+    }
+
+    #[test]
+    fn bind_addr() {
+        let socket = KrpcSocket::new(&Config {
+            bind_addr: Some(SocketAddr::from(([127, 0, 0, 1], 0))),
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert_eq!(*socket.local_addr().ip(), std::net::Ipv4Addr::LOCALHOST);
+        assert_ne!(socket.local_addr().port(), 0);
+
+        let error = KrpcSocket::new(&Config {
+            bind_addr: Some(SocketAddr::from(([127, 0, 0, 1], 0))),
+            port: Some(0),
+            ..Default::default()
+        })
+        .unwrap_err();
+
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+
+        let error = KrpcSocket::new(&Config {
+            bind_addr: Some("[::1]:0".parse().unwrap()),
+            ..Default::default()
+        })
+        .unwrap_err();
+
+        assert_eq!(error.kind(), std::io::ErrorKind::Unsupported);
+    }
+
     #[test]
     fn tid() {
         let mut socket = KrpcSocket::server().unwrap();
@@ -411,7 +1347,10 @@ mod test {
                 server.inflight_requests.push(InflightRequest {
                     tid: 8,
                     to: client_address,
-                    sent_at: Instant::now(),
+                    sent_at: Some(Instant::now()),
+                    timeout: DEFAULT_REQUEST_TIMEOUT,
+                    retransmit: None,
+                    span: Span::none(),
                 });
 
                 if let Some((message, from)) = server.recv_from() {
@@ -449,7 +1388,10 @@ mod test {
         server.inflight_requests.push(InflightRequest {
             tid: 8,
             to: SocketAddrV4::new([127, 0, 0, 1].into(), client_address.port() + 1),
-            sent_at: Instant::now(),
+            sent_at: Some(Instant::now()),
+            timeout: DEFAULT_REQUEST_TIMEOUT,
+            retransmit: None,
+            span: Span::none(),
         });
 
         let response = ResponseSpecific::Ping(PingResponseArguments {
@@ -464,6 +1406,47 @@ mod test {
                 server.recv_from().is_none(),
                 "Should not receive a response from wrong address"
             );
+            assert_eq!(server.response_address_mismatches(), 1);
+        });
+
+        client.response(server_address, 8, response);
+
+        server_thread.join().unwrap();
+    }
+
+    #[test]
+    fn accept_any_response_address() {
+        let mut server = KrpcSocket::new(&Config {
+            accept_any_response_address: true,
+            ..Default::default()
+        })
+        .unwrap();
+        let server_address = server.local_addr();
+
+        let mut client = KrpcSocket::client().unwrap();
+
+        let client_address = client.local_addr();
+
+        server.inflight_requests.push(InflightRequest {
+            tid: 8,
+            to: SocketAddrV4::new([127, 0, 0, 1].into(), client_address.port() + 1),
+            sent_at: Some(Instant::now()),
+            timeout: DEFAULT_REQUEST_TIMEOUT,
+            retransmit: None,
+            span: Span::none(),
+        });
+
+        let response = ResponseSpecific::Ping(PingResponseArguments {
+            responder_id: Id::random(),
+        });
+
+        let server_thread = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(5));
+            assert!(
+                server.recv_from().is_some(),
+                "Should receive a response from wrong address"
+            );
+            assert_eq!(server.response_address_mismatches(), 1);
         });
 
         client.response(server_address, 8, response);
@@ -0,0 +1,141 @@
+//! Detect unreachable destinations from ICMP errors, see [crate::DhtBuilder::detect_unreachable].
+//!
+//! Only supported on Linux, where ICMP errors for unconnected UDP sockets are
+//! queued on the socket's error queue (with `IP_RECVERR`), including the destination
+//! of the packet that triggered them. Other platforms either don't report these
+//! errors, or don't tell which destination they are for.
+
+use std::{io, net::SocketAddrV4, net::UdpSocket};
+
+#[cfg(target_os = "linux")]
+pub(crate) use linux::{enable, recv_unreachable};
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) use unsupported::{enable, recv_unreachable};
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::{
+        io,
+        mem::{size_of, zeroed},
+        net::{Ipv4Addr, SocketAddrV4, UdpSocket},
+        os::fd::AsRawFd,
+    };
+
+    /// Ask the kernel to queue ICMP errors on the socket's error queue.
+    pub(crate) fn enable(socket: &UdpSocket) -> io::Result<()> {
+        let enabled: libc::c_int = 1;
+
+        // SAFETY: the file descriptor is valid for the lifetime of `socket`,
+        // and the option value is a `c_int` of the given size.
+        let result = unsafe {
+            libc::setsockopt(
+                socket.as_raw_fd(),
+                libc::IPPROTO_IP,
+                libc::IP_RECVERR,
+                &enabled as *const libc::c_int as *const libc::c_void,
+                size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        };
+
+        if result == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
+    /// Read one error from the socket's error queue, without blocking, and return the
+    /// destination it is for, if it is an ICMP destination unreachable error.
+    ///
+    /// Returns `Err` with [io::ErrorKind::WouldBlock] once the queue is empty.
+    pub(crate) fn recv_unreachable(socket: &UdpSocket) -> io::Result<Option<SocketAddrV4>> {
+        // SAFETY: all buffers outlive the `recvmsg` call, and their sizes are passed along,
+        // control messages are only read within the returned `msg_controllen`.
+        unsafe {
+            let mut destination: libc::sockaddr_in = zeroed();
+            let mut data = [0_u8; 64];
+            let mut iov = libc::iovec {
+                iov_base: data.as_mut_ptr() as *mut libc::c_void,
+                iov_len: data.len(),
+            };
+            // Aligned for `cmsghdr`.
+            let mut control = [0_u64; 64];
+
+            let mut message: libc::msghdr = zeroed();
+            message.msg_name = &mut destination as *mut libc::sockaddr_in as *mut libc::c_void;
+            message.msg_namelen = size_of::<libc::sockaddr_in>() as libc::socklen_t;
+            message.msg_iov = &mut iov;
+            message.msg_iovlen = 1;
+            message.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+            message.msg_controllen = size_of::<[u64; 64]>() as _;
+
+            let result = libc::recvmsg(
+                socket.as_raw_fd(),
+                &mut message,
+                libc::MSG_ERRQUEUE | libc::MSG_DONTWAIT,
+            );
+
+            if result < 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            if destination.sin_family != libc::AF_INET as libc::sa_family_t {
+                return Ok(None);
+            }
+
+            let mut cmsg = libc::CMSG_FIRSTHDR(&message);
+
+            while !cmsg.is_null() {
+                if (*cmsg).cmsg_level == libc::IPPROTO_IP && (*cmsg).cmsg_type == libc::IP_RECVERR {
+                    let error = &*(libc::CMSG_DATA(cmsg) as *const libc::sock_extended_err);
+
+                    if error.ee_origin == libc::SO_EE_ORIGIN_ICMP
+                        && error.ee_errno == libc::ECONNREFUSED as u32
+                    {
+                        return Ok(Some(SocketAddrV4::new(
+                            Ipv4Addr::from(u32::from_be(destination.sin_addr.s_addr)),
+                            u16::from_be(destination.sin_port),
+                        )));
+                    }
+                }
+
+                cmsg = libc::CMSG_NXTHDR(&message, cmsg);
+            }
+
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod unsupported {
+    use std::{io, net::SocketAddrV4, net::UdpSocket};
+
+    pub(crate) fn enable(_socket: &UdpSocket) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Detecting unreachable destinations is only supported on Linux",
+        ))
+    }
+
+    pub(crate) fn recv_unreachable(_socket: &UdpSocket) -> io::Result<Option<SocketAddrV4>> {
+        Err(io::ErrorKind::WouldBlock.into())
+    }
+}
+
+/// Drain the socket's error queue, and return the unreachable destinations found.
+pub(crate) fn drain(socket: &UdpSocket) -> Vec<SocketAddrV4> {
+    let mut unreachable = Vec::new();
+
+    loop {
+        match recv_unreachable(socket) {
+            Ok(Some(address)) => unreachable.push(address),
+            Ok(None) => {}
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+            Err(_) => break,
+        }
+    }
+
+    unreachable
+}